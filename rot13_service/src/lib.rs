@@ -44,6 +44,8 @@
 /// - `GPP_ROT13_Encrypt_Rsp`: #6.10
 /// - `GPP_ROT13_Decrypt_Req`: #6.11
 /// - `GPP_ROT13_Decrypt_Rsp`: #6.11
+/// - `GPP_ROT13_EncryptBatch_Req`: #6.12
+/// - `GPP_ROT13_EncryptBatch_Rsp`: #6.12
 ///
 ///  The underlying implementations of encryption and decryption in ROT13 are identical, as ROT13
 ///  is reversible. It thus really doesn't matter which is called as they do the same thing.
@@ -58,7 +60,7 @@
 /// - `svc_name`: h'87bae713b08f5e28b9ee4aa6e202440e'
 /// - `login_method`: [0]
 ///
-/// - `$$svc_features` //= (128 => [0, 1])   // "encrypt" and "decrypt"
+/// - `$$svc_features` //= (128 => [0, 1, 2])   // "encrypt", "decrypt" and "encrypt batch"
 ///
 /// ## Encrypt
 ///
@@ -111,6 +113,31 @@
 /// - 1: space character detected
 /// - 2: numeric character detected
 /// - 3: some other symbol detected
+///
+/// ## Encrypt Batch
+///
+/// `GPP_ROT13_EncryptBatch_Req` carries an array of strings to be "encrypted" in a single
+/// message. It is encoded in CDDL as follows:
+///
+/// ```cddl
+/// GPP_ROT13_EncryptBatch_Req = #6.12 ({
+///   1 => [+ tstr]
+/// })
+/// ```
+///
+/// The service responds with `GPP_ROT13_EncryptBatch_Rsp`, which is encoded as follows:
+///
+///  ```cddl
+/// GPP_ROT13_EncryptBatch_Rsp = #6.12 ({
+///   (1 => [+ (tstr / { 2 => uint })] / 2 => uint)
+/// })
+/// ```
+///
+/// If map item 1 is present, the array contains one entry for each string in the request, in the
+/// same order. Each entry is either the "encrypted" string or, if that string could not be
+/// "encrypted", a map holding the error code (as for `GPP_ROT13_Encrypt_Rsp`). If map item 2 is
+/// present, the whole request was rejected. This happens, with error code 4, if the request holds
+/// more than `GPP_ROT13_MAX_BATCH_SIZE` strings.
 // Pull in std if we are testing or if it is defined as feature (because we run tests on a
 // platform supporting I/O and full feature set.
 #[cfg(any(feature = "std", test))]
@@ -128,8 +155,8 @@ use std::mem::size_of;
 
 use tps_client_common::c_login::LOGIN_PUBLIC;
 use tps_client_common::c_structs::ServiceVersion;
-use tps_minicbor::decoder::{is_map, is_tag, CBORDecoder, SequenceBuffer};
-use tps_minicbor::encoder::CBORBuilder;
+use tps_minicbor::decoder::{is_tag, ArrayBuf, CBORDecoder, SequenceBuffer};
+use tps_minicbor::encoder::{CBORBuilder, EncodeBuffer};
 use tps_minicbor::error::CBORError;
 use tps_minicbor::types::{array, map, tag, CBOR};
 
@@ -158,11 +185,17 @@ pub const GPP_ROT13_ENCRYPT_RSP: u32 = 10;
 pub const GPP_ROT13_DECRYPT_REQ: u32 = 11;
 /// ROT 13 Service message tag: GPP_Rot13_Decrypt_Rsp
 pub const GPP_ROT13_DECRYPT_RSP: u32 = 11;
+/// ROT 13 Service message tag: GPP_Rot13_EncryptBatch_Req
+pub const GPP_ROT13_ENCRYPT_BATCH_REQ: u32 = 12;
+/// ROT 13 Service message tag: GPP_Rot13_EncryptBatch_Rsp
+pub const GPP_ROT13_ENCRYPT_BATCH_RSP: u32 = 12;
 
 /// ROT13 service features: encrypt service
 pub const GPP_ROT13_ENCRYPT_SERVICE: u32 = 0;
 /// ROT13 service features: decrypt service
 pub const GPP_ROT13_DECRYPT_SERVICE: u32 = 1;
+/// ROT13 service features: encrypt batch service
+pub const GPP_ROT13_ENCRYPT_BATCH_SERVICE: u32 = 2;
 
 /// UUID uniquely identifying the ROT13 service
 pub const GPP_ROT13_SERVICE_NAME: [u8; 16] = [
@@ -196,13 +229,161 @@ pub const GPP_ROT13_ERROR_OTHER: u32 = 3;
 pub const GPP_ROT13_ERROR_TOO_LARGE: u32 = 4;
 
 pub const MAX_STRING_SIZE: usize = 256 * size_of::<char>();
+/// The maximum number of strings accepted in a `GPP_ROT13_EncryptBatch_Req`
+pub const GPP_ROT13_MAX_BATCH_SIZE: usize = 16;
 
+/// The transform applied by [`rot13_transform`].
 #[derive(Copy, Clone, Debug, PartialEq)]
-enum Rot13Operation {
+pub enum Rot13Operation {
+    /// "Encrypt" the input.
     Encode,
+    /// "Decrypt" the input.
     Decode,
 }
 
+/// Errors returned by [`rot13_transform`].
+///
+/// Each error converts into the `uint` error code carried in a response message using
+/// `u32::from`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Rot13Error {
+    /// The input contains a whitespace character (`GPP_ROT13_ERROR_SPACE`).
+    Space,
+    /// The input contains a numeric character (`GPP_ROT13_ERROR_NUMERIC`).
+    Numeric,
+    /// The input is empty or contains some other symbol (`GPP_ROT13_ERROR_OTHER`).
+    Other,
+    /// The input is longer than `MAX_STRING_SIZE`, or does not fit into the output buffer
+    /// (`GPP_ROT13_ERROR_TOO_LARGE`).
+    TooLarge,
+}
+
+impl From<Rot13Error> for u32 {
+    fn from(e: Rot13Error) -> Self {
+        match e {
+            Rot13Error::Space => GPP_ROT13_ERROR_SPACE,
+            Rot13Error::Numeric => GPP_ROT13_ERROR_NUMERIC,
+            Rot13Error::Other => GPP_ROT13_ERROR_OTHER,
+            Rot13Error::TooLarge => GPP_ROT13_ERROR_TOO_LARGE,
+        }
+    }
+}
+
+/***************************************************************************************************
+ * Requests
+ **************************************************************************************************/
+/// A request message received by the ROT13 service.
+///
+/// The strings and arrays borrow from the buffer holding the request, so no copy is made.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Rot13Request<'a> {
+    /// `TPS_GetFeatures_Req`. The message body is not examined.
+    GetFeatures,
+    /// `GPP_ROT13_Encrypt_Req`, carrying the text to "encrypt".
+    Encrypt(&'a str),
+    /// `GPP_ROT13_Decrypt_Req`, carrying the text to "decrypt".
+    Decrypt(&'a str),
+    /// `GPP_ROT13_EncryptBatch_Req`, carrying the array of strings to "encrypt". The entries are
+    /// checked individually when the request is handled.
+    EncryptBatch(ArrayBuf<'a>),
+}
+
+/***************************************************************************************************
+ * Message Dispatch
+ **************************************************************************************************/
+/// Signature of a message handler function.
+///
+/// A handler receives a `CBORDecoder` positioned at the start of the message body (i.e. the item
+/// enclosed by the message tag) and a `CBORBuilder` into which the response message is encoded.
+pub type MessageHandlerFn =
+    for<'a, 'b> fn(&mut CBORDecoder<'a>, &mut CBORBuilder<'b>) -> Result<(), CBORError>;
+
+/// `MessageDispatcher` maps message tags onto the handler functions which process them.
+///
+/// The table of handlers is a borrowed slice, so a dispatcher can be built as a `static` without
+/// requiring allocation. New messages are supported by adding entries to the table rather than
+/// by modifying the dispatch logic.
+pub struct MessageDispatcher<'t> {
+    handlers: &'t [(u32, MessageHandlerFn)],
+}
+
+impl<'t> MessageDispatcher<'t> {
+    /// Construct a `MessageDispatcher` from a table of `(message tag, handler)` pairs.
+    pub const fn new(handlers: &'t [(u32, MessageHandlerFn)]) -> Self {
+        Self { handlers }
+    }
+
+    /// Call the handler registered for `msg_id`.
+    ///
+    /// Returns `Err(CBORError::IncompatibleType)` if there is no handler for `msg_id`.
+    pub fn dispatch(
+        &self,
+        msg_id: u32,
+        decoder: &mut CBORDecoder,
+        encoder: &mut CBORBuilder,
+    ) -> Result<(), CBORError> {
+        match self.handlers.iter().find(|(id, _)| *id == msg_id) {
+            Some((_, handler)) => handler(decoder, encoder),
+            None => Err(CBORError::IncompatibleType),
+        }
+    }
+}
+
+/// The message dispatch table for the ROT13 service.
+///
+/// Each handler decodes the message body into a [`Rot13Request`], which is then handled by
+/// [`handle_request`].
+static ROT13_DISPATCHER: MessageDispatcher<'static> = MessageDispatcher::new(&[
+    (TPS_GET_FEATURES_REQ, handle_get_features_req),
+    (GPP_ROT13_ENCRYPT_REQ, handle_encrypt_req),
+    (GPP_ROT13_DECRYPT_REQ, handle_decrypt_req),
+    (GPP_ROT13_ENCRYPT_BATCH_REQ, handle_encrypt_batch_req),
+]);
+
+/// Handler for `TPS_GetFeatures_Req`. The message body is not examined.
+fn handle_get_features_req(
+    _decoder: &mut CBORDecoder,
+    encoder: &mut CBORBuilder,
+) -> Result<(), CBORError> {
+    handle_request(Rot13Request::GetFeatures, encoder)
+}
+
+/// Handler for `GPP_ROT13_Encrypt_Req`, whose body is `{ 1: tstr }`.
+fn handle_encrypt_req(decoder: &mut CBORDecoder, encoder: &mut CBORBuilder) -> Result<(), CBORError> {
+    handle_request(Rot13Request::Encrypt(request_text(decoder)?), encoder)
+}
+
+/// Handler for `GPP_ROT13_Decrypt_Req`, whose body is `{ 1: tstr }`.
+fn handle_decrypt_req(decoder: &mut CBORDecoder, encoder: &mut CBORBuilder) -> Result<(), CBORError> {
+    handle_request(Rot13Request::Decrypt(request_text(decoder)?), encoder)
+}
+
+/// Handler for `GPP_ROT13_EncryptBatch_Req`, whose body is `{ 1: [+ tstr] }`.
+fn handle_encrypt_batch_req(
+    decoder: &mut CBORDecoder,
+    encoder: &mut CBORBuilder,
+) -> Result<(), CBORError> {
+    match request_item(decoder)? {
+        CBOR::Array(ab) => handle_request(Rot13Request::EncryptBatch(ab), encoder),
+        _ => Err(CBORError::IncompatibleType),
+    }
+}
+
+/// Get the item at key 1 of a request message body, which is a map.
+fn request_item<'a>(decoder: &CBORDecoder<'a>) -> Result<CBOR<'a>, CBORError> {
+    let mut item = None;
+    let _ = decoder.map(|mb| {
+        item = mb.get_int(1);
+        Ok(())
+    })?;
+    item.ok_or(CBORError::IncompatibleType)
+}
+
+/// Get the text of an encrypt or decrypt request message body, `{ 1: tstr }`.
+fn request_text<'a>(decoder: &CBORDecoder<'a>) -> Result<&'a str, CBORError> {
+    <&str>::try_from(request_item(decoder)?).map_err(|_| CBORError::MalformedEncoding)
+}
+
 /***************************************************************************************************
  * Rust Entry Point
  **************************************************************************************************/
@@ -213,9 +394,11 @@ enum Rot13Operation {
 /// the incoming message and encode the outgoing message, respectively. One consequence is that at
 /// the level of this service, separate buffers are used for input and output.
 ///
-/// Messages are dispatched to the appropriate handler function based on the message tag. This
-/// service supports tags 1 (TPS_GetFeatures_Req/Rsp), 10 (GPP_ROT13_Encrypt_Req/Rsp) and 11
-/// (GPP_ROT13_Decrypt_Req/Rsp)
+/// Messages are dispatched to the appropriate handler function based on the message tag, using
+/// the [`MessageDispatcher`] table. Each handler decodes the message body into a
+/// [`Rot13Request`] and passes it to [`handle_request`]. This service supports tags 1
+/// (TPS_GetFeatures_Req/Rsp), 10 (GPP_ROT13_Encrypt_Req/Rsp), 11 (GPP_ROT13_Decrypt_Req/Rsp)
+/// and 12 (GPP_ROT13_EncryptBatch_Req/Rsp)
 ///
 /// In this function we are generally returning `Err(CBORError)`, which will be converted into
 /// a `u32` before it is passed back to the connector.
@@ -227,56 +410,31 @@ pub fn message_handler<'b>(
     // The tag contains the message ID
     decoder.decode_with(is_tag(), |cbor| {
         let mut msg_id: u64 = 0;
-        let msg_body = CBORDecoder::from_tag(cbor, &mut msg_id)?;
+        let mut msg_body = CBORDecoder::from_tag(cbor, &mut msg_id)?;
+        let msg_id = u32::try_from(msg_id).map_err(|_| CBORError::IncompatibleType)?;
         let mut encoder = CBORBuilder::new(out_msg_buf);
-        match msg_id as u32 {
-            // GET_FEATURES_REQ (tag 1) - Don't care about contents
-            TPS_GET_FEATURES_REQ => handle_get_features(&mut encoder),
-            // ROT13_ENCRYPT_REQ (tag 10) - we should have { 1: tstr }
-            GPP_ROT13_ENCRYPT_REQ => {
-                let _ = msg_body.decode_with(is_map(), |cbor| {
-                    let mut encoder = CBORBuilder::new(out_msg_buf);
-                    if let CBOR::Map(mb) = cbor {
-                        // Get the CBOR item at key == 1, which should be a tstr
-                        if let Some(text_body) = mb.get_int(1) {
-                            handle_encrypt_req(&text_body, &mut encoder)
-                        } else {
-                            Err(CBORError::IncompatibleType)
-                        }
-                    } else {
-                        Err(CBORError::IncompatibleType)
-                    }
-                })?;
-                Ok(())
-            }
-            // ROT13_ENCRYPT_REQ (tag 11) - we should have { 1: tstr }
-            GPP_ROT13_DECRYPT_REQ => {
-                let _ = msg_body.decode_with(is_map(), |cbor| {
-                    let mut encoder = CBORBuilder::new(out_msg_buf);
-                    if let CBOR::Map(mb) = cbor {
-                        // Get the CBOR item at key == 1, which should be a tstr
-                        if let Some(text_body) = mb.get_int(1) {
-                            handle_decrypt_req(&text_body, &mut encoder)
-                        } else {
-                            Err(CBORError::IncompatibleType)
-                        }
-                    } else {
-                        Err(CBORError::IncompatibleType)
-                    }
-                })?;
-                Ok(())
-            }
-            _ => Err(CBORError::IncompatibleType),
-        }
+        ROT13_DISPATCHER.dispatch(msg_id, &mut msg_body, &mut encoder)
     })?;
     Ok(())
 }
 
+/// Handle a decoded request, encoding the response message into `encoder`.
+///
+/// This is separate from [`message_handler`] so that the handling of each message can be tested
+/// with constructed [`Rot13Request`] values.
+pub fn handle_request(request: Rot13Request, encoder: &mut CBORBuilder) -> Result<(), CBORError> {
+    match request {
+        Rot13Request::GetFeatures => handle_get_features(encoder),
+        Rot13Request::Encrypt(text) => rot13_rsp_helper(Rot13Operation::Encode, text, encoder),
+        Rot13Request::Decrypt(text) => rot13_rsp_helper(Rot13Operation::Decode, text, encoder),
+        Rot13Request::EncryptBatch(ab) => handle_encrypt_batch(ab, encoder),
+    }
+}
+
 /// Handler for the `TPS_GetFeatures_Req/Rsp` message pair.
 ///
-/// In this case, as the `TPS_GetFeatures_Req` message is so simple, it is handled entirely by the
-/// caller. For many messages, this will not be the case. This is the reason for having only a
-/// `CBOREncoder` parameter to the function.
+/// In this case, as the `TPS_GetFeatures_Req` message is so simple, the message body is not
+/// examined at all. For many messages, this will not be the case.
 fn handle_get_features(encoder: &mut CBORBuilder) -> Result<(), CBORError> {
     match encoder
         // Tag: Message ID
@@ -293,12 +451,13 @@ fn handle_get_features(encoder: &mut CBORBuilder) -> Result<(), CBORError> {
                         &TPS_GET_FEATURES_LOGIN_METHOD_KEY,
                         &array(|buf| buf.insert(&LOGIN_PUBLIC)),
                     )?
-                    // 0x80 => [0, 1]
+                    // 0x80 => [0, 1, 2]
                     .insert_key_value(
                         &GPP_SVC_FEATURES_KEY,
                         &array(|buf| {
                             buf.insert(&GPP_ROT13_ENCRYPT_SERVICE)?
-                                .insert(&GPP_ROT13_DECRYPT_SERVICE)
+                                .insert(&GPP_ROT13_DECRYPT_SERVICE)?
+                                .insert(&GPP_ROT13_ENCRYPT_BATCH_SERVICE)
                         }),
                     )
             }))
@@ -308,80 +467,132 @@ fn handle_get_features(encoder: &mut CBORBuilder) -> Result<(), CBORError> {
     }
 }
 
-/// Handler for the `TPS_GetFeatures_Req/Rsp` message pair.
-///
-/// In this case, as the `TPS_GetFeatures_Req` message is so simple, it is handled entirely by the
-/// caller. For many messages, this will not be the case. This is the reason for having only a
-/// `CBOREncoder` parameter to the function.
-fn handle_encrypt_req<'b>(
-    decoder: &'b CBOR<'b>,
-    encoder: &'b mut CBORBuilder<'b>,
-) -> Result<(), CBORError> {
-    rot13_req_helper(Rot13Operation::Encode, decoder, encoder)
+/// Handler for the `GPP_ROT13_EncryptBatch_Req/Rsp` message pair.
+///
+/// `ab` is the array of strings from the message body `{ 1: [+ tstr] }`. Each string is
+/// "encrypted" independently, so a bad string produces an error entry in the response array
+/// without affecting the others.
+fn handle_encrypt_batch(ab: ArrayBuf, encoder: &mut CBORBuilder) -> Result<(), CBORError> {
+    if ab.len() > GPP_ROT13_MAX_BATCH_SIZE {
+        let error_code = CBOR::UInt(GPP_ROT13_ERROR_TOO_LARGE as u64);
+        encoder.insert(&tag(GPP_ROT13_ENCRYPT_BATCH_RSP as u64, |buf| {
+            buf.insert(&map(|buf| {
+                buf.insert_key_value(&GPP_ROT13_ERROR_KEY, &error_code)
+            }))
+        }))?;
+        return Ok(());
+    }
+    encoder.insert(&tag(GPP_ROT13_ENCRYPT_BATCH_RSP as u64, |buf| {
+        buf.insert(&map(|buf| {
+            buf.insert_key_value(
+                &GPP_ROT13_CIPHERTEXT_KEY,
+                &array(|buf| {
+                    for item in ab {
+                        let _ = rot13_batch_item(item, buf)?;
+                    }
+                    Ok(buf)
+                }),
+            )
+        }))
+    }))?;
+    Ok(())
 }
 
-fn handle_decrypt_req<'b>(
-    decoder: &'b CBOR<'b>,
-    encoder: &'b mut CBORBuilder<'b>,
-) -> Result<(), CBORError> {
-    rot13_req_helper(Rot13Operation::Decode, decoder, encoder)
+/// Encode the response array entry for a single string in a `GPP_ROT13_EncryptBatch_Req`: either
+/// the "encrypted" string or a `{ 2 => uint }` error map.
+fn rot13_batch_item<'f, 'b>(
+    item: CBOR,
+    buf: &'f mut EncodeBuffer<'b>,
+) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+    let mut ciphertext_buf: [u8; MAX_STRING_SIZE] = [0; MAX_STRING_SIZE];
+    let result = match <&str>::try_from(item) {
+        Ok(plaintext) => rot13_transform(Rot13Operation::Encode, plaintext, &mut ciphertext_buf),
+        Err(_) => Err(Rot13Error::Other),
+    };
+    match result {
+        Ok(ciphertext_len) => match core::str::from_utf8(&ciphertext_buf[0..ciphertext_len]) {
+            Ok(ciphertext) => buf.insert(&ciphertext),
+            Err(_) => Err(CBORError::UTF8Error),
+        },
+        Err(e) => {
+            let error_code = CBOR::UInt(u32::from(e) as u64);
+            buf.insert(&map(|buf| {
+                buf.insert_key_value(&GPP_ROT13_ERROR_KEY, &error_code)
+            }))
+        }
+    }
 }
 
-fn rot13_req_helper<'b>(
+/// Handler for the `GPP_ROT13_Encrypt_Req/Rsp` and `GPP_ROT13_Decrypt_Req/Rsp` message pairs.
+fn rot13_rsp_helper(
     op: Rot13Operation,
-    decoder: &'b CBOR<'b>,
-    encoder: &'b mut CBORBuilder<'b>,
+    plaintext: &str,
+    encoder: &mut CBORBuilder,
 ) -> Result<(), CBORError> {
-    let may_plaintext = <&str>::try_from(*decoder);
-
     let (msg_id, text_key) = if op == Rot13Operation::Encode {
         (GPP_ROT13_ENCRYPT_RSP, GPP_ROT13_CIPHERTEXT_KEY)
     } else {
         (GPP_ROT13_DECRYPT_RSP, GPP_ROT13_PLAINTEXT_KEY)
     };
 
-    if let Ok(plaintext) = may_plaintext {
-        // Want this to work as no_std, so we cannot use strings. There are some contortions here
-        // with a stack allocated [u8] which we later convert to &str using core::str::from_utf8()
-        let mut ciphertext_buf: [u8; MAX_STRING_SIZE] = [0; MAX_STRING_SIZE];
-
-        match rot13(op, plaintext, &mut ciphertext_buf.as_mut_slice()) {
-            Ok(ciphertext_len) => {
-                match core::str::from_utf8(&ciphertext_buf.as_slice()[0..ciphertext_len]) {
-                    Ok(ciphertext) => {
-                        encoder.insert(&tag(msg_id as u64, |buf| {
-                            buf.insert(&map(|buf| buf.insert_key_value(&text_key, &ciphertext)))
-                        }))?;
-                        Ok(())
-                    }
-                    Err(_) => Err(CBORError::UTF8Error),
+    // Want this to work as no_std, so we cannot use strings. There are some contortions here
+    // with a stack allocated [u8] which we later convert to &str using core::str::from_utf8()
+    let mut ciphertext_buf: [u8; MAX_STRING_SIZE] = [0; MAX_STRING_SIZE];
+
+    match rot13_transform(op, plaintext, &mut ciphertext_buf) {
+        Ok(ciphertext_len) => {
+            match core::str::from_utf8(&ciphertext_buf.as_slice()[0..ciphertext_len]) {
+                Ok(ciphertext) => {
+                    encoder.insert(&tag(msg_id as u64, |buf| {
+                        buf.insert(&map(|buf| buf.insert_key_value(&text_key, &ciphertext)))
+                    }))?;
+                    Ok(())
                 }
-            }
-            Err(e) => {
-                let error_code = CBOR::UInt(e as u64);
-                encoder.insert(&tag(msg_id as u64, |buf| {
-                    buf.insert(&map(|buf| {
-                        buf.insert_key_value(&GPP_ROT13_ERROR_KEY, &error_code)
-                    }))
-                }))?;
-                Ok(())
+                Err(_) => Err(CBORError::UTF8Error),
             }
         }
-    } else {
-        Err(CBORError::MalformedEncoding)
+        Err(e) => {
+            let error_code = CBOR::UInt(u32::from(e) as u64);
+            encoder.insert(&tag(msg_id as u64, |buf| {
+                buf.insert(&map(|buf| {
+                    buf.insert_key_value(&GPP_ROT13_ERROR_KEY, &error_code)
+                }))
+            }))?;
+            Ok(())
+        }
     }
 }
 
-fn rot13<'b>(operation: Rot13Operation, input: &str, output: &mut [u8]) -> Result<usize, u32> {
+/***************************************************************************************************
+ * Service Logic
+ **************************************************************************************************/
+/// Apply the ROT13 transform to `input`, writing the result into `output`.
+///
+/// This is the service logic used by the message handlers, without any CBOR message handling.
+/// On success, returns the number of bytes written to `output`, which always hold valid UTF-8.
+///
+/// ```
+/// use rot13_service::{rot13_transform, Rot13Error, Rot13Operation};
+///
+/// let mut out = [0u8; 16];
+/// let len = rot13_transform(Rot13Operation::Encode, "Hello", &mut out).unwrap();
+/// assert_eq!(&out[..len], b"Uryyb");
+/// assert_eq!(rot13_transform(Rot13Operation::Decode, "a b", &mut out), Err(Rot13Error::Space));
+/// ```
+pub fn rot13_transform(
+    operation: Rot13Operation,
+    input: &str,
+    output: &mut [u8],
+) -> Result<usize, Rot13Error> {
     // In this function we only alter values in the ASCII 'A'-'Z', 'a'-'z' range, which means that
     // input_bytes is always a valid unicode string
     let a_lower = u8::from(b'a');
     let a_upper = u8::from(b'A');
 
     if input.len() == 0 {
-        Err(GPP_ROT13_ERROR_OTHER)
-    } else if input.len() >= MAX_STRING_SIZE {
-        Err(GPP_ROT13_ERROR_TOO_LARGE)
+        Err(Rot13Error::Other)
+    } else if input.len() >= MAX_STRING_SIZE || input.len() > output.len() {
+        Err(Rot13Error::TooLarge)
     } else {
         let mut idx = 0;
         for character in input.chars() {
@@ -414,11 +625,11 @@ fn rot13<'b>(operation: Rot13Operation, input: &str, output: &mut [u8]) -> Resul
                 output[idx] = shifted_char;
                 idx += 1;
             } else if character.is_ascii_whitespace() {
-                return Err(GPP_ROT13_ERROR_SPACE);
+                return Err(Rot13Error::Space);
             } else if character.is_ascii_digit() {
-                return Err(GPP_ROT13_ERROR_NUMERIC);
+                return Err(Rot13Error::Numeric);
             } else {
-                return Err(GPP_ROT13_ERROR_OTHER);
+                return Err(Rot13Error::Other);
             }
         }
         Ok(idx)
@@ -21,14 +21,18 @@
 extern crate rot13_service;
 
 use rot13_service::{
-    message_handler, GPP_ROT13_CIPHERTEXT_KEY, GPP_ROT13_DECRYPT_REQ, GPP_ROT13_DECRYPT_RSP,
-    GPP_ROT13_ENCRYPT_REQ, GPP_ROT13_ENCRYPT_RSP, GPP_ROT13_ERROR_NUMERIC, GPP_ROT13_ERROR_OTHER,
-    GPP_ROT13_ERROR_SPACE, GPP_ROT13_PLAINTEXT_KEY,
+    handle_request, message_handler, rot13_transform, MessageDispatcher, Rot13Error,
+    Rot13Operation, Rot13Request,
+    GPP_ROT13_CIPHERTEXT_KEY, GPP_ROT13_DECRYPT_REQ, GPP_ROT13_DECRYPT_RSP,
+    GPP_ROT13_ENCRYPT_BATCH_REQ, GPP_ROT13_ENCRYPT_BATCH_RSP, GPP_ROT13_ENCRYPT_REQ,
+    GPP_ROT13_ENCRYPT_RSP, GPP_ROT13_ERROR_KEY, GPP_ROT13_ERROR_NUMERIC, GPP_ROT13_ERROR_OTHER,
+    GPP_ROT13_ERROR_SPACE, GPP_ROT13_ERROR_TOO_LARGE, GPP_ROT13_MAX_BATCH_SIZE,
+    GPP_ROT13_PLAINTEXT_KEY,
 };
-use tps_minicbor::decoder::{is_map, is_tag_with_value, SequenceBuffer};
+use tps_minicbor::decoder::{is_map, is_tag_with_value, CBORDecoder, SequenceBuffer};
 use tps_minicbor::encoder::CBORBuilder;
 use tps_minicbor::error::CBORError;
-use tps_minicbor::types::{map, tag, CBOR};
+use tps_minicbor::types::{array, map, tag, CBOR};
 
 #[test]
 fn test_encode_lowercase_success() -> Result<(), CBORError> {
@@ -346,3 +350,210 @@ fn test_decode_other_failure() -> Result<(), CBORError> {
     }
     Ok(())
 }
+
+#[test]
+fn test_unknown_message_rejected() -> Result<(), CBORError> {
+    let mut send_buf = [0u8; 100];
+    let mut receive_buf = [0u8; 100];
+
+    let mut encoder = CBORBuilder::new(&mut send_buf);
+    let encode_buf = encoder
+        .insert(&tag(42, |buf| {
+            buf.insert(&map(|buf| {
+                buf.insert_key_value(&GPP_ROT13_PLAINTEXT_KEY, &"abc")
+            }))
+        }))?
+        .encoded()?;
+    assert!(message_handler(encode_buf, &mut receive_buf).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_encrypt_batch() -> Result<(), CBORError> {
+    let mut send_buf = [0u8; 100];
+    let mut receive_buf = [0u8; 100];
+
+    {
+        let mut encoder = CBORBuilder::new(&mut send_buf);
+        let encode_buf = encoder
+            .insert(&tag(GPP_ROT13_ENCRYPT_BATCH_REQ as u64, |buf| {
+                buf.insert(&map(|buf| {
+                    buf.insert_key_value(
+                        &GPP_ROT13_PLAINTEXT_KEY,
+                        &array(|buf| buf.insert(&"Hello")?.insert(&"no way")?.insert(&"abc")),
+                    )
+                }))
+            }))?
+            .encoded()?;
+        message_handler(encode_buf, &mut receive_buf)?;
+    }
+    {
+        let decode_iter = SequenceBuffer::new(&receive_buf).into_iter();
+        let (_, rsp) = is_tag_with_value(GPP_ROT13_ENCRYPT_BATCH_RSP as u64)(decode_iter)?;
+        let CBOR::Tag(tb) = rsp else { panic!("Expected tag") };
+        let (_, body) = is_map()(tb.into_iter())?;
+        let CBOR::Map(mb) = body else { panic!("Expected map") };
+        let Some(CBOR::Array(ab)) = mb.get_int(GPP_ROT13_CIPHERTEXT_KEY as i64) else {
+            panic!("Expected array of results")
+        };
+        assert_eq!(ab.len(), 3);
+        assert_eq!(ab.item::<&str>(0)?, "Uryyb");
+        match ab.item::<CBOR>(1)? {
+            CBOR::Map(err) => assert_eq!(
+                err.get_int(GPP_ROT13_ERROR_KEY as i64),
+                Some(CBOR::UInt(GPP_ROT13_ERROR_SPACE as u64))
+            ),
+            _ => panic!("Expected error map"),
+        }
+        assert_eq!(ab.item::<&str>(2)?, "nop");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_encrypt_batch_too_large() -> Result<(), CBORError> {
+    let mut send_buf = [0u8; 100];
+    let mut receive_buf = [0u8; 100];
+
+    {
+        let mut encoder = CBORBuilder::new(&mut send_buf);
+        let encode_buf = encoder
+            .insert(&tag(GPP_ROT13_ENCRYPT_BATCH_REQ as u64, |buf| {
+                buf.insert(&map(|buf| {
+                    buf.insert_key_value(
+                        &GPP_ROT13_PLAINTEXT_KEY,
+                        &array(|buf| {
+                            for _ in 0..=GPP_ROT13_MAX_BATCH_SIZE {
+                                let _ = buf.insert(&"a")?;
+                            }
+                            Ok(buf)
+                        }),
+                    )
+                }))
+            }))?
+            .encoded()?;
+        message_handler(encode_buf, &mut receive_buf)?;
+    }
+    {
+        let decode_iter = SequenceBuffer::new(&receive_buf).into_iter();
+        let (_, rsp) = is_tag_with_value(GPP_ROT13_ENCRYPT_BATCH_RSP as u64)(decode_iter)?;
+        let CBOR::Tag(tb) = rsp else { panic!("Expected tag") };
+        let (_, body) = is_map()(tb.into_iter())?;
+        let CBOR::Map(mb) = body else { panic!("Expected map") };
+        assert_eq!(
+            mb.get_int(GPP_ROT13_ERROR_KEY as i64),
+            Some(CBOR::UInt(GPP_ROT13_ERROR_TOO_LARGE as u64))
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_decode_request() -> Result<(), CBORError> {
+    let mut send_buf = [0u8; 100];
+    let mut receive_buf = [0u8; 100];
+    let mut encoder = CBORBuilder::new(&mut send_buf);
+    let encode_buf = encoder
+        .insert(&tag(GPP_ROT13_DECRYPT_REQ as u64, |buf| {
+            buf.insert(&map(|buf| {
+                buf.insert_key_value(&GPP_ROT13_CIPHERTEXT_KEY, &"Uryyb")
+            }))
+        }))?
+        .encoded()?;
+    message_handler(encode_buf, &mut receive_buf)?;
+    let decode_iter = SequenceBuffer::new(&receive_buf).into_iter();
+    let (_, rsp) = is_tag_with_value(GPP_ROT13_DECRYPT_RSP as u64)(decode_iter)?;
+    let CBOR::Tag(tb) = rsp else { panic!("Expected tag") };
+    let (_, body) = is_map()(tb.into_iter())?;
+    let CBOR::Map(mb) = body else { panic!("Expected map") };
+    assert_eq!(mb.lookup::<u32, &str>(GPP_ROT13_PLAINTEXT_KEY)?, "Hello");
+
+    // TPS_GetFeatures_Req and GPP_ROT13_EncryptBatch_Req: 12({1: ["a"]})
+    message_handler(&[0xc1, 0xa0], &mut receive_buf)?;
+    message_handler(&[0xcc, 0xa1, 0x01, 0x81, 0x61, 0x61], &mut receive_buf)?;
+
+    // Unknown message, missing key, text which is not a tstr and a message which is not tagged
+    assert!(matches!(
+        message_handler(&[0xd8, 0x2a, 0xa0], &mut receive_buf),
+        Err(CBORError::IncompatibleType)
+    ));
+    assert!(matches!(
+        message_handler(&[0xca, 0xa0], &mut receive_buf),
+        Err(CBORError::IncompatibleType)
+    ));
+    assert!(matches!(
+        message_handler(&[0xca, 0xa1, 0x01, 0x01], &mut receive_buf),
+        Err(CBORError::MalformedEncoding)
+    ));
+    assert!(matches!(
+        message_handler(&[0xa0], &mut receive_buf),
+        Err(CBORError::ExpectedType("tag"))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_message_dispatcher() -> Result<(), CBORError> {
+    // A dispatcher which only supports TPS_GetFeatures_Req, on a different tag
+    fn handle_get_features(
+        _decoder: &mut CBORDecoder,
+        encoder: &mut CBORBuilder,
+    ) -> Result<(), CBORError> {
+        handle_request(Rot13Request::GetFeatures, encoder)
+    }
+    static DISPATCHER: MessageDispatcher<'static> =
+        MessageDispatcher::new(&[(42, handle_get_features)]);
+
+    let mut receive_buf = [0u8; 100];
+    let mut decoder = CBORDecoder::new(SequenceBuffer::new(&[0xa0]));
+    let mut encoder = CBORBuilder::new(&mut receive_buf);
+    DISPATCHER.dispatch(42, &mut decoder, &mut encoder)?;
+    let decode_iter = SequenceBuffer::new(encoder.encoded()?).into_iter();
+    let _ = is_tag_with_value(1)(decode_iter)?; // TPS_GetFeatures_Rsp
+
+    let mut encoder = CBORBuilder::new(&mut receive_buf);
+    assert!(matches!(
+        DISPATCHER.dispatch(1, &mut decoder, &mut encoder),
+        Err(CBORError::IncompatibleType)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_handle_request() -> Result<(), CBORError> {
+    let mut receive_buf = [0u8; 100];
+    let mut encoder = CBORBuilder::new(&mut receive_buf);
+    handle_request(Rot13Request::Encrypt("Hello"), &mut encoder)?;
+
+    let decode_iter = SequenceBuffer::new(encoder.encoded()?).into_iter();
+    let (_, rsp) = is_tag_with_value(GPP_ROT13_ENCRYPT_RSP as u64)(decode_iter)?;
+    let CBOR::Tag(tb) = rsp else { panic!("Expected tag") };
+    let (_, body) = is_map()(tb.into_iter())?;
+    let CBOR::Map(mb) = body else { panic!("Expected map") };
+    assert_eq!(mb.lookup::<u32, &str>(GPP_ROT13_CIPHERTEXT_KEY)?, "Uryyb");
+    Ok(())
+}
+
+#[test]
+fn test_rot13_transform() {
+    let mut out = [0u8; 64];
+
+    let len = rot13_transform(Rot13Operation::Encode, "TheQuickBrownFox", &mut out).unwrap();
+    assert_eq!(&out[..len], b"GurDhvpxOebjaSbk");
+    let len = rot13_transform(Rot13Operation::Decode, "GurDhvpxOebjaSbk", &mut out).unwrap();
+    assert_eq!(&out[..len], b"TheQuickBrownFox");
+
+    assert_eq!(rot13_transform(Rot13Operation::Encode, "a b", &mut out), Err(Rot13Error::Space));
+    assert_eq!(rot13_transform(Rot13Operation::Encode, "a1", &mut out), Err(Rot13Error::Numeric));
+    assert_eq!(rot13_transform(Rot13Operation::Encode, "a!", &mut out), Err(Rot13Error::Other));
+    assert_eq!(rot13_transform(Rot13Operation::Encode, "", &mut out), Err(Rot13Error::Other));
+    assert_eq!(
+        rot13_transform(Rot13Operation::Encode, "abcdef", &mut out[..5]),
+        Err(Rot13Error::TooLarge)
+    );
+
+    assert_eq!(u32::from(Rot13Error::Space), GPP_ROT13_ERROR_SPACE);
+    assert_eq!(u32::from(Rot13Error::Numeric), GPP_ROT13_ERROR_NUMERIC);
+    assert_eq!(u32::from(Rot13Error::Other), GPP_ROT13_ERROR_OTHER);
+    assert_eq!(u32::from(Rot13Error::TooLarge), GPP_ROT13_ERROR_TOO_LARGE);
+}
@@ -41,7 +41,10 @@ struct Cli {
     #[arg(short, long, value_name = "CDDL_FILE")]
     cddl: String,
     #[arg(short, long)]
-    prelude: bool
+    prelude: bool,
+    /// Only process the rules reachable from this rule
+    #[arg(short, long, value_name = "RULE")]
+    root: Option<String>
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -49,6 +52,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let with_prelude = cmd_line.prelude;
     let rc_filename = Rc::new(cmd_line.cddl.to_string());
-    let ast = read(with_prelude, Rc::clone(&rc_filename))?;
+    let mut ast = read(with_prelude, Rc::clone(&rc_filename))?;
+    if let Some(root) = &cmd_line.root {
+        ast = select_reachable(ast, root)?;
+    }
     Ok(println!("CDDL = {:?}", ast))
 }
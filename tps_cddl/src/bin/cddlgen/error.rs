@@ -27,6 +27,8 @@ pub enum CddlError {
     CddlParseError(CDDLParseError),
     #[error("Value has already been assigned. to {0}. Reassignment not allowed")]
     ReassignmentError(String),
+    #[error("Duplicate member key {1} in rule {0}")]
+    DuplicateMemberKey(String, String),
     #[error("Fatal runtime error")]
     FatalError
 }
@@ -42,7 +42,10 @@ struct Cli {
     #[arg(short, long, value_name = "CDDL_FILE")]
     cddl: String,
     #[arg(short, long)]
-    prelude: bool
+    prelude: bool,
+    /// Only process the rules reachable from this rule
+    #[arg(short, long, value_name = "RULE")]
+    root: Option<String>
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -50,7 +53,10 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let with_prelude = cmd_line.prelude;
     let rc_filename = Rc::new(cmd_line.cddl.to_string());
-    let ast = read(with_prelude, Rc::clone(&rc_filename))?;
+    let mut ast = read(with_prelude, Rc::clone(&rc_filename))?;
+    if let Some(root) = &cmd_line.root {
+        ast = select_reachable(ast, root)?;
+    }
     let mut ir = IRStore::new();
     pass1(&mut ir, &ast)?;
 
@@ -61,6 +67,7 @@ fn pass1<'a, 'b>(ir: &'a mut IRStore, ast: &'b CDDL) -> Result<(), CddlError> wh
     for item in ast {
         match item {
             Rule::TypeDef(s, None, Assignment::Assign, typ) => {
+                check_member_keys(s, typ)?;
                 // In this case it is an error for the key to exist already
                 ir.try_insert(s,  typ)?
             },
@@ -72,3 +79,72 @@ fn pass1<'a, 'b>(ir: &'a mut IRStore, ast: &'b CDDL) -> Result<(), CddlError> wh
     }
     Ok(())
 }
+
+/// Check that no member key is repeated within any map defined by the rule `name`. Only keys
+/// which are literal values (e.g. `1: int`, `1 => int` or `a: int`) are compared, since type keys
+/// such as `int => bool` may legitimately overlap.
+fn check_member_keys(name: &String, typ: &Type) -> Result<(), CddlError> {
+    match typ {
+        Type::Types(ts) => {
+            for t in ts {
+                check_member_keys(name, t)?
+            }
+            Ok(())
+        },
+        Type::GroupMap(group) => {
+            let mut keys: Vec<&Value> = Vec::new();
+            for item in group {
+                if let GroupItem::Key(Some(key), _, _) = item {
+                    if let Some(v) = member_key_value(key) {
+                        if keys.contains(&v) {
+                            return Err(CddlError::DuplicateMemberKey(name.clone(), format!("{:?}", v)));
+                        }
+                        keys.push(v);
+                    }
+                }
+            }
+            Ok(())
+        },
+        _ => Ok(())
+    }
+}
+
+/// Return the literal value of a member key, if it has one.
+fn member_key_value(key: &MemberKey) -> Option<&Value> {
+    match key {
+        MemberKey::FromValue(v) => Some(&**v),
+        MemberKey::FromType(t, _) => match &**t {
+            Type::Value(v) => Some(v),
+            _ => None
+        }
+    }
+}
+
+// Unit Tests
+//*************************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass1_str(text: &str) -> Result<(), CddlError> {
+        let (_, ast) = cddl(text).expect("CDDL should parse");
+        let mut ir = IRStore::new();
+        pass1(&mut ir, &ast)
+    }
+
+    #[test]
+    fn duplicate_member_key_t() {
+        match pass1_str("foo = { 1: int, 2: tstr, 1: bstr }\n") {
+            Err(CddlError::DuplicateMemberKey(rule, key)) => {
+                assert_eq!(rule, "foo");
+                assert_eq!(key, "Int(1)");
+            },
+            other => panic!("Expected DuplicateMemberKey, got {:?}", other)
+        }
+        assert!(matches!(pass1_str("foo = { 1 => int, 1: bstr }\n"),
+                         Err(CddlError::DuplicateMemberKey(_, _))));
+        assert!(matches!(pass1_str("foo = { a: int, \"a\": bstr }\n"),
+                         Err(CddlError::DuplicateMemberKey(_, _))));
+        assert!(pass1_str("foo = { 1: int, 2: bstr, a: int, int => tstr, tstr => int }\n").is_ok());
+    }
+}
@@ -21,12 +21,14 @@
 pub mod ast;
 pub mod hexfloat;
 pub mod parse;
+pub mod reach;
 
 pub use ast::{
     Assignment, GenericParam, Group, GroupItem, MemberKey, Occurs, Operator, Value, Rule,
     Type, CDDL,
 };
 pub use parse::cddl;
+pub use reach::{reachable_rules, select_reachable};
 use std::fs;
 use thiserror::Error;
 use std::rc::Rc;
@@ -144,4 +146,6 @@ pub enum CDDLParseError {
     NoFile,
     #[error("Unexpected end of file")]
     Incomplete,
+    #[error("Rule {0} is not defined")]
+    UnknownRule(String),
 }
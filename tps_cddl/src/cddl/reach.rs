@@ -0,0 +1,164 @@
+/***************************************************************************************************
+ * Copyright (c) 2021 Jeremy O'Donoghue. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * Reachability analysis over the CDDL AST
+ **************************************************************************************************/
+use crate::cddl::ast::{GroupItem, MemberKey, Rule, Type, CDDL};
+use crate::cddl::CDDLParseError;
+use std::collections::HashSet;
+
+/// Return the names of all of the rules which can be reached from the rule `root`, including
+/// `root` itself, by following references to other rules. Returns `None` if `root` is not
+/// defined in `ast`.
+///
+/// Names which do not correspond to a rule in `ast` (e.g. generic parameters) are ignored.
+pub fn reachable_rules(ast: &CDDL, root: &str) -> Option<HashSet<String>> {
+    let defined: HashSet<&str> = ast.iter().map(rule_name).collect();
+    if !defined.contains(root) {
+        return None;
+    }
+    let mut reached = HashSet::new();
+    let mut pending = vec![root.to_string()];
+    while let Some(name) = pending.pop() {
+        if reached.contains(&name) {
+            continue;
+        }
+        // There may be several rules with the same name where `/=` or `//=` is used
+        let mut refs = Vec::new();
+        for rule in ast.iter().filter(|r| rule_name(r) == name) {
+            match rule {
+                Rule::TypeDef(_, _, _, typ) => type_refs(typ, &mut refs),
+                Rule::GroupDef(_, _, _, item) => group_item_refs(item, &mut refs),
+            }
+        }
+        pending.extend(
+            refs.into_iter()
+                .filter(|r| defined.contains(r.as_str()) && !reached.contains(r)),
+        );
+        let _ = reached.insert(name);
+    }
+    Some(reached)
+}
+
+/// Return only the rules in `ast` which can be reached from the rule `root`, in their original
+/// order. This prunes the prelude and any definitions which are not needed for `root`.
+///
+/// Returns `CDDLParseError::UnknownRule` if `root` is not defined in `ast`.
+pub fn select_reachable(ast: CDDL, root: &str) -> Result<CDDL, CDDLParseError> {
+    let reached =
+        reachable_rules(&ast, root).ok_or_else(|| CDDLParseError::UnknownRule(root.to_string()))?;
+    Ok(ast
+        .into_iter()
+        .filter(|rule| reached.contains(rule_name(rule)))
+        .collect())
+}
+
+/// (private) The name of the rule defined by `rule`.
+fn rule_name(rule: &Rule) -> &str {
+    match rule {
+        Rule::TypeDef(name, _, _, _) => name,
+        Rule::GroupDef(name, _, _, _) => name,
+    }
+}
+
+/// (private) Append the names of the rules referenced by `typ` to `refs`.
+fn type_refs(typ: &Type, refs: &mut Vec<String>) {
+    match typ {
+        Type::Rule(name, args) | Type::Unwrap(name, args) | Type::GroupNameEnum(name, args) => {
+            refs.push(name.clone());
+            args.iter().flatten().for_each(|t| type_refs(t, refs));
+        }
+        Type::Types(ts) => ts.iter().for_each(|t| type_refs(t, refs)),
+        Type::GroupMap(g) | Type::GroupArray(g) | Type::GroupEnum(g) => {
+            g.iter().for_each(|item| group_item_refs(item, refs))
+        }
+        Type::Tagged(_, t) => type_refs(t, refs),
+        Type::Combined(t1, t2, _) => {
+            type_refs(t1, refs);
+            type_refs(t2, refs);
+        }
+        Type::Value(_) | Type::Major(_, _) | Type::Any => (),
+    }
+}
+
+/// (private) Append the names of the rules referenced by `item` to `refs`.
+fn group_item_refs(item: &GroupItem, refs: &mut Vec<String>) {
+    match item {
+        GroupItem::Key(key, typ, _) => {
+            if let Some(key) = key {
+                if let MemberKey::FromType(t, _) = &**key {
+                    type_refs(t, refs)
+                }
+            }
+            type_refs(typ, refs)
+        }
+        GroupItem::Name(name, _, args) => {
+            refs.push(name.clone());
+            args.iter().flatten().for_each(|t| type_refs(t, refs));
+        }
+        GroupItem::Grp(g, _) => g.iter().for_each(|item| group_item_refs(item, refs)),
+    }
+}
+
+//*************************************************************************************************
+// Unit Tests
+//*************************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cddl::parse::cddl;
+
+    fn parse(text: &str) -> CDDL {
+        cddl(text).expect("CDDL should parse").1
+    }
+
+    #[test]
+    fn reachable_rules_t() {
+        let ast = parse(
+            "msg = { 1: header, 2: body, ? 3: [* item] }\n\
+             header = bstr\n\
+             body = #6.24(payload)\n\
+             payload = tstr / int\n\
+             item = ( name: tstr, value )\n\
+             value = uint\n\
+             unrelated = { 1: other }\n\
+             other = int\n\
+             cycle = [* cycle]\n",
+        );
+        let reached = reachable_rules(&ast, "msg").unwrap();
+        let mut names: Vec<&str> = reached.iter().map(|s| s.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["body", "header", "item", "msg", "payload", "value"]);
+        assert_eq!(reachable_rules(&ast, "cycle").unwrap().len(), 1);
+        assert_eq!(reachable_rules(&ast, "missing"), None);
+    }
+
+    #[test]
+    fn select_reachable_t() {
+        let ast = parse("a = [b, c]\nunused = a\nb = 1\nc = 2\nc /= 3\n");
+        let selected = select_reachable(ast, "a").unwrap();
+        let names: Vec<&str> = selected.iter().map(rule_name).collect();
+        assert_eq!(names, vec!["a", "b", "c", "c"]);
+        assert_eq!(
+            select_reachable(parse("a = 1\n"), "b"),
+            Err(CDDLParseError::UnknownRule("b".to_string()))
+        );
+    }
+}
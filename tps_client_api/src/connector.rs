@@ -24,8 +24,9 @@
 /// a single instance of [[TPSC_GetConnectorAPI]] to exist. Using `dlopen()` and `dlsym()` on
 /// systems with dynamic linking would allow a multi-connector implementation.
 use std::ptr;
+use std::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
 
-use tps_client_common::c_structs::{ConnectionData, ServiceIdentifier, UUID};
+use tps_client_common::c_structs::{ConnectionData, MessageBuffer, ServiceIdentifier, UUID};
 use tps_connector::Connector;
 use tps_error::{from_c_error_code, TPSError};
 
@@ -98,9 +99,29 @@ pub(crate) fn open_session(instance: &Connector, service_instance: &UUID) -> Res
     let open_fn = instance.open_session;
     let mut session_id: u32 = 0;
     let c_retval = unsafe { open_fn(service_instance, &mut session_id) };
-    match from_c_error_code(c_retval, None) {
-        Ok(()) => Ok(session_id),
-        Err(e) => Err(e),
+    from_c_error_code(c_retval, None).map(|_| session_id)
+}
+
+/// Connect to a connector instance and open a session on the new connection.
+///
+/// Returns (connection id, session id) on success. If the session cannot be opened, the
+/// connection is closed again before the error from `open_session` is returned, so that it is
+/// not leaked on the service side.
+#[cfg_attr(feature = "trace", trace)]
+pub(crate) fn connect_and_open_session(
+    instance: &Connector,
+    connection_method: u32,
+    connection_data: Option<&ConnectionData>,
+    service_instance: &UUID,
+) -> Result<(u32, u32), TPSError> {
+    let connection_id = connect(instance, connection_method, connection_data)?;
+    match open_session(instance, service_instance) {
+        Ok(session_id) => Ok((connection_id, session_id)),
+        Err(e) => {
+            // Best effort: the open_session failure is the error the caller needs to see
+            let _ = disconnect(instance, connection_id);
+            Err(e)
+        }
     }
 }
 
@@ -113,6 +134,10 @@ pub(crate) fn close_session(instance: &Connector, session_id: u32) -> Result<(),
 }
 
 /// Execute a transaction
+///
+/// The connector API does not report the size of buffer needed when `out_buf` is too small, so
+/// no length hint can be passed to [`from_c_error_code`] here, and a short buffer is reported as
+/// `TPSError::BadState`.
 #[cfg_attr(feature = "trace", trace)]
 pub(crate) fn execute_transaction(
     instance: &Connector,
@@ -131,9 +156,39 @@ pub(crate) fn execute_transaction(
             &mut transaction_id,
         )
     };
-    match from_c_error_code(c_retval, None) {
-        Ok(()) => Ok(transaction_id),
-        Err(e) => Err(e),
+    from_c_error_code(c_retval, None).map(|_| transaction_id)
+}
+
+/// Execute a transaction using the message buffers passed through the client API.
+///
+/// On success, `recv_buffer.size` is set to the length of the response. On failure it is set to
+/// zero, so that a caller cannot mistake the contents left from a previous transaction for a
+/// response.
+///
+/// With the `validate_cbor` feature, a request which is not a single well-formed CBOR item is
+/// rejected with `TPSError::BadFormat` without being passed to the connector.
+#[cfg_attr(feature = "trace", trace)]
+pub(crate) fn execute_message_transaction(
+    instance: &Connector,
+    send_buffer: &MessageBuffer,
+    recv_buffer: &mut MessageBuffer,
+) -> Result<(), TPSError> {
+    let send = unsafe { &*slice_from_raw_parts(send_buffer.message, send_buffer.size) };
+    #[cfg(feature = "validate_cbor")]
+    if !tps_minicbor::decoder::is_well_formed(send) {
+        recv_buffer.size = 0;
+        return Err(TPSError::BadFormat);
+    }
+    let recv = unsafe { &mut *slice_from_raw_parts_mut(recv_buffer.message, recv_buffer.maxsize) };
+    match execute_transaction(instance, send, recv) {
+        Ok(_) => {
+            recv_buffer.size = recv.len();
+            Ok(())
+        }
+        Err(e) => {
+            recv_buffer.size = 0;
+            Err(e)
+        }
     }
 }
 
@@ -147,3 +202,301 @@ pub(crate) fn cancel_transaction(
     let c_retval = unsafe { cancel_fn(transaction_id) };
     from_c_error_code(c_retval, None)
 }
+
+/***************************************************************************************************
+ * Tests
+ **************************************************************************************************/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tps_client_common::c_errors::*;
+    use tps_client_common::c_priv::MessageBufferPriv;
+
+    // Every function of the test connector returns the value held here.
+    static RETVAL: AtomicU32 = AtomicU32::new(SUCCESS);
+    // Number of services the test connector claims to need room for on `ERROR_SHORT_BUFFER`.
+    const SERVICES_WANTED: usize = 7;
+
+    unsafe extern "C" fn test_connect(_: u32, _: *const ConnectionData, id: *mut u32) -> u32 {
+        *id = 1;
+        RETVAL.load(Ordering::SeqCst)
+    }
+    unsafe extern "C" fn test_disconnect(_: u32) -> u32 {
+        RETVAL.load(Ordering::SeqCst)
+    }
+    unsafe extern "C" fn test_service_discovery(_: *mut ServiceIdentifier, len: *mut usize) -> u32 {
+        let retval = RETVAL.load(Ordering::SeqCst);
+        *len = if retval == ERROR_SHORT_BUFFER {
+            SERVICES_WANTED
+        } else {
+            0
+        };
+        retval
+    }
+    unsafe extern "C" fn test_open_session(_: *const UUID, id: *mut u32) -> u32 {
+        *id = 2;
+        RETVAL.load(Ordering::SeqCst)
+    }
+    unsafe extern "C" fn test_close_session(_: u32) -> u32 {
+        RETVAL.load(Ordering::SeqCst)
+    }
+    unsafe extern "C" fn test_execute_transaction(
+        _: *const u8,
+        _: usize,
+        _: *mut u8,
+        _: usize,
+        id: *mut u32,
+    ) -> u32 {
+        *id = 3;
+        RETVAL.load(Ordering::SeqCst)
+    }
+    unsafe extern "C" fn test_cancel_transaction(_: u32) -> u32 {
+        RETVAL.load(Ordering::SeqCst)
+    }
+
+    const TEST_CONNECTOR: Connector = Connector {
+        connect: test_connect,
+        disconnect: test_disconnect,
+        service_discovery: test_service_discovery,
+        open_session: test_open_session,
+        close_session: test_close_session,
+        execute_transaction: test_execute_transaction,
+        cancel_transaction: test_cancel_transaction,
+    };
+
+    /// Code returned by the connector and the `TPSError` expected, as a `u32`, where the call
+    /// provides no buffer size hint.
+    const CODES: [(u32, u32); 16] = [
+        (ERROR_GENERIC, ERROR_GENERIC),
+        (ERROR_ACCESS_DENIED, ERROR_ACCESS_DENIED),
+        (ERROR_CANCEL, ERROR_CANCEL),
+        (ERROR_BAD_FORMAT, ERROR_BAD_FORMAT),
+        (ERROR_NOT_IMPLEMENTED, ERROR_NOT_IMPLEMENTED),
+        (ERROR_NOT_SUPPORTED, ERROR_NOT_SUPPORTED),
+        (ERROR_NO_DATA, ERROR_NO_DATA),
+        (ERROR_OUT_OF_MEMORY, ERROR_OUT_OF_MEMORY),
+        (ERROR_BUSY, ERROR_BUSY),
+        (ERROR_COMMUNICATION, ERROR_COMMUNICATION),
+        (ERROR_SECURITY, ERROR_SECURITY),
+        (ERROR_SHORT_BUFFER, ERROR_BAD_STATE),
+        (ERROR_DEPRECATED, ERROR_DEPRECATED),
+        (ERROR_BAD_IDENTIFIER, ERROR_BAD_IDENTIFIER),
+        (ERROR_NULL_POINTER, ERROR_NULL_POINTER),
+        (ERROR_BAD_STATE, ERROR_BAD_STATE),
+    ];
+
+    fn as_code<T>(result: Result<T, TPSError>) -> u32 {
+        match result {
+            Ok(_) => SUCCESS,
+            Err(e) => e.into(),
+        }
+    }
+
+    // All cases run in a single test as they share the connector return value.
+    #[test]
+    fn connector_error_mapping() {
+        let uuid = UUID { bytes: [0; 16] };
+        let mut services = [ServiceIdentifier::new(), ServiceIdentifier::new()];
+        let mut out_buf = [0u8; 4];
+
+        RETVAL.store(SUCCESS, Ordering::SeqCst);
+        assert_eq!(connect(&TEST_CONNECTOR, 0, None).unwrap(), 1);
+        assert_eq!(open_session(&TEST_CONNECTOR, &uuid).unwrap(), 2);
+        assert_eq!(
+            execute_transaction(&TEST_CONNECTOR, &[], &mut out_buf).unwrap(),
+            3
+        );
+        assert_eq!(
+            service_discovery(&TEST_CONNECTOR, &mut services).unwrap(),
+            0
+        );
+
+        for (code, expected) in CODES {
+            RETVAL.store(code, Ordering::SeqCst);
+            assert_eq!(as_code(connect(&TEST_CONNECTOR, 0, None)), expected);
+            assert_eq!(as_code(disconnect(&TEST_CONNECTOR, 1)), expected);
+            assert_eq!(as_code(open_session(&TEST_CONNECTOR, &uuid)), expected);
+            assert_eq!(as_code(close_session(&TEST_CONNECTOR, 2)), expected);
+            assert_eq!(
+                as_code(execute_transaction(&TEST_CONNECTOR, &[], &mut out_buf)),
+                expected
+            );
+            assert_eq!(as_code(cancel_transaction(&TEST_CONNECTOR, 3)), expected);
+        }
+
+        // Service discovery is the only call able to report the buffer size needed
+        RETVAL.store(ERROR_SHORT_BUFFER, Ordering::SeqCst);
+        assert!(matches!(
+            service_discovery(&TEST_CONNECTOR, &mut services),
+            Err(TPSError::ShortBuffer(SERVICES_WANTED))
+        ));
+    }
+
+    // Loopback connector for `connect_and_open_session`. It has its own state, so that it does
+    // not interfere with `connector_error_mapping`.
+    static OPEN_RETVAL: AtomicU32 = AtomicU32::new(SUCCESS);
+    static EXECUTE_RETVAL: AtomicU32 = AtomicU32::new(SUCCESS);
+    static DISCONNECTED: AtomicU32 = AtomicU32::new(0);
+
+    unsafe extern "C" fn loopback_connect(_: u32, _: *const ConnectionData, id: *mut u32) -> u32 {
+        *id = 42;
+        SUCCESS
+    }
+    unsafe extern "C" fn loopback_disconnect(id: u32) -> u32 {
+        DISCONNECTED.store(id, Ordering::SeqCst);
+        SUCCESS
+    }
+    unsafe extern "C" fn loopback_open_session(_: *const UUID, id: *mut u32) -> u32 {
+        *id = 7;
+        OPEN_RETVAL.load(Ordering::SeqCst)
+    }
+
+    unsafe extern "C" fn loopback_execute_transaction(
+        in_buf: *const u8,
+        in_len: usize,
+        out_buf: *mut u8,
+        out_len: usize,
+        id: *mut u32,
+    ) -> u32 {
+        ptr::copy_nonoverlapping(in_buf, out_buf, in_len.min(out_len));
+        *id = 9;
+        EXECUTE_RETVAL.load(Ordering::SeqCst)
+    }
+
+    const LOOPBACK_CONNECTOR: Connector = Connector {
+        connect: loopback_connect,
+        disconnect: loopback_disconnect,
+        open_session: loopback_open_session,
+        execute_transaction: loopback_execute_transaction,
+        ..TEST_CONNECTOR
+    };
+
+    #[test]
+    fn failed_open_session_disconnects() {
+        let uuid = UUID { bytes: [0; 16] };
+
+        OPEN_RETVAL.store(SUCCESS, Ordering::SeqCst);
+        DISCONNECTED.store(0, Ordering::SeqCst);
+        assert_eq!(
+            connect_and_open_session(&LOOPBACK_CONNECTOR, 0, None, &uuid).unwrap(),
+            (42, 7)
+        );
+        assert_eq!(DISCONNECTED.load(Ordering::SeqCst), 0);
+
+        OPEN_RETVAL.store(ERROR_BUSY, Ordering::SeqCst);
+        assert!(matches!(
+            connect_and_open_session(&LOOPBACK_CONNECTOR, 0, None, &uuid),
+            Err(TPSError::Busy)
+        ));
+        assert_eq!(DISCONNECTED.load(Ordering::SeqCst), 42);
+    }
+
+    #[test]
+    fn failed_transaction_resets_recv_size() {
+        // "png", so that the request is also well-formed CBOR
+        let mut request = *b"cpng";
+        let mut response = [0u8; 4];
+        let send_buffer = MessageBuffer {
+            message: request.as_mut_ptr(),
+            size: request.len(),
+            maxsize: request.len(),
+            imp: MessageBufferPriv::new(),
+        };
+        let mut recv_buffer = MessageBuffer {
+            message: response.as_mut_ptr(),
+            size: 0,
+            maxsize: response.len(),
+            imp: MessageBufferPriv::new(),
+        };
+
+        EXECUTE_RETVAL.store(SUCCESS, Ordering::SeqCst);
+        execute_message_transaction(&LOOPBACK_CONNECTOR, &send_buffer, &mut recv_buffer).unwrap();
+        assert_eq!(recv_buffer.size, 4);
+        assert_eq!(&response, b"cpng");
+
+        EXECUTE_RETVAL.store(ERROR_COMMUNICATION, Ordering::SeqCst);
+        assert!(matches!(
+            execute_message_transaction(&LOOPBACK_CONNECTOR, &send_buffer, &mut recv_buffer),
+            Err(TPSError::CommunicationError)
+        ));
+        assert_eq!(recv_buffer.size, 0);
+    }
+
+    #[cfg(feature = "validate_cbor")]
+    #[test]
+    fn malformed_request_not_sent() {
+        // "png" with the last byte missing
+        let mut request = *b"cpn";
+        let mut response = [0u8; 4];
+        let send_buffer = MessageBuffer {
+            message: request.as_mut_ptr(),
+            size: request.len(),
+            maxsize: request.len(),
+            imp: MessageBufferPriv::new(),
+        };
+        let mut recv_buffer = MessageBuffer {
+            message: response.as_mut_ptr(),
+            size: 4,
+            maxsize: response.len(),
+            imp: MessageBufferPriv::new(),
+        };
+
+        assert!(matches!(
+            execute_message_transaction(&LOOPBACK_CONNECTOR, &send_buffer, &mut recv_buffer),
+            Err(TPSError::BadFormat)
+        ));
+        assert_eq!(recv_buffer.size, 0);
+        // The loopback connector would have copied the request into the response
+        assert_eq!(response, [0u8; 4]);
+    }
+
+    #[cfg(feature = "validate_cbor")]
+    #[test]
+    fn indefinite_and_float_requests_sent() {
+        // Always succeeds, so that this does not depend on `EXECUTE_RETVAL`
+        unsafe extern "C" fn echo_execute_transaction(
+            in_buf: *const u8,
+            in_len: usize,
+            out_buf: *mut u8,
+            out_len: usize,
+            _: *mut u32,
+        ) -> u32 {
+            ptr::copy_nonoverlapping(in_buf, out_buf, in_len.min(out_len));
+            SUCCESS
+        }
+        const ECHO_CONNECTOR: Connector = Connector {
+            execute_transaction: echo_execute_transaction,
+            ..LOOPBACK_CONNECTOR
+        };
+
+        // [_ 1], {_ 1: 1}, (_ "a"), 1.5 and [_ 1.5]
+        let requests: [&[u8]; 5] = [
+            &[0x9f, 0x01, 0xff],
+            &[0xbf, 0x01, 0x01, 0xff],
+            &[0x7f, 0x61, 0x61, 0xff],
+            &[0xf9, 0x3e, 0x00],
+            &[0x9f, 0xfa, 0x3f, 0xc0, 0x00, 0x00, 0xff],
+        ];
+        for request in requests.iter() {
+            let mut send = [0u8; 8];
+            send[..request.len()].copy_from_slice(request);
+            let mut response = [0u8; 8];
+            let send_buffer = MessageBuffer {
+                message: send.as_mut_ptr(),
+                size: request.len(),
+                maxsize: send.len(),
+                imp: MessageBufferPriv::new(),
+            };
+            let mut recv_buffer = MessageBuffer {
+                message: response.as_mut_ptr(),
+                size: 0,
+                maxsize: response.len(),
+                imp: MessageBufferPriv::new(),
+            };
+
+            execute_message_transaction(&ECHO_CONNECTOR, &send_buffer, &mut recv_buffer).unwrap();
+            assert_eq!(&response[..request.len()], *request);
+        }
+    }
+}
@@ -36,11 +36,11 @@ extern crate tps_error;
 mod connector;
 mod services;
 
-use std::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
 use tps_client_common::c_structs::{
     ConnectionData, MessageBuffer, ServiceIdentifier, ServiceSelector, ServiceVersion, Session,
     UUID,
 };
+use tps_connector::Connector;
 use tps_error::TPSError;
 
 /***************************************************************************************************
@@ -104,6 +104,11 @@ pub fn close_session(session: &Session) -> Result<(), TPSError> {
 
 /// The function sends a request message and receives a response message within the specified
 /// session.
+///
+/// If the transaction fails, `recv_buffer.size` is set to zero.
+///
+/// When built with the `validate_cbor` feature, the request must be a single well-formed CBOR
+/// item. Any other request fails with `TPSError::BadFormat` and is not sent to the service.
 #[cfg_attr(feature = "trace", trace)]
 pub fn execute_transaction(
     session: &Session,
@@ -113,14 +118,9 @@ pub fn execute_transaction(
     // TODO: fallible, and should not be
     let service_id = unsafe { session.service_id.as_ref() }.unwrap();
     if let Some(connector) = find_service(service_id) {
-        let send = unsafe { &*slice_from_raw_parts(send_buffer.message, send_buffer.size) };
-        let recv = unsafe {
-            &mut *slice_from_raw_parts_mut((*recv_buffer).message, (*recv_buffer).maxsize)
-        };
-        connector::execute_transaction(connector, send, recv)?;
-        recv_buffer.size = recv.len();
-        Ok(())
+        connector::execute_message_transaction(connector, send_buffer, recv_buffer)
     } else {
+        recv_buffer.size = 0;
         Err(TPSError::CommunicationError)
     }
 }
@@ -153,6 +153,15 @@ pub fn initialize_transaction(
     Ok(())
 }
 
+/// The function returns an iterator over all of the connectors registered with the TPS Client API.
+///
+/// This is intended for diagnostic purposes, such as displaying the service topology of the
+/// platform. It is read-only and does not connect to any connector, so it is safe to call at any
+/// time.
+pub fn list_connectors() -> impl Iterator<Item = &'static Connector> {
+    services::connectors()
+}
+
 /// The function opens a new session between the TPS Client and the TPS Service identified by the
 /// service structure.
 #[cfg_attr(feature = "trace", trace)]
@@ -164,8 +173,12 @@ pub fn open_session(
 ) -> Result<(), TPSError> {
     // look up the Connector associated with `uuid`
     if let Some(connector) = services::find_service(uuid) {
-        let connection_id = connector::connect(connector, connection_method, connection_data)?;
-        let session_id = connector::open_session(connector, uuid)?;
+        let (connection_id, session_id) = connector::connect_and_open_session(
+            connector,
+            connection_method,
+            connection_data,
+            uuid,
+        )?;
         *session = Session {
             service_id: uuid,
             session_id,
@@ -220,3 +233,152 @@ pub fn service_discovery(
     )?;
     Ok(matched_services)
 }
+
+/// The function behaves as [`service_discovery`], and in addition records in `connectors_array`
+/// the connector which provides each of the services returned in `service_ids_array`.
+///
+/// `connectors_array[n]` is the connector for `service_ids_array[n]`. Returns
+/// `TPSError::ShortBuffer` with the number of matched services if `connectors_array` is too
+/// short to hold an entry for each of them.
+#[cfg_attr(feature = "trace", trace)]
+pub fn service_discovery_with_connectors(
+    service_selector: &ServiceSelector,
+    service_ids_array: &mut [ServiceIdentifier],
+    connectors_array: &mut [Option<&'static Connector>],
+) -> Result<usize, TPSError> {
+    let matched_services = service_discovery(service_selector, service_ids_array)?;
+    if connectors_array.len() < matched_services {
+        return Err(TPSError::ShortBuffer(matched_services));
+    }
+    for (service, connector) in service_ids_array[..matched_services]
+        .iter()
+        .zip(connectors_array.iter_mut())
+    {
+        *connector = find_service(&service.service_instance);
+    }
+    Ok(matched_services)
+}
+
+/***************************************************************************************************
+ * Tests
+ **************************************************************************************************/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+    use tps_client_common::c_errors::{ERROR_NOT_IMPLEMENTED, ERROR_SHORT_BUFFER, SUCCESS};
+    use tps_client_common::c_structs::{ServiceBounds, ServiceRange};
+
+    // Service instances reported by the test connector.
+    const INSTANCES: [UUID; 2] = [UUID { bytes: [1; 16] }, UUID { bytes: [2; 16] }];
+
+    unsafe extern "C" fn test_connect(_: u32, _: *const ConnectionData, id: *mut u32) -> u32 {
+        *id = 1;
+        SUCCESS
+    }
+    unsafe extern "C" fn test_disconnect(_: u32) -> u32 {
+        SUCCESS
+    }
+    unsafe extern "C" fn test_service_discovery(
+        services: *mut ServiceIdentifier,
+        len: *mut usize,
+    ) -> u32 {
+        if *len < INSTANCES.len() {
+            *len = INSTANCES.len();
+            return ERROR_SHORT_BUFFER;
+        }
+        for (i, instance) in INSTANCES.iter().enumerate() {
+            *services.add(i) = ServiceIdentifier {
+                service_instance: instance.clone(),
+                ..ServiceIdentifier::new()
+            };
+        }
+        *len = INSTANCES.len();
+        SUCCESS
+    }
+    unsafe extern "C" fn test_open_session(_: *const UUID, _: *mut u32) -> u32 {
+        ERROR_NOT_IMPLEMENTED
+    }
+    unsafe extern "C" fn test_close_session(_: u32) -> u32 {
+        ERROR_NOT_IMPLEMENTED
+    }
+    unsafe extern "C" fn test_execute_transaction(
+        _: *const u8,
+        _: usize,
+        _: *mut u8,
+        _: usize,
+        _: *mut u32,
+    ) -> u32 {
+        ERROR_NOT_IMPLEMENTED
+    }
+    unsafe extern "C" fn test_cancel_transaction(_: u32) -> u32 {
+        ERROR_NOT_IMPLEMENTED
+    }
+
+    static TEST_CONNECTOR: Connector = Connector {
+        connect: test_connect,
+        disconnect: test_disconnect,
+        service_discovery: test_service_discovery,
+        open_session: test_open_session,
+        close_session: test_close_session,
+        execute_transaction: test_execute_transaction,
+        cancel_transaction: test_cancel_transaction,
+    };
+
+    // The connector registered with the TPS Client API in the test build.
+    #[no_mangle]
+    unsafe extern "C" fn TPSC_GetConnectorAPI() -> *const Connector {
+        &TEST_CONNECTOR
+    }
+
+    fn any_service() -> ServiceSelector {
+        ServiceSelector {
+            service_id: UUID_NIL,
+            secure_component_type: UUID_NIL,
+            secure_component_instance: UUID_NIL,
+            service_version_range: ServiceRange {
+                lowest_acceptable_version: ServiceBounds::NoBounds,
+                first_excluded_version: ServiceBounds::NoBounds,
+                last_excluded_version: ServiceBounds::NoBounds,
+                highest_acceptable_version: ServiceBounds::NoBounds,
+            },
+        }
+    }
+
+    #[test]
+    fn list_connectors_returns_registered_connectors() {
+        let mut connectors = list_connectors();
+        assert!(ptr::eq(connectors.next().unwrap(), &TEST_CONNECTOR));
+        assert!(connectors.next().is_none());
+    }
+
+    #[test]
+    fn service_discovery_maps_services_to_connectors() {
+        let mut services = [
+            ServiceIdentifier::new(),
+            ServiceIdentifier::new(),
+            ServiceIdentifier::new(),
+        ];
+        let mut connectors = [None, None];
+        assert_eq!(
+            service_discovery_with_connectors(&any_service(), &mut services, &mut connectors)
+                .unwrap(),
+            2
+        );
+        for (service, (instance, connector)) in services
+            .iter()
+            .zip(INSTANCES.iter().zip(connectors.iter()))
+        {
+            assert_eq!(&service.service_instance, instance);
+            assert!(ptr::eq(connector.unwrap(), &TEST_CONNECTOR));
+        }
+
+        // One connector slot is not enough for two services
+        let mut connectors = [None];
+        assert!(matches!(
+            service_discovery_with_connectors(&any_service(), &mut services, &mut connectors),
+            Err(TPSError::ShortBuffer(2))
+        ));
+        assert!(connectors[0].is_none());
+    }
+}
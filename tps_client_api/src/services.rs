@@ -78,6 +78,13 @@ static CONNECTORS: Lazy<&[Option<&Connector>]> = Lazy::new(|| unsafe {
     INSTANCE.as_slice()
 });
 
+/// Return an iterator over all of the connectors registered with this TPS Client API instance.
+///
+/// This is read-only: no connection is made to any connector.
+pub(crate) fn connectors() -> impl Iterator<Item = &'static Connector> {
+    CONNECTORS.iter().filter_map(|maybe_connector| *maybe_connector)
+}
+
 /***************************************************************************************************
  * Services
  **************************************************************************************************/
@@ -112,18 +119,20 @@ fn add_service(uuid: &UUID, connector: &'static Connector) -> Result<(), TPSErro
     // Service will be initialized exactly once
     let _ = init_service();
 
-    if find_service(uuid).is_none() {
-        let mut services_guard = SERVICES.get().lock();
-        let services = services_guard.deref_mut();
-        let services_array = services.inner.as_mut();
-        for slot in services_array {
-            if slot.is_none() {
-                *slot = Some(Service {
-                    uuid: uuid.clone(),
-                    connector,
-                });
-                return Ok(());
-            }
+    // The service is already known from an earlier service discovery
+    if find_service(uuid).is_some() {
+        return Ok(());
+    }
+    let mut services_guard = SERVICES.get().lock();
+    let services = services_guard.deref_mut();
+    let services_array = services.inner.as_mut();
+    for slot in services_array {
+        if slot.is_none() {
+            *slot = Some(Service {
+                uuid: uuid.clone(),
+                connector,
+            });
+            return Ok(());
         }
     }
     Err(TPSError::GenericError)
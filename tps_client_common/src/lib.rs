@@ -441,3 +441,59 @@ pub mod c_priv {
         }
     }
 }
+
+/***************************************************************************************************
+ * ABI layout checks
+ *
+ * The structures above are shared with C via cbindgen, so any change to their size or alignment
+ * is a breaking change to the C ABI. These checks make such a change fail the build. If a change
+ * is intentional, update the expected values (and the C headers) together.
+ **************************************************************************************************/
+
+mod abi_checks {
+    use super::c_priv::*;
+    use super::c_structs::*;
+    use std::mem::{align_of, size_of};
+
+    macro_rules! assert_layout {
+        ($t:ty, $size:expr, $align:expr) => {
+            const _: () = assert!(
+                size_of::<$t>() == $size,
+                concat!("size of ", stringify!($t))
+            );
+            const _: () = assert!(
+                align_of::<$t>() == $align,
+                concat!("alignment of ", stringify!($t))
+            );
+        };
+    }
+
+    // Structures with no pointer-sized members have the same layout on all targets.
+    assert_layout!(UUID, 16, 1);
+    assert_layout!(ServiceVersion, 12, 4);
+    assert_layout!(ServiceBounds, 16, 4);
+    assert_layout!(ServiceIdentifier, 76, 4);
+    assert_layout!(ServiceRange, 64, 4);
+    assert_layout!(ServiceSelector, 112, 4);
+    assert_layout!(ServiceSpecPriv, 8, 4);
+    assert_layout!(SessionPriv, 8, 4);
+    assert_layout!(MessageBufferPriv, 4, 4);
+
+    #[cfg(target_pointer_width = "64")]
+    mod ptr64 {
+        use super::*;
+
+        assert_layout!(ConnectionData, 16, 8);
+        assert_layout!(Session, 24, 8);
+        assert_layout!(MessageBuffer, 32, 8);
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    mod ptr32 {
+        use super::*;
+
+        assert_layout!(ConnectionData, 8, 4);
+        assert_layout!(Session, 16, 4);
+        assert_layout!(MessageBuffer, 16, 4);
+    }
+}
@@ -0,0 +1,129 @@
+/***************************************************************************************************
+ * Copyright (c) 2022 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * Integrity framing for stream connectors
+ *
+ * A frame is the payload followed by a 4 byte trailer holding the CRC-32 (IEEE 802.3) of the
+ * payload in network (big-endian) byte order. This detects corruption in the transport between
+ * the connector and the service. It does not provide any protection against deliberate
+ * modification.
+ **************************************************************************************************/
+use tps_error::TPSError;
+
+/// Length of the CRC trailer appended to each frame
+pub const CRC_LEN: usize = 4;
+
+/// Write `payload` followed by its CRC-32 trailer to `out`, returning the length of the frame.
+///
+/// Returns `TPSError::ShortBuffer` with the required length if `out` is too small.
+pub fn write_with_crc(payload: &[u8], out: &mut [u8]) -> Result<usize, TPSError> {
+    let frame_len = payload.len() + CRC_LEN;
+    if out.len() < frame_len {
+        return Err(TPSError::ShortBuffer(frame_len));
+    }
+    out[..payload.len()].copy_from_slice(payload);
+    out[payload.len()..frame_len].copy_from_slice(&crc32(payload).to_be_bytes());
+    Ok(frame_len)
+}
+
+/// Check the CRC-32 trailer of the frame in `buf`, returning the payload if it is correct.
+///
+/// Returns `TPSError::CommunicationError` if the frame is too short to hold a trailer or if the
+/// trailer does not match the payload.
+pub fn read_verify_crc(buf: &[u8]) -> Result<&[u8], TPSError> {
+    if buf.len() < CRC_LEN {
+        return Err(TPSError::CommunicationError);
+    }
+    let (payload, trailer) = buf.split_at(buf.len() - CRC_LEN);
+    if trailer == crc32(payload).to_be_bytes() {
+        Ok(payload)
+    } else {
+        Err(TPSError::CommunicationError)
+    }
+}
+
+/// (private) Compute the CRC-32 (IEEE 802.3, reflected polynomial 0xEDB88320) of `bytes`.
+///
+/// A bitwise implementation is used, rather than a lookup table, to keep code size small.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/***************************************************************************************************
+ * Tests
+ **************************************************************************************************/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn crc_frame_round_trip() {
+        let payload = [0xa1, 0x01, 0x63, 0x61, 0x62, 0x63];
+        let mut out = [0u8; 16];
+        let frame_len = write_with_crc(&payload, &mut out).unwrap();
+        assert_eq!(frame_len, payload.len() + CRC_LEN);
+        assert_eq!(read_verify_crc(&out[..frame_len]).unwrap(), &payload);
+
+        // Empty payload
+        let frame_len = write_with_crc(&[], &mut out).unwrap();
+        assert_eq!(read_verify_crc(&out[..frame_len]).unwrap(), &[]);
+    }
+
+    #[test]
+    fn crc_frame_errors() {
+        let payload = [0x01, 0x02, 0x03];
+        let mut out = [0u8; 6];
+        assert!(matches!(
+            write_with_crc(&payload, &mut out),
+            Err(TPSError::ShortBuffer(7))
+        ));
+
+        let mut out = [0u8; 7];
+        let _ = write_with_crc(&payload, &mut out).unwrap();
+        // Corrupt the payload, then the trailer
+        for i in [1, 5] {
+            let mut corrupt = out;
+            corrupt[i] ^= 0x10;
+            assert!(matches!(
+                read_verify_crc(&corrupt),
+                Err(TPSError::CommunicationError)
+            ));
+        }
+        // Too short to hold a trailer
+        assert!(matches!(
+            read_verify_crc(&out[..3]),
+            Err(TPSError::CommunicationError)
+        ));
+    }
+}
@@ -23,6 +23,8 @@ extern crate tps_error;
 
 use tps_client_common::c_structs::*;
 
+pub mod frame;
+
 /** The Connector structure is exposed by every instance of a connector, and defines the function
  * calls between the TPS Client API and the connector implementation.
  */
@@ -133,3 +133,68 @@ pub fn from_c_error_code(item: u32, buf_size: Option<usize>) -> Result<(), TPSEr
         _ => Err(TPSError::GenericError),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::discriminant;
+
+    /// Every `TPSError` variant. The match in `all_variants_listed` fails to compile if a variant
+    /// is added without updating this list.
+    fn all_variants() -> [TPSError; 16] {
+        [
+            TPSError::GenericError,
+            TPSError::AccessDenied,
+            TPSError::Cancel,
+            TPSError::BadFormat,
+            TPSError::NotImplemented,
+            TPSError::NotSupported,
+            TPSError::NoData,
+            TPSError::OutOfMemory,
+            TPSError::Busy,
+            TPSError::CommunicationError,
+            TPSError::SecurityError,
+            TPSError::ShortBuffer(32),
+            TPSError::Deprecated,
+            TPSError::BadIdentifier,
+            TPSError::NullPointer,
+            TPSError::BadState,
+        ]
+    }
+
+    #[test]
+    fn all_variants_listed() {
+        for e in all_variants() {
+            match e {
+                TPSError::GenericError
+                | TPSError::AccessDenied
+                | TPSError::Cancel
+                | TPSError::BadFormat
+                | TPSError::NotImplemented
+                | TPSError::NotSupported
+                | TPSError::NoData
+                | TPSError::OutOfMemory
+                | TPSError::Busy
+                | TPSError::CommunicationError
+                | TPSError::SecurityError
+                | TPSError::ShortBuffer(_)
+                | TPSError::Deprecated
+                | TPSError::BadIdentifier
+                | TPSError::NullPointer
+                | TPSError::BadState => (),
+            }
+        }
+    }
+
+    #[test]
+    fn error_code_round_trip() {
+        for e in all_variants() {
+            let expected = discriminant(&e);
+            let code: u32 = e.into();
+            match from_c_error_code(code, Some(0)) {
+                Err(back) => assert_eq!(discriminant(&back), expected, "code {:#x}", code),
+                Ok(()) => panic!("code {:#x} converted to success", code),
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+/***************************************************************************************************
+ * Copyright (c) 2023 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+
+//! Compare repeated look-ups in a 20 entry map with integer keys, using `MapBuf::lookup`
+//! directly and through a `MapIndex`. Run with `cargo run --release --example map_lookup`.
+
+extern crate tps_minicbor;
+
+use tps_minicbor::decoder::*;
+use tps_minicbor::error::CBORError;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+const KEYS: u8 = 20;
+const ROUNDS: u32 = 100_000;
+
+fn main() -> Result<(), CBORError> {
+    // {0: 0, 1: 100, ..., 19: 1900}, with the keys encoded in reverse order
+    let mut bytes = vec![0xa0 | KEYS];
+    for key in (0..KEYS).rev() {
+        let value = (key as u16 * 100).to_be_bytes();
+        bytes.extend_from_slice(&[key, 0x19, value[0], value[1]]);
+    }
+
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        let start = Instant::now();
+        let mut sum = 0u64;
+        for _ in 0..ROUNDS {
+            for key in 0..KEYS {
+                sum += black_box(mb).lookup::<u8, u64>(key)?;
+            }
+        }
+        println!("MapBuf::lookup:   {:?} (sum {})", start.elapsed(), sum);
+
+        let start = Instant::now();
+        let mut sum = 0u64;
+        for _ in 0..ROUNDS {
+            let index = black_box(mb).int_index::<{ KEYS as usize }>()?;
+            for key in 0..KEYS {
+                sum += index.lookup::<u64>(key as i64)?;
+            }
+        }
+        println!("MapIndex::lookup: {:?} (sum {})", start.elapsed(), sum);
+        Ok(())
+    })?;
+    Ok(())
+}
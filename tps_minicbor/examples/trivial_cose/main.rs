@@ -42,7 +42,8 @@ use std::io;
 use std::io::Write;
 
 use tps_minicbor::debug::{print_hex, Diag};
-use tps_minicbor::decoder::{CBORDecoder, SequenceBuffer, ArrayBuf, MapBuf};
+use tps_minicbor::cose::{protected, sign1_decode, Algorithm, HEADER_ALG};
+use tps_minicbor::decoder::{CBORDecoder, SequenceBuffer};
 use tps_minicbor::encoder::*;
 use tps_minicbor::error::CBORError;
 use tps_minicbor::types::*;
@@ -74,12 +75,6 @@ fn print_bytes(s: &str, buf: &SequenceBuffer) {
     println!();
 }
 
-fn dup_from_slice(src: &[u8], dest: &mut Vec<u8>) {
-    for i in src {
-        dest.push(*i);
-    }
-}
-
 // Generate the COSE_Sign1 "to be signed" structure defined in RFC9052 Section 4.4. This is
 // required for both signing and verifying
 fn construct_to_be_signed<'a>(
@@ -165,8 +160,9 @@ fn main() -> Result<(), Box<dyn Error>> {
             // Protected headers: << { alg: ECDSA256 } >>
             let mut ph_buf: [u8; 64] = [0; 64];
             let mut prot_hdrs = CBORBuilder::new(&mut ph_buf);
-            let protected_headers =
-                prot_hdrs.insert(&map(|protected| protected.insert_key_value(&1, &(-7))))?;
+            let protected_headers = prot_hdrs.insert(&map(|protected| {
+                protected.insert_key_value(&HEADER_ALG, &Algorithm::ES256)
+            }))?;
 
             // payload: 'This is the content.'
             let payload = b"This is the content.".as_slice();
@@ -198,47 +194,23 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Verify a COSE_Sign1 structure
     let verifier = CBORDecoder::new(bytes);
-    let mut alg = 0;
+    let mut alg = None;
     let mut kid: [u8; 2] = [0; 2];
-    let mut protected_hdrs = Vec::<u8>::new();
-    let mut payload = Vec::<u8>::new();
-    let mut signature = Vec::<u8>::new();
 
     // Extract the critical bits of the COSE Sign1 structure
-    let _v = verifier
-        .tag(|tb| {
-            if tb.get_tag() == 18 {
-                let ab = tb.item::<ArrayBuf>()?;
-                // Protected Headers
-                let prot_hdr = ab.item::<&[u8]>(0)?;
-                if prot_hdr.len() > 0 {
-                    let _not_empty = CBORDecoder::from_slice(prot_hdr)
-                        .map(|mb| {
-                            dup_from_slice(prot_hdr, &mut protected_hdrs);
-                            alg = mb.lookup(1)?;
-                            Ok(())
-                        })?;
-                }
-                // Unprotected headers
-                let unprot_hdr = ab.item::<MapBuf>(1)?;
-                let _ = &kid.copy_from_slice(&unprot_hdr.lookup::<u64, &[u8]>(4)?[0..=1]);
-                // Payload
-                dup_from_slice(ab.item::<&[u8]>(2)?, &mut payload);
-                // Signature
-                dup_from_slice(ab.item::<&[u8]>(3)?, &mut signature);
-                Ok(())
-            } else {
-                Err(CBORError::ExpectedTag(18))
-            }
-        })?;
+    let sign1 = sign1_decode(&verifier)?;
+    // Protected Headers
+    let (protected_bytes, protected_hdrs) = protected(CBOR::Bstr(sign1.protected))?;
+    if !protected_hdrs.is_empty() {
+        alg = Some(protected_hdrs.lookup::<i64, Algorithm>(HEADER_ALG)?);
+    }
+    // Unprotected headers
+    let _ = &kid.copy_from_slice(&sign1.unprotected.lookup::<u64, &[u8]>(4)?[0..=1]);
+    println!("Headers: alg {:?}, kid {:?}", alg, kid);
 
     // Verify the signature and extracted values
-    match cose_verify1(
-        protected_hdrs.as_slice(),
-        payload.as_slice(),
-        signature.as_slice(),
-    ) {
-        Ok(()) => println!("Verification succeeded: message content {:?}", payload),
+    match cose_verify1(protected_bytes, sign1.payload, sign1.signature) {
+        Ok(()) => println!("Verification succeeded: message content {:?}", sign1.payload),
         Err(_) => println!("Verification failed"),
     }
 
@@ -26,7 +26,10 @@
  **************************************************************************************************/
 use std::convert::TryFrom;
 use crate::ast::CBOR;
-use crate::decode::{DecodeBufIterator, DecodeBufIteratorSource};
+#[cfg(feature = "full")]
+use crate::cbor_diag::RawState;
+use crate::constants::MT_ARRAY;
+use crate::decode::{DecodeBufIterator, DecodeBufIteratorSource, DecodeOptions};
 
 #[cfg(feature = "trace")]
 use func_trace::trace;
@@ -44,7 +47,8 @@ func_trace::init_depth_var!();
 /// a CBOR array with an exposed slice-like API.
 ///
 /// This CBOR buffer implementation does not support indefinite length items.
-#[derive(PartialEq, Debug, Copy, Clone)]
+#[derive(PartialEq, Copy, Clone)]
+#[cfg_attr(not(feature = "full"), derive(Debug))]
 pub struct ArrayBuf<'buf> {
     bytes: &'buf [u8],
     n_items: usize,
@@ -60,6 +64,13 @@ impl<'buf> ArrayBuf<'buf> {
         }
     }
 
+    /// Return the internal state of the `ArrayBuf` for debugging. The `Debug` implementation shows
+    /// the contents in CBOR diagnostic notation instead.
+    #[cfg(feature = "full")]
+    pub fn raw_state(&self) -> RawState<'buf> {
+        RawState::new("ArrayBuf", self.bytes, "n_items", self.n_items as u64)
+    }
+
     /// Return the number of items in the `ArrayBuf`.
     #[cfg_attr(feature = "trace", trace)]
     #[inline]
@@ -74,12 +85,44 @@ impl<'buf> ArrayBuf<'buf> {
         self.n_items == 0 && self.bytes.len() == 0
     }
 
-    /// Return the `n`th value (zero indexed) in the `ArrayBuf` as a CBOR item.
+    /// Check that the number of items in the `ArrayBuf` is in the range `min..=max`.
+    ///
+    /// This corresponds to a CDDL occurrence constraint such as `2*5 int`. Returns
+    /// `CBORError::ArrayLength` with the actual number of items if it is out of range.
+    ///
+    /// ```
+    ///# use tps_minicbor::decoder::CBORDecoder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// // [1, 2, 3]
+    /// let _ = CBORDecoder::from_slice(&[0x83, 0x01, 0x02, 0x03]).array(|ab| {
+    ///     ab.check_len(2, 5)?;
+    ///     assert!(matches!(ab.check_len(4, 5), Err(CBORError::ArrayLength(3))));
+    ///     Ok(())
+    /// })?;
+    ///# Ok(())
+    ///# }
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    #[inline]
+    pub fn check_len(&self, min: usize, max: usize) -> Result<(), CBORError> {
+        if (min..=max).contains(&self.n_items) {
+            Ok(())
+        } else {
+            Err(CBORError::ArrayLength(self.n_items))
+        }
+    }
+
+    /// Return the `n`th value (zero indexed) in the `ArrayBuf` as a CBOR item, or `None` if
+    /// `n >= self.len()`.
     ///
     /// Worst case performance of this function is O(n) in standalone form, but performance is
     /// likely to be O(n^2) if used for random access in general.
     #[cfg_attr(feature = "trace", trace)]
     pub fn index(&self, n: usize) -> Option<CBOR> {
+        if n >= self.n_items {
+            return None;
+        }
         let mut count = 0;
         let mut it = self.into_iter();
         let mut item = it.next();
@@ -92,6 +135,9 @@ impl<'buf> ArrayBuf<'buf> {
 
     /// Return the `n`th value (zero indexed) in the `ArrayBuf`, converted (fallibly) from CBOR.
     ///
+    /// Returns `CBORError::IndexOutOfBounds` if `idx >= self.len()`, and
+    /// `CBORError::IncompatibleType` if the item cannot be converted.
+    ///
     /// Worst case performance of this function is O(n) in standalone form, but performance is
     /// likely to be O(n^2) if used for random access in general.
     pub fn item<V>(&'buf self, idx: usize) -> Result<V, CBORError>
@@ -105,6 +151,41 @@ impl<'buf> ArrayBuf<'buf> {
             None => Err(CBORError::IndexOutOfBounds)
         }
     }
+
+    /// Return an iterator over the items of the array, each paired with its (zero based) index
+    /// and converted (fallibly) from CBOR. The iterator stops after `self.len()` items.
+    ///
+    /// Conversion errors are returned per item as `CBORError::IncompatibleType`, and iteration
+    /// continues with the following item.
+    ///
+    /// ```
+    ///# use tps_minicbor::decoder::CBORDecoder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// // [10, "a", 30]
+    /// let _ = CBORDecoder::from_slice(&[0x83, 0x0a, 0x61, 0x61, 0x18, 0x1e]).array(|ab| {
+    ///     let mut items = ab.enumerate_as::<u8>();
+    ///     assert!(matches!(items.next(), Some(Ok((0, 10)))));
+    ///     assert!(matches!(items.next(), Some(Err(CBORError::IncompatibleType))));
+    ///     assert!(matches!(items.next(), Some(Ok((2, 30)))));
+    ///     assert!(items.next().is_none());
+    ///     Ok(())
+    /// })?;
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn enumerate_as<V>(self) -> impl Iterator<Item = Result<(usize, V), CBORError>> + 'buf
+    where
+        V: TryFrom<CBOR<'buf>> + 'buf,
+    {
+        self.into_iter()
+            .take(self.n_items)
+            .enumerate()
+            .map(|(idx, cbor)| match V::try_from(cbor) {
+                Ok(v) => Ok((idx, v)),
+                Err(_) => Err(CBORError::IncompatibleType),
+            })
+    }
 }
 
 impl<'buf> IntoIterator for ArrayBuf<'buf> {
@@ -118,10 +199,21 @@ impl<'buf> IntoIterator for ArrayBuf<'buf> {
             buf: self.bytes,
             index: 0,
             source: DecodeBufIteratorSource::Array,
+            options: DecodeOptions::default(),
+            last_tag: None,
         }
     }
 }
 
+/// The [`EncodeItem`] instance for `ArrayBuf` re-encodes a decoded array. The contents are
+/// copied unchanged, so the encoding of the array items is preserved exactly.
+impl<'buf> EncodeItem for ArrayBuf<'buf> {
+    fn encode<'f, 'b>(&self, buf: &'f mut EncodeBuffer<'b>) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        let _ = buf.sized_start(MT_ARRAY, self.n_items as u64)?;
+        buf.insert_bstr_cbor(self.bytes)
+    }
+}
+
 /***************************************************************************************************
  * Encoding Arrays
  **************************************************************************************************/
@@ -183,3 +275,95 @@ pub fn array<F>(f: F) -> Array<F>
 {
     Array::new(f)
 }
+
+/// A container structure for the closure used to encode a CBOR array whose number of items is
+/// known in advance.
+///
+/// Users should never need to directly instantiate `ArraySized`. Instead, see [`array_sized`].
+pub struct ArraySized<F>
+where F: for<'f, 'buf> Fn(&'f mut EncodeBuffer<'buf>) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+    n: usize,
+    f: F
+}
+
+/// `ArraySized` provides a constructor to contain the closure that constructs it
+impl<F> ArraySized<F> where
+    F: for<'f, 'buf> Fn(&'f mut EncodeBuffer<'buf>) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+    pub fn new(n: usize, f: F) -> ArraySized<F> { ArraySized { n, f } }
+}
+
+/// The [`EncodeItem`] instance for `ArraySized` writes the array header immediately. In debug
+/// builds, it checks that the closure inserted the expected number of items.
+impl<F> EncodeItem for ArraySized<F>
+where F: for<'f, 'buf> Fn(&'f mut EncodeBuffer<'buf>) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>
+{
+    fn encode<'f, 'buf>(&self, buf: &'f mut EncodeBuffer<'buf>) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let start = buf.sized_start(MT_ARRAY, self.n as u64)?;
+        let _ = (self.f)(buf)?;
+        debug_assert_eq!(buf.items_since(start), self.n);
+        Ok(buf)
+    }
+}
+
+/// A convenience function for the user to create an instance of a CBOR array where the number of
+/// items, `n`, is known in advance. The header is written immediately, avoiding the need to fix
+/// up the array length once the contents are known.
+///
+/// The user is trusted to insert exactly `n` items in release builds. Debug builds check this.
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::array_sized;
+///
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 16];
+///    let expected : &[u8] = &[131, 1, 2, 3];
+///
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    let _ = encoder.insert(&array_sized(3, |buff| {
+///        buff.insert(&1)?.insert(&2)?.insert(&3)
+///    }));
+///    assert_eq!(encoder.encoded()?, expected);
+///#    Ok(())
+///# }
+/// ```
+pub fn array_sized<F>(n: usize, f: F) -> ArraySized<F>
+    where F: for<'f, 'buf> Fn(&'f mut EncodeBuffer<'buf>) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>
+{
+    ArraySized::new(n, f)
+}
+
+/// An empty CBOR array. This is encoded directly as `0x80`, with no closure and no length fix-up.
+///
+/// Users should never need to directly instantiate `EmptyArray`. Instead, see [`empty_array`].
+#[derive(Debug, Copy, Clone)]
+pub struct EmptyArray;
+
+impl EncodeItem for EmptyArray {
+    fn encode<'f, 'buf>(&self, buf: &'f mut EncodeBuffer<'buf>) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let _ = buf.sized_start(MT_ARRAY, 0)?;
+        Ok(buf)
+    }
+}
+
+/// A convenience function for the user to create an empty CBOR array, `[]`.
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::empty_array;
+///
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 16];
+///
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    let _ = encoder.insert(&empty_array());
+///    assert_eq!(encoder.encoded()?, &[0x80]);
+///#    Ok(())
+///# }
+/// ```
+#[inline]
+pub fn empty_array() -> EmptyArray {
+    EmptyArray
+}
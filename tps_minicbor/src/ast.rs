@@ -31,6 +31,7 @@ use crate::tag::TagBuf;
 
 use std::convert::TryFrom;
 use std::mem::transmute;
+use std::time::Duration;
 
 #[cfg(feature = "float")]
 use half::f16;
@@ -57,7 +58,7 @@ func_trace::init_depth_var!();
 ///   the array
 /// - Maps are stored as a number of pairs and an immutable borrowed slice over the contents of the
 ///   map
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Clone)]
 #[cfg(feature = "full")]
 pub enum CBOR<'buf> {
     /// A CBOR positive integer, which always has a u64 internal representation regardless of how
@@ -83,7 +84,9 @@ pub enum CBOR<'buf> {
     Map(MapBuf<'buf>),
     /// A CBOR tagged item, there [`TagBuf`] holds the tagged CBOR item.
     Tag(TagBuf<'buf>),
-    /// A CBOR simple value.
+    /// A CBOR simple value. The decoder always returns simple values 20..=23 as
+    /// [`CBOR::False`], [`CBOR::True`], [`CBOR::Null`] and [`CBOR::Undefined`], so this variant
+    /// only ever holds values outside that range when produced by decoding.
     Simple(u8),
     /// A CBOR `false` value.
     False,
@@ -146,7 +149,9 @@ pub enum CBOR<'buf> {
     Map(MapBuf<'buf>),
     /// A CBOR tagged item, there [`TagBuf`] holds the tagged CBOR item.
     Tag(TagBuf<'buf>),
-    /// A CBOR simple value.
+    /// A CBOR simple value. The decoder always returns simple values 20..=23 as
+    /// [`CBOR::False`], [`CBOR::True`], [`CBOR::Null`] and [`CBOR::Undefined`], so this variant
+    /// only ever holds values outside that range when produced by decoding.
     Simple(u8),
     /// A CBOR `false` value.
     False,
@@ -195,7 +200,9 @@ pub enum CBOR<'buf> {
     Map(MapBuf<'buf>),
     /// A CBOR tagged item, there [`TagBuf`] holds the tagged CBOR item.
     Tag(TagBuf<'buf>),
-    /// A CBOR simple value.
+    /// A CBOR simple value. The decoder always returns simple values 20..=23 as
+    /// [`CBOR::False`], [`CBOR::True`], [`CBOR::Null`] and [`CBOR::Undefined`], so this variant
+    /// only ever holds values outside that range when produced by decoding.
     Simple(u8),
     /// A CBOR `false` value.
     False,
@@ -210,6 +217,312 @@ pub enum CBOR<'buf> {
     Eof,
 }
 
+/// The CBOR major type of an item (RFC8949, Section 3.1). This allows code to branch on the
+/// type of an item, e.g. where a CDDL choice allows several types, without attempting
+/// conversions.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum CBORType {
+    /// Major type 0: an unsigned integer.
+    UInt,
+    /// Major type 1: a negative integer.
+    NInt,
+    /// Major type 2: a byte string.
+    Bstr,
+    /// Major type 3: a text string.
+    Tstr,
+    /// Major type 4: an array.
+    Array,
+    /// Major type 5: a map.
+    Map,
+    /// Major type 6: a tagged item, including items such as date-times which are decoded from
+    /// standard tags.
+    Tag,
+    /// Major type 7: a simple value (including `false`, `true`, `null` and `undefined`) or a
+    /// float.
+    Simple,
+}
+
+/// The representation used for a number, e.g. where a CDDL type is `int / float` and the
+/// interpretation depends on whether the sender used an integer or a float.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum NumberKind {
+    /// An unsigned integer (major type 0).
+    UInt,
+    /// A negative integer (major type 1).
+    NInt,
+    /// A float of any width. Only returned if the `float` feature is enabled.
+    Float,
+}
+
+/// A label, as used for COSE header parameters and CWT claims: `label = int / tstr`
+/// (RFC9052, Section 1.5).
+///
+/// # Example
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use tps_minicbor::types::{Label, CBOR};
+///
+/// assert_eq!(Label::try_from(CBOR::NInt(0)).unwrap(), Label::Int(-1));
+/// assert_eq!(Label::try_from(CBOR::Tstr("kid")).unwrap(), Label::Text("kid"));
+/// assert!(Label::try_from(CBOR::Bstr(&[])).is_err());
+/// ```
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Label<'buf> {
+    /// An integer label.
+    Int(i64),
+    /// A text string label.
+    Text(&'buf str),
+}
+
+/// Attempt to convert a CBOR item into a `Label`.
+///
+/// This fails with `CBORError::OutOfRange` for integers which do not fit in an `i64`, and with
+/// `CBORError::IncompatibleType` for items which are not integers or text strings.
+impl<'buf> TryFrom<CBOR<'buf>> for Label<'buf> {
+    type Error = CBORError;
+
+    #[cfg_attr(feature = "trace", trace)]
+    fn try_from(value: CBOR<'buf>) -> core::result::Result<Self, Self::Error> {
+        match value {
+            CBOR::UInt(_) | CBOR::NInt(_) => Ok(Label::Int(i64::try_from(value)?)),
+            CBOR::Tstr(s) => Ok(Label::Text(s)),
+            _ => Err(CBORError::IncompatibleType),
+        }
+    }
+}
+
+/// Convert a `Label` into CBOR
+impl<'buf> From<Label<'buf>> for CBOR<'buf> {
+    #[inline]
+    fn from(label: Label<'buf>) -> Self {
+        match label {
+            Label::Int(v) => CBOR::from(v),
+            Label::Text(s) => CBOR::Tstr(s),
+        }
+    }
+}
+
+/// Convert an i64 into an integer `Label`
+impl<'buf> From<i64> for Label<'buf> {
+    #[inline]
+    fn from(v: i64) -> Self {
+        Label::Int(v)
+    }
+}
+
+/// Convert an &str into a text `Label`
+impl<'buf> From<&'buf str> for Label<'buf> {
+    #[inline]
+    fn from(s: &'buf str) -> Self {
+        Label::Text(s)
+    }
+}
+
+/// One segment of a path into nested maps and arrays, as used by [`MapBuf::lookup_path`].
+///
+/// `Int` and `Text` select a value from a map by key, while `Index` selects the (zero indexed)
+/// item of an array.
+///
+/// [`MapBuf::lookup_path`]: crate::decoder::MapBuf::lookup_path
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Key<'buf> {
+    /// An integer map key.
+    Int(i64),
+    /// A text string map key.
+    Text(&'buf str),
+    /// An array index.
+    Index(usize),
+}
+
+/***************************************************************************************************
+ * Helper methods on CBOR items
+ **************************************************************************************************/
+
+impl<'buf> CBOR<'buf> {
+    /// Return the major type of the item, or `None` for the internal `Eof` marker.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::types::{CBORType, CBOR};
+    ///
+    /// assert_eq!(CBOR::Tstr("a").cbor_type(), Some(CBORType::Tstr));
+    /// assert_eq!(CBOR::Null.cbor_type(), Some(CBORType::Simple));
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn cbor_type(&self) -> Option<CBORType> {
+        match self {
+            CBOR::UInt(_) => Some(CBORType::UInt),
+            CBOR::NInt(_) => Some(CBORType::NInt),
+            CBOR::Bstr(_) => Some(CBORType::Bstr),
+            CBOR::Tstr(_) => Some(CBORType::Tstr),
+            CBOR::Array(_) => Some(CBORType::Array),
+            CBOR::Map(_) => Some(CBORType::Map),
+            CBOR::Tag(_) => Some(CBORType::Tag),
+            #[cfg(feature = "full")]
+            CBOR::DateTime(_) | CBOR::Epoch(_) => Some(CBORType::Tag),
+            CBOR::Simple(_) | CBOR::False | CBOR::True | CBOR::Null | CBOR::Undefined => {
+                Some(CBORType::Simple)
+            }
+            #[cfg(feature = "float")]
+            CBOR::Float64(_) | CBOR::Float32(_) | CBOR::Float16(_) => Some(CBORType::Simple),
+            CBOR::Eof => None,
+        }
+    }
+
+    /// Return the representation of a number, or `None` if the item is not a number.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::types::{NumberKind, CBOR};
+    ///
+    /// assert_eq!(CBOR::UInt(1).number_kind(), Some(NumberKind::UInt));
+    /// assert_eq!(CBOR::NInt(0).number_kind(), Some(NumberKind::NInt));
+    /// assert_eq!(CBOR::Tstr("1").number_kind(), None);
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn number_kind(&self) -> Option<NumberKind> {
+        match self {
+            CBOR::UInt(_) => Some(NumberKind::UInt),
+            CBOR::NInt(_) => Some(NumberKind::NInt),
+            #[cfg(feature = "float")]
+            CBOR::Float64(_) | CBOR::Float32(_) | CBOR::Float16(_) => Some(NumberKind::Float),
+            _ => None,
+        }
+    }
+
+    /// Return the item with simple values 20..=23 replaced by the equivalent named variant
+    /// ([`CBOR::False`], [`CBOR::True`], [`CBOR::Null`] or [`CBOR::Undefined`]). All other items
+    /// are returned unchanged.
+    ///
+    /// Decoded items are already normalized; this is useful for items constructed directly.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// assert_eq!(CBOR::Simple(21).normalized(), CBOR::True);
+    /// assert_eq!(CBOR::Simple(16).normalized(), CBOR::Simple(16));
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn normalized(self) -> Self {
+        match self {
+            CBOR::Simple(20) => CBOR::False,
+            CBOR::Simple(21) => CBOR::True,
+            CBOR::Simple(22) => CBOR::Null,
+            CBOR::Simple(23) => CBOR::Undefined,
+            other => other,
+        }
+    }
+
+    /// Copy the contents of a `bstr` item into `out`, returning the number of bytes copied.
+    ///
+    /// Returns `CBORError::ShortBuffer` with the required length if `out` is too small to hold
+    /// the `bstr` contents, and `CBORError::IncompatibleType` if the item is not a `bstr`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// let mut out = [0u8; 8];
+    /// let n = CBOR::Bstr(&[1, 2, 3]).copy_bytes_into(&mut out).unwrap();
+    /// assert_eq!(&out[..n], &[1, 2, 3]);
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn copy_bytes_into(&self, out: &mut [u8]) -> Result<usize, CBORError> {
+        match self {
+            CBOR::Bstr(bytes) => {
+                if bytes.len() > out.len() {
+                    Err(CBORError::ShortBuffer(bytes.len()))
+                } else {
+                    out[..bytes.len()].copy_from_slice(bytes);
+                    Ok(bytes.len())
+                }
+            }
+            _ => Err(CBORError::IncompatibleType),
+        }
+    }
+
+    /// Copy the contents of a `bstr` item into `out`, which must be exactly the length of the
+    /// `bstr`. This is intended for fixed size values such as key identifiers or UUIDs.
+    ///
+    /// Returns `CBORError::WrongLength` with the actual length if the lengths differ, and
+    /// `CBORError::IncompatibleType` if the item is not a `bstr`.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn copy_exact_into(&self, out: &mut [u8]) -> Result<(), CBORError> {
+        match self {
+            CBOR::Bstr(bytes) => {
+                if bytes.len() != out.len() {
+                    Err(CBORError::WrongLength(bytes.len()))
+                } else {
+                    out.copy_from_slice(bytes);
+                    Ok(())
+                }
+            }
+            _ => Err(CBORError::IncompatibleType),
+        }
+    }
+
+    /// Convert an integer item to `u32`, saturating at the bounds of `u32` rather than failing.
+    ///
+    /// > **Warning**: this conversion is lossy. Values above `u32::MAX` are returned as
+    /// > `u32::MAX` and all negative values are returned as `0`. It is intended for display and
+    /// > telemetry purposes only and must never be used for security-relevant values such as
+    /// > lengths, counters or identifiers. Use `u32::try_from()`, which fails on overflow, for
+    /// > those.
+    ///
+    /// Returns `CBORError::IncompatibleType` if the item is not an integer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// assert_eq!(CBOR::UInt(0x1_0000_0000).as_u32_saturating().unwrap(), u32::MAX);
+    /// assert_eq!(CBOR::NInt(5).as_u32_saturating().unwrap(), 0);
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn as_u32_saturating(&self) -> Result<u32, CBORError> {
+        match *self {
+            CBOR::UInt(v) => Ok(u32::try_from(v).unwrap_or(u32::MAX)),
+            CBOR::NInt(_) => Ok(0),
+            _ => Err(CBORError::IncompatibleType),
+        }
+    }
+
+    /// Convert an integer item to `i32`, saturating at the bounds of `i32` rather than failing.
+    ///
+    /// > **Warning**: this conversion is lossy. Values above `i32::MAX` are returned as
+    /// > `i32::MAX` and values below `i32::MIN` are returned as `i32::MIN`. It is intended for
+    /// > display and telemetry purposes only and must never be used for security-relevant values.
+    /// > Use `i32::try_from()`, which fails on overflow, for those.
+    ///
+    /// Returns `CBORError::IncompatibleType` if the item is not an integer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// assert_eq!(CBOR::UInt(u64::MAX).as_i32_saturating().unwrap(), i32::MAX);
+    /// assert_eq!(CBOR::NInt(u64::MAX).as_i32_saturating().unwrap(), i32::MIN);
+    /// assert_eq!(CBOR::NInt(9).as_i32_saturating().unwrap(), -10);
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn as_i32_saturating(&self) -> Result<i32, CBORError> {
+        match *self {
+            CBOR::UInt(v) => Ok(i32::try_from(v).unwrap_or(i32::MAX)),
+            // NInt(v) encodes -1 - v
+            CBOR::NInt(v) => Ok(i32::try_from(v).map_or(i32::MIN, |v| -1 - v)),
+            _ => Err(CBORError::IncompatibleType),
+        }
+    }
+}
+
 /***************************************************************************************************
  * Standard Trait Implementations: From value to CBOR. Always succeeds
  **************************************************************************************************/
@@ -337,11 +650,15 @@ impl<'buf> From<&'buf [u8]> for CBOR<'buf> {
  **************************************************************************************************/
 
 /// Attempt to convert CBOR into bool
+///
+/// Both the named variants and the equivalent simple values (`CBOR::Simple(20)` for `false` and
+/// `CBOR::Simple(21)` for `true`) are accepted.
 impl<'buf> TryFrom<CBOR<'buf>> for bool {
     type Error = CBORError;
 
     #[cfg_attr(feature = "trace", trace)]
     fn try_from(value: CBOR) -> core::result::Result<Self, Self::Error> {
+        let value = value.normalized();
         if let CBOR::True = value {
             Ok(true)
         } else if let CBOR::False = value {
@@ -655,3 +972,64 @@ impl<'buf> TryFrom<CBOR<'buf>> for MapBuf<'buf> {
         }
     }
 }
+
+/// Attempt to turn a CBOR item into a TagBuf. This allows a tagged value to be looked up in a
+/// map or array in the same way as the other container types, e.g. `mb.lookup::<_, TagBuf>(1)`.
+impl<'buf> TryFrom<CBOR<'buf>> for TagBuf<'buf> {
+    type Error = CBORError;
+
+    #[cfg_attr(feature = "trace", trace)]
+    fn try_from(value: CBOR<'buf>) -> Result<Self, Self::Error> {
+        match value {
+            CBOR::Tag(tb) => Ok(tb),
+            _ => Err(CBORError::IncompatibleType),
+        }
+    }
+}
+
+/// Attempt to convert CBOR into a `Duration`, such as a relative timeout.
+///
+/// A non-negative integer is a number of seconds. An epoch-based time delta (tag 1) may also be
+/// used, holding a non-negative integer or, with the `float` feature, a non-negative float, which
+/// allows fractional seconds. Negative values return `CBORError::IncompatibleType`.
+impl<'buf> TryFrom<CBOR<'buf>> for Duration {
+    type Error = CBORError;
+
+    #[cfg_attr(feature = "trace", trace)]
+    fn try_from(value: CBOR<'buf>) -> Result<Self, Self::Error> {
+        match value {
+            CBOR::UInt(secs) => Ok(Duration::from_secs(secs)),
+            CBOR::Tag(tb) if tb.get_tag() == 1 => match tb.into_iter().next() {
+                Some(CBOR::UInt(secs)) => Ok(Duration::from_secs(secs)),
+                #[cfg(feature = "float")]
+                Some(CBOR::Float64(secs)) => duration_from_f64(secs),
+                #[cfg(feature = "float")]
+                Some(CBOR::Float32(secs)) => duration_from_f64(secs as f64),
+                #[cfg(feature = "float")]
+                Some(CBOR::Float16(secs)) => duration_from_f64(secs.to_f64()),
+                _ => Err(CBORError::IncompatibleType),
+            },
+            #[cfg(feature = "full")]
+            CBOR::Epoch(secs) if secs >= 0 => Ok(Duration::from_secs(secs as u64)),
+            _ => Err(CBORError::IncompatibleType),
+        }
+    }
+}
+
+/// Attempt to convert a reference to a CBOR item into a `Duration`. See the conversion from
+/// `CBOR`.
+impl<'a, 'buf> TryFrom<&'a CBOR<'buf>> for Duration {
+    type Error = CBORError;
+
+    #[inline]
+    fn try_from(value: &'a CBOR<'buf>) -> Result<Self, Self::Error> {
+        Duration::try_from(*value)
+    }
+}
+
+/// (private) Convert a number of seconds held in a float into a `Duration`. Negative, infinite
+/// and NaN values are rejected.
+#[cfg(feature = "float")]
+fn duration_from_f64(secs: f64) -> Result<Duration, CBORError> {
+    Duration::try_from_secs_f64(secs).map_err(|_| CBORError::IncompatibleType)
+}
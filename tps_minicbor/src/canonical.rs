@@ -0,0 +1,386 @@
+/***************************************************************************************************
+ * Copyright (c) 2023 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * rs_minicbor canonical re-serializer
+ *
+ * Re-encodes arbitrary well-formed CBOR in the deterministic encoding of RFC8949, Section 4.2,
+ * e.g. before received CBOR is signed. Neither the Rust standard library nor an allocator is
+ * required: maps are sorted in place in the destination buffer.
+ **************************************************************************************************/
+use core::cmp::Ordering;
+
+use crate::constants::*;
+use crate::decode::{item_end, parse_bytestring, parse_unsigned, DecodeLimits};
+use crate::error::CBORError;
+
+#[cfg(feature = "float")]
+use crate::ast::CBOR;
+#[cfg(feature = "float")]
+use crate::decode::SequenceBuffer;
+#[cfg(feature = "float")]
+use crate::encode::{EncodeBuffer, EncodeItem};
+
+#[cfg(feature = "trace")]
+use func_trace::trace;
+
+#[cfg(feature = "trace")]
+func_trace::init_depth_var!();
+
+/// Write the deterministic encoding (RFC8949, Section 4.2.1) of the CBOR in `src` into `dst`,
+/// returning the number of bytes written. If `src` is a CBOR sequence, each item is re-encoded
+/// in turn.
+///
+/// The deterministic encoding uses:
+///
+/// - The shortest form of every integer, length, tag and simple value head.
+/// - Definite lengths only. Indefinite length strings are joined into a single string and
+///   indefinite length arrays and maps are given their length.
+/// - Map keys sorted in the bytewise lexicographic order of their deterministic encodings.
+///   Integer keys therefore always come before text string keys, as the major type is in the
+///   first byte. For the small integer and short text keys used by CWT and EAT this is also the
+///   length-first order of RFC7049, Section 3.9.
+/// - The shortest float encoding which preserves the value, with NaN written as `0xf97e00`.
+///   Floats require the `float` feature, and otherwise return `CBORError::NotImplemented`.
+///
+/// Errors are:
+///
+/// - `CBORError::ShortBuffer` with the required length if `dst` is too small. `dst` is not
+///   changed in this case.
+/// - `CBORError::NotAllowed` if a map contains the same key twice, as such a map has no
+///   deterministic encoding.
+/// - `CBORError::LimitExceeded` if arrays, maps and tags are nested deeper than the default
+///   [`DecodeLimits::max_depth`].
+/// - Any error from decoding `src` if it is not well-formed.
+///
+/// ```
+///# use tps_minicbor::canonical::canonicalize;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // {_ "b": 0x1800, "a": [_ 1]} (indefinite lengths, unsorted keys, 1 as two bytes)
+/// let src = [0xbf, 0x61, 0x62, 0x18, 0x01, 0x61, 0x61, 0x9f, 0x01, 0xff, 0xff];
+/// let mut dst = [0u8; 16];
+/// let len = canonicalize(&src, &mut dst)?;
+/// // {"a": [1], "b": 1}
+/// assert_eq!(&dst[..len], &[0xa2, 0x61, 0x61, 0x81, 0x01, 0x61, 0x62, 0x01]);
+///# Ok(())
+///# }
+/// ```
+#[cfg_attr(feature = "trace", trace)]
+pub fn canonicalize(src: &[u8], dst: &mut [u8]) -> Result<usize, CBORError> {
+    let required = sequence(src, &mut Sink::measure())?;
+    if dst.len() < required {
+        return Err(CBORError::ShortBuffer(required));
+    }
+    sequence(src, &mut Sink::write(dst))
+}
+
+/// (private) Destination for canonical output. With no buffer, the output is only measured.
+#[derive(Debug)]
+struct Sink<'a> {
+    buf: Option<&'a mut [u8]>,
+    index: usize,
+}
+
+impl<'a> Sink<'a> {
+    fn measure() -> Self {
+        Sink {
+            buf: None,
+            index: 0,
+        }
+    }
+
+    fn write(buf: &'a mut [u8]) -> Self {
+        Sink {
+            buf: Some(buf),
+            index: 0,
+        }
+    }
+
+    /// Append `bytes` to the output.
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), CBORError> {
+        let end = self.index + bytes.len();
+        if let Some(buf) = &mut self.buf {
+            buf.get_mut(self.index..end)
+                .ok_or(CBORError::EndOfBuffer)?
+                .copy_from_slice(bytes);
+        }
+        self.index = end;
+        Ok(())
+    }
+
+    /// Append the shortest head for major type `mt` with argument `value`.
+    fn head(&mut self, mt: u8, value: u64) -> Result<(), CBORError> {
+        let mut head = [0u8; 9];
+        let len = match value {
+            0..=23 => {
+                head[0] = mt | value as u8;
+                1
+            }
+            24..=0xff => {
+                head[0] = mt | PAYLOAD_ONE_BYTE;
+                head[1] = value as u8;
+                2
+            }
+            0x100..=0xffff => {
+                head[0] = mt | PAYLOAD_TWO_BYTES;
+                head[1..3].copy_from_slice(&(value as u16).to_be_bytes());
+                3
+            }
+            0x1_0000..=0xffff_ffff => {
+                head[0] = mt | PAYLOAD_FOUR_BYTES;
+                head[1..5].copy_from_slice(&(value as u32).to_be_bytes());
+                5
+            }
+            _ => {
+                head[0] = mt | PAYLOAD_EIGHT_BYTES;
+                head[1..9].copy_from_slice(&value.to_be_bytes());
+                9
+            }
+        };
+        self.bytes(&head[..len])
+    }
+
+    /// Move the map entry written from `key_start`, whose key ends at `key_end`, into sorted
+    /// position among the entries already written from `start`.
+    fn sort_entry(
+        &mut self,
+        start: usize,
+        key_start: usize,
+        key_end: usize,
+    ) -> Result<(), CBORError> {
+        let end = self.index;
+        if let Some(buf) = &mut self.buf {
+            let mut pos = start;
+            while pos < key_start {
+                // Entries already written are canonical, so have definite lengths
+                let pos_key_end = item_end(buf, pos)?;
+                match buf[key_start..key_end].cmp(&buf[pos..pos_key_end]) {
+                    Ordering::Less => break,
+                    Ordering::Equal => return Err(CBORError::NotAllowed),
+                    Ordering::Greater => pos = item_end(buf, pos_key_end)?,
+                }
+            }
+            buf[pos..end].rotate_right(end - key_start);
+        }
+        Ok(())
+    }
+}
+
+/// (private) Re-encode every item in `src`, returning the length of the output.
+fn sequence(src: &[u8], out: &mut Sink) -> Result<usize, CBORError> {
+    let mut index = 0;
+    while index < src.len() {
+        index = item(src, index, 0, out)?;
+    }
+    Ok(out.index)
+}
+
+/// (private) Return the depth of the items within an array, map or tag at `depth`, or
+/// `CBORError::LimitExceeded` if they would be nested too deeply.
+#[inline]
+fn nested(depth: usize) -> Result<usize, CBORError> {
+    if depth >= DecodeLimits::default().max_depth {
+        Err(CBORError::LimitExceeded)
+    } else {
+        Ok(depth + 1)
+    }
+}
+
+/// (private) Re-encode the item starting at `index` in `src`, which is enclosed by `depth`
+/// arrays, maps and tags, returning the index of the next item.
+fn item(src: &[u8], index: usize, depth: usize, out: &mut Sink) -> Result<usize, CBORError> {
+    let mt_ai_byte = *src.get(index).ok_or(CBORError::EndOfBuffer)?;
+    let mt = mt_ai_byte & !AI_MASK;
+    match mt_ai_byte {
+        0x5f | 0x7f => indefinite_string(src, index, out),
+        0x9f | 0xbf => {
+            let n_items = indefinite_items(src, index + 1, nested(depth)?)?;
+            let n_items = if mt == MT_MAP {
+                if n_items % 2 != 0 {
+                    return Err(CBORError::MalformedEncoding);
+                }
+                n_items / 2
+            } else {
+                n_items
+            };
+            // Skip the break byte after the last item
+            Ok(container(src, index + 1, depth, mt, n_items, out)? + 1)
+        }
+        0xf9..=0xfb => float(src, index, out),
+        _ => {
+            let (next_index, value) = parse_unsigned(src, index)?;
+            let value = value.as_u64();
+            match mt {
+                MT_BSTR | MT_TSTR => {
+                    let (next_index, bytes) = parse_bytestring(src, index)?;
+                    if mt == MT_TSTR && core::str::from_utf8(bytes).is_err() {
+                        return Err(CBORError::UTF8Error);
+                    }
+                    out.head(mt, bytes.len() as u64)?;
+                    out.bytes(bytes)?;
+                    Ok(next_index)
+                }
+                MT_ARRAY | MT_MAP => container(src, next_index, depth, mt, value, out),
+                MT_TAG => {
+                    out.head(mt, value)?;
+                    item(src, next_index, nested(depth)?, out)
+                }
+                // Simple values 24 to 31 are not well-formed in the two byte form
+                MT_SIMPLE if mt_ai_byte == 0xf8 && value < 32 => Err(CBORError::MalformedEncoding),
+                _ => {
+                    out.head(mt, value)?;
+                    Ok(next_index)
+                }
+            }
+        }
+    }
+}
+
+/// (private) Re-encode an array or map at `depth` with `n_items` items (or pairs) starting at
+/// `index` in `src`, returning the index after the last item.
+fn container(
+    src: &[u8],
+    mut index: usize,
+    depth: usize,
+    mt: u8,
+    n_items: u64,
+    out: &mut Sink,
+) -> Result<usize, CBORError> {
+    let depth = nested(depth)?;
+    out.head(mt, n_items)?;
+    let start = out.index;
+    // Each item is at least one byte, so this fails quickly if n_items is bogus
+    for _ in 0..n_items {
+        let key_start = out.index;
+        index = item(src, index, depth, out)?;
+        if mt == MT_MAP {
+            let key_end = out.index;
+            index = item(src, index, depth, out)?;
+            out.sort_entry(start, key_start, key_end)?;
+        }
+    }
+    Ok(index)
+}
+
+/// (private) Count the items in an indefinite length array or map whose first item, at `depth`,
+/// is at `index` in `src`.
+///
+/// The items are skipped rather than re-encoded, so that counting the items of nested indefinite
+/// length containers does not re-encode their contents once for each level of nesting.
+fn indefinite_items(src: &[u8], mut index: usize, depth: usize) -> Result<u64, CBORError> {
+    let mut n_items = 0;
+    while *src.get(index).ok_or(CBORError::EndOfBuffer)? != 0xff {
+        index = skip(src, index, depth)?;
+        n_items += 1;
+    }
+    Ok(n_items)
+}
+
+/// (private) Return the index of the end of the item at `depth` starting at `index` in `src`.
+/// Like [`item_end`], only the heads of items are read, but indefinite length arrays and maps
+/// are supported and the nesting depth is limited.
+fn skip(src: &[u8], index: usize, depth: usize) -> Result<usize, CBORError> {
+    let mt_ai_byte = *src.get(index).ok_or(CBORError::EndOfBuffer)?;
+    let mt = mt_ai_byte & !AI_MASK;
+    match mt_ai_byte {
+        0x9f | 0xbf => {
+            let depth = nested(depth)?;
+            let mut index = index + 1;
+            while *src.get(index).ok_or(CBORError::EndOfBuffer)? != 0xff {
+                index = skip(src, index, depth)?;
+            }
+            Ok(index + 1)
+        }
+        0x5f | 0x7f => item_end(src, index),
+        _ => match mt {
+            MT_ARRAY | MT_MAP | MT_TAG => {
+                let depth = nested(depth)?;
+                let (mut index, value) = parse_unsigned(src, index)?;
+                let n_items = match mt {
+                    MT_ARRAY => value.as_u64(),
+                    MT_MAP => value.as_u64().saturating_mul(2),
+                    _ => 1,
+                };
+                // Each item is at least one byte, so this fails quickly if n_items is bogus
+                for _ in 0..n_items {
+                    index = skip(src, index, depth)?;
+                }
+                Ok(index)
+            }
+            _ => item_end(src, index),
+        },
+    }
+}
+
+/// (private) Re-encode an indefinite length `bstr` or `tstr` at `index` in `src` as a single
+/// definite length string, returning the index after the break byte.
+fn indefinite_string(src: &[u8], index: usize, out: &mut Sink) -> Result<usize, CBORError> {
+    let mt = src[index] & !AI_MASK;
+    // Check the chunks and find the total length, then copy them
+    let mut len = 0;
+    let mut chunk_index = index + 1;
+    loop {
+        match src.get(chunk_index) {
+            Some(&0xff) => break,
+            Some(&chunk_mt_ai) if chunk_mt_ai & !AI_MASK == mt && chunk_mt_ai & AI_MASK < 28 => {
+                let (next_index, chunk) = parse_bytestring(src, chunk_index)?;
+                if mt == MT_TSTR && core::str::from_utf8(chunk).is_err() {
+                    return Err(CBORError::UTF8Error);
+                }
+                len += chunk.len();
+                chunk_index = next_index;
+            }
+            Some(_) => return Err(CBORError::MalformedEncoding),
+            None => return Err(CBORError::EndOfBuffer),
+        }
+    }
+    out.head(mt, len as u64)?;
+    let mut chunk_index = index + 1;
+    while src[chunk_index] != 0xff {
+        let (next_index, chunk) = parse_bytestring(src, chunk_index)?;
+        out.bytes(chunk)?;
+        chunk_index = next_index;
+    }
+    Ok(chunk_index + 1)
+}
+
+/// (private) Re-encode the float at `index` in `src` in its shortest form, returning the index
+/// of the next item.
+#[cfg(feature = "float")]
+fn float(src: &[u8], index: usize, out: &mut Sink) -> Result<usize, CBORError> {
+    let mut it = SequenceBuffer::new(&src[index..]).into_iter();
+    let cbor = it.try_next()?;
+    let mut bytes = [0u8; 9];
+    let mut buf = EncodeBuffer::new(&mut bytes);
+    let _ = match cbor {
+        CBOR::Float16(v) => v.encode(&mut buf)?,
+        CBOR::Float32(v) => v.encode(&mut buf)?,
+        CBOR::Float64(v) => v.encode(&mut buf)?,
+        _ => return Err(CBORError::MalformedEncoding),
+    };
+    out.bytes(buf.encoded()?)?;
+    Ok(index + it.index)
+}
+
+/// (private) Floats are not supported without the `float` feature.
+#[cfg(not(feature = "float"))]
+fn float(_src: &[u8], _index: usize, _out: &mut Sink) -> Result<usize, CBORError> {
+    Err(CBORError::NotImplemented)
+}
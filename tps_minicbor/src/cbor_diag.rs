@@ -63,6 +63,9 @@ use std::error::Error;
 #[cfg(any(feature = "full", test))]
 use std::fmt::Debug;
 
+#[cfg(feature = "full")]
+use std::fmt;
+
 #[cfg(any(feature = "full", test))]
 use std::fmt::Display;
 
@@ -72,6 +75,9 @@ use std::io::{Read, Write};
 #[cfg(any(feature = "full", test))]
 use std::string::String;
 
+#[cfg(feature = "full")]
+use std::vec::Vec;
+
 #[cfg(feature = "float")]
 use half::f16;
 
@@ -79,17 +85,50 @@ use half::f16;
 use chrono::{DateTime, FixedOffset};
 
 #[cfg(any(feature = "full", test))]
-use crate::decoder::{ArrayBuf, CBORDecoder, DecodeBufIterator, MapBuf, SequenceBuffer, TagBuf};
+use crate::decoder::{ArrayBuf, DecodeBufIterator, MapBuf, SequenceBuffer, TagBuf};
+
+#[cfg(all(any(feature = "full", test), feature = "combinators"))]
+use crate::decoder::CBORDecoder;
 
 #[cfg(any(feature = "full", test))]
 use crate::types::CBOR;
 
+#[cfg(feature = "full")]
+use crate::error::CBORError;
+
+#[cfg(feature = "full")]
+use crate::encode::{EncodeBuffer, EncodeContext};
+
 /// Trait defining helper functions for conveniently displaying information in CBOR
 /// diagnostic format.
 #[cfg(any(feature = "full", test))]
 pub trait Diag {
     /// Pretty-print this item in CBOR diagnostic format to the provided writer instance
     fn cbor_diag(&self, outfp: &mut dyn Write) -> Result<(), Box<dyn Error>>;
+
+    /// Print an annotated dump with one line per item, in the form `@<offset>+<len>: <item>`,
+    /// where `offset` and `len` give the position of the item in the source buffer. Items
+    /// nested in arrays, maps and tags are listed, indented, after their container.
+    ///
+    /// This requires access to the source buffer, so the default implementation returns an
+    /// error of kind `std::io::ErrorKind::Unsupported`.
+    ///
+    /// ```
+    ///# use tps_minicbor::debug::Diag;
+    ///# use tps_minicbor::decoder::SequenceBuffer;
+    /// let mut out = Vec::new();
+    /// SequenceBuffer::new(&[0x01, 0x82, 0x02, 0x03]).with_offsets(&mut out).unwrap();
+    /// assert_eq!(
+    ///     String::from_utf8(out).unwrap(),
+    ///     "@0+1: 1 \n@1+3: [\n@2+1:   2 \n@3+1:   3 \n"
+    /// );
+    /// ```
+    fn with_offsets(&self, _outfp: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "byte offsets require the source buffer",
+        )))
+    }
 }
 
 #[cfg(any(feature = "full", test))]
@@ -100,6 +139,11 @@ impl<'a> Diag for SequenceBuffer<'a> {
         }
         Ok(())
     }
+
+    fn with_offsets(&self, outfp: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        diag_with_offsets(outfp, self.into_iter(), 0, 0)?;
+        Ok(())
+    }
 }
 
 #[cfg(any(feature = "full", test))]
@@ -110,7 +154,7 @@ impl<'a> Diag for CBOR<'a> {
     }
 }
 
-#[cfg(any(feature = "full", test))]
+#[cfg(all(any(feature = "full", test), feature = "combinators"))]
 impl<'a> Diag for CBORDecoder<'a> {
     fn cbor_diag(&self, outfp: &mut dyn Write) -> Result<(), Box<dyn Error>> {
         let it = self.into_inner().into_iter();
@@ -119,6 +163,55 @@ impl<'a> Diag for CBORDecoder<'a> {
         }
         Ok(())
     }
+
+    fn with_offsets(&self, outfp: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        diag_with_offsets(outfp, self.into_inner().into_iter(), 0, 0)?;
+        Ok(())
+    }
+}
+
+/// Write one `@<offset>+<len>: <item>` line for each item remaining in `it`, recursing into
+/// arrays, maps and tags. `base` is the offset of `it.buf` in the source buffer.
+///
+/// The contents of a container always end where the container itself ends, which allows the
+/// offset of the contents to be recovered from the length of the container's own buffer.
+#[cfg(any(feature = "full", test))]
+fn diag_with_offsets(
+    outfp: &mut dyn Write,
+    mut it: DecodeBufIterator,
+    base: usize,
+    idt: u32,
+) -> Result<(), std::io::Error> {
+    loop {
+        let start = it.index;
+        let item = match it.next() {
+            Some(item) => item,
+            None => return Ok(()),
+        };
+        let end = it.index;
+        write!(outfp, "@{}+{}:{}", base + start, end - start, indent(idt))?;
+        let contents = match item {
+            CBOR::Array(ab) => {
+                writeln!(outfp, " [")?;
+                ab.into_iter()
+            }
+            CBOR::Map(mb) => {
+                writeln!(outfp, " {{")?;
+                mb.into_iter()
+            }
+            CBOR::Tag(tb) => {
+                writeln!(outfp, " {}(", tb.get_tag())?;
+                tb.into_iter()
+            }
+            _ => {
+                item.diag(outfp, 0)?;
+                writeln!(outfp)?;
+                continue;
+            }
+        };
+        let contents_base = base + end - contents.buf.len();
+        diag_with_offsets(outfp, contents, contents_base, idt + 1)?;
+    }
 }
 
 /// The DiagFormatter trait should be implemented for any data structure that is intended to be
@@ -485,3 +578,760 @@ pub fn print_hex(b: u8) -> &'static str {
     ];
     table[b as usize]
 }
+
+/***************************************************************************************************
+ * Debug formatting
+ **************************************************************************************************/
+
+/// `Debug` for decoded items renders compact, single line CBOR diagnostic notation, such as
+/// `{1: 2, 3: [4, 5]}`, which is far easier to read in a failed assertion than buffer offsets.
+///
+/// ```
+///# use tps_minicbor::decoder::SequenceBuffer;
+///# use tps_minicbor::types::CBOR;
+/// let bytes = [0xa2, 0x01, 0x02, 0x03, 0x82, 0x04, 0x05];
+/// let item = SequenceBuffer::new(&bytes).into_iter().next().unwrap();
+/// assert_eq!(format!("{:?}", item), "{1: 2, 3: [4, 5]}");
+/// if let CBOR::Map(mb) = item {
+///     assert_eq!(format!("{:?}", mb.raw_state()), "MapBuf { bytes: [1, 2, 3, 130, 4, 5], n_pairs: 2 }");
+/// }
+/// ```
+#[cfg(feature = "full")]
+impl<'buf> Debug for CBOR<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CBOR::UInt(v) => write!(f, "{}", v),
+            CBOR::NInt(v) => write!(f, "{}", -1i128 - (*v as i128)),
+            CBOR::Float64(v) => debug_float(f, *v, v),
+            CBOR::Float32(v) => debug_float(f, *v as f64, v),
+            CBOR::Float16(v) => debug_float(f, v.to_f64(), &v.to_f32()),
+            CBOR::Bstr(bs) => {
+                write!(f, "h'")?;
+                for b in bs.iter() {
+                    write!(f, "{}", print_hex(*b))?;
+                }
+                write!(f, "'")
+            }
+            CBOR::Tstr(s) => write!(f, "{:?}", s),
+            CBOR::Array(ab) => ab.fmt(f),
+            CBOR::Map(mb) => mb.fmt(f),
+            CBOR::Tag(tb) => tb.fmt(f),
+            CBOR::Simple(v) => write!(f, "simple({})", v),
+            CBOR::False => write!(f, "false"),
+            CBOR::True => write!(f, "true"),
+            CBOR::Null => write!(f, "null"),
+            CBOR::Undefined => write!(f, "undefined"),
+            CBOR::Eof => write!(f, "Eof"),
+            CBOR::DateTime(dt) => write!(f, "0(\"{}\")", dt.format("%Y-%m-%dT%H:%M:%S%z")),
+            CBOR::Epoch(ep) => write!(f, "1({})", ep),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl<'buf> Debug for ArrayBuf<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, item) in self.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            item.fmt(f)?;
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(feature = "full")]
+impl<'buf> Debug for MapBuf<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        let mut it = self.into_iter();
+        let mut first = true;
+        while let (Some(key), Some(value)) = (it.next(), it.next()) {
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+            write!(f, "{:?}: {:?}", key, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[cfg(feature = "full")]
+impl<'buf> Debug for TagBuf<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.get_tag())?;
+        if let Some(item) = self.into_iter().next() {
+            item.fmt(f)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// (private) Write a float in diagnostic notation. `value` is used to detect the special values
+/// and `repr` gives the shortest representation at the precision of the encoding.
+#[cfg(feature = "full")]
+fn debug_float(f: &mut fmt::Formatter<'_>, value: f64, repr: &dyn Debug) -> fmt::Result {
+    if value.is_nan() {
+        write!(f, "NaN")
+    } else if value.is_infinite() {
+        write!(f, "{}Infinity", if value < 0.0 { "-" } else { "" })
+    } else {
+        write!(f, "{:?}", repr)
+    }
+}
+
+/// The internal state of an [`ArrayBuf`], [`MapBuf`] or [`TagBuf`]: the buffer contents and the
+/// item count or tag value. This is returned by `raw_state()` on each of these types, for use when
+/// debugging the decoder itself.
+#[cfg(feature = "full")]
+pub struct RawState<'buf> {
+    name: &'static str,
+    bytes: &'buf [u8],
+    field: &'static str,
+    value: u64,
+}
+
+#[cfg(feature = "full")]
+impl<'buf> RawState<'buf> {
+    /// (crate) Construct the state of the buffer `name`, where `field` is the name of the field
+    /// holding `value` alongside the buffer contents.
+    pub(crate) fn new(
+        name: &'static str,
+        bytes: &'buf [u8],
+        field: &'static str,
+        value: u64,
+    ) -> RawState<'buf> {
+        RawState {
+            name,
+            bytes,
+            field,
+            value,
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl<'buf> Debug for RawState<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct(self.name)
+            .field("bytes", &self.bytes)
+            .field(self.field, &self.value)
+            .finish()
+    }
+}
+
+/***************************************************************************************************
+ * Semantic comparison
+ **************************************************************************************************/
+
+/// Compare two buffers of encoded CBOR for semantic equality, i.e. whether they encode the same
+/// sequence of data items, regardless of the details of the encoding. This is intended for use in
+/// tests which should not depend on the exact byte layout of an encoding.
+///
+/// - Integers are equal if they have the same value, whatever the width of their encoding.
+/// - Floats are equal if they have the same value, whatever their precision. All NaN values are
+///   considered equal to each other (this differs from IEEE 754, where NaN is never equal to
+///   anything). Integers are never equal to floats, so `1` and `1.0` are different.
+/// - A `bstr` is never equal to a `tstr`, even if the bytes are the same.
+/// - Arrays are equal if they have semantically equal items in the same order.
+/// - Maps are equal if they have the same number of entries and every (key, value) pair in each
+///   map has a semantically equal pair in the other. The order of entries is not significant.
+/// - Tagged items are equal if they have the same tag value and semantically equal content.
+///
+/// Both buffers are fully decoded before they are compared, and the first decoding error found
+/// is returned.
+///
+/// ```
+///# use tps_minicbor::debug::semantic_eq;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // {1: 2, "a": [3]} and {"a": [3], 1: 2}, with 2 and 3 encoded on two bytes
+/// let a = [0xa2, 0x01, 0x02, 0x61, 0x61, 0x81, 0x03];
+/// let b = [0xa2, 0x61, 0x61, 0x81, 0x18, 0x03, 0x01, 0x18, 0x02];
+/// assert!(semantic_eq(&a, &b)?);
+/// // h'61' is not the same as "a"
+/// assert!(!semantic_eq(&[0x41, 0x61], &[0x61, 0x61])?);
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "full")]
+pub fn semantic_eq(a: &[u8], b: &[u8]) -> Result<bool, CBORError> {
+    check_well_formed(a)?;
+    check_well_formed(b)?;
+    let mut items_a = SequenceBuffer::new(a).into_iter();
+    let mut items_b = SequenceBuffer::new(b).into_iter();
+    loop {
+        match (items_a.next(), items_b.next()) {
+            (None, None) => return Ok(true),
+            (Some(x), Some(y)) if item_eq(&x, &y) => (),
+            _ => return Ok(false),
+        }
+    }
+}
+
+/// (private) Decode every item of `buf`, returning the first error found.
+#[cfg(feature = "full")]
+fn check_well_formed(buf: &[u8]) -> Result<(), CBORError> {
+    let mut it = SequenceBuffer::new(buf).into_iter();
+    while it.index < buf.len() {
+        let _ = it.try_next()?;
+    }
+    Ok(())
+}
+
+/// (private) Semantic equality of two decoded items. See [`semantic_eq`].
+#[cfg(feature = "full")]
+fn item_eq(x: &CBOR, y: &CBOR) -> bool {
+    match (x, y) {
+        (CBOR::Array(xa), CBOR::Array(ya)) => {
+            xa.len() == ya.len() && xa.into_iter().zip(*ya).all(|(x, y)| item_eq(&x, &y))
+        }
+        (CBOR::Map(xm), CBOR::Map(ym)) => {
+            xm.len() == ym.len() && map_pairs_in(*xm, *ym) && map_pairs_in(*ym, *xm)
+        }
+        (CBOR::Tag(xt), CBOR::Tag(yt)) => {
+            xt.get_tag() == yt.get_tag()
+                && xt.into_iter().zip(*yt).all(|(x, y)| item_eq(&x, &y))
+        }
+        _ => match (float_value(x), float_value(y)) {
+            (Some(fx), Some(fy)) => fx == fy || (fx.is_nan() && fy.is_nan()),
+            _ => x == y,
+        },
+    }
+}
+
+/// (private) Return `true` if every (key, value) pair in `m1` has a semantically equal pair in
+/// `m2`.
+#[cfg(feature = "full")]
+fn map_pairs_in(m1: MapBuf, m2: MapBuf) -> bool {
+    let mut items = m1.into_iter();
+    while let (Some(k1), Some(v1)) = (items.next(), items.next()) {
+        let mut candidates = m2.into_iter();
+        let mut found = false;
+        while let (Some(k2), Some(v2)) = (candidates.next(), candidates.next()) {
+            if item_eq(&k1, &k2) && item_eq(&v1, &v2) {
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            return false;
+        }
+    }
+    true
+}
+
+/// (private) The value of a float item as an `f64`, or `None` if the item is not a float.
+#[cfg(feature = "full")]
+fn float_value(cbor: &CBOR) -> Option<f64> {
+    match cbor {
+        CBOR::Float16(v) => Some(v.to_f64()),
+        CBOR::Float32(v) => Some(*v as f64),
+        CBOR::Float64(v) => Some(*v),
+        _ => None,
+    }
+}
+
+/// Convert the CBOR item in `cbor` to JSON, writing it to `sink`, following the conversion
+/// rules of RFC8949, Section 6.1. This is intended for inspecting CBOR with JSON tooling: the
+/// conversion is lossy, so the JSON cannot in general be converted back to the same CBOR.
+///
+/// - Integers, and finite floats, become JSON numbers.
+/// - Byte strings become base64url strings without padding. Inside tag 22 or 23 they become
+///   base64 strings with padding, or base16 strings in upper case, respectively (RFC8949,
+///   Section 3.4.5.2).
+/// - Text strings, arrays, `true`, `false` and `null` become their JSON equivalents.
+/// - NaN and infinite floats, `undefined` and other simple values become `null`.
+/// - Tags are dropped, and only their content is converted. Dates are written as RFC3339 text
+///   and epoch times as numbers.
+/// - Maps become JSON objects. Text string keys are used as they are, integer keys become their
+///   decimal representation, byte string keys are encoded as byte string values are, and any
+///   other key is written as the text of its own JSON conversion. Distinct CBOR keys can
+///   therefore become the same JSON key, e.g. `1` and `"1"`.
+///
+/// Errors are:
+///
+/// - `CBORError::TrailingData` if `cbor` holds more than one item, and
+///   `CBORError::EndOfBuffer` if it is empty.
+/// - Any error from decoding `cbor` if it is not well-formed. Nothing is written in this case.
+/// - `CBORError::EndOfBuffer` if writing to `sink` fails, e.g. because it is full.
+///
+/// ```
+///# use tps_minicbor::debug::to_json;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // {"a": [1, -2], 3: h'fffe'}
+/// let cbor = [0xa2, 0x61, 0x61, 0x82, 0x01, 0x21, 0x03, 0x42, 0xff, 0xfe];
+/// let mut json = Vec::new();
+/// to_json(&cbor, &mut json)?;
+/// assert_eq!(json, br#"{"a":[1,-2],"3":"__4"}"#);
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "full")]
+pub fn to_json<W: Write>(cbor: &[u8], mut sink: W) -> Result<(), CBORError> {
+    check_well_formed(cbor)?;
+    let mut items = SequenceBuffer::new(cbor).into_iter();
+    let item = items.next().ok_or(CBORError::EndOfBuffer)?;
+    if items.next().is_some() {
+        return Err(CBORError::TrailingData);
+    }
+    json_item(&mut sink, &item, JsonBytes::Base64Url).map_err(|_| CBORError::EndOfBuffer)
+}
+
+/// (private) The encoding of byte strings in JSON, which tags 21 to 23 select for the byte
+/// strings they enclose.
+#[cfg(feature = "full")]
+#[derive(Copy, Clone)]
+enum JsonBytes {
+    Base64Url,
+    Base64,
+    Base16,
+}
+
+/// (private) Write the JSON conversion of `item`. See [`to_json`].
+#[cfg(feature = "full")]
+fn json_item(out: &mut dyn Write, item: &CBOR, bytes: JsonBytes) -> std::io::Result<()> {
+    match item {
+        CBOR::UInt(v) => write!(out, "{}", v),
+        CBOR::NInt(v) => write!(out, "{}", -1i128 - (*v as i128)),
+        CBOR::Float64(v) => json_float(out, *v, v),
+        CBOR::Float32(v) => json_float(out, *v as f64, v),
+        CBOR::Float16(v) => json_float(out, v.to_f64(), &v.to_f32()),
+        CBOR::Bstr(b) => json_bytes(out, b, bytes),
+        CBOR::Tstr(s) => json_string(out, s),
+        CBOR::Array(ab) => {
+            out.write_all(b"[")?;
+            for (n, item) in ab.into_iter().enumerate() {
+                if n > 0 {
+                    out.write_all(b",")?;
+                }
+                json_item(out, &item, bytes)?;
+            }
+            out.write_all(b"]")
+        }
+        CBOR::Map(mb) => {
+            out.write_all(b"{")?;
+            let mut items = mb.into_iter();
+            let mut first = true;
+            while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                if !first {
+                    out.write_all(b",")?;
+                }
+                first = false;
+                json_key(out, &key, bytes)?;
+                out.write_all(b":")?;
+                json_item(out, &value, bytes)?;
+            }
+            out.write_all(b"}")
+        }
+        CBOR::Tag(tb) => {
+            let bytes = match tb.get_tag() {
+                21 => JsonBytes::Base64Url,
+                22 => JsonBytes::Base64,
+                23 => JsonBytes::Base16,
+                _ => bytes,
+            };
+            match tb.into_iter().next() {
+                Some(content) => json_item(out, &content, bytes),
+                None => out.write_all(b"null"),
+            }
+        }
+        CBOR::True => out.write_all(b"true"),
+        CBOR::False => out.write_all(b"false"),
+        CBOR::Null | CBOR::Undefined | CBOR::Simple(_) | CBOR::Eof => out.write_all(b"null"),
+        CBOR::DateTime(dt) => json_string(out, &dt.to_rfc3339()),
+        CBOR::Epoch(v) => write!(out, "{}", v),
+    }
+}
+
+/// (private) Write a map key as a JSON string. See [`to_json`].
+#[cfg(feature = "full")]
+fn json_key(out: &mut dyn Write, key: &CBOR, bytes: JsonBytes) -> std::io::Result<()> {
+    match key {
+        CBOR::Tstr(_) | CBOR::Bstr(_) => json_item(out, key, bytes),
+        CBOR::UInt(_) | CBOR::NInt(_) => {
+            out.write_all(b"\"")?;
+            json_item(out, key, bytes)?;
+            out.write_all(b"\"")
+        }
+        _ => {
+            let mut text = Vec::new();
+            json_item(&mut text, key, bytes)?;
+            json_string(out, &String::from_utf8_lossy(&text))
+        }
+    }
+}
+
+/// (private) Write a float as a JSON number, or `null` if it is NaN or infinite. `repr` is the
+/// value at its encoded precision, which gives the shortest representation.
+#[cfg(feature = "full")]
+fn json_float(out: &mut dyn Write, value: f64, repr: &dyn Debug) -> std::io::Result<()> {
+    if value.is_finite() {
+        write!(out, "{:?}", repr)
+    } else {
+        out.write_all(b"null")
+    }
+}
+
+/// (private) Write `s` as a JSON string, escaping it as required by RFC8259, Section 7.
+#[cfg(feature = "full")]
+fn json_string(out: &mut dyn Write, s: &str) -> std::io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+/// (private) Write `b` as a JSON string in the `encoding` selected (RFC4648).
+#[cfg(feature = "full")]
+fn json_bytes(out: &mut dyn Write, b: &[u8], encoding: JsonBytes) -> std::io::Result<()> {
+    const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    const BASE64URL: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    out.write_all(b"\"")?;
+    match encoding {
+        JsonBytes::Base16 => {
+            for byte in b {
+                write!(out, "{:02X}", byte)?;
+            }
+        }
+        JsonBytes::Base64 | JsonBytes::Base64Url => {
+            let (alphabet, pad) = match encoding {
+                JsonBytes::Base64 => (BASE64, true),
+                _ => (BASE64URL, false),
+            };
+            for chunk in b.chunks(3) {
+                let n = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+                let chars = [18, 12, 6, 0].map(|shift| alphabet[(n >> shift) as usize & 0x3f]);
+                out.write_all(&chars[..chunk.len() + 1])?;
+                if pad {
+                    out.write_all(&b"=="[..3 - chunk.len()])?;
+                }
+            }
+        }
+    }
+    out.write_all(b"\"")
+}
+
+/// Parse the JSON document `json` (RFC8259) and write the equivalent CBOR item to `out`,
+/// returning the number of bytes written. This is intended for writing test inputs in JSON.
+///
+/// - Numbers without a fraction or exponent become integers if they fit in a CBOR integer.
+///   Other numbers become floats, in their shortest encoding which preserves the value.
+/// - Strings become text strings, and object keys become text string keys.
+/// - Arrays and objects become definite length arrays and maps, with the members in the order
+///   they are written. Repeated object keys are kept.
+/// - `true`, `false` and `null` become their CBOR equivalents.
+///
+/// JSON has no byte strings, tags, `undefined` or other simple values, so these are never
+/// produced: a conversion with [`to_json`] followed by `from_json` does not in general give back
+/// the original CBOR.
+///
+/// Errors are:
+///
+/// - `CBORError::MalformedEncoding` if `json` is not valid JSON, e.g. it has an unpaired UTF-16
+///   surrogate escape, and `CBORError::TrailingData` if it holds more than one value.
+/// - `CBORError::OutOfRange` if a number is too large for a 64 bit float.
+/// - `CBORError::LimitExceeded` if arrays and objects are nested more than 64 deep.
+/// - `CBORError::EndOfBuffer` if `out` is too small.
+///
+/// ```
+///# use tps_minicbor::debug::from_json;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// let mut out = [0u8; 32];
+/// let len = from_json(r#"{"a": [1, -2], "b": 1.5}"#, &mut out)?;
+/// assert_eq!(
+///     &out[..len],
+///     &[0xa2, 0x61, 0x61, 0x82, 0x01, 0x21, 0x61, 0x62, 0xf9, 0x3e, 0x00]
+/// );
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "full")]
+pub fn from_json(json: &str, out: &mut [u8]) -> Result<usize, CBORError> {
+    let mut buf = EncodeBuffer::new(out);
+    let mut parser = JsonParser { json, index: 0 };
+    parser.value(&mut buf, 0)?;
+    parser.skip_whitespace();
+    if parser.index < json.len() {
+        return Err(CBORError::TrailingData);
+    }
+    Ok(buf.encoded()?.len())
+}
+
+/// (private) The maximum nesting of arrays and objects accepted by [`from_json`].
+#[cfg(feature = "full")]
+const JSON_MAX_DEPTH: usize = 64;
+
+/// (private) A recursive descent JSON parser, which encodes each value as it is parsed.
+#[cfg(feature = "full")]
+struct JsonParser<'a> {
+    json: &'a str,
+    index: usize,
+}
+
+#[cfg(feature = "full")]
+impl<'a> JsonParser<'a> {
+    /// Parse a value, with any whitespace before it, at nesting depth `depth`.
+    fn value(&mut self, out: &mut EncodeBuffer, depth: usize) -> Result<(), CBORError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'[') | Some(b'{') if depth == JSON_MAX_DEPTH => Err(CBORError::LimitExceeded),
+            Some(b'[') => self.array(out, depth + 1),
+            Some(b'{') => self.object(out, depth + 1),
+            Some(b'"') => {
+                let _ = out.insert(&self.string()?.as_str())?;
+                Ok(())
+            }
+            Some(b't') => self.literal("true", &CBOR::True, out),
+            Some(b'f') => self.literal("false", &CBOR::False, out),
+            Some(b'n') => self.literal("null", &CBOR::Null, out),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(out),
+            _ => Err(CBORError::MalformedEncoding),
+        }
+    }
+
+    /// Parse an array, whose members are at nesting depth `depth`.
+    fn array(&mut self, out: &mut EncodeBuffer, depth: usize) -> Result<(), CBORError> {
+        let mut ctx = EncodeContext::new();
+        let _ = out.array_start(&mut ctx)?;
+        self.index += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.index += 1;
+        } else {
+            loop {
+                self.value(out, depth)?;
+                if self.separator(b']')? {
+                    break;
+                }
+            }
+        }
+        let _ = out.array_finalize(&ctx)?;
+        Ok(())
+    }
+
+    /// Parse an object, whose members are at nesting depth `depth`.
+    fn object(&mut self, out: &mut EncodeBuffer, depth: usize) -> Result<(), CBORError> {
+        let mut ctx = EncodeContext::new();
+        let _ = out.map_start(&mut ctx)?;
+        self.index += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.index += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some(b'"') {
+                    return Err(CBORError::MalformedEncoding);
+                }
+                let _ = out.insert(&self.string()?.as_str())?;
+                self.skip_whitespace();
+                if self.peek() != Some(b':') {
+                    return Err(CBORError::MalformedEncoding);
+                }
+                self.index += 1;
+                self.value(out, depth)?;
+                if self.separator(b'}')? {
+                    break;
+                }
+            }
+        }
+        let _ = out.map_finalize(&ctx)?;
+        Ok(())
+    }
+
+    /// Consume the `,` between members, returning `false`, or the `close` character at the end
+    /// of an array or object, returning `true`.
+    fn separator(&mut self, close: u8) -> Result<bool, CBORError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b',') => {
+                self.index += 1;
+                Ok(false)
+            }
+            Some(c) if c == close => {
+                self.index += 1;
+                Ok(true)
+            }
+            _ => Err(CBORError::MalformedEncoding),
+        }
+    }
+
+    /// Parse a string, replacing any escape sequences.
+    fn string(&mut self) -> Result<String, CBORError> {
+        let mut text = String::new();
+        self.index += 1;
+        let mut start = self.index;
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    text.push_str(&self.json[start..self.index]);
+                    self.index += 1;
+                    return Ok(text);
+                }
+                Some(b'\\') => {
+                    text.push_str(&self.json[start..self.index]);
+                    self.index += 1;
+                    text.push(self.escape()?);
+                    start = self.index;
+                }
+                Some(c) if c >= 0x20 => self.index += 1,
+                _ => return Err(CBORError::MalformedEncoding),
+            }
+        }
+    }
+
+    /// Parse the escape sequence following a `\`.
+    fn escape(&mut self) -> Result<char, CBORError> {
+        let c = self.peek().ok_or(CBORError::MalformedEncoding)?;
+        self.index += 1;
+        match c {
+            b'"' => Ok('"'),
+            b'\\' => Ok('\\'),
+            b'/' => Ok('/'),
+            b'b' => Ok('\u{8}'),
+            b'f' => Ok('\u{c}'),
+            b'n' => Ok('\n'),
+            b'r' => Ok('\r'),
+            b't' => Ok('\t'),
+            b'u' => {
+                let unit = self.hex4()?;
+                let code = match unit {
+                    0xd800..=0xdbff if self.json[self.index..].starts_with("\\u") => {
+                        self.index += 2;
+                        match self.hex4()? {
+                            low @ 0xdc00..=0xdfff => {
+                                0x10000 + ((unit - 0xd800) << 10) + low - 0xdc00
+                            }
+                            _ => return Err(CBORError::MalformedEncoding),
+                        }
+                    }
+                    _ => unit,
+                };
+                // Unpaired surrogates are not characters, so are rejected here
+                std::char::from_u32(code).ok_or(CBORError::MalformedEncoding)
+            }
+            _ => Err(CBORError::MalformedEncoding),
+        }
+    }
+
+    /// Parse the four hexadecimal digits of a `\u` escape.
+    fn hex4(&mut self) -> Result<u32, CBORError> {
+        let digits = self
+            .json
+            .get(self.index..self.index + 4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or(CBORError::MalformedEncoding)?;
+        self.index += 4;
+        u32::from_str_radix(digits, 16).map_err(|_| CBORError::MalformedEncoding)
+    }
+
+    /// Parse a number, encoding it as an integer if it has no fraction or exponent and is in
+    /// range, and otherwise as a float.
+    fn number(&mut self, out: &mut EncodeBuffer) -> Result<(), CBORError> {
+        let start = self.index;
+        if self.peek() == Some(b'-') {
+            self.index += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.index += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(CBORError::MalformedEncoding),
+        }
+        let mut integer = true;
+        if self.peek() == Some(b'.') {
+            self.index += 1;
+            self.required_digits()?;
+            integer = false;
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.index += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.index += 1;
+            }
+            self.required_digits()?;
+            integer = false;
+        }
+        let text = &self.json[start..self.index];
+        if integer {
+            match text.parse::<i128>() {
+                Ok(v) if v >= -(1i128 << 64) && v <= u64::MAX as i128 => {
+                    let _ = out.insert(&v)?;
+                    return Ok(());
+                }
+                _ => (),
+            }
+        }
+        let v = text
+            .parse::<f64>()
+            .map_err(|_| CBORError::MalformedEncoding)?;
+        if !v.is_finite() {
+            return Err(CBORError::OutOfRange);
+        }
+        let _ = out.insert(&v)?;
+        Ok(())
+    }
+
+    /// Consume any decimal digits.
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.index += 1;
+        }
+    }
+
+    /// Consume at least one decimal digit.
+    fn required_digits(&mut self) -> Result<(), CBORError> {
+        match self.peek() {
+            Some(b'0'..=b'9') => {
+                self.digits();
+                Ok(())
+            }
+            _ => Err(CBORError::MalformedEncoding),
+        }
+    }
+
+    /// Consume `word`, encoding `item` for it.
+    fn literal(
+        &mut self,
+        word: &str,
+        item: &CBOR,
+        out: &mut EncodeBuffer,
+    ) -> Result<(), CBORError> {
+        if !self.json[self.index..].starts_with(word) {
+            return Err(CBORError::MalformedEncoding);
+        }
+        self.index += word.len();
+        let _ = out.insert(item)?;
+        Ok(())
+    }
+
+    /// Consume any JSON whitespace.
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.index += 1;
+        }
+    }
+
+    /// The next byte of the document, if any.
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.index).copied()
+    }
+}
@@ -59,6 +59,8 @@ pub const PAYLOAD_TWO_BYTES: u8 = 25;
 pub const PAYLOAD_FOUR_BYTES: u8 = 26;
 /// Indicates eight bytes of length of value information follows MT/AI byte
 pub const PAYLOAD_EIGHT_BYTES: u8 = 27;
+/// Additional information values 28 to 30 are reserved by RFC 8949 and are not well-formed
+pub const AI_RESERVED: core::ops::RangeInclusive<u8> = 28..=30;
 // Indicates an indefinite number of bytes follow. Note that this option is not supported
 // in the current version of rs_minicbor.
 //pub const PAYLOAD_INDEFINITE_BYTES: u8 = 31;
@@ -0,0 +1,960 @@
+/***************************************************************************************************
+ * Copyright (c) 2021-2022 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * rs_minicbor COSE structure support
+ *
+ * Helpers to build and parse the CBOR structure of COSE messages (RFC9052). No cryptographic
+ * operations are performed here: computing and checking signatures, MACs and ciphertexts is the
+ * responsibility of the caller.
+ **************************************************************************************************/
+use core::convert::TryFrom;
+
+use crate::array::{array, ArrayBuf};
+use crate::ast::CBOR;
+use crate::decode::SequenceBuffer;
+#[cfg(feature = "combinators")]
+use crate::decode::DecodeBufIterator;
+#[cfg(feature = "combinators")]
+use crate::decode_combinators::CBORDecoder;
+use crate::encode::{EncodeBuffer, EncodeItem};
+use crate::error::CBORError;
+use crate::map::{map, MapBuf};
+use crate::tag::tag;
+
+#[cfg(feature = "trace")]
+use func_trace::trace;
+
+#[cfg(feature = "trace")]
+func_trace::init_depth_var!();
+
+/// CBOR tag for a COSE_Encrypt0 message (RFC9052, Section 2)
+pub const COSE_ENCRYPT0_TAG: u64 = 16;
+/// CBOR tag for a COSE_Sign1 message (RFC9052, Section 2)
+pub const COSE_SIGN1_TAG: u64 = 18;
+/// CBOR tag for a COSE_Sign message (RFC9052, Section 2)
+pub const COSE_SIGN_TAG: u64 = 98;
+
+/// COSE header label: `alg` (RFC9052, Section 3.1)
+pub const HEADER_ALG: i64 = 1;
+/// COSE header label: `IV` (RFC9052, Section 3.1)
+pub const HEADER_IV: i64 = 5;
+
+/***************************************************************************************************
+ * Algorithms
+ **************************************************************************************************/
+
+/// The COSE algorithms supported by the helpers in this module, with their identifiers from the
+/// IANA COSE Algorithms registry (RFC9053).
+///
+/// Only the common signature, MAC and AES-GCM content encryption algorithms are included. Any
+/// other identifier is rejected with `CBORError::NotAllowed` when converting from an integer.
+///
+/// ```
+///# use core::convert::TryFrom;
+///# use tps_minicbor::cose::Algorithm;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// assert_eq!(Algorithm::try_from(-7)?, Algorithm::ES256);
+/// assert_eq!(i64::from(Algorithm::HMAC256), 5);
+/// assert!(matches!(Algorithm::try_from(-65535), Err(CBORError::NotAllowed)));
+///# Ok(())
+///# }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+pub enum Algorithm {
+    /// ECDSA with SHA-256
+    ES256,
+    /// ECDSA with SHA-384
+    ES384,
+    /// ECDSA with SHA-512
+    ES512,
+    /// EdDSA
+    EdDSA,
+    /// RSASSA-PSS with SHA-256
+    PS256,
+    /// RSASSA-PSS with SHA-384
+    PS384,
+    /// RSASSA-PSS with SHA-512
+    PS512,
+    /// HMAC with SHA-256, truncated to 64 bits
+    HMAC256_64,
+    /// HMAC with SHA-256
+    HMAC256,
+    /// HMAC with SHA-384
+    HMAC384,
+    /// HMAC with SHA-512
+    HMAC512,
+    /// AES-GCM with a 128 bit key
+    A128GCM,
+    /// AES-GCM with a 192 bit key
+    A192GCM,
+    /// AES-GCM with a 256 bit key
+    A256GCM,
+}
+
+impl Algorithm {
+    /// The identifier of the algorithm, as used in the `alg` header.
+    pub fn id(self) -> i64 {
+        match self {
+            Algorithm::ES256 => -7,
+            Algorithm::ES384 => -35,
+            Algorithm::ES512 => -36,
+            Algorithm::EdDSA => -8,
+            Algorithm::PS256 => -37,
+            Algorithm::PS384 => -38,
+            Algorithm::PS512 => -39,
+            Algorithm::HMAC256_64 => 4,
+            Algorithm::HMAC256 => 5,
+            Algorithm::HMAC384 => 6,
+            Algorithm::HMAC512 => 7,
+            Algorithm::A128GCM => 1,
+            Algorithm::A192GCM => 2,
+            Algorithm::A256GCM => 3,
+        }
+    }
+}
+
+impl From<Algorithm> for i64 {
+    fn from(alg: Algorithm) -> Self {
+        alg.id()
+    }
+}
+
+/// Convert an `alg` identifier to an [`Algorithm`]. Returns `CBORError::NotAllowed` if the
+/// algorithm is not supported.
+impl TryFrom<i64> for Algorithm {
+    type Error = CBORError;
+
+    fn try_from(id: i64) -> Result<Self, Self::Error> {
+        match id {
+            -7 => Ok(Algorithm::ES256),
+            -35 => Ok(Algorithm::ES384),
+            -36 => Ok(Algorithm::ES512),
+            -8 => Ok(Algorithm::EdDSA),
+            -37 => Ok(Algorithm::PS256),
+            -38 => Ok(Algorithm::PS384),
+            -39 => Ok(Algorithm::PS512),
+            4 => Ok(Algorithm::HMAC256_64),
+            5 => Ok(Algorithm::HMAC256),
+            6 => Ok(Algorithm::HMAC384),
+            7 => Ok(Algorithm::HMAC512),
+            1 => Ok(Algorithm::A128GCM),
+            2 => Ok(Algorithm::A192GCM),
+            3 => Ok(Algorithm::A256GCM),
+            _ => Err(CBORError::NotAllowed),
+        }
+    }
+}
+
+/// Convert a CBOR integer to an [`Algorithm`], so that it can be used with
+/// [`MapBuf::lookup`]. Text string algorithm names are not supported.
+impl<'buf> TryFrom<CBOR<'buf>> for Algorithm {
+    type Error = CBORError;
+
+    fn try_from(cbor: CBOR<'buf>) -> Result<Self, Self::Error> {
+        match cbor {
+            CBOR::UInt(_) | CBOR::NInt(_) => {
+                let id = i64::try_from(cbor).map_err(|_| CBORError::NotAllowed)?;
+                Algorithm::try_from(id)
+            }
+            _ => Err(CBORError::ExpectedType("int")),
+        }
+    }
+}
+
+/// An `Algorithm` is encoded as its integer identifier.
+impl EncodeItem for Algorithm {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        self.id().encode(buf)
+    }
+}
+
+/// Decode a COSE algorithm identifier.
+///
+/// Returns `CBORError::ExpectedType("int")` if the next item is not an integer, and
+/// `CBORError::NotAllowed` if it is not a supported [`Algorithm`].
+///
+/// This function requires the `combinators` feature, which is enabled by default, as it uses
+/// the decode combinators.
+///
+/// ```
+///# use tps_minicbor::cose::{is_cose_alg, Algorithm};
+///# use tps_minicbor::decoder::SequenceBuffer;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// let (_, alg) = is_cose_alg()(SequenceBuffer::new(&[0x26]).into_iter())?;
+/// assert_eq!(alg, Algorithm::ES256);
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "combinators")]
+pub fn is_cose_alg<'buf>(
+) -> impl Fn(DecodeBufIterator<'buf>) -> Result<(DecodeBufIterator<'buf>, Algorithm), CBORError> {
+    move |mut iter| match iter.next() {
+        Some(cbor) => Ok((iter, Algorithm::try_from(cbor)?)),
+        None => Err(iter.no_item_error()),
+    }
+}
+
+/***************************************************************************************************
+ * COSE_Encrypt0
+ **************************************************************************************************/
+
+/// The encoder for a COSE_Encrypt0 structure. See [`encrypt0_encode`].
+pub struct Encrypt0<'a> {
+    protected: &'a [u8],
+    iv: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+/// The [`EncodeItem`] instance for `Encrypt0` writes the tagged COSE_Encrypt0 structure:
+/// `16([protected, {5: iv}, ciphertext])`.
+impl<'a> EncodeItem for Encrypt0<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        tag(COSE_ENCRYPT0_TAG, |buf| {
+            buf.insert(&array(|buf| {
+                buf.insert(&self.protected)?
+                    .insert(&map(|buf| buf.insert_key_value(&HEADER_IV, &self.iv)))?
+                    .insert(&self.ciphertext)
+            }))
+        })
+        .encode(buf)
+    }
+}
+
+/// Construct a COSE_Encrypt0 structure for insertion into a [`CBORBuilder`] or [`EncodeBuffer`].
+///
+/// - `protected` is the serialized protected header map, which should contain the `alg`
+///   header. It may be empty.
+/// - `iv` is placed in the unprotected header map with label 5.
+/// - `ciphertext` is the output of the AEAD operation, performed by the caller.
+///
+/// ```
+///# use tps_minicbor::cose::encrypt0_encode;
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // Protected header: << {1: 1} >> (alg: A128GCM)
+/// let protected = [0xa1, 0x01, 0x01];
+/// let mut buffer = [0u8; 32];
+/// let mut encoder = CBORBuilder::new(&mut buffer);
+/// encoder.insert(&encrypt0_encode(&protected, &[0x10, 0x11], &[0xca, 0xfe]))?;
+/// assert_eq!(encoder.encoded()?, &[0xd0, 0x83, 0x43, 0xa1, 0x01, 0x01, 0xa1, 0x05, 0x42, 0x10,
+///                                  0x11, 0x42, 0xca, 0xfe]);
+///# Ok(())
+///# }
+/// ```
+///
+/// [`CBORBuilder`]: crate::encoder::CBORBuilder
+pub fn encrypt0_encode<'a>(
+    protected: &'a [u8],
+    iv: &'a [u8],
+    ciphertext: &'a [u8],
+) -> Encrypt0<'a> {
+    Encrypt0 {
+        protected,
+        iv,
+        ciphertext,
+    }
+}
+
+/// The contents of a decoded COSE_Encrypt0 structure. All of the byte strings borrow from the
+/// buffer holding the message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Encrypt0Message<'buf> {
+    /// The serialized protected header map, as required to construct the AEAD additional data.
+    pub protected: &'buf [u8],
+    /// The `alg` value from the protected header, if present.
+    pub alg: Option<i64>,
+    /// The `IV` value from the unprotected header.
+    pub iv: &'buf [u8],
+    /// The ciphertext.
+    pub ciphertext: &'buf [u8],
+}
+
+/// Parse a COSE_Encrypt0 structure. The structure may be tagged with tag 16 or untagged.
+///
+/// Returns `CBORError::ExpectedTag(16)` if the item carries a different tag,
+/// `CBORError::KeyNotPresent` if there is no IV in the unprotected headers and
+/// `CBORError::MalformedEncoding` if the structure is not a three element array of the expected
+/// types.
+#[cfg_attr(feature = "trace", trace)]
+pub fn encrypt0_decode(cbor: CBOR) -> Result<Encrypt0Message, CBORError> {
+    let body = match cbor {
+        CBOR::Tag(tb) if tb.get_tag() == COSE_ENCRYPT0_TAG => {
+            tb.into_iter().next().ok_or(CBORError::MalformedEncoding)?
+        }
+        CBOR::Tag(_) => return Err(CBORError::ExpectedTag(COSE_ENCRYPT0_TAG)),
+        other => other,
+    };
+    let ab = match body {
+        CBOR::Array(ab) if ab.len() == 3 => ab,
+        _ => return Err(CBORError::MalformedEncoding),
+    };
+    let mut items = ab.into_iter();
+    let (protected, unprotected, ciphertext) = match (items.next(), items.next(), items.next()) {
+        (Some(CBOR::Bstr(p)), Some(CBOR::Map(u)), Some(CBOR::Bstr(c))) => (p, u, c),
+        _ => return Err(CBORError::MalformedEncoding),
+    };
+    let iv = match unprotected.get_int(HEADER_IV) {
+        Some(CBOR::Bstr(iv)) => iv,
+        Some(_) => return Err(CBORError::MalformedEncoding),
+        None => return Err(CBORError::KeyNotPresent),
+    };
+    Ok(Encrypt0Message {
+        protected,
+        alg: protected_alg(protected)?,
+        iv,
+        ciphertext,
+    })
+}
+
+/***************************************************************************************************
+ * COSE_Sign1
+ **************************************************************************************************/
+
+/// The components of a decoded COSE_Sign1 structure. Nothing is copied: all of the components
+/// borrow from the buffer holding the message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sign1Parts<'buf> {
+    /// The serialized protected header map, as required to construct the `Sig_structure`.
+    pub protected: &'buf [u8],
+    /// The unprotected header map.
+    pub unprotected: MapBuf<'buf>,
+    /// The payload.
+    pub payload: &'buf [u8],
+    /// The signature.
+    pub signature: &'buf [u8],
+}
+
+/// Parse a COSE_Sign1 structure, `18([protected, unprotected, payload, signature])`, from the
+/// next item in `decoder`. The signature is not verified.
+///
+/// Returns `CBORError::ExpectedTag(18)` if the next item is not tagged 18 and
+/// `CBORError::MalformedEncoding` if the structure is not a four element array of the expected
+/// types. Detached payloads (`nil`) are rejected: use [`sign1_decode_detached`] for these. On
+/// error, the decoder is not advanced.
+///
+/// This function requires the `combinators` feature, which is enabled by default, as it uses
+/// the decode combinators.
+///
+/// ```
+///# use tps_minicbor::cose::sign1_decode;
+///# use tps_minicbor::decoder::CBORDecoder;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // 18([<< {1: -7} >>, {4: '11'}, 'hi', h'0102'])
+/// let decoder = CBORDecoder::from_slice(&[
+///     0xd2, 0x84, 0x43, 0xa1, 0x01, 0x26, 0xa1, 0x04, 0x42, 0x31, 0x31, 0x42, 0x68, 0x69, 0x42,
+///     0x01, 0x02,
+/// ]);
+/// let sign1 = sign1_decode(&decoder)?;
+/// assert_eq!(sign1.protected, &[0xa1, 0x01, 0x26]);
+/// assert_eq!(sign1.unprotected.lookup::<u64, &[u8]>(4)?, b"11");
+/// assert_eq!(sign1.payload, b"hi");
+/// assert_eq!(sign1.signature, &[0x01, 0x02]);
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "combinators")]
+#[cfg_attr(feature = "trace", trace)]
+pub fn sign1_decode<'buf>(decoder: &CBORDecoder<'buf>) -> Result<Sign1Parts<'buf>, CBORError> {
+    sign1_decode_parts(decoder, None)
+}
+
+/// Parse a COSE_Sign1 structure with a detached payload, `18([protected, unprotected, nil,
+/// signature])`, from the next item in `decoder`. The externally supplied `payload` is returned
+/// in [`Sign1Parts::payload`], ready for use with [`sig_structure1`] to verify the signature.
+///
+/// Returns `CBORError::MalformedEncoding` if the payload in the message is not `nil`. Other
+/// errors are as for [`sign1_decode`]. On error, the decoder is not advanced.
+///
+/// This function requires the `combinators` feature, which is enabled by default, as it uses
+/// the decode combinators.
+///
+/// ```
+///# use tps_minicbor::cose::sign1_decode_detached;
+///# use tps_minicbor::decoder::CBORDecoder;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // 18([<< {1: -7} >>, {}, nil, h'0102'])
+/// let decoder = CBORDecoder::from_slice(&[
+///     0xd2, 0x84, 0x43, 0xa1, 0x01, 0x26, 0xa0, 0xf6, 0x42, 0x01, 0x02,
+/// ]);
+/// let sign1 = sign1_decode_detached(&decoder, b"hi")?;
+/// assert_eq!(sign1.payload, b"hi");
+/// assert_eq!(sign1.signature, &[0x01, 0x02]);
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "combinators")]
+#[cfg_attr(feature = "trace", trace)]
+pub fn sign1_decode_detached<'buf>(
+    decoder: &CBORDecoder<'buf>,
+    payload: &'buf [u8],
+) -> Result<Sign1Parts<'buf>, CBORError> {
+    sign1_decode_parts(decoder, Some(payload))
+}
+
+/// (private) Parse a COSE_Sign1 structure. If `detached` is `Some`, the payload in the message
+/// must be `nil` and the supplied payload is used instead.
+#[cfg(feature = "combinators")]
+fn sign1_decode_parts<'buf>(
+    decoder: &CBORDecoder<'buf>,
+    detached: Option<&'buf [u8]>,
+) -> Result<Sign1Parts<'buf>, CBORError> {
+    decoder.decode_parsed(|mut it: DecodeBufIterator<'buf>| {
+        let body = match it.next() {
+            Some(CBOR::Tag(tb)) if tb.get_tag() == COSE_SIGN1_TAG => {
+                tb.into_iter().next().ok_or(CBORError::MalformedEncoding)?
+            }
+            Some(_) => return Err(CBORError::ExpectedTag(COSE_SIGN1_TAG)),
+            None => return Err(CBORError::EndOfBuffer),
+        };
+        let ab = match body {
+            CBOR::Array(ab) if ab.len() == 4 => ab,
+            _ => return Err(CBORError::MalformedEncoding),
+        };
+        let mut items = ab.into_iter();
+        let (protected, unprotected, payload, signature) =
+            (items.next(), items.next(), items.next(), items.next());
+        let payload = match (payload, detached) {
+            (Some(CBOR::Bstr(payload)), None) => Some(payload),
+            (Some(CBOR::Null), Some(payload)) => Some(payload),
+            _ => None,
+        };
+        match (protected, unprotected, payload, signature) {
+            (
+                Some(CBOR::Bstr(protected)),
+                Some(CBOR::Map(unprotected)),
+                Some(payload),
+                Some(CBOR::Bstr(signature)),
+            ) => Ok((
+                it,
+                Sign1Parts {
+                    protected,
+                    unprotected,
+                    payload,
+                    signature,
+                },
+            )),
+            _ => Err(CBORError::MalformedEncoding),
+        }
+    })
+}
+
+/// The encoder for a COSE_Sign1 structure with a detached payload. See [`sign1_detached`].
+pub struct Sign1Detached<'a> {
+    protected: &'a [u8],
+    unprotected: &'a dyn EncodeItem,
+    signature: &'a [u8],
+}
+
+/// The [`EncodeItem`] instance for `Sign1Detached` writes the tagged COSE_Sign1 structure with
+/// `nil` in place of the payload: `18([protected, unprotected, nil, signature])`.
+impl<'a> EncodeItem for Sign1Detached<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        tag(COSE_SIGN1_TAG, |buf| {
+            buf.insert(&array(|buf| {
+                buf.insert(&self.protected)?
+                    .insert(self.unprotected)?
+                    .insert(&CBOR::Null)?
+                    .insert(&self.signature)
+            }))
+        })
+        .encode(buf)
+    }
+}
+
+/// Sign `payload_for_signing` and construct a COSE_Sign1 structure with a detached payload, for
+/// insertion into a [`CBORBuilder`] or [`EncodeBuffer`] (RFC9052, Section 4.1).
+///
+/// - `protected` is the serialized protected header map. It may be empty.
+/// - `unprotected` is the unprotected header map, e.g. built with [`map`] or
+///   [`empty_map`](crate::types::empty_map).
+/// - `payload_for_signing` is the detached content. It is covered by the signature but is not
+///   included in the message.
+/// - `scratch` is used to hold the `Sig_structure`, so it must be large enough for the payload
+///   and protected header plus a few bytes of framing.
+/// - `signer` is passed the serialized `Sig_structure` and returns the signature.
+///
+/// ```
+///# use tps_minicbor::cose::sign1_detached;
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::empty_map;
+///# fn main() -> Result<(), CBORError> {
+/// let mut scratch = [0u8; 32];
+/// let signature = [0x5a; 4];
+/// let unprotected = empty_map();
+/// let sign1 = sign1_detached(&[], &unprotected, b"hi", &mut scratch, |_tbs| Ok(&signature))?;
+/// let mut buffer = [0u8; 16];
+/// let mut encoder = CBORBuilder::new(&mut buffer);
+/// encoder.insert(&sign1)?;
+/// assert_eq!(encoder.encoded()?, &[0xd2, 0x84, 0x40, 0xa0, 0xf6, 0x44, 0x5a, 0x5a, 0x5a, 0x5a]);
+///# Ok(())
+///# }
+/// ```
+///
+/// [`CBORBuilder`]: crate::encoder::CBORBuilder
+pub fn sign1_detached<'a, F>(
+    protected: &'a [u8],
+    unprotected: &'a dyn EncodeItem,
+    payload_for_signing: &[u8],
+    scratch: &mut [u8],
+    signer: F,
+) -> Result<Sign1Detached<'a>, CBORError>
+where
+    F: FnOnce(&[u8]) -> Result<&'a [u8], CBORError>,
+{
+    let tbs = sig_structure1(protected, &[], payload_for_signing, scratch)?;
+    Ok(Sign1Detached {
+        protected,
+        unprotected,
+        signature: signer(tbs)?,
+    })
+}
+
+/// Serialize the `Sig_structure` for a COSE_Sign1 structure into `out`, returning the encoded
+/// bytes, which are the input to the signature algorithm (RFC9052, Section 4.4):
+/// `["Signature1", protected, external_aad, payload]`.
+///
+/// Returns `CBORError::EndOfBuffer` if `out` is too small.
+///
+/// ```
+///# use tps_minicbor::cose::sig_structure1;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// let mut out = [0u8; 32];
+/// let tbs = sig_structure1(&[0xa1, 0x01, 0x26], &[], b"hi", &mut out)?;
+/// assert_eq!(&tbs[..12], b"\x84\x6aSignature1");
+/// assert_eq!(&tbs[12..], &[0x43, 0xa1, 0x01, 0x26, 0x40, 0x42, 0x68, 0x69]);
+///# Ok(())
+///# }
+/// ```
+pub fn sig_structure1<'s>(
+    protected: &[u8],
+    external_aad: &[u8],
+    payload: &[u8],
+    out: &'s mut [u8],
+) -> Result<&'s [u8], CBORError> {
+    let len = {
+        let mut buf = EncodeBuffer::new(out);
+        buf.insert(&array(|buf| {
+            buf.insert(&"Signature1")?
+                .insert(&protected)?
+                .insert(&external_aad)?
+                .insert(&payload)
+        }))?;
+        buf.encoded()?.len()
+    };
+    Ok(&out[..len])
+}
+
+/***************************************************************************************************
+ * COSE_Sign
+ **************************************************************************************************/
+
+/// A COSE_Signature structure, `[protected, unprotected, signature]`, holding the signature of one
+/// signer of a COSE_Sign message (RFC9052, Section 4.1).
+pub struct CoseSignature<'a> {
+    /// The serialized protected header map of the signer. It may be empty.
+    pub protected: &'a [u8],
+    /// The unprotected header map of the signer.
+    pub unprotected: &'a dyn EncodeItem,
+    /// The signature, computed over the output of [`sig_structure`].
+    pub signature: &'a [u8],
+}
+
+/// The [`EncodeItem`] instance for `CoseSignature` writes the three element array.
+impl<'a> EncodeItem for CoseSignature<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        array(|buf| {
+            buf.insert(&self.protected)?
+                .insert(self.unprotected)?
+                .insert(&self.signature)
+        })
+        .encode(buf)
+    }
+}
+
+/// The encoder for a COSE_Sign structure. See [`sign_encode`].
+pub struct Sign<'a> {
+    protected: &'a [u8],
+    unprotected: &'a dyn EncodeItem,
+    payload: &'a [u8],
+    signatures: &'a [CoseSignature<'a>],
+}
+
+/// The [`EncodeItem`] instance for `Sign` writes the tagged COSE_Sign structure:
+/// `98([protected, unprotected, payload, [+ COSE_Signature]])`.
+impl<'a> EncodeItem for Sign<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        if self.signatures.is_empty() {
+            return Err(CBORError::MalformedEncoding);
+        }
+        tag(COSE_SIGN_TAG, |buf| {
+            buf.insert(&array(|buf| {
+                buf.insert(&self.protected)?
+                    .insert(self.unprotected)?
+                    .insert(&self.payload)?
+                    .insert(&array(|buf| {
+                        for signature in self.signatures {
+                            let _ = buf.insert(signature)?;
+                        }
+                        Ok(buf)
+                    }))
+            }))
+        })
+        .encode(buf)
+    }
+}
+
+/// Construct a COSE_Sign structure, with one or more signers, for insertion into a
+/// [`CBORBuilder`] or [`EncodeBuffer`] (RFC9052, Section 4.1).
+///
+/// - `protected` is the serialized protected header map of the message body. It may be empty.
+/// - `unprotected` is the unprotected header map of the message body.
+/// - `payload` is the content which is signed.
+/// - `signatures` holds a [`CoseSignature`] for each signer. Each signature is computed over
+///   the output of [`sig_structure`] for that signer.
+///
+/// Encoding returns `CBORError::MalformedEncoding` if `signatures` is empty.
+///
+/// ```
+///# use tps_minicbor::cose::{sign_encode, CoseSignature};
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::empty_map;
+///# fn main() -> Result<(), CBORError> {
+/// let unprotected = empty_map();
+/// let signatures = [CoseSignature { protected: &[], unprotected: &unprotected, signature: &[0x5a] }];
+/// let mut buffer = [0u8; 16];
+/// let mut encoder = CBORBuilder::new(&mut buffer);
+/// encoder.insert(&sign_encode(&[], &unprotected, b"hi", &signatures))?;
+/// assert_eq!(encoder.encoded()?, &[0xd8, 0x62, 0x84, 0x40, 0xa0, 0x42, 0x68, 0x69, 0x81, 0x83,
+///                                  0x40, 0xa0, 0x41, 0x5a]);
+///# Ok(())
+///# }
+/// ```
+///
+/// [`CBORBuilder`]: crate::encoder::CBORBuilder
+pub fn sign_encode<'a>(
+    protected: &'a [u8],
+    unprotected: &'a dyn EncodeItem,
+    payload: &'a [u8],
+    signatures: &'a [CoseSignature<'a>],
+) -> Sign<'a> {
+    Sign {
+        protected,
+        unprotected,
+        payload,
+        signatures,
+    }
+}
+
+/// The components of one COSE_Signature in a decoded COSE_Sign structure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignatureParts<'buf> {
+    /// The serialized protected header map of the signer.
+    pub protected: &'buf [u8],
+    /// The unprotected header map of the signer.
+    pub unprotected: MapBuf<'buf>,
+    /// The signature.
+    pub signature: &'buf [u8],
+}
+
+/// The components of a decoded COSE_Sign structure. Nothing is copied: all of the components
+/// borrow from the buffer holding the message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignParts<'buf> {
+    /// The serialized protected header map of the message body.
+    pub protected: &'buf [u8],
+    /// The unprotected header map of the message body.
+    pub unprotected: MapBuf<'buf>,
+    /// The payload.
+    pub payload: &'buf [u8],
+    signatures: ArrayBuf<'buf>,
+}
+
+impl<'buf> SignParts<'buf> {
+    /// The number of signers.
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    /// Always `false`, as a COSE_Sign structure has at least one signer.
+    pub fn is_empty(&self) -> bool {
+        self.signatures.len() == 0
+    }
+
+    /// Iterate over the signers, in the order in which they appear in the message. The
+    /// signatures are not verified.
+    pub fn signatures(&self) -> impl Iterator<Item = SignatureParts<'buf>> + 'buf {
+        self.signatures
+            .into_iter()
+            .filter_map(|cbor| signature_parts(cbor).ok())
+    }
+}
+
+/// (private) Check the structure of a COSE_Signature, `[protected, unprotected, signature]`.
+fn signature_parts(cbor: CBOR) -> Result<SignatureParts, CBORError> {
+    let ab = match cbor {
+        CBOR::Array(ab) if ab.len() == 3 => ab,
+        _ => return Err(CBORError::MalformedEncoding),
+    };
+    let mut items = ab.into_iter();
+    match (items.next(), items.next(), items.next()) {
+        (
+            Some(CBOR::Bstr(protected)),
+            Some(CBOR::Map(unprotected)),
+            Some(CBOR::Bstr(signature)),
+        ) => Ok(SignatureParts {
+            protected,
+            unprotected,
+            signature,
+        }),
+        _ => Err(CBORError::MalformedEncoding),
+    }
+}
+
+/// Parse a COSE_Sign structure, `98([protected, unprotected, payload, [+ COSE_Signature]])`,
+/// from the next item in `decoder`. The signatures are not verified: each one can be checked
+/// against the output of [`sig_structure`] for that signer.
+///
+/// Returns `CBORError::ExpectedTag(98)` if the next item is not tagged 98 and
+/// `CBORError::MalformedEncoding` if the structure, or any of the COSE_Signature structures it
+/// holds, does not have the expected types, or if there are no signers. On error, the decoder is
+/// not advanced.
+///
+/// This function requires the `combinators` feature, which is enabled by default, as it uses
+/// the decode combinators.
+///
+/// ```
+///# use tps_minicbor::cose::sign_decode;
+///# use tps_minicbor::decoder::CBORDecoder;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // 98([h'', {}, 'hi', [[h'', {}, h'5a']]])
+/// let decoder = CBORDecoder::from_slice(&[
+///     0xd8, 0x62, 0x84, 0x40, 0xa0, 0x42, 0x68, 0x69, 0x81, 0x83, 0x40, 0xa0, 0x41, 0x5a,
+/// ]);
+/// let sign = sign_decode(&decoder)?;
+/// assert_eq!(sign.payload, b"hi");
+/// assert_eq!(sign.len(), 1);
+/// assert_eq!(sign.signatures().next().unwrap().signature, &[0x5a]);
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "combinators")]
+#[cfg_attr(feature = "trace", trace)]
+pub fn sign_decode<'buf>(decoder: &CBORDecoder<'buf>) -> Result<SignParts<'buf>, CBORError> {
+    decoder.decode_parsed(|mut it: DecodeBufIterator<'buf>| {
+        let body = match it.next() {
+            Some(CBOR::Tag(tb)) if tb.get_tag() == COSE_SIGN_TAG => {
+                tb.into_iter().next().ok_or(CBORError::MalformedEncoding)?
+            }
+            Some(_) => return Err(CBORError::ExpectedTag(COSE_SIGN_TAG)),
+            None => return Err(CBORError::EndOfBuffer),
+        };
+        let ab = match body {
+            CBOR::Array(ab) if ab.len() == 4 => ab,
+            _ => return Err(CBORError::MalformedEncoding),
+        };
+        let mut items = ab.into_iter();
+        match (items.next(), items.next(), items.next(), items.next()) {
+            (
+                Some(CBOR::Bstr(protected)),
+                Some(CBOR::Map(unprotected)),
+                Some(CBOR::Bstr(payload)),
+                Some(CBOR::Array(signatures)),
+            ) if !signatures.is_empty() => {
+                for signature in signatures {
+                    let _ = signature_parts(signature)?;
+                }
+                Ok((
+                    it,
+                    SignParts {
+                        protected,
+                        unprotected,
+                        payload,
+                        signatures,
+                    },
+                ))
+            }
+            _ => Err(CBORError::MalformedEncoding),
+        }
+    })
+}
+
+/// Serialize the `Sig_structure` for one signer of a COSE_Sign structure into `out`, returning
+/// the encoded bytes, which are the input to the signature algorithm (RFC9052, Section 4.4):
+/// `["Signature", body_protected, sign_protected, external_aad, payload]`.
+///
+/// Returns `CBORError::EndOfBuffer` if `out` is too small.
+///
+/// ```
+///# use tps_minicbor::cose::sig_structure;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// let mut out = [0u8; 32];
+/// let tbs = sig_structure(&[], &[0xa1, 0x01, 0x26], &[], b"hi", &mut out)?;
+/// assert_eq!(&tbs[..11], b"\x85\x69Signature");
+/// assert_eq!(&tbs[11..], &[0x40, 0x43, 0xa1, 0x01, 0x26, 0x40, 0x42, 0x68, 0x69]);
+///# Ok(())
+///# }
+/// ```
+pub fn sig_structure<'s>(
+    body_protected: &[u8],
+    sign_protected: &[u8],
+    external_aad: &[u8],
+    payload: &[u8],
+    out: &'s mut [u8],
+) -> Result<&'s [u8], CBORError> {
+    let len = {
+        let mut buf = EncodeBuffer::new(out);
+        buf.insert(&array(|buf| {
+            buf.insert(&"Signature")?
+                .insert(&body_protected)?
+                .insert(&sign_protected)?
+                .insert(&external_aad)?
+                .insert(&payload)
+        }))?;
+        buf.encoded()?.len()
+    };
+    Ok(&out[..len])
+}
+
+/***************************************************************************************************
+ * Headers
+ **************************************************************************************************/
+
+/// An empty protected header. This is encoded as an empty `bstr`, `0x40`.
+///
+/// Users should never need to directly instantiate `EmptyProtected`. Instead, see
+/// [`empty_protected`].
+#[derive(Debug, Copy, Clone)]
+pub struct EmptyProtected;
+
+impl EncodeItem for EmptyProtected {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let empty: &[u8] = &[];
+        empty.encode(buf)
+    }
+}
+
+/// Create the protected header for a message with no protected header parameters.
+///
+/// RFC9052, Section 3 requires this to be a zero length `bstr`, `0x40`, and not a `bstr`
+/// wrapping an empty map, `0x41 0xa0`. The two have the same meaning, but they are different
+/// inputs to the `Sig_structure` or `Enc_structure`, so a signature made over one does not
+/// verify against the other.
+///
+/// ```
+///# use tps_minicbor::cose::empty_protected;
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// let mut buffer = [0u8; 4];
+/// let mut encoder = CBORBuilder::new(&mut buffer);
+/// encoder.insert(&empty_protected())?;
+/// assert_eq!(encoder.encoded()?, &[0x40]);
+///# Ok(())
+///# }
+/// ```
+#[inline]
+pub fn empty_protected() -> EmptyProtected {
+    EmptyProtected
+}
+
+/// Decode a protected header item, `bstr .cbor header_map / bstr .size 0` (RFC9052, Section 3).
+///
+/// Returns both the raw bytes of the `bstr`, which are needed unchanged to construct the
+/// `Sig_structure` or `Enc_structure`, and the header map parsed from them. Both borrow from the
+/// original buffer.
+///
+/// Both forms of an empty protected header are accepted and give an empty map: a zero length
+/// `bstr`, as produced by [`empty_protected`], and a `bstr` wrapping an empty map. Only the map
+/// is normalized; the raw bytes are returned as received, as the signature is made over them.
+///
+/// Returns `CBORError::ExpectedType("bstr")` if `cbor` is not a `bstr`, and
+/// `CBORError::MalformedEncoding` if the `bstr` does not contain exactly one map.
+///
+/// ```
+///# use tps_minicbor::cose::{protected, HEADER_ALG};
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::CBOR;
+///# fn main() -> Result<(), CBORError> {
+/// // << {1: -7} >>
+/// let (raw, mb) = protected(CBOR::Bstr(&[0xa1, 0x01, 0x26]))?;
+/// assert_eq!(raw, &[0xa1, 0x01, 0x26]);
+/// assert_eq!(mb.lookup::<i64, i64>(HEADER_ALG)?, -7);
+///# Ok(())
+///# }
+/// ```
+#[cfg_attr(feature = "trace", trace)]
+pub fn protected<'buf>(cbor: CBOR<'buf>) -> Result<(&'buf [u8], MapBuf<'buf>), CBORError> {
+    let raw = match cbor {
+        CBOR::Bstr(raw) => raw,
+        _ => return Err(CBORError::ExpectedType("bstr")),
+    };
+    if raw.is_empty() {
+        return Ok((raw, MapBuf::new(raw, 0)));
+    }
+    let mut it = SequenceBuffer::new(raw).into_iter();
+    match it.try_next() {
+        Ok(CBOR::Map(mb)) if it.index == raw.len() => Ok((raw, mb)),
+        _ => Err(CBORError::MalformedEncoding),
+    }
+}
+
+/// (private) Extract the integer `alg` value from a serialized protected header map. An empty
+/// protected header has no `alg`.
+fn protected_alg(raw: &[u8]) -> Result<Option<i64>, CBORError> {
+    let (_, mb) = protected(CBOR::Bstr(raw))?;
+    header_alg(mb)
+}
+
+/// (private) Extract the integer `alg` value from a header map.
+fn header_alg(mb: MapBuf) -> Result<Option<i64>, CBORError> {
+    match mb.get_int(HEADER_ALG) {
+        Some(alg) => Ok(Some(i64::try_from(alg)?)),
+        None => Ok(None),
+    }
+}
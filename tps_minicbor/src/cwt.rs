@@ -0,0 +1,98 @@
+/***************************************************************************************************
+ * Copyright (c) 2023 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * rs_minicbor CWT structure support
+ *
+ * Helpers for the CWT tag (RFC8392, Section 6), which may wrap the COSE message carrying a CBOR
+ * Web Token. The COSE message itself is built and parsed using the `cose` module.
+ **************************************************************************************************/
+#[cfg(feature = "combinators")]
+use crate::decode_combinators::CBORDecoder;
+use crate::encode::CBORBuilder;
+use crate::error::CBORError;
+use crate::tag::TagBuf;
+
+#[cfg(feature = "trace")]
+use func_trace::trace;
+
+#[cfg(feature = "trace")]
+func_trace::init_depth_var!();
+
+/// CBOR tag for a CWT (RFC8392, Section 6). The tag is optional: a CWT may also be an untagged
+/// COSE message.
+pub const CWT_TAG: u64 = 61;
+
+/// Write `61(cose)` into `out`, returning the number of bytes written.
+///
+/// `cose` is an encoded COSE message, such as a tagged COSE_Sign1 structure. It is copied
+/// unchanged, and it is the responsibility of the caller to ensure that it is valid CBOR.
+///
+/// Returns `CBORError::EndOfBuffer` if `out` is too small.
+///
+/// ```
+///# use tps_minicbor::cwt::wrap;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // 18([h'', {}, h'', h''])
+/// let cose = [0xd2, 0x84, 0x40, 0xa0, 0x40, 0x40];
+/// let mut out = [0u8; 16];
+/// let n = wrap(&cose, &mut out)?;
+/// assert_eq!(&out[..n], &[0xd8, 0x3d, 0xd2, 0x84, 0x40, 0xa0, 0x40, 0x40]);
+///# Ok(())
+///# }
+/// ```
+#[cfg_attr(feature = "trace", trace)]
+pub fn wrap(cose: &[u8], out: &mut [u8]) -> Result<usize, CBORError> {
+    let mut encoder = CBORBuilder::new(out);
+    let _ = encoder.insert(&TagBuf::new(cose, CWT_TAG))?;
+    Ok(encoder.encoded()?.len())
+}
+
+/// Skip the CWT tag, 61, if the next item in `decoder` has it, leaving the decoder positioned at
+/// the COSE message. If the next item does not have the tag, the decoder is left unchanged, so
+/// that tagged and untagged CWTs can be parsed in the same way.
+///
+/// If the next item starts with tag 61 but cannot be decoded, the decode error is returned.
+///
+/// This function requires the `combinators` feature, which is enabled by default, as it uses
+/// the decode combinators.
+///
+/// ```
+///# use tps_minicbor::cose::sign1_decode;
+///# use tps_minicbor::cwt::unwrap;
+///# use tps_minicbor::decoder::CBORDecoder;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // 61(18([h'', {}, 'hi', h'']))
+/// let decoder = CBORDecoder::from_slice(&[
+///     0xd8, 0x3d, 0xd2, 0x84, 0x40, 0xa0, 0x42, 0x68, 0x69, 0x40,
+/// ]);
+/// let sign1 = sign1_decode(unwrap(&decoder)?)?;
+/// assert_eq!(sign1.payload, b"hi");
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "combinators")]
+#[cfg_attr(feature = "trace", trace)]
+pub fn unwrap<'d, 'buf>(
+    decoder: &'d CBORDecoder<'buf>,
+) -> Result<&'d CBORDecoder<'buf>, CBORError> {
+    decoder.skip_tag(CWT_TAG, &[0xd8, 0x3d])
+}
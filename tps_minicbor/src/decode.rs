@@ -59,7 +59,7 @@ use crate::map::MapBuf;
 use crate::tag::TagBuf;
 use crate::utils::within;
 
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::mem::size_of;
 use std::str::from_utf8;
 
@@ -86,19 +86,21 @@ pub enum AnyUnsigned {
 }
 
 impl<'buf> AnyUnsigned {
-    /// Convert `AnyUnsigned` into a `usize`. ALways succeeds.
+    /// Convert `AnyUnsigned` into a `usize`, for use as a length or item count. Returns
+    /// `CBORError::LengthOverflow` if the value does not fit in a `usize`, e.g. a 64 bit length
+    /// on a 32 bit target.
     #[cfg_attr(feature = "trace", trace)]
-    fn as_usize(self) -> usize {
+    fn try_into_usize(self) -> Result<usize> {
         match self {
-            Self::U8(v) => v as usize,
-            Self::U16(v) => v as usize,
-            Self::U32(v) => v as usize,
-            Self::U64(v) => v as usize,
+            Self::U8(v) => Ok(v as usize),
+            Self::U16(v) => Ok(usize::from(v)),
+            Self::U32(v) => usize::try_from(v).map_err(|_| CBORError::LengthOverflow),
+            Self::U64(v) => usize::try_from(v).map_err(|_| CBORError::LengthOverflow),
         }
     }
     /// Convert `AnyUnsigned` into a `u64`. ALways succeeds.
     #[cfg_attr(feature = "trace", trace)]
-    fn as_u64(self) -> u64 {
+    pub(crate) fn as_u64(self) -> u64 {
         match self {
             Self::U8(v) => v as u64,
             Self::U16(v) => v as u64,
@@ -108,7 +110,9 @@ impl<'buf> AnyUnsigned {
     }
     /// Convert `AnyUnsigned` into a `CBOR::Simple` value. We follow the rules in [RFC8949] for
     /// Simple values: 20..23 have particular meanings; 24..31 are illegal; values must be encoded
-    /// on 8 bits (the larger values are encodings for floats).
+    /// on 8 bits (the larger values are encodings for floats). Values 20..23 are always
+    /// normalized to `CBOR::False`, `CBOR::True`, `CBOR::Null` and `CBOR::Undefined`, and are
+    /// never returned as `CBOR::Simple`.
     #[cfg_attr(feature = "trace", trace)]
     fn try_into_simple(self) -> Result<CBOR<'buf>> {
         match self {
@@ -157,6 +161,106 @@ impl<'buf> SequenceBuffer<'buf> {
     }
 }
 
+/// A reader for an append-only CBOR log, where each record is a single CBOR item and records
+/// follow each other without delimiters, as in an RFC8742 CBOR sequence. A record stored with a
+/// length prefix as a `bstr` is returned as a `CBOR::Bstr`.
+///
+/// The log may end with a partial record, e.g. when records accumulate in a ring buffer. Iteration
+/// stops before a partial record, which is never returned. [`LogReader::consumed`] gives the
+/// number of bytes holding complete records, so that reading can resume from that point once more
+/// data has arrived.
+///
+/// Records are found using only the heads of the items they contain, then decoded, so a
+/// malformed record is returned as an error, after which iteration stops. Indefinite length
+/// arrays and maps are not supported.
+///
+/// ```
+///# use tps_minicbor::decoder::LogReader;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::CBOR;
+///# fn main() -> Result<(), CBORError> {
+/// // 1, "ab", then a partial [1, 2]
+/// let log = [0x01, 0x62, 0x61, 0x62, 0x82, 0x01];
+/// let mut reader = LogReader::new(&log);
+/// assert_eq!(reader.next().transpose()?, Some(CBOR::UInt(1)));
+/// assert_eq!(reader.next().transpose()?, Some(CBOR::Tstr("ab")));
+/// assert_eq!(reader.next().transpose()?, None);
+/// assert!(reader.is_truncated());
+/// assert_eq!(reader.consumed(), 4);
+/// assert_eq!(reader.remaining(), &[0x82, 0x01]);
+///# Ok(())
+///# }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct LogReader<'buf> {
+    bytes: &'buf [u8],
+    consumed: usize,
+    failed: bool,
+}
+
+impl<'buf> LogReader<'buf> {
+    /// Construct a new `LogReader` over the records in `bytes`.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn new(bytes: &'buf [u8]) -> LogReader<'buf> {
+        LogReader {
+            bytes,
+            consumed: 0,
+            failed: false,
+        }
+    }
+
+    /// Return the number of bytes holding the records returned so far.
+    #[inline]
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Return the bytes following the records returned so far. Once iteration has finished, this
+    /// is the partial record, if any, at the end of the log.
+    #[inline]
+    pub fn remaining(&self) -> &'buf [u8] {
+        &self.bytes[self.consumed..]
+    }
+
+    /// Return `true` if the bytes following the records returned so far are the start of a record
+    /// which is not yet complete.
+    pub fn is_truncated(&self) -> bool {
+        matches!(
+            item_end(self.bytes, self.consumed),
+            Err(CBORError::EndOfBuffer)
+        ) && self.consumed < self.bytes.len()
+    }
+}
+
+impl<'buf> Iterator for LogReader<'buf> {
+    type Item = Result<CBOR<'buf>>;
+
+    /// Return the next complete record, or `None` at the end of the log or at a partial record.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.consumed >= self.bytes.len() {
+            return None;
+        }
+        let record = match item_end(self.bytes, self.consumed) {
+            Ok(end) => &self.bytes[self.consumed..end],
+            Err(CBORError::EndOfBuffer) => return None,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+        match SequenceBuffer::new(record).into_iter().try_next() {
+            Ok(item) => {
+                self.consumed += record.len();
+                Some(Ok(item))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
 /// A `DecodeBufIterator` can be constructed from any of `SequenceBuffer`, `ArrayBuf`, `MapBuf`
 /// or `TagBuf`. We keep track of which of these was the source of the iterator as it has some
 /// impact on which combinator operations are allowed.
@@ -168,6 +272,67 @@ pub enum DecodeBufIteratorSource {
     Tag,
 }
 
+/// Options which modify the behaviour of the decoder.
+///
+/// The options are carried by each `DecodeBufIterator` so that they are visible to all of the
+/// decode combinators. The default value of each option gives the standard behaviour.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeOptions {
+    /// If `true`, [`is_any`](crate::decoder::is_any) transparently unwraps tagged items, returning
+    /// the enclosed item rather than `CBOR::Tag`. The tag value is recorded in
+    /// [`DecodeBufIterator::last_tag`].
+    pub unwrap_unknown_tags: bool,
+    /// If `true`, the decoder enforces a stricter conformance profile, as is typical of
+    /// canonical and security-sensitive contexts such as COSE and CWT. Currently this means that
+    /// map keys which are tagged items, at any depth, are rejected with `CBORError::NotAllowed`,
+    /// that well-known tags whose content has the wrong type are rejected with
+    /// `CBORError::TagContentMismatch`, and that floats and simple values which do not use their
+    /// shortest encoding are rejected with `CBORError::NonCanonical`.
+    pub strict: bool,
+    /// If `true`, indefinite length strings, arrays and maps are rejected with
+    /// `CBORError::IndefiniteNotAllowed`, as required by profiles which mandate definite length
+    /// encoding for determinism.
+    pub require_definite: bool,
+    /// If `true`, NaN and infinite floats are rejected with `CBORError::NotAllowed`, as required
+    /// by profiles in which only finite numeric values are meaningful.
+    pub reject_non_finite_floats: bool,
+}
+
+/// Limits and conformance checks applied when validating a complete buffer with
+/// [`CBORDecoder::validate`](crate::decoder::CBORDecoder::validate).
+///
+/// The default limits allow a nesting depth of 16 and place no limit on item counts or lengths.
+/// No strict or canonical checks are applied by default.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeLimits {
+    /// The maximum nesting depth of arrays, maps and tags. A top-level array has a depth of 1.
+    pub max_depth: usize,
+    /// The maximum number of items in an array, or of entries in a map.
+    pub max_items: usize,
+    /// The maximum length, in bytes, of a `bstr` or `tstr`.
+    pub max_string_len: usize,
+    /// If `true`, the checks of the strict decoding profile (see [`DecodeOptions::strict`]) are
+    /// applied.
+    pub strict: bool,
+    /// If `true`, the encoding must meet the core deterministic encoding requirements (RFC8949,
+    /// Section 4.2.1): integers and lengths use the shortest encoding, lengths are definite, and
+    /// map keys are unique and sorted in the bytewise lexicographic order of their encodings.
+    /// Floats must use the shortest width which preserves their value (Section 4.2.2).
+    pub canonical: bool,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_depth: 16,
+            max_items: usize::MAX,
+            max_string_len: usize::MAX,
+            strict: false,
+            canonical: false,
+        }
+    }
+}
+
 /// `DecodeBuffer` Iterator adapter to keep track of current position in `DecodeBuf`.
 #[derive(Debug, Clone, Copy)]
 pub struct DecodeBufIterator<'buf> {
@@ -177,6 +342,10 @@ pub struct DecodeBufIterator<'buf> {
     pub index: usize,
     /// The source of this `DecodeBufIterator instance.
     pub source: DecodeBufIteratorSource,
+    /// The decoding options in force for this iterator.
+    pub options: DecodeOptions,
+    /// The value of the last tag unwrapped because `options.unwrap_unknown_tags` is set.
+    pub last_tag: Option<u64>,
 }
 
 impl<'buf> IntoIterator for SequenceBuffer<'buf> {
@@ -190,17 +359,66 @@ impl<'buf> IntoIterator for SequenceBuffer<'buf> {
             buf: self.bytes,
             index: 0,
             source: Sequence,
+            options: DecodeOptions::default(),
+            last_tag: None,
         }
     }
 }
 
 impl<'buf> DecodeBufIterator<'buf> {
+    /// Parse the next CBOR item. This is the same as [`Iterator::next`], except that the reason
+    /// for a failure is returned: `CBORError::EndOfBuffer` if there are no more items, or the
+    /// error which prevented the next item from being decoded. The iterator is not advanced on
+    /// failure.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn try_next(&mut self) -> Result<CBOR<'buf>> {
+        if self.index < self.buf.len() {
+            self.item()
+        } else {
+            Err(CBORError::EndOfBuffer)
+        }
+    }
+
+    /// (crate) The error to report when [`Iterator::next`] returns `None`: either
+    /// `CBORError::IndefiniteNotAllowed` if the next item was rejected by the `require_definite`
+    /// option, `CBORError::NotAllowed` if it was rejected by the `reject_non_finite_floats`
+    /// option, or `CBORError::EndOfBuffer`.
+    #[cfg(feature = "combinators")]
+    pub(crate) fn no_item_error(&self) -> CBORError {
+        if self.options.require_definite && is_indefinite(self.buf, self.index) {
+            CBORError::IndefiniteNotAllowed
+        } else if (self.options.reject_non_finite_floats
+            && is_non_finite_float(self.buf, self.index))
+            || (self.options.strict
+                && matches!(check_strict_keys(self.buf, self.index), Err(CBORError::NotAllowed)))
+        {
+            CBORError::NotAllowed
+        } else {
+            CBORError::EndOfBuffer
+        }
+    }
+
     /// Parse a single CBOR item from DecodeBufIterator. On exit, `self.index` will point at the
     /// start of the next item (if there is one)
     #[cfg_attr(feature = "trace", trace)]
     #[inline]
     fn item(&mut self) -> Result<CBOR<'buf>> {
+        if self.options.require_definite && is_indefinite(self.buf, self.index) {
+            return Err(CBORError::IndefiniteNotAllowed);
+        }
+        if self.options.reject_non_finite_floats && is_non_finite_float(self.buf, self.index) {
+            return Err(CBORError::NotAllowed);
+        }
         let (next_index, cbor) = parse_item(self.buf, self.index)?;
+        if self.options.strict {
+            if !is_preferred_simple(self.buf, self.index)? {
+                return Err(CBORError::NonCanonical);
+            }
+            // Nested items are decoded with the default options, so check them all now
+            if matches!(cbor, CBOR::Array(_) | CBOR::Map(_) | CBOR::Tag(_)) {
+                check_strict_keys(self.buf, self.index)?;
+            }
+        }
         self.index = next_index;
         Ok(cbor)
     }
@@ -233,7 +451,7 @@ impl<'buf> Iterator for DecodeBufIterator<'buf> {
 /// previously returned next item index as an error will be returned if it is out of bounds.
 #[cfg(all(feature = "float", feature = "full"))]
 fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
-    if within(buf, start_index, 0) {
+    if within(buf, start_index, 1) {
         let mt_ai_byte = buf[start_index];
         match mt_ai_byte {
             // Positive integers
@@ -245,7 +463,8 @@ fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
             // Byte Strings
             0x40..=0x5b => parse_bytestring(buf, start_index)
                 .map(|(next_idx, bytes)| (next_idx, CBOR::Bstr(bytes))),
-            // TODO: 0x5f - indefinite length byte string
+            // Indefinite length byte string
+            0x5f => parse_indefinite_string(buf, start_index),
             // UTF8 strings
             0x60..=0x7b => {
                 let (next_index, raw_bytes) = parse_bytestring(buf, start_index)?;
@@ -254,7 +473,8 @@ fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
                     Err(_) => Err(CBORError::UTF8Error),
                 }
             }
-            // TODO: 0x7f - indefinite length string
+            // Indefinite length UTF8 string
+            0x7f => parse_indefinite_string(buf, start_index),
             // Arrays
             0x80..=0x9b => parse_array(buf, start_index),
             // TODO: 0x9f - indefinite length array
@@ -280,6 +500,10 @@ fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
                 let (next_index, val) = parse_f64(buf, start_index)?;
                 Ok((next_index, CBOR::Float64(val)))
             }
+            // Reserved additional information values are not well-formed for any major type
+            _ if AI_RESERVED.contains(&(mt_ai_byte & AI_MASK)) => {
+                Err(CBORError::ReservedAdditionalInfo)
+            }
             _ => Err(CBORError::NotImplemented),
         }
     } else {
@@ -290,7 +514,7 @@ fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
 // Version for no float and no full
 #[cfg(not(feature = "float"))]
 fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
-    if within(buf, start_index, 0) {
+    if within(buf, start_index, 1) {
         let mt_ai_byte = buf[start_index];
         match mt_ai_byte {
             // Positive integers
@@ -302,7 +526,8 @@ fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
             // Byte Strings
             0x40..=0x5b => parse_bytestring(buf, start_index)
                 .map(|(next_idx, bytes)| (next_idx, CBOR::Bstr(bytes))),
-            // TODO: 0x5f - indefinite length byte string
+            // Indefinite length byte string
+            0x5f => parse_indefinite_string(buf, start_index),
             // UTF8 strings
             0x60..=0x7b => {
                 let (next_index, raw_bytes) = parse_bytestring(buf, start_index)?;
@@ -311,7 +536,8 @@ fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
                     Err(_) => Err(CBORError::UTF8Error),
                 }
             }
-            // TODO: 0x7f - indefinite length string
+            // Indefinite length UTF8 string
+            0x7f => parse_indefinite_string(buf, start_index),
             // Arrays
             0x80..=0x9b => parse_array(buf, start_index),
             // TODO: 0x9f - indefinite length array
@@ -325,6 +551,10 @@ fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
                 let (next_index, v) = parse_unsigned(buf, start_index)?;
                 Ok((next_index, v.try_into_simple()?))
             }
+            // Reserved additional information values are not well-formed for any major type
+            _ if AI_RESERVED.contains(&(mt_ai_byte & AI_MASK)) => {
+                Err(CBORError::ReservedAdditionalInfo)
+            }
             _ => Err(CBORError::NotImplemented),
         }
     } else {
@@ -343,7 +573,7 @@ fn parse_item(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
 #[cfg_attr(feature = "trace", trace)]
 pub(crate) fn parse_unsigned(buf: &[u8], start_index: usize) -> Result<(usize, AnyUnsigned)> {
     // We do not care about the value of the MT bits
-    if within(buf, start_index, 0) {
+    if within(buf, start_index, 1) {
         let ai = buf[start_index] & AI_MASK;
         if ai <= PAYLOAD_AI_BITS {
             Ok((start_index + size_of::<u8>(), AnyUnsigned::U8(ai)))
@@ -375,6 +605,8 @@ pub(crate) fn parse_unsigned(buf: &[u8], start_index: usize) -> Result<(usize, A
                 Ok(bytes) => Ok((next_index, AnyUnsigned::U64(u64::from_be_bytes(bytes)))),
                 Err(_) => Err(CBORError::BadSliceLength),
             }
+        } else if AI_RESERVED.contains(&ai) {
+            Err(CBORError::ReservedAdditionalInfo)
         } else {
             Err(CBORError::MalformedEncoding)
         }
@@ -397,7 +629,7 @@ fn parse_f64(buf: &[u8], start_index: usize) -> Result<(usize, f64)> {
     let (next_index, item_slice) = read_extent(buf, start_index + 1, size_of::<f64>())?;
     let result: core::result::Result<[u8; 8], _> = item_slice.try_into();
     match result {
-        Ok(bytes) => Ok((next_index, f64::from_be_bytes(bytes))),
+        Ok(bytes) => Ok((next_index, canonical_nan_f64(f64::from_be_bytes(bytes)))),
         Err(_) => Err(CBORError::BadSliceLength),
     }
 }
@@ -412,7 +644,7 @@ fn parse_f32(buf: &[u8], start_index: usize) -> Result<(usize, f32)> {
     let (next_index, item_slice) = read_extent(buf, start_index + 1, size_of::<f32>())?;
     let result: core::result::Result<[u8; 4], _> = item_slice.try_into();
     match result {
-        Ok(bytes) => Ok((next_index, f32::from_be_bytes(bytes))),
+        Ok(bytes) => Ok((next_index, canonical_nan_f32(f32::from_be_bytes(bytes)))),
         Err(_) => Err(CBORError::BadSliceLength),
     }
 }
@@ -427,11 +659,45 @@ fn parse_f16(buf: &[u8], start_index: usize) -> Result<(usize, f16)> {
     let (next_index, item_slice) = read_extent(buf, start_index + 1, size_of::<f16>())?;
     let result: core::result::Result<[u8; 2], _> = item_slice.try_into();
     match result {
-        Ok(bytes) => Ok((next_index, f16::from_be_bytes(bytes))),
+        Ok(bytes) => Ok((next_index, canonical_nan_f16(f16::from_be_bytes(bytes)))),
         Err(_) => Err(CBORError::BadSliceLength),
     }
 }
 
+/// Any NaN bit pattern is accepted on decode, but is normalized to the canonical quiet NaN so
+/// that all decoded NaN values are bitwise identical.
+#[cfg(feature = "float")]
+#[inline]
+fn canonical_nan_f64(v: f64) -> f64 {
+    if v.is_nan() {
+        f64::NAN
+    } else {
+        v
+    }
+}
+
+/// Normalize any 32 bit NaN value to the canonical quiet NaN.
+#[cfg(feature = "float")]
+#[inline]
+fn canonical_nan_f32(v: f32) -> f32 {
+    if v.is_nan() {
+        f32::NAN
+    } else {
+        v
+    }
+}
+
+/// Normalize any 16 bit NaN value to the canonical quiet NaN.
+#[cfg(feature = "float")]
+#[inline]
+fn canonical_nan_f16(v: f16) -> f16 {
+    if v.is_nan() {
+        f16::NAN
+    } else {
+        v
+    }
+}
+
 /***************************************************************************************************
  * Bytestring, Arrays, Maps and String Helpers
  **************************************************************************************************/
@@ -441,11 +707,259 @@ fn parse_f16(buf: &[u8], start_index: usize) -> Result<(usize, f16)> {
 #[cfg_attr(feature = "trace", trace)]
 pub(crate) fn parse_bytestring(buf: &[u8], start_index: usize) -> Result<(usize, &[u8])> {
     let (start_bstr_index, value) = parse_unsigned(buf, start_index)?;
-    let length = value.as_usize();
+    let length = value.try_into_usize()?;
     let (next_item_index, bytes) = read_extent(buf, start_bstr_index, length)?;
     Ok((next_item_index, bytes))
 }
 
+/// Return `true` if `bytes` holds exactly one well-formed CBOR item, nested no deeper than the
+/// default [`DecodeLimits`]. Text strings must be valid UTF-8. Indefinite length items and
+/// floating point items are accepted whether or not the `full` and `float` features are enabled.
+/// This check does not need the `combinators` feature.
+///
+/// This is a cheap pre-check for untrusted input. Use
+/// [`CBORDecoder::validate`](crate::decoder::CBORDecoder::validate) to apply other limits or to
+/// find out why an item was rejected.
+///
+/// # Example
+///
+/// ```
+/// use tps_minicbor::decoder::is_well_formed;
+///
+/// // [1, "a"]
+/// assert!(is_well_formed(&[0x82, 0x01, 0x61, 0x61]));
+/// // [1, "a"] with a truncated text string
+/// assert!(!is_well_formed(&[0x82, 0x01, 0x61]));
+/// // 1, 2
+/// assert!(!is_well_formed(&[0x01, 0x02]));
+/// ```
+#[cfg_attr(feature = "trace", trace)]
+pub fn is_well_formed(bytes: &[u8]) -> bool {
+    matches!(
+        validate_item(bytes, 0, 0, &DecodeLimits::default()),
+        Ok(next_index) if next_index == bytes.len()
+    )
+}
+
+/// (crate) Check that the item starting at `index` in `buf`, and everything nested within it,
+/// meets `limits`. `depth` is the number of arrays, maps and tags enclosing the item. Returns the
+/// index of the next item.
+///
+/// Arrays, maps and tags are walked here rather than with [`parse_item`], so that the nesting
+/// depth is checked before recursing into the contents. Indefinite length items are walked up to
+/// their break byte, and floats are only checked for length, so neither needs the `full` or
+/// `float` features.
+pub(crate) fn validate_item(
+    buf: &[u8],
+    index: usize,
+    depth: usize,
+    limits: &DecodeLimits,
+) -> Result<usize> {
+    let mt_ai_byte = *buf.get(index).ok_or(CBORError::EndOfBuffer)?;
+    let mt = mt_ai_byte & !AI_MASK;
+    let indefinite = is_indefinite(buf, index);
+    if indefinite && limits.canonical {
+        return Err(CBORError::IndefiniteNotAllowed);
+    }
+    if limits.canonical && mt != MT_SIMPLE && !is_preferred_head(buf, index)? {
+        return Err(CBORError::NonCanonical);
+    }
+    if (limits.strict || limits.canonical) && !is_preferred_simple(buf, index)? {
+        return Err(CBORError::NonCanonical);
+    }
+    match mt {
+        MT_ARRAY | MT_MAP => {
+            let (mut next_index, n_items) = if indefinite {
+                (index + 1, None)
+            } else {
+                let (next_index, value) = parse_unsigned(buf, index)?;
+                (next_index, Some(value.try_into_usize()?))
+            };
+            if depth >= limits.max_depth || matches!(n_items, Some(n) if n > limits.max_items) {
+                return Err(CBORError::LimitExceeded);
+            }
+            let mut count = 0;
+            let mut last_key: Option<&[u8]> = None;
+            loop {
+                match n_items {
+                    Some(n) if count == n => break,
+                    None if *buf.get(next_index).ok_or(CBORError::EndOfBuffer)? == 0xff => {
+                        next_index += 1;
+                        break;
+                    }
+                    None if count == limits.max_items => return Err(CBORError::LimitExceeded),
+                    _ => count += 1,
+                }
+                let item_index = next_index;
+                next_index = validate_item(buf, item_index, depth + 1, limits)?;
+                if mt == MT_MAP {
+                    let key = &buf[item_index..next_index];
+                    if limits.strict && key[0] & !AI_MASK == MT_TAG {
+                        return Err(CBORError::NotAllowed);
+                    }
+                    if limits.canonical {
+                        if matches!(last_key, Some(last) if key <= last) {
+                            return Err(CBORError::NonCanonical);
+                        }
+                        last_key = Some(key);
+                    }
+                    next_index = validate_item(buf, next_index, depth + 1, limits)?;
+                }
+            }
+            Ok(next_index)
+        }
+        MT_TAG => {
+            let (content_index, tag) = parse_unsigned(buf, index)?;
+            if depth >= limits.max_depth {
+                return Err(CBORError::LimitExceeded);
+            }
+            let next_index = validate_item(buf, content_index, depth + 1, limits)?;
+            if limits.strict {
+                TagBuf::new(&buf[content_index..next_index], tag.as_u64())
+                    .check_strict_content()?;
+            }
+            Ok(next_index)
+        }
+        MT_BSTR | MT_TSTR if indefinite => {
+            let mut next_index = index + 1;
+            let mut len = 0usize;
+            loop {
+                match buf.get(next_index) {
+                    Some(&0xff) => break,
+                    Some(&chunk_mt_ai)
+                        if chunk_mt_ai & !AI_MASK == mt && chunk_mt_ai & AI_MASK < 28 =>
+                    {
+                        let (chunk_end, chunk) = parse_bytestring(buf, next_index)?;
+                        if mt == MT_TSTR && from_utf8(chunk).is_err() {
+                            return Err(CBORError::UTF8Error);
+                        }
+                        len = len.saturating_add(chunk.len());
+                        if len > limits.max_string_len {
+                            return Err(CBORError::LimitExceeded);
+                        }
+                        next_index = chunk_end;
+                    }
+                    Some(_) => return Err(CBORError::MalformedEncoding),
+                    None => return Err(CBORError::EndOfBuffer),
+                }
+            }
+            Ok(next_index + 1)
+        }
+        _ => {
+            let next_index = match mt_ai_byte {
+                0xf9 => read_extent(buf, index + 1, 2)?.0,
+                0xfa => read_extent(buf, index + 1, 4)?.0,
+                0xfb => read_extent(buf, index + 1, 8)?.0,
+                // A break byte is only allowed where an indefinite length item ends
+                0xff => return Err(CBORError::MalformedEncoding),
+                _ => {
+                    let (next_index, cbor) = parse_item(buf, index)?;
+                    let len = match cbor {
+                        CBOR::Bstr(bytes) => bytes.len(),
+                        CBOR::Tstr(text) => text.len(),
+                        _ => 0,
+                    };
+                    if len > limits.max_string_len {
+                        return Err(CBORError::LimitExceeded);
+                    }
+                    next_index
+                }
+            };
+            Ok(next_index)
+        }
+    }
+}
+
+/// (private) Return `true` if the integer or length in the head of the item at `index` in `buf`
+/// uses the shortest possible encoding.
+fn is_preferred_head(buf: &[u8], index: usize) -> Result<bool> {
+    let (_, value) = parse_unsigned(buf, index)?;
+    let preferred_ai = match value.as_u64() {
+        v @ 0..=23 => v as u8,
+        24..=0xff => PAYLOAD_ONE_BYTE,
+        0x100..=0xffff => PAYLOAD_TWO_BYTES,
+        0x1_0000..=0xffff_ffff => PAYLOAD_FOUR_BYTES,
+        _ => PAYLOAD_EIGHT_BYTES,
+    };
+    Ok(buf[index] & AI_MASK == preferred_ai)
+}
+
+/// (private) Return `false` if the item at `index` in `buf` is a float or simple value which
+/// could have been encoded in fewer bytes without changing its value (RFC8949, Section 4.2.2).
+/// NaN has a 16 bit encoding, so is only preferred as a 16 bit float. Other items return `true`.
+fn is_preferred_simple(buf: &[u8], index: usize) -> Result<bool> {
+    match buf.get(index) {
+        // Simple values 0 to 23 are encoded in the initial byte
+        Some(0xf8) => Ok(*buf.get(index + 1).ok_or(CBORError::EndOfBuffer)? >= 32),
+        #[cfg(feature = "float")]
+        Some(0xfa) => {
+            let (_, bytes) = read_extent(buf, index + 1, size_of::<f32>())?;
+            let v = f32::from_be_bytes(bytes.try_into().map_err(|_| CBORError::BadSliceLength)?);
+            Ok(!v.is_nan() && f16::from_f32(v).to_f32().to_bits() != v.to_bits())
+        }
+        #[cfg(feature = "float")]
+        Some(0xfb) => {
+            // Any value which fits in 16 bits also fits in 32 bits
+            let (_, bytes) = read_extent(buf, index + 1, size_of::<f64>())?;
+            let v = f64::from_be_bytes(bytes.try_into().map_err(|_| CBORError::BadSliceLength)?);
+            Ok(!v.is_nan() && ((v as f32) as f64).to_bits() != v.to_bits())
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Return `true` if the item at `index` in `buf` is an indefinite length string, array or map.
+#[inline]
+fn is_indefinite(buf: &[u8], index: usize) -> bool {
+    matches!(buf.get(index), Some(0x5f | 0x7f | 0x9f | 0xbf))
+}
+
+/// Return `true` if the item at `index` in `buf` is a NaN or infinite float of any size, i.e. a
+/// float whose exponent bits are all set.
+#[inline]
+fn is_non_finite_float(buf: &[u8], index: usize) -> bool {
+    let (len, exponent_mask) = match buf.get(index) {
+        Some(0xf9) => (2, 0x7c00u64),
+        Some(0xfa) => (4, 0x7f80_0000),
+        Some(0xfb) => (8, 0x7ff0_0000_0000_0000),
+        _ => return false,
+    };
+    match buf.get(index + 1..index + 1 + len) {
+        Some(bytes) => {
+            let bits = bytes.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b));
+            bits & exponent_mask == exponent_mask
+        }
+        None => false,
+    }
+}
+
+/// Parse an indefinite length `bstr` or `tstr`, which is a sequence of definite length chunks of
+/// the same major type, terminated by a "break" byte (RFC8949, Section 3.2.3). Chunks which are
+/// themselves of indefinite length, or which are of a different major type, are malformed.
+///
+/// A well-formed indefinite length string cannot be represented as a single slice over the
+/// buffer, so `CBORError::NotImplemented` is returned once the chunks have been validated.
+#[cfg_attr(feature = "trace", trace)]
+fn parse_indefinite_string(buf: &[u8], start_index: usize) -> Result<(usize, CBOR<'_>)> {
+    let mt = buf[start_index] & !AI_MASK;
+    let mut index = start_index + 1;
+    loop {
+        match buf.get(index) {
+            Some(&0xff) => break,
+            Some(&chunk_mt_ai) if chunk_mt_ai & !AI_MASK == mt && chunk_mt_ai & AI_MASK < 28 => {
+                let (next_index, chunk) = parse_bytestring(buf, index)?;
+                if mt == MT_TSTR && from_utf8(chunk).is_err() {
+                    return Err(CBORError::UTF8Error);
+                }
+                index = next_index;
+            }
+            Some(_) => return Err(CBORError::MalformedEncoding),
+            None => return Err(CBORError::EndOfBuffer),
+        }
+    }
+    Err(CBORError::NotImplemented)
+}
+
 /// Parse an array. An array of length N is simply a sequence of N CBOR Items, some of which
 /// could themselves be arrays or maps.
 ///
@@ -455,7 +969,7 @@ pub(crate) fn parse_bytestring(buf: &[u8], start_index: usize) -> Result<(usize,
 #[cfg_attr(feature = "trace", trace)]
 fn parse_array(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
     let (array_start_index, u_value) = parse_unsigned(buf, start_index)?;
-    let n_items = u_value.as_usize();
+    let n_items = u_value.try_into_usize()?;
     let next_index = skip_items(buf, array_start_index, n_items)?;
 
     // No need to check that length + index is legal - already checked in skip_item
@@ -474,8 +988,9 @@ fn parse_array(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
 #[cfg_attr(feature = "trace", trace)]
 fn parse_map(buf: &[u8], start_index: usize) -> Result<(usize, CBOR)> {
     let (array_start_index, value) = parse_unsigned(buf, start_index)?;
-    let n_pairs = value.as_usize();
-    let n_items = n_pairs * 2; // We read pairs of Items
+    let n_pairs = value.try_into_usize()?;
+    // We read pairs of Items
+    let n_items = n_pairs.checked_mul(2).ok_or(CBORError::LengthOverflow)?;
     let next_index = skip_items(buf, array_start_index, n_items)?;
 
     // No need to check that length + index is legal - already checked in skip_item
@@ -531,6 +1046,84 @@ fn skip_items(buf: &[u8], start_index: usize, n_items: usize) -> Result<usize> {
     }
 }
 
+/// (crate) Return the index of the end of the item starting at `index` in `buf`. Arrays, maps and
+/// tags are walked using only the heads of the items within them.
+///
+/// Unlike [`parse_item`], no `CBOR` is constructed and the contents of strings are not checked,
+/// so this finds the span of an item cheaply, e.g. before copying it unchanged. Indefinite
+/// length strings are supported, but indefinite length arrays and maps are not.
+#[cfg_attr(feature = "trace", trace)]
+pub(crate) fn item_end(buf: &[u8], index: usize) -> Result<usize> {
+    let mt_ai_byte = *buf.get(index).ok_or(CBORError::EndOfBuffer)?;
+    let mt = mt_ai_byte & !AI_MASK;
+    match mt_ai_byte {
+        0x5f | 0x7f => {
+            let mut next_index = index + 1;
+            loop {
+                match buf.get(next_index) {
+                    Some(&0xff) => return Ok(next_index + 1),
+                    Some(&chunk) if chunk & !AI_MASK == mt && chunk & AI_MASK < 28 => {
+                        next_index = parse_bytestring(buf, next_index)?.0;
+                    }
+                    Some(_) => return Err(CBORError::MalformedEncoding),
+                    None => return Err(CBORError::EndOfBuffer),
+                }
+            }
+        }
+        0x9f | 0xbf => Err(CBORError::NotImplemented),
+        _ => {
+            let (mut next_index, value) = parse_unsigned(buf, index)?;
+            match mt {
+                MT_BSTR | MT_TSTR => Ok(read_extent(buf, next_index, value.try_into_usize()?)?.0),
+                MT_ARRAY | MT_MAP => {
+                    let n_items = if mt == MT_MAP {
+                        value.try_into_usize()?.saturating_mul(2)
+                    } else {
+                        value.try_into_usize()?
+                    };
+                    // Each item is at least one byte, so this fails quickly if n_items is bogus
+                    for _ in 0..n_items {
+                        next_index = item_end(buf, next_index)?;
+                    }
+                    Ok(next_index)
+                }
+                MT_TAG => item_end(buf, next_index),
+                _ => Ok(next_index),
+            }
+        }
+    }
+}
+
+/// (private) Check the item starting at `index` in `buf` against the strict decoding profile,
+/// returning the index of the next item. Map keys which are tagged items are rejected with
+/// `CBORError::NotAllowed`, in the item itself and in every array, map and tag nested within it.
+///
+/// The item has already been parsed, so it is well-formed and contains no indefinite length
+/// arrays or maps.
+#[cfg_attr(feature = "trace", trace)]
+fn check_strict_keys(buf: &[u8], index: usize) -> Result<usize> {
+    let mt = *buf.get(index).ok_or(CBORError::EndOfBuffer)? & !AI_MASK;
+    match mt {
+        MT_ARRAY | MT_MAP | MT_TAG if !is_indefinite(buf, index) => {
+            let (mut next_index, value) = parse_unsigned(buf, index)?;
+            let n_items = match mt {
+                MT_ARRAY => value.try_into_usize()?,
+                MT_MAP => value.try_into_usize()?.saturating_mul(2),
+                _ => 1,
+            };
+            for i in 0..n_items {
+                let is_key = mt == MT_MAP && i % 2 == 0;
+                if is_key && buf.get(next_index).map(|b| b & !AI_MASK) == Some(MT_TAG) {
+                    return Err(CBORError::NotAllowed);
+                }
+                next_index = check_strict_keys(buf, next_index)?;
+            }
+            Ok(next_index)
+        }
+        _ => item_end(buf, index),
+    }
+}
+
 /// Return the index of the next item to parse and a slice over the item within `buf`.
 #[cfg_attr(feature = "trace", trace)]
 fn read_extent(buf: &[u8], start: usize, length: usize) -> Result<(usize, &[u8])> {
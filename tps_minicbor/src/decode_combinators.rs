@@ -25,7 +25,8 @@
  * nor an allocator.
  *
  * The decode combinators do make use of dynamic dispatch and have some memory penalty in return
- * for a more comfortable API. They can be disabled using the `embedded` build feature.
+ * for a more comfortable API. They are built with the `combinators` feature, which is enabled by
+ * default.
  **************************************************************************************************/
 /// # CBOR Decode Combinators
 ///
@@ -130,25 +131,39 @@
 /// ```
 
 use crate::array::ArrayBuf;
-use crate::ast::CBOR;
-use crate::decode::{DecodeBufIterator, SequenceBuffer};
+use crate::ast::{CBORType, CBOR};
+use crate::decode::{item_end, validate_item, DecodeBufIterator, DecodeLimits, SequenceBuffer};
 use crate::error::CBORError;
 use crate::map::MapBuf;
-use crate::tag::TagBuf;
+use crate::tag::{TagBuf, SELF_DESCRIBE_TAG};
 use core::convert::TryFrom;
+use core::hint::black_box;
 
 use std::cell::{Ref, RefCell};
 use std::convert::From;
+use std::fmt;
+
+#[cfg(feature = "trace")]
+use func_trace::trace;
+
+#[cfg(feature = "trace")]
+func_trace::init_depth_var!();
 
 /// Alias for the Result type for all CBOR decode combinators.
 type DCResult<'buf> = core::result::Result<(DecodeBufIterator<'buf>, CBOR<'buf>), CBORError>;
 /// Alias for the Result type where the output type, `O`, is generic.
 type DCPResult<'buf, O> = core::result::Result<(DecodeBufIterator<'buf>, O), CBORError>;
+/// Alias for an entry in the handler table of [`CBORDecoder::dispatch_by_type`].
+type TypeHandler<'h, 'buf> = (CBORType, &'h dyn Fn(CBOR<'buf>) -> Result<(), CBORError>);
 
 /***************************************************************************************************
  * Top Level Decoder API
  **************************************************************************************************/
 
+/// The maximum length of the slices passed to the callback of
+/// [`CBORDecoder::decode_bstr_streaming`].
+const BSTR_STREAM_CHUNK_LEN: usize = 64;
+
 /// CBORDecoder provides a smart wrapper over a byte slice, keeping information on the current
 /// state of CBOR decoding.
 ///
@@ -158,6 +173,35 @@ type DCPResult<'buf, O> = core::result::Result<(DecodeBufIterator<'buf>, O), CBO
 /// You can build instances of `CBORDecoder` from byte slices, tagged items, arrays and maps
 pub struct CBORDecoder<'buf> {
     decode_buf_iter: RefCell<DecodeBufIterator<'buf>>,
+    on_tag: Option<&'buf dyn Fn(u64)>,
+}
+
+/// The `Debug` instance for `CBORDecoder` shows the current decoding position, which is mainly
+/// useful when tracing a combinator chain with the `trace` feature.
+impl<'buf> fmt::Debug for CBORDecoder<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CBORDecoder")
+            .field("decode_buf_iter", &self.decode_buf_iter)
+            .field("on_tag", &self.on_tag.is_some())
+            .finish()
+    }
+}
+
+/// (private) Call `callback` with the value of each tag in `item`, in the order in which they are
+/// encoded. This includes tags within arrays, maps and tag contents, at any depth.
+fn report_tags(item: CBOR, callback: &dyn Fn(u64)) {
+    let nested = match item {
+        CBOR::Tag(tb) => {
+            callback(tb.get_tag());
+            tb.into_iter()
+        }
+        CBOR::Array(ab) => ab.into_iter(),
+        CBOR::Map(mb) => mb.into_iter(),
+        _ => return,
+    };
+    for item in nested {
+        report_tags(item, callback);
+    }
 }
 
 impl<'buf> CBORDecoder<'buf> {
@@ -166,6 +210,7 @@ impl<'buf> CBORDecoder<'buf> {
     pub fn new(b: SequenceBuffer<'buf>) -> Self {
         Self {
             decode_buf_iter: RefCell::new(b.into_iter()),
+            on_tag: None,
         }
     }
 
@@ -183,17 +228,101 @@ impl<'buf> CBORDecoder<'buf> {
     pub fn from_slice(b: &'buf [u8]) -> Self {
         Self {
             decode_buf_iter: RefCell::new(SequenceBuffer::new(b).into_iter()),
+            on_tag: None,
         }
     }
 
+    /// Check that `bytes` holds exactly one well-formed CBOR item which meets `limits`, without
+    /// decoding it into any particular structure. This is intended to be run on untrusted input
+    /// before it is processed.
+    ///
+    /// The first error found is returned:
+    ///
+    /// - `CBORError::LimitExceeded` if a nesting depth, item count or length limit is exceeded.
+    /// - `CBORError::EofExpected` if there is data after the item.
+    /// - `CBORError::NonCanonical` or `CBORError::IndefiniteNotAllowed` if
+    ///   `limits.canonical` is set and the encoding is not deterministic.
+    /// - `CBORError::NotAllowed` or `CBORError::TagContentMismatch` if `limits.strict` is set and
+    ///   a strict profile check fails.
+    /// - The decode error, if the item is not well-formed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, DecodeLimits};
+    /// use tps_minicbor::error::CBORError;
+    ///
+    /// let limits = DecodeLimits { max_depth: 1, ..Default::default() };
+    /// // [1, 2]
+    /// assert!(CBORDecoder::validate(&[0x82, 0x01, 0x02], &limits).is_ok());
+    /// // [[1]]
+    /// assert!(matches!(
+    ///     CBORDecoder::validate(&[0x81, 0x81, 0x01], &limits),
+    ///     Err(CBORError::LimitExceeded)
+    /// ));
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn validate(bytes: &[u8], limits: &DecodeLimits) -> Result<(), CBORError> {
+        let next_index = validate_item(bytes, 0, 0, limits)?;
+        if next_index == bytes.len() {
+            Ok(())
+        } else {
+            Err(CBORError::EofExpected)
+        }
+    }
+
+    /// Reset the decoder to decode `new_buf` from its start, as a new sequence of items. The
+    /// options set with [`CBORDecoder::strict`], [`CBORDecoder::require_definite`],
+    /// [`CBORDecoder::unwrap_unknown_tags`] and [`CBORDecoder::reject_non_finite_floats`] and any
+    /// [`CBORDecoder::on_tag`] callback are kept, so that a long-running service can configure a
+    /// decoder once and use it for every message. The last tag seen is cleared.
+    ///
+    /// The decoder borrows each buffer for its whole lifetime `'buf`, not just until the next
+    /// reset, so every buffer passed to `reset` must outlive the decoder. This suits messages
+    /// held in a longer-lived store, such as slices of a buffer holding a stream of messages. It
+    /// does not allow a single receive buffer to be overwritten with each new message while the
+    /// decoder exists: in that case, construct a new decoder for each message, which is cheap as
+    /// a decoder does not allocate.
+    ///
+    /// # Example
+    ///
+    /// ```
+    ///# use tps_minicbor::decoder::CBORDecoder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// // [1, 2], then [_ 1]
+    /// let messages: [&[u8]; 2] = [&[0x82, 0x01, 0x02], &[0x9f, 0x01, 0xff]];
+    /// let mut decoder = CBORDecoder::from_slice(messages[0]).require_definite(true);
+    /// let _ = decoder.array(|ab| {
+    ///     assert_eq!(ab.len(), 2);
+    ///     Ok(())
+    /// })?;
+    /// decoder.reset(messages[1]);
+    /// assert!(matches!(
+    ///     decoder.array(|_| Ok(())),
+    ///     Err(CBORError::IndefiniteNotAllowed)
+    /// ));
+    ///# Ok(())
+    ///# }
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn reset(&mut self, new_buf: &'buf [u8]) {
+        let options = self.decode_buf_iter.get_mut().options;
+        let mut it = SequenceBuffer::new(new_buf).into_iter();
+        it.options = options;
+        *self.decode_buf_iter.get_mut() = it;
+    }
+
     /// Construct an instance of `CBORDecoder` from the CBOR item enclosed within a Tag, allowing
     /// decode within a CBOR Tag using the CBORDecoder API.
+    #[cfg_attr(feature = "trace", trace)]
     #[inline]
     pub fn from_tag(cbor: CBOR<'buf>, tag_value: &mut u64) -> Result<Self, CBORError> {
         if let CBOR::Tag(tb) = cbor {
             *tag_value = tb.get_tag();
             Ok(Self {
                 decode_buf_iter: RefCell::new(tb.into_iter()),
+                on_tag: None,
             })
         } else {
             Err(CBORError::ExpectedType("CBOR Map"))
@@ -209,24 +338,29 @@ impl<'buf> CBORDecoder<'buf> {
     /// # Example
     ///
     /// ```
-    ///  use tps_minicbor::decoder::{CBORDecoder};
+    ///  use std::convert::TryFrom;
+    ///  use tps_minicbor::decoder::{CBORDecoder, is_uint};
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
     ///
-    ///  let _ = CBORDecoder::from_slice(&[0x82, 0x61, 0x61, 0xa1, 0x61, 0x62, 0x61, 0x63])
+    ///  CBORDecoder::from_slice(&[0x82, 0x61, 0x61, 0x82, 0x01, 0x02])
     ///             .array(|ab| {
     ///                 assert_eq!(ab.len(), 2);
-    ///                 let _ = CBORDecoder::from_array(ab.item(2)?)?
-    ///                     .map(|mb| {
-    ///                     assert_eq!(mb.lookup::<&str, &str>("b")?, "c");
-    ///                     Ok(())
-    ///                 });
-    ///                 Ok(())
-    ///             });
+    ///                 CBORDecoder::from_array(ab.item(1)?)?
+    ///                     .decode_with(is_uint(), |cbor| Ok(assert_eq!(u8::try_from(cbor)?, 1)))?
+    ///                     .decode_with(is_uint(), |cbor| Ok(assert_eq!(u8::try_from(cbor)?, 2)))?
+    ///                     .finalize()
+    ///             })?;
+    ///# Ok(())
+    ///# }
     /// ```
+    #[cfg_attr(feature = "trace", trace)]
     #[inline]
     pub fn from_array(cbor: CBOR<'buf>) -> Result<Self, CBORError> {
         if let CBOR::Array(ab) = cbor {
             Ok(Self {
                 decode_buf_iter: RefCell::new(ab.into_iter()),
+                on_tag: None,
             })
         } else {
             Err(CBORError::ExpectedType("CBOR Array"))
@@ -235,30 +369,389 @@ impl<'buf> CBORDecoder<'buf> {
 
     /// Construct an instance of `CBORDecoder` from a CBOR Array, allowing decoding within a CBOR
     /// Map using the CBORDecoder API.
+    #[cfg_attr(feature = "trace", trace)]
     #[inline]
     pub fn from_map(cbor: CBOR<'buf>) -> Result<Self, CBORError> {
         if let CBOR::Map(mb) = cbor {
             Ok(Self {
                 decode_buf_iter: RefCell::new(mb.into_iter()),
+                on_tag: None,
             })
         } else {
             Err(CBORError::ExpectedType("CBOR Map"))
         }
     }
 
+    /// Decode the standard TPS message envelope, which is a tag holding the message ID wrapping
+    /// the message body.
+    ///
+    /// The next item must be a tag whose value is one of `expected_tags`. On success, the message
+    /// ID and a `CBORDecoder` positioned at the message body are returned. The decoder options
+    /// (e.g. [`CBORDecoder::strict`]) also apply to the body decoder.
+    ///
+    /// Returns `CBORError::ExpectedType("tag")` if the next item is not a tag and
+    /// `CBORError::NotAllowed` if the tag is not one of `expected_tags`. The decoder is not
+    /// advanced on failure.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    ///
+    /// // 10({1: "a"})
+    /// let decoder = CBORDecoder::from_slice(&[0xca, 0xa1, 0x01, 0x61, 0x61]);
+    /// let (msg_id, body) = decoder.message(&[1, 10, 11]).unwrap();
+    /// assert_eq!(msg_id, 10);
+    /// let _ = body.map(|mb| {
+    ///     assert_eq!(mb.lookup::<u64, &str>(1)?, "a");
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn message(&self, expected_tags: &[u32]) -> Result<(u32, CBORDecoder<'buf>), CBORError> {
+        let (it, cbor) = is_tag()(self.snapshot())?;
+        let tb = match cbor {
+            CBOR::Tag(tb) => tb,
+            _ => return Err(CBORError::ExpectedType("tag")),
+        };
+        let msg_id = match u32::try_from(tb.get_tag()) {
+            Ok(id) if expected_tags.contains(&id) => id,
+            _ => return Err(CBORError::NotAllowed),
+        };
+        self.advance(it);
+        let mut body = tb.into_iter();
+        body.options = it.options;
+        Ok((
+            msg_id,
+            Self {
+                decode_buf_iter: RefCell::new(body),
+                on_tag: None,
+            },
+        ))
+    }
+
+    /// Skip a self-described CBOR tag, 55799 (RFC8949, Section 3.4.6), if the next item has one,
+    /// leaving the decoder positioned at the tagged content. If the next item does not have the
+    /// tag, the decoder is left unchanged. This is normally called before decoding the first
+    /// item of a buffer which may be prefixed with the CBOR magic number, `0xd9d9f7`.
+    ///
+    /// If the next item starts with `0xd9d9f7` but cannot be decoded, e.g. because the tag has no
+    /// content, the decode error is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_uint};
+    ///
+    /// // 55799(1), 2
+    /// let bytes = [0xd9, 0xd9, 0xf7, 0x01, 0x02];
+    /// let decoder = CBORDecoder::from_slice(&bytes);
+    /// decoder
+    ///     .skip_self_describe()?
+    ///     .decode_with(is_uint(), |_| Ok(()))?
+    ///     .decode_with(is_uint(), |_| Ok(()))?;
+    ///# Ok::<(), tps_minicbor::error::CBORError>(())
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn skip_self_describe(&self) -> Result<&Self, CBORError> {
+        self.skip_tag(SELF_DESCRIBE_TAG, &[0xd9, 0xd9, 0xf7])
+    }
+
+    /// (crate) Skip tag `tag` if the next item has it, leaving the decoder positioned at the
+    /// tagged content. Otherwise the decoder is left unchanged. `head` is the encoding of the
+    /// tag: if the next item starts with `head` but cannot be decoded, the error is returned.
+    #[cfg_attr(feature = "trace", trace)]
+    pub(crate) fn skip_tag(&self, tag: u64, head: &[u8]) -> Result<&Self, CBORError> {
+        let mut it = self.snapshot();
+        match it.try_next() {
+            Ok(CBOR::Tag(tb)) if tb.get_tag() == tag => {
+                // Step back to the start of the tag content
+                it.index -= tb.into_iter().buf.len();
+                if let Some(callback) = self.on_tag {
+                    callback(tag);
+                }
+                *self.decode_buf_iter.borrow_mut() = it;
+                Ok(self)
+            }
+            Err(e) if it.buf[it.index..].starts_with(head) => Err(e),
+            _ => Ok(self),
+        }
+    }
+
+    /// Set whether tagged items are transparently unwrapped by [`is_any`]. The default is that
+    /// tags are preserved and [`is_any`] returns `CBOR::Tag`.
+    ///
+    /// When tags are unwrapped, the value of the last tag removed can be obtained using
+    /// [`CBORDecoder::last_tag`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_any};
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// let decoder = CBORDecoder::from_slice(&[0xd8, 0x20, 0x01]).unwrap_unknown_tags(true);
+    /// let _ = decoder.decode_with(is_any(), |cbor| {
+    ///     assert_eq!(cbor, CBOR::UInt(1));
+    ///     Ok(())
+    /// });
+    /// assert_eq!(decoder.last_tag(), Some(32));
+    /// ```
+    #[inline]
+    pub fn unwrap_unknown_tags(self, unwrap: bool) -> Self {
+        self.decode_buf_iter.borrow_mut().options.unwrap_unknown_tags = unwrap;
+        self
+    }
+
+    /// Set whether the decoder applies the strict conformance profile. The default is that
+    /// strict checks are not applied.
+    ///
+    /// In strict mode, arrays, maps and tags are rejected with `CBORError::NotAllowed` if any map
+    /// within them, at any depth, has a key which is a tagged item. Well-known tags decoded
+    /// using [`is_tag`], [`is_tag_with_value`], [`is_any`] or [`CBORDecoder::tag`] are rejected with
+    /// `CBORError::TagContentMismatch` if their content does not have the type required for the
+    /// tag (for example, tag 2 must enclose a `bstr`).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_map};
+    /// use tps_minicbor::error::CBORError;
+    ///
+    /// // {1(0): 1}
+    /// let bytes = [0xa1, 0xc1, 0x00, 0x01];
+    /// let decoder = CBORDecoder::from_slice(&bytes).strict(true);
+    /// assert!(matches!(decoder.decode_with(is_map(), |_| Ok(())), Err(CBORError::NotAllowed)));
+    /// ```
+    #[inline]
+    pub fn strict(self, strict: bool) -> Self {
+        self.decode_buf_iter.borrow_mut().options.strict = strict;
+        self
+    }
+
+    /// Set whether the decoder rejects indefinite length items. The default is that indefinite
+    /// length items are decoded where they are supported.
+    ///
+    /// When set, any indefinite length `bstr`, `tstr`, array or map in the decoder's own item
+    /// sequence is rejected with `CBORError::IndefiniteNotAllowed`. Arrays, maps and tags which
+    /// contain indefinite length items cannot be decoded in any case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_any};
+    /// use tps_minicbor::error::CBORError;
+    ///
+    /// // [_ 1, 2]
+    /// let decoder = CBORDecoder::from_slice(&[0x9f, 0x01, 0x02, 0xff]).require_definite(true);
+    /// assert!(matches!(
+    ///     decoder.decode_with(is_any(), |_| Ok(())),
+    ///     Err(CBORError::IndefiniteNotAllowed)
+    /// ));
+    /// ```
+    #[inline]
+    pub fn require_definite(self, require: bool) -> Self {
+        self.decode_buf_iter.borrow_mut().options.require_definite = require;
+        self
+    }
+
+    /// Set whether the decoder rejects NaN and infinite floats. The default is that all floats
+    /// are decoded.
+    ///
+    /// When set, any NaN or infinite float in the decoder's own item sequence is rejected with
+    /// `CBORError::NotAllowed`. This is intended for profiles such as EAT and CWT, in which only
+    /// finite numeric values are meaningful. As for [`CBORDecoder::require_definite`], items
+    /// inside arrays, maps and tags which are decoded separately are not checked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_any};
+    /// use tps_minicbor::error::CBORError;
+    ///
+    /// // Infinity, as a 16 bit float
+    /// let decoder = CBORDecoder::from_slice(&[0xf9, 0x7c, 0x00]).reject_non_finite_floats(true);
+    /// assert!(matches!(
+    ///     decoder.decode_with(is_any(), |_| Ok(())),
+    ///     Err(CBORError::NotAllowed)
+    /// ));
+    /// ```
+    #[inline]
+    pub fn reject_non_finite_floats(self, reject: bool) -> Self {
+        self.decode_buf_iter
+            .borrow_mut()
+            .options
+            .reject_non_finite_floats = reject;
+        self
+    }
+
+    /// Register a callback which is called with the tag value of each tag decoded by this
+    /// `CBORDecoder`. This is an instrumentation hook, for example to record which COSE or CWT
+    /// tags are used by a token, and it cannot affect the result of decoding.
+    ///
+    /// The callback fires once for each tag in the items consumed by the decoder, in the order in
+    /// which the tags are encoded, whether or not the tags are unwrapped. This includes tags at
+    /// any depth within arrays, maps and tag contents, such as each tag of the chain
+    /// `55799(61(...))`. Decoders created from nested items, e.g. using
+    /// [`CBORDecoder::from_array`], do not inherit the callback, so each tag is reported once.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use core::cell::RefCell;
+    /// use tps_minicbor::decoder::{CBORDecoder, is_any};
+    ///
+    /// let seen = RefCell::new(Vec::new());
+    /// let record = |tag| seen.borrow_mut().push(tag);
+    /// // 55799(61(1)), 2
+    /// let decoder = CBORDecoder::from_slice(&[0xd9, 0xd9, 0xf7, 0xd8, 0x3d, 0x01, 0x02])
+    ///     .on_tag(&record);
+    /// let _ = decoder.decode_with(is_any(), |_| Ok(()));
+    /// let _ = decoder.decode_with(is_any(), |_| Ok(()));
+    /// assert_eq!(*seen.borrow(), vec![55799, 61]);
+    /// ```
+    #[inline]
+    pub fn on_tag(mut self, callback: &'buf dyn Fn(u64)) -> Self {
+        self.on_tag = Some(callback);
+        self
+    }
+
+    /// Return the value of the last tag unwrapped when the `unwrap_unknown_tags` option is set.
+    #[inline]
+    pub fn last_tag(&self) -> Option<u64> {
+        self.decode_buf_iter.borrow().last_tag
+    }
+
     /// Obtain the internal iterator of a `CBORDecoder`
     #[inline]
     pub fn into_inner(&self) -> Ref<DecodeBufIterator> {
         self.decode_buf_iter.borrow()
     }
 
+    /// (private) Take a copy of the decoder's current position, to be handed to a parser.
+    ///
+    /// Parsers take their [`DecodeBufIterator`] by value and return the advanced iterator only
+    /// on success, so a failed parse leaves the decoder where it was. The copy is cheap:
+    /// `DecodeBufIterator` is `Copy` and holds only a slice reference, an index, and a few bytes
+    /// of source and option state, so this is a small memcpy rather than a heap clone. Should
+    /// the iterator ever grow heavier, this and [`CBORDecoder::advance`] are the only places that
+    /// need to change to advance the borrowed iterator in place.
+    #[inline(always)]
+    fn snapshot(&self) -> DecodeBufIterator<'buf> {
+        *self.decode_buf_iter.borrow()
+    }
+
+    /// (private) Commit the position reached by a successful parse.
+    ///
+    /// If an `on_tag` callback is registered, it is called for the tags in the items consumed.
+    /// This is done here, rather than as items are produced, because parsers may backtrack and
+    /// each tag should be reported exactly once.
+    #[cfg_attr(feature = "trace", trace)]
+    #[inline(always)]
+    fn advance(&self, it: DecodeBufIterator<'buf>) {
+        if let Some(callback) = self.on_tag {
+            let mut consumed = self.snapshot();
+            while consumed.index < it.index {
+                match consumed.next() {
+                    Some(item) => report_tags(item, callback),
+                    None => break,
+                }
+            }
+        }
+        *self.decode_buf_iter.borrow_mut() = it;
+    }
+
     /// When decoding maps, arrays and tags, the closures require finalizing to obtain
     /// the correct return type.
+    #[cfg_attr(feature = "trace", trace)]
     #[inline]
     pub fn finalize(&self) -> Result<(), CBORError> {
         Ok(())
     }
 
+    /// As [`CBORDecoder::finalize`], but also check that every item in the decoder has been
+    /// consumed. Returns `CBORError::TrailingData` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_uint};
+    /// use tps_minicbor::error::CBORError;
+    ///
+    /// let mut v = 0u64;
+    /// let decoder = CBORDecoder::from_slice(&[0x01, 0x02]);
+    /// let _ = decoder.value(is_uint(), &mut v).unwrap();
+    /// assert!(matches!(decoder.finalize_strict(), Err(CBORError::TrailingData)));
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn finalize_strict(&self) -> Result<(), CBORError> {
+        let it = self.snapshot();
+        if it.index < it.buf.len() {
+            Err(CBORError::TrailingData)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// As [`CBORDecoder::finalize`], but return the number of bytes consumed by the decoder.
+    /// Unlike [`CBORDecoder::finalize_strict`], trailing data is not an error.
+    ///
+    /// This is useful when the buffer holds several concatenated messages: the value returned
+    /// is the offset of the next message, which is also the start of
+    /// [`CBORDecoder::remaining_bytes`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_uint};
+    /// use tps_minicbor::error::CBORError;
+    ///
+    /// let mut v = 0u64;
+    /// let decoder = CBORDecoder::from_slice(&[0x18, 0x64, 0x02]);
+    /// let _ = decoder.value(is_uint(), &mut v).unwrap();
+    /// assert_eq!(decoder.finish().unwrap(), 2);
+    /// assert_eq!(decoder.remaining_bytes(), &[0x02]);
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn finish(&self) -> Result<usize, CBORError> {
+        Ok(self.snapshot().index)
+    }
+
+    /// Return the bytes which have not yet been consumed by the decoder, starting with the
+    /// encoding of the next item.
+    #[cfg_attr(feature = "trace", trace)]
+    #[inline]
+    pub fn remaining_bytes(&self) -> &'buf [u8] {
+        let it = self.snapshot();
+        &it.buf[it.index..]
+    }
+
+    /// Return the total encoded length of the next item, including its head and any nested
+    /// items, without decoding it or advancing the decoder. The encoding of the next item is then
+    /// `&decoder.remaining_bytes()[..len]`, which can be copied or forwarded unchanged.
+    ///
+    /// The contents of strings are not checked, so a `tstr` which is not valid UTF-8 is not
+    /// detected. Indefinite length arrays and maps are not supported and return
+    /// `CBORError::NotImplemented`. `CBORError::EndOfBuffer` is returned if there is no next
+    /// item or it is truncated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    ///
+    /// // [1, {2: "a"}], 3
+    /// let decoder = CBORDecoder::from_slice(&[0x82, 0x01, 0xa1, 0x02, 0x61, 0x61, 0x03]);
+    /// let len = decoder.next_item_len().unwrap();
+    /// assert_eq!(len, 6);
+    /// assert_eq!(&decoder.remaining_bytes()[len..], &[0x03]);
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn next_item_len(&self) -> Result<usize, CBORError> {
+        let it = self.snapshot();
+        Ok(item_end(it.buf, it.index)? - it.index)
+    }
+
     /// Decode a value from a [`CBORDecoder`] instance.
     ///
     /// The compiler will attempt, if required, to convert the returned value, which depends on the
@@ -294,14 +787,15 @@ impl<'buf> CBORDecoder<'buf> {
     /// assert_eq!(result, "I love tps_minicbor");
     ///
     /// ```
+    #[cfg_attr(feature = "trace", trace(disable(parser, value)))]
     pub fn value<'t, F, T: Copy, V>(&self, parser: F, value: &'t mut V) -> Result<&Self, CBORError>
     where
         'buf: 't,
         V: TryFrom<T> + Clone,
         F: Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf, T>,
     {
-        let (it, v) = parser(self.decode_buf_iter.borrow().clone())?;
-        self.decode_buf_iter.replace(it);
+        let (it, v) = parser(self.snapshot())?;
+        self.advance(it);
         match V::try_from(v) {
             Ok(val) => {
                 *value = val;
@@ -350,8 +844,8 @@ impl<'buf> CBORDecoder<'buf> {
     where
         C: FnOnce(MapBuf<'buf>) -> Result<(), CBORError>,
     {
-        let (it, mb) = decode_map()(self.decode_buf_iter.borrow().clone())?;
-        self.decode_buf_iter.replace(it);
+        let (it, mb) = decode_map()(self.snapshot())?;
+        self.advance(it);
         closure(mb)?;
         Ok(self)
     }
@@ -383,26 +877,30 @@ impl<'buf> CBORDecoder<'buf> {
     /// # Example
     ///
     /// ```
-    /// use tps_minicbor::decoder::{CBORDecoder, decode_tstr};
+    /// use tps_minicbor::decoder::CBORDecoder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
     ///
-    /// let _ = CBORDecoder::from_slice(&[
+    /// CBORDecoder::from_slice(&[
     ///    0x98, 0x19, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
     ///    0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x18, 0x18,
     ///    0x19,
     /// ])
     ///   .array(|ab| {
-    ///     for i in 0..=ab.len() {
-    ///        assert_eq!(ab.item::<u8>(i)?, i.clone() as u8 + 1);
+    ///     for i in 0..ab.len() {
+    ///        assert_eq!(ab.item::<u8>(i)?, i as u8 + 1);
     ///     }
     ///     Ok(())
-    /// });
+    /// })?;
+    ///# Ok(())
+    ///# }
     /// ```
     pub fn array<C>(&self, closure: C) -> Result<&Self, CBORError>
     where
         C: FnOnce(ArrayBuf<'buf>) -> Result<(), CBORError>,
     {
-        let (it, ab) = decode_array()(self.decode_buf_iter.borrow().clone())?;
-        self.decode_buf_iter.replace(it);
+        let (it, ab) = decode_array()(self.snapshot())?;
+        self.advance(it);
         closure(ab)?;
         Ok(self)
     }
@@ -450,42 +948,278 @@ impl<'buf> CBORDecoder<'buf> {
     where
         C: FnOnce(TagBuf<'buf>) -> Result<(), CBORError>,
     {
-        let (it, tb) = decode_tag()(self.decode_buf_iter.borrow().clone())?;
-        self.decode_buf_iter.replace(it);
+        let (it, tb) = decode_tag()(self.snapshot())?;
+        self.advance(it);
         closure(tb)?;
         Ok(self)
     }
 
+    /// Decode a map wrapped in tag `tag`, calling `closure` with the [`MapBuf`] of the map. This
+    /// combines [`CBORDecoder::tag`] and [`CBORDecoder::from_map`] for the common case of a
+    /// tagged structure.
+    ///
+    /// Returns `CBORError::ExpectedTag(tag)` if the next item is not tagged `tag`, and
+    /// `CBORError::ExpectedType` if the tagged item is not a map. On error, the decoder is not
+    /// advanced and `closure` is not called.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    ///
+    /// // 61({8: 2})
+    /// let _ = CBORDecoder::from_slice(&[0xd8, 0x3d, 0xa1, 0x08, 0x02]).tagged_map(61, |mb| {
+    ///     assert_eq!(mb.lookup::<u8, u8>(8)?, 2);
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg_attr(feature = "trace", trace(disable(closure)))]
+    pub fn tagged_map<C>(&self, tag: u64, closure: C) -> Result<&Self, CBORError>
+    where
+        C: FnOnce(MapBuf<'buf>) -> Result<(), CBORError>,
+    {
+        let (it, tb) = self.tagged_item(tag)?;
+        match tb.into_iter().next() {
+            Some(CBOR::Map(mb)) => {
+                self.advance(it);
+                closure(mb)?;
+                Ok(self)
+            }
+            _ => Err(CBORError::ExpectedType("CBOR Map")),
+        }
+    }
+
+    /// Decode an array wrapped in tag `tag`, calling `closure` with the [`ArrayBuf`] of the
+    /// array. This is the usual shape of COSE structures, such as `18([...])` for COSE_Sign1.
+    ///
+    /// Returns `CBORError::ExpectedTag(tag)` if the next item is not tagged `tag`, and
+    /// `CBORError::ExpectedType` if the tagged item is not an array. On error, the decoder is not
+    /// advanced and `closure` is not called.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    ///
+    /// // 18([h'', {}, 'hi', h''])
+    /// let bytes = [0xd2, 0x84, 0x40, 0xa0, 0x42, 0x68, 0x69, 0x40];
+    /// let _ = CBORDecoder::from_slice(&bytes).tagged_array(18, |ab| {
+    ///     assert_eq!(ab.len(), 4);
+    ///     assert_eq!(ab.item::<&[u8]>(2)?, b"hi");
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg_attr(feature = "trace", trace(disable(closure)))]
+    pub fn tagged_array<C>(&self, tag: u64, closure: C) -> Result<&Self, CBORError>
+    where
+        C: FnOnce(ArrayBuf<'buf>) -> Result<(), CBORError>,
+    {
+        let (it, tb) = self.tagged_item(tag)?;
+        match tb.into_iter().next() {
+            Some(CBOR::Array(ab)) => {
+                self.advance(it);
+                closure(ab)?;
+                Ok(self)
+            }
+            _ => Err(CBORError::ExpectedType("CBOR Array")),
+        }
+    }
+
+    /// (private) Decode the next item, which must be tagged `tag`, without advancing the decoder.
+    fn tagged_item(&self, tag: u64) -> Result<(DecodeBufIterator<'buf>, TagBuf<'buf>), CBORError> {
+        match decode_tag()(self.snapshot()) {
+            Ok((it, tb)) if tb.get_tag() == tag => Ok((it, tb)),
+            Ok(_) | Err(CBORError::ExpectedType(_)) => Err(CBORError::ExpectedTag(tag)),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Run `parser` over the next item in the iterator. If it completes successfully, run
     /// `closure` using the result obtained. This allows some result to be built up from
     /// parsing.
     ///
     /// TODO: currently the lifetime management does not allow assignment of references to `self`
     /// within the `closure`.
+    #[cfg_attr(feature = "trace", trace(disable(parser, closure)))]
     pub fn decode_with<F, C>(&'buf self, parser: F, mut closure: C) -> Result<&'buf Self, CBORError>
     where
         F: Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>,
         C: FnMut(CBOR<'buf>) -> Result<(), CBORError>,
     {
-        let (it, cbor) = parser(self.decode_buf_iter.borrow().clone())?;
-        self.decode_buf_iter.replace(it);
+        let (it, cbor) = parser(self.snapshot())?;
+        self.advance(it);
         closure(cbor)?;
         Ok(self)
     }
 
+    /// Run `parser` over the next item in the iterator. If it completes successfully, run
+    /// `closure` with the result obtained and the raw encoded bytes of the item that was parsed.
+    ///
+    /// The raw slice covers the complete encoding of the item, including any tag prefix, so it
+    /// can be hashed or signed directly without re-encoding the decoded value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_tag};
+    ///
+    /// let _ = CBORDecoder::from_slice(&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0, 0x01])
+    ///     .decode_with_raw(is_tag(), |_cbor, raw| {
+    ///         assert_eq!(raw, &[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0]);
+    ///         Ok(())
+    ///     });
+    /// ```
+    #[cfg_attr(feature = "trace", trace(disable(parser, closure)))]
+    pub fn decode_with_raw<F, C>(&self, parser: F, mut closure: C) -> Result<&Self, CBORError>
+    where
+        F: Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>,
+        C: FnMut(CBOR<'buf>, &'buf [u8]) -> Result<(), CBORError>,
+    {
+        let start_it = self.snapshot();
+        let (it, cbor) = parser(start_it)?;
+        let raw = start_it
+            .buf
+            .get(start_it.index..it.index)
+            .ok_or(CBORError::EndOfBuffer)?;
+        self.advance(it);
+        closure(cbor, raw)?;
+        Ok(self)
+    }
+
+    /// Decode exactly one item using `parser`, then pass the bytes remaining after that item to
+    /// `then`. This supports framing where a single CBOR item is followed by an opaque binary
+    /// trailer.
+    ///
+    /// On success, the decoded item and the result of `then` are returned, and the decoder is
+    /// positioned at the end of the buffer, as the trailer is considered to have been consumed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_uint};
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// let (item, trailer_len) = CBORDecoder::from_slice(&[0x18, 0x2a, 0xde, 0xad, 0xbe, 0xef])
+    ///     .decode_one_then(is_uint(), |trailer| {
+    ///         assert_eq!(trailer, &[0xde, 0xad, 0xbe, 0xef]);
+    ///         trailer.len()
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(item, CBOR::UInt(42));
+    /// assert_eq!(trailer_len, 4);
+    /// ```
+    pub fn decode_one_then<F, C, T>(&self, parser: F, then: C) -> Result<(CBOR<'buf>, T), CBORError>
+    where
+        F: Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>,
+        C: FnOnce(&'buf [u8]) -> T,
+    {
+        let (it, cbor) = parser(self.snapshot())?;
+        let trailer = it.buf.get(it.index..).unwrap_or(&[]);
+        self.advance(it);
+        // The trailer is not CBOR, so it is skipped without being decoded
+        self.decode_buf_iter.borrow_mut().index = it.buf.len();
+        Ok((cbor, then(trailer)))
+    }
+
+    /// Decode the next item as a `bstr`, passing its contents to `callback` in order, as a
+    /// sequence of slices of at most 64 bytes. This allows large payloads, such as a firmware
+    /// image, to be processed (e.g. hashed) incrementally.
+    ///
+    /// Currently the complete `bstr` must be present in the decoder's buffer. The callback is
+    /// not called for an empty `bstr`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    ///
+    /// let mut total = 0;
+    /// let _ = CBORDecoder::from_slice(&[0x43, 0x01, 0x02, 0x03])
+    ///     .decode_bstr_streaming(|chunk| total += chunk.len());
+    /// assert_eq!(total, 3);
+    /// ```
+    #[cfg_attr(feature = "trace", trace(disable(callback)))]
+    pub fn decode_bstr_streaming<C>(&self, mut callback: C) -> Result<&Self, CBORError>
+    where
+        C: FnMut(&[u8]),
+    {
+        let (it, cbor) = is_bstr()(self.snapshot())?;
+        self.advance(it);
+        if let CBOR::Bstr(bytes) = cbor {
+            bytes.chunks(BSTR_STREAM_CHUNK_LEN).for_each(&mut callback);
+        }
+        Ok(self)
+    }
+
+    /// (crate) Run `parser` over the next item in the iterator, returning its output. The decoder
+    /// only advances if the parse is successful.
+    #[cfg(any(feature = "cose", feature = "eat"))]
+    pub(crate) fn decode_parsed<F, O>(&self, parser: F) -> Result<O, CBORError>
+    where
+        F: Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf, O>,
+    {
+        let (it, output) = parser(self.snapshot())?;
+        self.advance(it);
+        Ok(output)
+    }
+
+    /// Decode the next item, whatever its type, and run the first handler in `handlers` which is
+    /// registered for the major type of the item. This allows a field which may take one of
+    /// several types to be decoded declaratively, rather than with a chain of `or` parsers.
+    ///
+    /// If no handler matches the type of the item, `CBORError::NotAllowed` is returned and the
+    /// decoder is not advanced.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::CBORType;
+    ///
+    /// let seen = Cell::new(None);
+    /// let on_map = |_| {
+    ///     seen.set(Some(CBORType::Map));
+    ///     Ok(())
+    /// };
+    /// let on_array = |_| {
+    ///     seen.set(Some(CBORType::Array));
+    ///     Ok(())
+    /// };
+    /// let _ = CBORDecoder::from_slice(&[0x82, 0x01, 0x02])
+    ///     .dispatch_by_type(&[(CBORType::Map, &on_map), (CBORType::Array, &on_array)]);
+    /// assert_eq!(seen.get(), Some(CBORType::Array));
+    /// ```
+    #[cfg_attr(feature = "trace", trace(disable(handlers)))]
+    pub fn dispatch_by_type(
+        &self,
+        handlers: &[TypeHandler<'_, 'buf>],
+    ) -> Result<&Self, CBORError> {
+        let (it, cbor) = is_any()(self.snapshot())?;
+        let item_type = cbor.cbor_type();
+        let (_, handler) = handlers
+            .iter()
+            .find(|(t, _)| Some(*t) == item_type)
+            .ok_or(CBORError::NotAllowed)?;
+        self.advance(it);
+        handler(cbor)?;
+        Ok(self)
+    }
+
     /// Optionally run `parser` over the next item in the iterator. If parsing is successful,
     /// run `closure` using the result obtained. If parsing is unsuccessful, continue with the
     /// iterator state unchanged.
     ///
     /// TODO: currently the lifetime management does not allow assignment of references to `self`
     /// within the `closure`.
+    #[cfg_attr(feature = "trace", trace(disable(parser, closure)))]
     pub fn opt<F, C>(&self, parser: F, closure: C) -> Result<&Self, CBORError>
     where
         F: Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>,
         C: Fn(CBOR<'buf>) -> Result<(), CBORError>,
     {
-        let (it, opt_cbor) = opt(&parser)(self.decode_buf_iter.borrow().clone())?;
-        self.decode_buf_iter.replace(it);
+        let (it, opt_cbor) = opt(&parser)(self.snapshot())?;
+        self.advance(it);
         if let Some(cbor) = opt_cbor {
             closure(cbor)?;
         }
@@ -494,13 +1228,14 @@ impl<'buf> CBORDecoder<'buf> {
 
     /// Run `parser` over the next item in the iterator. If it completes successfully, do nothing.
     /// If the parse fails, an error value will be returned.
+    #[cfg_attr(feature = "trace", trace(disable(parser)))]
     #[inline]
     pub fn ignore<F, C>(&self, parser: F) -> Result<&Self, CBORError>
     where
         F: Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>,
     {
-        let (it, _cbor) = parser(self.decode_buf_iter.borrow().clone())?;
-        self.decode_buf_iter.replace(it);
+        let (it, _cbor) = parser(self.snapshot())?;
+        self.advance(it);
         Ok(self)
     }
 
@@ -509,14 +1244,15 @@ impl<'buf> CBORDecoder<'buf> {
     ///
     /// TODO: currently the lifetime management does not allow assignment of references to `self`
     /// within the `closure`.
+    #[cfg_attr(feature = "trace", trace(disable(parser, closure)))]
     pub fn cond<F, C>(&self, condition: bool, parser: F, closure: C) -> Result<&Self, CBORError>
     where
         F: Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>,
         C: Fn(CBOR<'buf>) -> Result<(), CBORError>,
     {
         if condition {
-            let (it, opt_cbor) = opt(&parser)(self.decode_buf_iter.borrow().clone())?;
-            self.decode_buf_iter.replace(it);
+            let (it, opt_cbor) = opt(&parser)(self.snapshot())?;
+            self.advance(it);
             if let Some(cbor) = opt_cbor {
                 closure(cbor)?;
             }
@@ -532,6 +1268,7 @@ impl<'buf> CBORDecoder<'buf> {
     ///
     /// TODO: currently the lifetime management does not allow assignment of references to `self`
     /// within the `closure`.
+    #[cfg_attr(feature = "trace", trace(disable(parser, closure)))]
     pub fn range<F, C>(
         &self,
         min: usize,
@@ -542,16 +1279,55 @@ impl<'buf> CBORDecoder<'buf> {
     where
         F: Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>,
         C: FnMut(usize, CBOR<'buf>) -> Result<(), CBORError>,
+    {
+        self.fold(min, max, parser, (), |(), no_parse, cbor| closure(no_parse, cbor))?;
+        Ok(self)
+    }
+
+    /// Run `parser` at least `min` and no more than `max` times, threading an accumulator
+    /// through the results. The accumulator starts as `init`, and each time `parser` executes
+    /// successfully, it is replaced by the result of calling `f` with the accumulator, the
+    /// iteration number and the result of the parse. The final value of the accumulator is
+    /// returned.
+    ///
+    /// This is the same as [`CBORDecoder::range`], but avoids the need for `f` to update
+    /// external state.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, is_uint};
+    /// use std::convert::TryFrom;
+    ///
+    /// // 1, 2, 3, "end"
+    /// let decoder = CBORDecoder::from_slice(&[0x01, 0x02, 0x03, 0x63, 0x65, 0x6e, 0x64]);
+    /// let sum = decoder
+    ///     .fold(1, 10, is_uint(), 0u64, |sum, _n, cbor| Ok(sum + u64::try_from(cbor)?))
+    ///     .unwrap();
+    /// assert_eq!(sum, 6);
+    /// ```
+    pub fn fold<F, A, C>(
+        &self,
+        min: usize,
+        max: usize,
+        parser: F,
+        init: A,
+        mut f: C,
+    ) -> Result<A, CBORError>
+    where
+        F: Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>,
+        C: FnMut(A, usize, CBOR<'buf>) -> Result<A, CBORError>,
     {
         let mut no_parse = 0;
+        let mut acc = init;
 
         loop {
             // Have to borrow parser here because we call many times.
-            let (it, opt_cbor) = opt(&parser)(self.decode_buf_iter.borrow().clone())?;
-            self.decode_buf_iter.replace(it);
+            let (it, opt_cbor) = opt(&parser)(self.snapshot())?;
+            self.advance(it);
             if let Some(cbor) = opt_cbor {
                 no_parse += 1;
-                closure(no_parse, cbor)?;
+                acc = f(acc, no_parse, cbor)?;
             } else {
                 // Parse failed, but this is not necessarily an error
                 if no_parse < min && min != 0 {
@@ -559,12 +1335,12 @@ impl<'buf> CBORDecoder<'buf> {
                     return Err(CBORError::RangeUnderflow(no_parse));
                 } else {
                     // Case 2: Success: we have parsed the minimum number of times or min == 0
-                    return Ok(self);
+                    return Ok(acc);
                 }
             }
             if no_parse == max {
                 // Case 3: we have parsed the maximum number of times
-                return Ok(self);
+                return Ok(acc);
             }
         }
     }
@@ -581,6 +1357,188 @@ impl<'buf> CBORDecoder<'buf> {
     {
         self.range(0, usize::MAX, parser, closure)
     }
+
+    /// Decode every remaining item in the decoder as a CBOR sequence, calling `closure` for
+    /// each item which is not equal to `pad`. Items equal to `pad` are skipped wherever they
+    /// occur, including before the first and after the last real item.
+    ///
+    /// This is an interoperability accommodation for peers which use a padding item, such as
+    /// `undefined`, as a separator between records. Padding is not skipped by any other
+    /// decoding function, so it is off unless this function is used.
+    ///
+    /// The `closure` function takes a `usize` for the number of the real item, counting from 1,
+    /// and a `cbor` for the item. Any error decoding an item, or returned by `closure`, is
+    /// returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// // 1, undefined, undefined, "a", undefined
+    /// let decoder = CBORDecoder::from_slice(&[0x01, 0xf7, 0xf7, 0x61, 0x61, 0xf7]);
+    /// let mut items = Vec::new();
+    /// let _ = decoder
+    ///     .sequence_skipping(CBOR::Undefined, |n, cbor| {
+    ///         items.push((n, cbor));
+    ///         Ok(())
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(items, vec![(1, CBOR::UInt(1)), (2, CBOR::Tstr("a"))]);
+    /// ```
+    #[cfg_attr(feature = "trace", trace(disable(closure)))]
+    pub fn sequence_skipping<C>(&self, pad: CBOR<'buf>, mut closure: C) -> Result<&Self, CBORError>
+    where
+        C: FnMut(usize, CBOR<'buf>) -> Result<(), CBORError>,
+    {
+        let mut no_items = 0;
+        loop {
+            let mut it = self.snapshot();
+            if it.index >= it.buf.len() {
+                return Ok(self);
+            }
+            let cbor = it.try_next()?;
+            self.advance(it);
+            if cbor != pad {
+                no_items += 1;
+                closure(no_items, cbor)?;
+            }
+        }
+    }
+
+    /// Start navigating into the next item, whatever its type, using a fluent [`Envelope`]. The
+    /// decoder is advanced past the item if it can be decoded.
+    ///
+    /// This expresses common message navigation paths, such as tag, then map, then key,
+    /// without nesting closures.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// # use tps_minicbor::error::CBORError;
+    /// # fn main() -> Result<(), CBORError> {
+    /// // 1000({1: {2: "abc"}})
+    /// let bytes = [0xd9, 0x03, 0xe8, 0xa1, 0x01, 0xa1, 0x02, 0x63, 0x61, 0x62, 0x63];
+    /// let decoder = CBORDecoder::from_slice(&bytes);
+    /// let text: &str = decoder.envelope().tag(1000).key(1).key(2).value()?;
+    /// assert_eq!(text, "abc");
+    /// decoder.finalize()
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn envelope(&self) -> Envelope<'buf> {
+        let mut it = self.snapshot();
+        let item = it.try_next();
+        if item.is_ok() {
+            self.advance(it);
+        }
+        Envelope { item }
+    }
+}
+
+/***************************************************************************************************
+ * Fluent navigation
+ **************************************************************************************************/
+
+/// A fluent API to navigate into a decoded item, created by [`CBORDecoder::envelope`].
+///
+/// Each step checks the current item and replaces it with an enclosed item. The first error is
+/// kept, and later steps are skipped, so errors only need to be handled once the navigation is
+/// complete, using [`Envelope::item`], [`Envelope::value`] or [`Envelope::decoder`].
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope<'buf> {
+    item: Result<CBOR<'buf>, CBORError>,
+}
+
+impl<'buf> Envelope<'buf> {
+    /// Step into the content of a tagged item, which must have tag `value`. Otherwise
+    /// `CBORError::ExpectedTag` is returned with the expected tag value.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn tag(self, value: u64) -> Self {
+        self.then(|cbor| match cbor {
+            CBOR::Tag(tb) if tb.get_tag() == value => tb.into_iter().try_next(),
+            _ => Err(CBORError::ExpectedTag(value)),
+        })
+    }
+
+    /// Check that the item is a map.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn map(self) -> Self {
+        self.then(|cbor| match cbor {
+            CBOR::Map(_) => Ok(cbor),
+            _ => Err(CBORError::ExpectedType("map")),
+        })
+    }
+
+    /// Check that the item is an array.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn array(self) -> Self {
+        self.then(|cbor| match cbor {
+            CBOR::Array(_) => Ok(cbor),
+            _ => Err(CBORError::ExpectedType("array")),
+        })
+    }
+
+    /// Step into the value of `key` in a map. Returns `CBORError::KeyNotPresent` if there is no
+    /// such key.
+    #[cfg_attr(feature = "trace", trace(disable(key)))]
+    pub fn key<K>(self, key: K) -> Self
+    where
+        K: core::convert::Into<CBOR<'buf>>,
+    {
+        self.then(|cbor| match cbor {
+            CBOR::Map(mb) => mb.get(&key.into()).ok_or(CBORError::KeyNotPresent),
+            _ => Err(CBORError::ExpectedType("map")),
+        })
+    }
+
+    /// Step into item `n` of an array. Returns `CBORError::IndexOutOfBounds` if the array is
+    /// too short.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn index(self, n: usize) -> Self {
+        self.then(|cbor| match cbor {
+            CBOR::Array(ab) => ab.into_iter().nth(n).ok_or(CBORError::IndexOutOfBounds),
+            _ => Err(CBORError::ExpectedType("array")),
+        })
+    }
+
+    /// Return the item reached, or the first error.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn item(self) -> Result<CBOR<'buf>, CBORError> {
+        self.item
+    }
+
+    /// Return the item reached, converted (fallibly) from CBOR, or the first error.
+    pub fn value<V>(self) -> Result<V, CBORError>
+    where
+        V: TryFrom<CBOR<'buf>>,
+    {
+        V::try_from(self.item?).map_err(|_| CBORError::IncompatibleType)
+    }
+
+    /// Return a [`CBORDecoder`] over the contents of the map or array reached, or the first
+    /// error.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn decoder(self) -> Result<CBORDecoder<'buf>, CBORError> {
+        match self.item? {
+            cbor @ CBOR::Map(_) => CBORDecoder::from_map(cbor),
+            cbor @ CBOR::Array(_) => CBORDecoder::from_array(cbor),
+            _ => Err(CBORError::ExpectedType("array/map")),
+        }
+    }
+
+    /// (private) Apply `f` to the item if there has been no error so far.
+    #[inline]
+    fn then<F>(self, f: F) -> Self
+    where
+        F: FnOnce(CBOR<'buf>) -> Result<CBOR<'buf>, CBORError>,
+    {
+        Envelope {
+            item: self.item.and_then(f),
+        }
+    }
 }
 
 /***************************************************************************************************
@@ -767,7 +1725,7 @@ pub fn is_uint<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::UInt(_)) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("uint")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -779,7 +1737,7 @@ pub fn is_nint<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::NInt(_)) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("uint")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -791,7 +1749,7 @@ pub fn is_bstr<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::Bstr(_)) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("bstr")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -803,19 +1761,22 @@ pub fn is_tstr<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::Tstr(_)) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("tstr")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
 
 /// Match a CBOR `simple` value
+///
+/// Simple values 20..23 are decoded as `false`, `true`, `null` and `undefined`, so they are not
+/// matched here - use [`is_bool`], [`is_null`] or [`is_undefined`] for those.
 pub fn is_simple<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
     move |mut iter| {
         let item = iter.next();
         match item {
             Some(cbor @ CBOR::Simple(_)) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("simple")),
-            None => Err(CBORError::EndOfBuffer)
+            None => Err(iter.no_item_error())
         }
     }
 }
@@ -827,7 +1788,7 @@ pub fn is_array<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::Array(_)) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("array")),
-            None => Err(CBORError::EndOfBuffer)
+            None => Err(iter.no_item_error())
         }
     }
 }
@@ -839,7 +1800,7 @@ pub fn is_map<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::Map(_)) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("map")),
-            None => Err(CBORError::EndOfBuffer)
+            None => Err(iter.no_item_error())
         }
     }
 }
@@ -851,7 +1812,7 @@ pub fn is_true<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::True) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("true")),
-            None => Err(CBORError::EndOfBuffer)
+            None => Err(iter.no_item_error())
         }
     }
 }
@@ -863,7 +1824,7 @@ pub fn is_false<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::False) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("false")),
-            None => Err(CBORError::EndOfBuffer)
+            None => Err(iter.no_item_error())
         }
     }
 }
@@ -875,7 +1836,7 @@ pub fn is_null<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
         match item {
             Some(cbor @ CBOR::Null) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("null")),
-            None => Err(CBORError::EndOfBuffer)
+            None => Err(iter.no_item_error())
         }
     }
 }
@@ -887,7 +1848,7 @@ pub fn is_undefined<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf
         match item {
             Some(cbor @ CBOR::Undefined) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("undefined")),
-            None => Err(CBORError::EndOfBuffer)
+            None => Err(iter.no_item_error())
         }
     }
 }
@@ -897,6 +1858,36 @@ pub fn is_int<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
     move |iter| DecodeParser::or(is_uint(), is_nint()).parse(iter)
 }
 
+/// Match a CBOR number (`int / float`). The matched item is returned unchanged, so the
+/// representation used by the sender is available from [`CBOR::number_kind`].
+pub fn is_number<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
+    move |mut iter| {
+        let item = iter.next();
+        match item {
+            Some(cbor) if cbor.number_kind().is_some() => Ok((iter, cbor)),
+            Some(_) => Err(CBORError::ExpectedType("number")),
+            None => Err(iter.no_item_error()),
+        }
+    }
+}
+
+/// Match a CBOR label (`int / tstr`), as used for COSE header parameters and CWT claims. Integers
+/// must fit in an `i64` (see [`Label`](crate::types::Label)).
+pub fn is_label<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
+    move |mut iter| {
+        let item = iter.next();
+        match item {
+            Some(cbor @ CBOR::Tstr(_)) => Ok((iter, cbor)),
+            Some(cbor @ (CBOR::UInt(_) | CBOR::NInt(_))) => {
+                let _ = i64::try_from(cbor)?;
+                Ok((iter, cbor))
+            }
+            Some(_) => Err(CBORError::ExpectedType("label")),
+            None => Err(iter.no_item_error()),
+        }
+    }
+}
+
 /// Match a CBOR boolean value
 pub fn is_bool<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
     move |iter| match decode_bool()(iter)? {
@@ -906,12 +1897,34 @@ pub fn is_bool<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
 }
 
 /// Match any CBOR type
+///
+/// If the `unwrap_unknown_tags` decoder option is set, tagged items are unwrapped (including any
+/// nested tags) and the enclosed item is returned. The outermost tag value is recorded in the
+/// `last_tag` field of the returned iterator.
 pub fn is_any<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
     move |mut iter| {
         let item = iter.next();
         match item {
+            Some(CBOR::Tag(tb)) if iter.options.unwrap_unknown_tags => {
+                iter.last_tag = Some(tb.get_tag());
+                let mut inner = tb;
+                loop {
+                    if iter.options.strict {
+                        inner.check_strict_content()?;
+                    }
+                    match inner.into_iter().next() {
+                        Some(CBOR::Tag(tb)) => inner = tb,
+                        Some(v) => return Ok((iter, v)),
+                        None => return Err(CBORError::MalformedEncoding),
+                    }
+                }
+            }
+            Some(CBOR::Tag(tb)) if iter.options.strict => {
+                tb.check_strict_content()?;
+                Ok((iter, CBOR::Tag(tb)))
+            }
             Some(v) => Ok((iter, v)),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -921,9 +1934,13 @@ pub fn is_tag<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
     move |mut iter| {
         let item = iter.next();
         match item {
+            Some(CBOR::Tag(tb)) if iter.options.strict => {
+                tb.check_strict_content()?;
+                Ok((iter, CBOR::Tag(tb)))
+            }
             Some(cbor @ CBOR::Tag(_)) => Ok((iter, cbor)),
             Some(_) => Err(CBORError::ExpectedType("tag")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -936,6 +1953,9 @@ pub fn is_tag_with_value<'buf>(v: u64) -> impl Fn(DecodeBufIterator<'buf>) -> DC
             Some(cbor @ CBOR::Tag(_)) => {
                 if let CBOR::Tag(tb) = cbor {
                     if tb.get_tag() == v {
+                        if iter.options.strict {
+                            tb.check_strict_content()?;
+                        }
                         Ok((iter, cbor))
                     } else {
                         Err(CBORError::ExpectedTag(v))
@@ -945,13 +1965,12 @@ pub fn is_tag_with_value<'buf>(v: u64) -> impl Fn(DecodeBufIterator<'buf>) -> DC
                 }
             }
             Some(_) => Err(CBORError::ExpectedType("tag")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
 
-/// Match a CBOR tag with a CBOR date_time
-#[cfg_attr(feature = "trace", trace)]
+/// Match a CBOR tag with a CBOR date_time. See also [`decode_date_time_raw`].
 #[cfg(feature = "full")]
 pub fn is_date_time<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
     is_tag_helper(0, |iter: DecodeBufIterator| {
@@ -966,8 +1985,49 @@ pub fn is_date_time<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf
     })
 }
 
+/// Decode a CBOR tag with a CBOR date_time, returning both the parsed date and time and the
+/// string exactly as it was encoded.
+///
+/// [`is_date_time`] only returns the parsed value, and re-encoding it may not reproduce the
+/// original string (for example `Z` is written as `+00:00`). Where the encoding must be preserved,
+/// for instance because a signature covers it, the returned string can be re-encoded unchanged
+/// with [`date_time_str`](crate::types::date_time_str).
+///
+/// ```
+///# use tps_minicbor::decoder::{decode_date_time_raw, SequenceBuffer};
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // 0("1970-01-01T00:00:00Z")
+/// let input = [0xc0, 0x74, 0x31, 0x39, 0x37, 0x30, 0x2d, 0x30, 0x31, 0x2d, 0x30, 0x31, 0x54,
+///              0x30, 0x30, 0x3a, 0x30, 0x30, 0x3a, 0x30, 0x30, 0x5a];
+/// let (_, (dt, raw)) = decode_date_time_raw()(SequenceBuffer::new(&input).into_iter())?;
+/// assert_eq!(dt.timestamp(), 0);
+/// assert_eq!(raw, "1970-01-01T00:00:00Z");
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "full")]
+pub fn decode_date_time_raw<'buf>() -> impl Fn(
+    DecodeBufIterator<'buf>,
+) -> DCPResult<
+    'buf,
+    (chrono::DateTime<chrono::FixedOffset>, &'buf str),
+> {
+    move |mut iter| match iter.next() {
+        Some(CBOR::Tag(tb)) if tb.get_tag() == 0 => match tb.into_iter().next() {
+            Some(CBOR::Tstr(date_time)) => match chrono::DateTime::parse_from_rfc3339(date_time) {
+                Ok(dt) => Ok((iter, (dt, date_time))),
+                _ => Err(CBORError::BadDateTime),
+            },
+            _ => Err(CBORError::ExpectedType("tstr")),
+        },
+        Some(CBOR::Tag(_)) => Err(CBORError::ExpectedTag(0)),
+        Some(_) => Err(CBORError::ExpectedType("tag")),
+        None => Err(iter.no_item_error()),
+    }
+}
+
 /// Match a CBOR tag with a CBOR epoch
-#[cfg_attr(feature = "trace", trace)]
 #[cfg(feature = "full")]
 pub fn is_epoch<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
     use core::convert::TryInto;
@@ -981,7 +2041,6 @@ pub fn is_epoch<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> {
     })
 }
 
-#[cfg_attr(feature = "trace", trace)]
 #[cfg(feature = "full")]
 fn is_tag_helper<'buf, F>(tag: u64, f: F) -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf>
 where
@@ -998,7 +2057,7 @@ where
                 }
             }
             Some(_) => Err(CBORError::ExpectedType("tag")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1025,7 +2084,7 @@ pub fn decode_uint<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf
         match item {
             Some(CBOR::UInt(v)) => Ok((iter, v as i128)),
             Some(_) => Err(CBORError::ExpectedType("uint")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1037,7 +2096,7 @@ pub fn decode_nint<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf
         match item {
             Some(CBOR::NInt(v)) => Ok((iter, -1 - (v as i128))),
             Some(_) => Err(CBORError::ExpectedType("nint")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1054,7 +2113,7 @@ pub fn decode_bstr<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf
         match item {
             Some(CBOR::Bstr(bs)) => Ok((iter, bs)),
             Some(_) => Err(CBORError::ExpectedType("bstr")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1066,11 +2125,92 @@ pub fn decode_tstr<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf
         match item {
             Some(CBOR::Tstr(ts)) => Ok((iter, ts)),
             Some(_) => Err(CBORError::ExpectedType("tstr")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
+        }
+    }
+}
+
+/// Decode a CBOR bytestring, succeeding only if it is equal to `expected`.
+///
+/// This is useful to check that a value computed at runtime, such as a nonce or challenge, has
+/// been echoed back correctly. Returns `CBORError::FailedPredicate` if the value does not match.
+///
+/// > The comparison returns as soon as a difference is found. Use [`expect_bytes_ct`] when
+/// > comparing secret values.
+///
+/// # Example
+///
+/// ```
+/// use tps_minicbor::decoder::{CBORDecoder, expect_bytes};
+/// use tps_minicbor::error::CBORError;
+///
+/// let nonce = [0x01, 0x02, 0x03];
+/// let decoder = CBORDecoder::from_slice(&[0x43, 0x01, 0x02, 0x03]);
+/// assert!(decoder.decode_with(expect_bytes(&nonce), |_| Ok(())).is_ok());
+/// ```
+pub fn expect_bytes<'buf, 'e>(
+    expected: &'e [u8],
+) -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> + 'e {
+    move |i| {
+        let (i, bs) = decode_bstr()(i)?;
+        if bs == expected {
+            Ok((i, CBOR::Bstr(bs)))
+        } else {
+            Err(CBORError::FailedPredicate)
+        }
+    }
+}
+
+/// Decode a CBOR bytestring, succeeding only if it is equal to `expected`, using a comparison
+/// whose timing does not depend on the contents of the bytestrings.
+///
+/// Only the lengths of the values may be inferred from the timing. Returns
+/// `CBORError::FailedPredicate` if the value does not match.
+pub fn expect_bytes_ct<'buf, 'e>(
+    expected: &'e [u8],
+) -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> + 'e {
+    move |i| {
+        let (i, bs) = decode_bstr()(i)?;
+        if ct_eq(bs, expected) {
+            Ok((i, CBOR::Bstr(bs)))
+        } else {
+            Err(CBORError::FailedPredicate)
+        }
+    }
+}
+
+/// Decode a CBOR text string, succeeding only if it is equal to `expected`.
+///
+/// Returns `CBORError::FailedPredicate` if the value does not match.
+pub fn expect_text<'buf, 'e>(
+    expected: &'e str,
+) -> impl Fn(DecodeBufIterator<'buf>) -> DCResult<'buf> + 'e {
+    move |i| {
+        let (i, ts) = decode_tstr()(i)?;
+        if ts == expected {
+            Ok((i, CBOR::Tstr(ts)))
+        } else {
+            Err(CBORError::FailedPredicate)
         }
     }
 }
 
+/// (private) Compare two byte slices in time which depends only on their lengths.
+///
+/// The accumulator is passed through `black_box` on every byte so that the compiler cannot
+/// return early once a difference has been found.
+#[inline(never)]
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a
+        .iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| black_box(acc | (x ^ y)));
+    black_box(diff) == 0
+}
+
 /// Decode a CBOR `bool` value
 pub fn decode_bool<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf, bool> {
     move |mut iter| {
@@ -1079,7 +2219,7 @@ pub fn decode_bool<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf
             Some(CBOR::True) => Ok((iter, true)),
             Some(CBOR::False) => Ok((iter, false)),
             Some(_) => Err(CBORError::ExpectedType("bool")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1091,7 +2231,7 @@ pub fn decode_null<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf
         match item {
             Some(CBOR::Null) => Ok((iter, CBOR::Null)),
             Some(_) => Err(CBORError::ExpectedType("null")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1103,7 +2243,7 @@ pub fn decode_undefined<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult
         match item {
             Some(CBOR::Undefined) => Ok((iter, CBOR::Undefined)),
             Some(_) => Err(CBORError::ExpectedType("undefined")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1115,7 +2255,7 @@ pub fn decode_simple<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'b
         match item {
             Some(CBOR::Simple(v)) => Ok((iter, v)),
             Some(_) => Err(CBORError::ExpectedType("simple")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1127,7 +2267,7 @@ pub fn decode_array<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'bu
         match item {
             Some(CBOR::Array(ab)) => Ok((iter, ab)),
             Some(_) => Err(CBORError::ExpectedType("array")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1139,7 +2279,7 @@ pub fn decode_map<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf,
         match item {
             Some(CBOR::Map(mb)) => Ok((iter, mb)),
             Some(_) => Err(CBORError::ExpectedType("map")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1149,9 +2289,14 @@ pub fn decode_tag<'buf>() -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf,
     move |mut iter| {
         let item = iter.next();
         match item {
-            Some(CBOR::Tag(tb)) => Ok((iter, tb)),
+            Some(CBOR::Tag(tb)) => {
+                if iter.options.strict {
+                    tb.check_strict_content()?;
+                }
+                Ok((iter, tb))
+            }
             Some(_) => Err(CBORError::ExpectedType("tag")),
-            None => Err(CBORError::EndOfBuffer),
+            None => Err(iter.no_item_error()),
         }
     }
 }
@@ -1193,6 +2338,41 @@ where
     }
 }
 
+/// Match a CDDL nullable type (`T / null`), returning `None` for a CBOR `null` and otherwise
+/// the result of `f` in `Some`.
+///
+/// Unlike [`opt`], which allows an item to be absent, the item must be present: an item which
+/// is neither `null` nor matched by `f` is an error.
+///
+/// ```
+///# use tps_minicbor::decoder::*;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::CBOR;
+///# fn main() -> Result<(), CBORError> {
+/// // null, 2
+/// let it = SequenceBuffer::new(&[0xf6, 0x02]).into_iter();
+/// let (it, first) = nullable(is_uint())(it)?;
+/// let (_it, second) = nullable(is_uint())(it)?;
+/// assert_eq!((first, second), (None, Some(CBOR::UInt(2))));
+///# Ok(())
+///# }
+/// ```
+pub fn nullable<'buf, O, F>(f: F) -> impl Fn(DecodeBufIterator<'buf>) -> DCPResult<'buf, Option<O>>
+where
+    F: DecodeParser<'buf, O>,
+{
+    move |i| {
+        let mut next = i;
+        match next.next() {
+            Some(CBOR::Null) => Ok((next, None)),
+            _ => match f.parse(i) {
+                Ok((i, o)) => Ok((i, Some(o))),
+                Err(e) => Err(e),
+            },
+        }
+    }
+}
+
 /// Match one of two rules, returning result in `Option<CBOR>`.
 pub fn or<'buf, O, F>(
     f1: F,
@@ -0,0 +1,450 @@
+/***************************************************************************************************
+ * Copyright (c) 2023 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * rs_minicbor EAT claims-set support
+ *
+ * A typed builder and decoder for the common claims of an Entity Attestation Token claims-set
+ * (draft-ietf-rats-eat-18). Other claims can be read from the map directly using `MapBuf`.
+ **************************************************************************************************/
+use core::convert::TryFrom;
+
+use crate::array::array;
+use crate::ast::CBOR;
+#[cfg(feature = "combinators")]
+use crate::decode::DecodeBufIterator;
+#[cfg(feature = "combinators")]
+use crate::decode_combinators::CBORDecoder;
+use crate::encode::{EncodeBuffer, EncodeItem};
+use crate::error::CBORError;
+use crate::map::{map, MapBuf};
+
+#[cfg(feature = "trace")]
+use func_trace::trace;
+
+#[cfg(feature = "trace")]
+func_trace::init_depth_var!();
+
+/// EAT claim key: `eat_nonce`
+pub const EAT_NONCE: i64 = 10;
+/// EAT claim key: `ueid`
+pub const EAT_UEID: i64 = 256;
+/// EAT claim key: `oemid`
+pub const EAT_OEMID: i64 = 258;
+/// EAT claim key: `hwversion`
+pub const EAT_HW_VERSION: i64 = 260;
+/// EAT claim key: `security-level`
+pub const EAT_SECURITY_LEVEL: i64 = 261;
+/// EAT claim key: `oemboot` (secure boot)
+pub const EAT_SECURE_BOOT: i64 = 262;
+/// EAT claim key: `dbgstat` (debug status)
+pub const EAT_DEBUG_STATUS: i64 = 263;
+
+/***************************************************************************************************
+ * Claim values
+ **************************************************************************************************/
+
+/// The value of the `security-level` claim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityLevel {
+    /// No particular security protection
+    Unrestricted,
+    /// Protected from remote attacks
+    Restricted,
+    /// Protected from remote attacks and from most software attacks on the device
+    SecureRestricted,
+    /// Protected from physical attacks
+    Hardware,
+}
+
+impl From<SecurityLevel> for u64 {
+    fn from(level: SecurityLevel) -> Self {
+        match level {
+            SecurityLevel::Unrestricted => 1,
+            SecurityLevel::Restricted => 2,
+            SecurityLevel::SecureRestricted => 3,
+            SecurityLevel::Hardware => 4,
+        }
+    }
+}
+
+/// Convert a `security-level` value. Returns `CBORError::NotAllowed` if the value is not defined.
+impl TryFrom<u64> for SecurityLevel {
+    type Error = CBORError;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
+        match v {
+            1 => Ok(SecurityLevel::Unrestricted),
+            2 => Ok(SecurityLevel::Restricted),
+            3 => Ok(SecurityLevel::SecureRestricted),
+            4 => Ok(SecurityLevel::Hardware),
+            _ => Err(CBORError::NotAllowed),
+        }
+    }
+}
+
+impl<'buf> TryFrom<CBOR<'buf>> for SecurityLevel {
+    type Error = CBORError;
+
+    fn try_from(cbor: CBOR<'buf>) -> Result<Self, Self::Error> {
+        SecurityLevel::try_from(u64::try_from(cbor)?)
+    }
+}
+
+/// A `SecurityLevel` is encoded as its integer value.
+impl EncodeItem for SecurityLevel {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        u64::from(*self).encode(buf)
+    }
+}
+
+/// The value of the `dbgstat` (debug status) claim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugStatus {
+    /// Debug is enabled
+    Enabled,
+    /// Debug is disabled, but may be enabled again
+    Disabled,
+    /// Debug has been disabled since boot
+    DisabledSinceBoot,
+    /// Debug is permanently disabled, except possibly by the manufacturer
+    DisabledPermanently,
+    /// Debug is permanently disabled, including for the manufacturer
+    DisabledFullyAndPermanently,
+}
+
+impl From<DebugStatus> for u64 {
+    fn from(status: DebugStatus) -> Self {
+        match status {
+            DebugStatus::Enabled => 0,
+            DebugStatus::Disabled => 1,
+            DebugStatus::DisabledSinceBoot => 2,
+            DebugStatus::DisabledPermanently => 3,
+            DebugStatus::DisabledFullyAndPermanently => 4,
+        }
+    }
+}
+
+/// Convert a `dbgstat` value. Returns `CBORError::NotAllowed` if the value is not defined.
+impl TryFrom<u64> for DebugStatus {
+    type Error = CBORError;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
+        match v {
+            0 => Ok(DebugStatus::Enabled),
+            1 => Ok(DebugStatus::Disabled),
+            2 => Ok(DebugStatus::DisabledSinceBoot),
+            3 => Ok(DebugStatus::DisabledPermanently),
+            4 => Ok(DebugStatus::DisabledFullyAndPermanently),
+            _ => Err(CBORError::NotAllowed),
+        }
+    }
+}
+
+impl<'buf> TryFrom<CBOR<'buf>> for DebugStatus {
+    type Error = CBORError;
+
+    fn try_from(cbor: CBOR<'buf>) -> Result<Self, Self::Error> {
+        DebugStatus::try_from(u64::try_from(cbor)?)
+    }
+}
+
+/// A `DebugStatus` is encoded as its integer value.
+impl EncodeItem for DebugStatus {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        u64::from(*self).encode(buf)
+    }
+}
+
+/// The value of the `oemid` claim: either an IANA Private Enterprise Number, or a random or
+/// IEEE OUI based identifier carried as a `bstr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OemId<'a> {
+    /// A Private Enterprise Number
+    Pen(u64),
+    /// A random (16 byte) or IEEE OUI (3 byte) identifier
+    Bytes(&'a [u8]),
+}
+
+impl<'a> TryFrom<CBOR<'a>> for OemId<'a> {
+    type Error = CBORError;
+
+    fn try_from(cbor: CBOR<'a>) -> Result<Self, Self::Error> {
+        match cbor {
+            CBOR::UInt(v) => Ok(OemId::Pen(v)),
+            CBOR::Bstr(bytes) => Ok(OemId::Bytes(bytes)),
+            _ => Err(CBORError::ExpectedType("uint / bstr")),
+        }
+    }
+}
+
+impl<'a> EncodeItem for OemId<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        match self {
+            OemId::Pen(v) => v.encode(buf),
+            OemId::Bytes(bytes) => bytes.encode(buf),
+        }
+    }
+}
+
+/// The value of the `hwversion` claim, `[version: tstr, ? scheme: int]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HwVersion<'a> {
+    /// The version string.
+    pub version: &'a str,
+    /// The versioning scheme (from the CoSWID version-scheme registry), if given.
+    pub scheme: Option<i64>,
+}
+
+impl<'a> TryFrom<CBOR<'a>> for HwVersion<'a> {
+    type Error = CBORError;
+
+    fn try_from(cbor: CBOR<'a>) -> Result<Self, Self::Error> {
+        let ab = match cbor {
+            CBOR::Array(ab) if ab.len() == 1 || ab.len() == 2 => ab,
+            CBOR::Array(ab) => return Err(CBORError::ArrayLength(ab.len())),
+            _ => return Err(CBORError::ExpectedType("array")),
+        };
+        let mut items = ab.into_iter();
+        let version = items.next().ok_or(CBORError::MalformedEncoding)?;
+        Ok(HwVersion {
+            version: <&str>::try_from(version)?,
+            scheme: items.next().map(i64::try_from).transpose()?,
+        })
+    }
+}
+
+impl<'a> EncodeItem for HwVersion<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        array(|buf| {
+            let _ = buf.insert(&self.version)?;
+            if let Some(scheme) = self.scheme {
+                let _ = buf.insert(&scheme)?;
+            }
+            Ok(buf)
+        })
+        .encode(buf)
+    }
+}
+
+/***************************************************************************************************
+ * Claims-set
+ **************************************************************************************************/
+
+/// The common claims of an EAT claims-set. Each claim is optional.
+///
+/// A `ClaimsSet` is built with the setter methods and inserted into a
+/// [`CBORBuilder`](crate::encoder::CBORBuilder) like any other [`EncodeItem`]. The claims are
+/// encoded as a map in canonical key order (RFC8949, Section 4.2.1), whatever the order in which
+/// they were set, and claims which are not set are omitted.
+///
+/// Decoding borrows every field from the buffer holding the token. Claims other than those in
+/// `ClaimsSet` are ignored, but can be read from the map using [`MapBuf`].
+///
+/// ```
+///# use tps_minicbor::eat::{ClaimsSet, DebugStatus};
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// let claims = ClaimsSet::new()
+///     .debug_status(DebugStatus::Disabled)
+///     .nonce(&[0x01, 0x02]);
+/// let mut buffer = [0u8; 16];
+/// let mut encoder = CBORBuilder::new(&mut buffer);
+/// encoder.insert(&claims)?;
+/// // {10: h'0102', 263: 1}
+/// assert_eq!(encoder.encoded()?, &[0xa2, 0x0a, 0x42, 0x01, 0x02, 0x19, 0x01, 0x07, 0x01]);
+///# Ok(())
+///# }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClaimsSet<'a> {
+    /// `eat_nonce`
+    pub nonce: Option<&'a [u8]>,
+    /// `ueid`
+    pub ueid: Option<&'a [u8]>,
+    /// `oemid`
+    pub oemid: Option<OemId<'a>>,
+    /// `hwversion`
+    pub hw_version: Option<HwVersion<'a>>,
+    /// `security-level`
+    pub security_level: Option<SecurityLevel>,
+    /// `oemboot`
+    pub secure_boot: Option<bool>,
+    /// `dbgstat`
+    pub debug_status: Option<DebugStatus>,
+}
+
+impl<'a> ClaimsSet<'a> {
+    /// Create a `ClaimsSet` with no claims.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the `eat_nonce` claim.
+    pub fn nonce(mut self, nonce: &'a [u8]) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    /// Set the `ueid` claim.
+    pub fn ueid(mut self, ueid: &'a [u8]) -> Self {
+        self.ueid = Some(ueid);
+        self
+    }
+
+    /// Set the `oemid` claim.
+    pub fn oemid(mut self, oemid: OemId<'a>) -> Self {
+        self.oemid = Some(oemid);
+        self
+    }
+
+    /// Set the `hwversion` claim.
+    pub fn hw_version(mut self, version: &'a str, scheme: Option<i64>) -> Self {
+        self.hw_version = Some(HwVersion { version, scheme });
+        self
+    }
+
+    /// Set the `security-level` claim.
+    pub fn security_level(mut self, level: SecurityLevel) -> Self {
+        self.security_level = Some(level);
+        self
+    }
+
+    /// Set the `oemboot` claim.
+    pub fn secure_boot(mut self, enabled: bool) -> Self {
+        self.secure_boot = Some(enabled);
+        self
+    }
+
+    /// Set the `dbgstat` claim.
+    pub fn debug_status(mut self, status: DebugStatus) -> Self {
+        self.debug_status = Some(status);
+        self
+    }
+
+    /// Read the claims from a claims-set map. Claims which are not present are `None`.
+    ///
+    /// Returns an error if a claim is present but its value does not have the expected type, or
+    /// if an enumerated claim has a value which is not defined.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn from_map(mb: MapBuf<'a>) -> Result<Self, CBORError> {
+        Ok(ClaimsSet {
+            nonce: mb.get_int(EAT_NONCE).map(<&[u8]>::try_from).transpose()?,
+            ueid: mb.get_int(EAT_UEID).map(<&[u8]>::try_from).transpose()?,
+            oemid: mb.get_int(EAT_OEMID).map(OemId::try_from).transpose()?,
+            hw_version: mb
+                .get_int(EAT_HW_VERSION)
+                .map(HwVersion::try_from)
+                .transpose()?,
+            security_level: mb
+                .get_int(EAT_SECURITY_LEVEL)
+                .map(SecurityLevel::try_from)
+                .transpose()?,
+            secure_boot: mb
+                .get_int(EAT_SECURE_BOOT)
+                .map(bool::try_from)
+                .transpose()?,
+            debug_status: mb
+                .get_int(EAT_DEBUG_STATUS)
+                .map(DebugStatus::try_from)
+                .transpose()?,
+        })
+    }
+}
+
+/// The [`EncodeItem`] instance for `ClaimsSet` writes the claims which are set as a map, in
+/// canonical key order.
+impl<'a> EncodeItem for ClaimsSet<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        // The keys are all small positive integers, so numeric order is canonical order
+        map(|buf| {
+            if let Some(nonce) = self.nonce {
+                let _ = buf.insert_key_value(&EAT_NONCE, &nonce)?;
+            }
+            if let Some(ueid) = self.ueid {
+                let _ = buf.insert_key_value(&EAT_UEID, &ueid)?;
+            }
+            if let Some(oemid) = &self.oemid {
+                let _ = buf.insert_key_value(&EAT_OEMID, oemid)?;
+            }
+            if let Some(hw_version) = &self.hw_version {
+                let _ = buf.insert_key_value(&EAT_HW_VERSION, hw_version)?;
+            }
+            if let Some(level) = &self.security_level {
+                let _ = buf.insert_key_value(&EAT_SECURITY_LEVEL, level)?;
+            }
+            if let Some(enabled) = &self.secure_boot {
+                let _ = buf.insert_key_value(&EAT_SECURE_BOOT, enabled)?;
+            }
+            if let Some(status) = &self.debug_status {
+                let _ = buf.insert_key_value(&EAT_DEBUG_STATUS, status)?;
+            }
+            Ok(buf)
+        })
+        .encode(buf)
+    }
+}
+
+/// Decode an EAT claims-set map from the next item in `decoder`. See [`ClaimsSet::from_map`].
+///
+/// Returns `CBORError::ExpectedType("map")` if the next item is not a map. On error, the decoder
+/// is not advanced.
+///
+/// This function requires the `combinators` feature, which is enabled by default, as it uses
+/// the decode combinators.
+///
+/// ```
+///# use tps_minicbor::decoder::CBORDecoder;
+///# use tps_minicbor::eat::{decode_claims, DebugStatus};
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// // {10: h'0102', 263: 1}
+/// let decoder = CBORDecoder::from_slice(&[0xa2, 0x0a, 0x42, 0x01, 0x02, 0x19, 0x01, 0x07, 0x01]);
+/// let claims = decode_claims(&decoder)?;
+/// assert_eq!(claims.nonce, Some([0x01, 0x02].as_slice()));
+/// assert_eq!(claims.debug_status, Some(DebugStatus::Disabled));
+/// assert_eq!(claims.ueid, None);
+///# Ok(())
+///# }
+/// ```
+#[cfg(feature = "combinators")]
+#[cfg_attr(feature = "trace", trace)]
+pub fn decode_claims<'buf>(decoder: &CBORDecoder<'buf>) -> Result<ClaimsSet<'buf>, CBORError> {
+    decoder.decode_parsed(|mut it: DecodeBufIterator<'buf>| match it.next() {
+        Some(CBOR::Map(mb)) => Ok((it, ClaimsSet::from_map(mb)?)),
+        Some(_) => Err(CBORError::ExpectedType("map")),
+        None => Err(it.no_item_error()),
+    })
+}
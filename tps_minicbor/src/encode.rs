@@ -24,15 +24,17 @@
  * is designed for use in constrained systems and requires neither the Rust standard library
  * nor an allocator.
  *
- * There is an optional simplified serialization API which has a small memory cost. This can be
- * disabled with the `embedded` profile option.
+ * The encoder does not depend on the decode combinators, so it is fully available when building
+ * without the `combinators` feature.
  **************************************************************************************************/
 use crate::ast::CBOR;
 use crate::constants::*;
-use crate::decoder::{is_any, CBORDecoder, SequenceBuffer};
+use crate::decoder::SequenceBuffer;
 use crate::error::CBORError;
 use crate::utils::within;
 
+use std::time::Duration;
+
 #[cfg(feature = "full")]
 use std::mem::size_of;
 
@@ -100,8 +102,71 @@ impl<'buf> CBORBuilder<'buf> {
         }
     }
 
+    /// Set whether encoding a NaN or infinite float fails with `CBORError::NotAllowed`. See
+    /// [`EncodeBuffer::reject_non_finite_floats`].
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::CBORBuilder;
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = CBORBuilder::new(&mut buffer).reject_non_finite_floats(true);
+    /// ```
+    #[inline]
+    pub fn reject_non_finite_floats(self, reject: bool) -> Self {
+        CBORBuilder {
+            buf: self.buf.reject_non_finite_floats(reject),
+        }
+    }
+
+    /// Create an instance of CBORBuilder which encodes a CBOR sequence (RFC8742).
+    ///
+    /// A `CBORBuilder` never wraps top-level items in a container, so each call to
+    /// [`CBORBuilder::insert`] at top level appends one more item to the sequence, and
+    /// [`CBORBuilder::build`] returns a [`SequenceBuffer`] over all of them. This constructor
+    /// behaves exactly like [`CBORBuilder::new`]; it exists to make the intent explicit.
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::CBORBuilder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = CBORBuilder::sequence(&mut buffer);
+    /// encoder.insert(&1u8)?.insert(&"a")?.insert(&true)?;
+    /// assert_eq!(encoder.encoded()?, &[0x01, 0x61, 0x61, 0xf5]);
+    /// assert_eq!(encoder.build()?.into_iter().count(), 3);
+    ///# Ok(())
+    ///# }
+    /// ```
+    #[inline]
+    pub fn sequence(buf: &'buf mut [u8]) -> Self {
+        Self::new(buf)
+    }
+
+    /// Create a builder which encodes into an internal `[u8; N]`, rather than a buffer supplied
+    /// by the caller. See [`FixedCBORBuilder`].
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::CBORBuilder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# use tps_minicbor::types::array;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut encoder = CBORBuilder::new_array::<8>();
+    /// encoder.insert(&array(|buff| buff.insert(&1u8)?.insert(&"a")))?;
+    /// assert!(matches!(encoder.insert(&"too long"), Err(CBORError::EndOfBuffer)));
+    ///
+    /// let (bytes, len) = encoder.into_array();
+    /// assert_eq!(&bytes[..len], &[0x82, 0x01, 0x61, 0x61]);
+    ///# Ok(())
+    ///# }
+    /// ```
+    #[inline]
+    pub fn new_array<const N: usize>() -> FixedCBORBuilder<N> {
+        FixedCBORBuilder::new()
+    }
+
     /// Insert an `EncodeItem` item into an `EncodeBuffer`.
     ///
+    /// Items inserted at top level are encoded one after another, forming a CBOR sequence.
+    ///
     /// ```
     ///# use tps_minicbor::encoder::CBORBuilder;
     ///# use tps_minicbor::error::CBORError;
@@ -120,6 +185,50 @@ impl<'buf> CBORBuilder<'buf> {
         Ok(self)
     }
 
+    /// Insert an `EncodeItem` item, returning the total number of bytes encoded so far.
+    ///
+    /// If the item does not fit, the error is returned and the builder is left exactly as it was
+    /// before the call, so that the buffer only ever holds complete items. This allows output to
+    /// be split into fixed size blocks, e.g. for CoAP block-wise transfer (RFC7959): insert items
+    /// into a builder over a block sized buffer until one fails with
+    /// `CBORError::EndOfBuffer`, send the block, and insert the same item again into a fresh
+    /// builder. An item which fails on an empty block is too large for any block.
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::CBORBuilder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let items = ["one", "two", "three"];
+    /// let mut blocks = Vec::new();
+    /// let mut block = [0u8; 8];
+    /// let mut encoder = CBORBuilder::sequence(&mut block);
+    /// for item in items.iter() {
+    ///     if let Err(CBORError::EndOfBuffer) = encoder.insert_tracked(item) {
+    ///         blocks.push(encoder.encoded()?.to_vec());
+    ///         encoder = CBORBuilder::sequence(&mut block);
+    ///         encoder.insert_tracked(item)?;
+    ///     }
+    /// }
+    /// blocks.push(encoder.encoded()?.to_vec());
+    /// assert_eq!(blocks[0], b"\x63one\x63two");
+    /// assert_eq!(blocks[1], b"\x65three");
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn insert_tracked(&mut self, item: &dyn EncodeItem) -> Result<usize, CBORError> {
+        let (index, items) = (self.buf.index, self.buf.items);
+        match self.buf.insert(item) {
+            Ok(_) => Ok(self.buf.index),
+            Err(e) => {
+                // Discard any part of the item already written
+                self.buf.bytes[index..].fill(0);
+                self.buf.index = index;
+                self.buf.items = items;
+                Err(e)
+            }
+        }
+    }
+
     /// Insert a CBOR encoded bstr into an `EncodeBuffer`.
     ///
     /// This is generally used for the `bstr .cbor ...` CDDL use-case. It is the responsibility
@@ -131,22 +240,356 @@ impl<'buf> CBORBuilder<'buf> {
         Ok(self)
     }
 
+    /// Insert an array in which every entry is a `bstr`. See [`EncodeBuffer::insert_bstr_array`].
+    #[inline]
+    pub fn insert_bstr_array(&mut self, items: &[&[u8]]) -> Result<&mut Self, CBORError> {
+        self.buf.insert_bstr_array(items)?;
+        Ok(self)
+    }
+
+    /// Insert a `tstr` whose content is taken from `bytes`, which must be valid UTF-8. See
+    /// [`EncodeBuffer::insert_tstr_from_bytes`].
+    #[inline]
+    pub fn insert_tstr_from_bytes(&mut self, bytes: &[u8]) -> Result<&mut Self, CBORError> {
+        self.buf.insert_tstr_from_bytes(bytes)?;
+        Ok(self)
+    }
+
+    /// Insert a simple value. See [`EncodeBuffer::insert_simple`].
+    #[inline]
+    pub fn insert_simple(&mut self, v: u8) -> Result<&mut Self, CBORError> {
+        self.buf.insert_simple(v)?;
+        Ok(self)
+    }
+
+    /// Reserve `n` bytes to be written later. See [`EncodeBuffer::reserve`].
+    #[inline]
+    pub fn reserve(&mut self, n: usize) -> Result<SlotToken, CBORError> {
+        self.buf.reserve(n)
+    }
+
+    /// Write `bytes` into a reserved slot. See [`EncodeBuffer::fill_slot`].
+    #[inline]
+    pub fn fill_slot(&mut self, slot: SlotToken, bytes: &[u8]) -> Result<&mut Self, CBORError> {
+        self.buf.fill_slot(slot, bytes)?;
+        Ok(self)
+    }
+
     /// Return the underlying slice with CBOR encoded data
     #[inline]
     pub fn encoded(&self) -> Result<&[u8], CBORError> {
         self.buf.encoded()
     }
 
-    /// Return an instance of `SequenceBuffer` owning the underlying slice
+    /// Return the number of bytes of CBOR encoded so far, without finalizing the builder.
+    ///
+    /// Arrays, maps and tags are always completed (and their lengths fixed up) within the call
+    /// to [`CBORBuilder::insert`] that adds them, so the value returned always covers complete
+    /// items only.
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::CBORBuilder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# use tps_minicbor::types::array;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 32];
+    /// let mut encoder = CBORBuilder::new(&mut buffer);
+    /// assert_eq!(encoder.len(), 0);
+    /// encoder.insert(&array(|buff| buff.insert(&1)?.insert(&"a")))?;
+    /// assert_eq!(encoder.len(), 4);
+    ///# Ok(())
+    ///# }
+    /// ```
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buf.index
+    }
+
+    /// Return `true` if nothing has been encoded yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buf.index == 0
+    }
+
+    /// Return an instance of `SequenceBuffer` owning the underlying slice.
+    ///
+    /// The `SequenceBuffer` covers every top-level item inserted, in insertion order.
     pub fn build(&'buf self) -> Result<SequenceBuffer<'buf>, CBORError> {
         Ok(SequenceBuffer::new(self.buf.encoded()?))
     }
 }
 
+/// A builder which owns its buffer, a `[u8; N]`, so that the common case of encoding into a fixed
+/// size buffer on the stack does not need a separate buffer declaration. No allocator is needed.
+///
+/// Instances are normally created with [`CBORBuilder::new_array`]. Items are inserted as for
+/// [`CBORBuilder`]. If an item does not fit, `CBORError::EndOfBuffer` is returned and the
+/// builder is left as it was before the call.
+#[derive(Debug)]
+pub struct FixedCBORBuilder<const N: usize> {
+    bytes: [u8; N],
+    index: usize,
+    items: usize,
+}
+
+impl<const N: usize> FixedCBORBuilder<N> {
+    /// Create an empty instance of `FixedCBORBuilder`.
+    pub fn new() -> Self {
+        FixedCBORBuilder {
+            bytes: [0u8; N],
+            index: 0,
+            items: 0,
+        }
+    }
+
+    /// Insert an `EncodeItem` item.
+    pub fn insert(&mut self, item: &dyn EncodeItem) -> Result<&mut Self, CBORError> {
+        let mut buf = EncodeBuffer {
+            bytes: &mut self.bytes,
+            index: self.index,
+            items: self.items,
+            reject_non_finite_floats: false,
+        };
+        match buf.insert(item) {
+            Ok(_) => {
+                self.index = buf.index;
+                self.items = buf.items;
+                Ok(self)
+            }
+            Err(e) => {
+                // Discard any part of the item already written
+                self.bytes[self.index..].fill(0);
+                Err(e)
+            }
+        }
+    }
+
+    /// Return the CBOR encoded so far.
+    #[inline]
+    pub fn encoded(&self) -> &[u8] {
+        &self.bytes[..self.index]
+    }
+
+    /// Return the number of bytes of CBOR encoded so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.index
+    }
+
+    /// Return `true` if nothing has been encoded yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.index == 0
+    }
+
+    /// Consume the builder, returning the underlying array and the number of bytes of CBOR
+    /// encoded at its start. The remainder of the array is zero.
+    #[inline]
+    pub fn into_array(self) -> ([u8; N], usize) {
+        (self.bytes, self.index)
+    }
+}
+
+impl<const N: usize> Default for FixedCBORBuilder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An encoder for messages built as two separately encoded parts, a header and a body, which must
+/// be contiguous when sent. For example, the message ID of a TPS transaction may be encoded as the
+/// header while the body is built by a different function.
+///
+/// Each part is a [`CBORBuilder`] over a buffer supplied by the caller. The parts are copied into
+/// a single output buffer, header first, by [`ConcatEncoder::finalize`].
+///
+/// ```
+///# use tps_minicbor::encoder::ConcatEncoder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::map;
+///# fn main() -> Result<(), CBORError> {
+/// let mut header = [0u8; 4];
+/// let mut body = [0u8; 16];
+/// let mut encoder = ConcatEncoder::new(&mut header, &mut body);
+/// encoder.header().insert(&10u8)?;
+/// encoder.body().insert(&map(|buff| buff.insert_key_value(&1, &"a")))?;
+/// assert_eq!(encoder.len(), 5);
+///
+/// let mut out = [0u8; 8];
+/// let message = encoder.finalize(&mut out)?;
+/// assert_eq!(message.bytes, &[0x0a, 0xa1, 0x01, 0x61, 0x61]);
+/// assert!(matches!(
+///     encoder.finalize(&mut [0u8; 4]),
+///     Err(CBORError::ShortBuffer(5))
+/// ));
+///# Ok(())
+///# }
+/// ```
+#[derive(Debug)]
+pub struct ConcatEncoder<'h, 'b> {
+    header: CBORBuilder<'h>,
+    body: CBORBuilder<'b>,
+}
+
+impl<'h, 'b> ConcatEncoder<'h, 'b> {
+    /// Create an instance of `ConcatEncoder` encoding the header into `header` and the body into
+    /// `body`.
+    pub fn new(header: &'h mut [u8], body: &'b mut [u8]) -> Self {
+        ConcatEncoder {
+            header: CBORBuilder::new(header),
+            body: CBORBuilder::new(body),
+        }
+    }
+
+    /// Return the builder for the header.
+    #[inline]
+    pub fn header(&mut self) -> &mut CBORBuilder<'h> {
+        &mut self.header
+    }
+
+    /// Return the builder for the body.
+    #[inline]
+    pub fn body(&mut self) -> &mut CBORBuilder<'b> {
+        &mut self.body
+    }
+
+    /// Return the total number of bytes of CBOR encoded so far, header and body together. This
+    /// is the size of buffer needed by [`ConcatEncoder::finalize`].
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.header.len() + self.body.len()
+    }
+
+    /// Return `true` if nothing has been encoded yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty() && self.body.is_empty()
+    }
+
+    /// Copy the header followed by the body into the start of `out`, returning a
+    /// [`SequenceBuffer`] over the complete message.
+    ///
+    /// Returns `CBORError::ShortBuffer` with the required length if `out` is too small, in which
+    /// case `out` is not changed.
+    pub fn finalize<'out>(&self, out: &'out mut [u8]) -> Result<SequenceBuffer<'out>, CBORError> {
+        let (header, body) = (self.header.encoded()?, self.body.encoded()?);
+        let required = header.len() + body.len();
+        if out.len() < required {
+            return Err(CBORError::ShortBuffer(required));
+        }
+        out[..header.len()].copy_from_slice(header);
+        out[header.len()..required].copy_from_slice(body);
+        Ok(SequenceBuffer::new(&out[..required]))
+    }
+}
+
 /***************************************************************************************************
  * Encode Buffer
  **************************************************************************************************/
 
+/// A slot of bytes reserved in an [`EncodeBuffer`] by [`EncodeBuffer::reserve`], which is
+/// written by passing the token to [`EncodeBuffer::fill_slot`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct SlotToken {
+    offset: usize,
+    len: usize,
+}
+
+impl SlotToken {
+    /// Return the offset of the slot from the start of the buffer.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Return the number of bytes in the slot.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` if the slot has no bytes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// A builder which assembles a text string from fragments in a buffer supplied by the caller, so
+/// that a `tstr` can be computed without an allocator.
+///
+/// Every push is bounds checked: if a fragment does not fit, `CBORError::EndOfBuffer` is returned
+/// and the builder is left as it was before the call. [`TstrBuilder::finish`] returns the
+/// assembled `&str`, which can then be inserted as a `tstr`.
+///
+/// ```
+///# use tps_minicbor::encoder::{CBORBuilder, TstrBuilder};
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// let mut text = [0u8; 8];
+/// let mut builder = TstrBuilder::new(&mut text);
+/// builder.push_str("id-")?.push_char('7')?;
+/// assert!(matches!(builder.push_str("too long"), Err(CBORError::EndOfBuffer)));
+/// let id = builder.finish()?;
+/// assert_eq!(id, "id-7");
+///
+/// let mut buffer = [0u8; 8];
+/// let mut encoder = CBORBuilder::new(&mut buffer);
+/// encoder.insert(&id)?;
+/// assert_eq!(encoder.encoded()?, &[0x64, 0x69, 0x64, 0x2d, 0x37]);
+///# Ok(())
+///# }
+/// ```
+#[derive(Debug)]
+pub struct TstrBuilder<'a> {
+    bytes: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> TstrBuilder<'a> {
+    /// Create an empty `TstrBuilder` which writes into `bytes`.
+    #[inline]
+    pub fn new(bytes: &'a mut [u8]) -> Self {
+        TstrBuilder { bytes, len: 0 }
+    }
+
+    /// Append `s` to the text string.
+    pub fn push_str(&mut self, s: &str) -> Result<&mut Self, CBORError> {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(CBORError::EndOfBuffer);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(self)
+    }
+
+    /// Append the UTF-8 encoding of `c` to the text string.
+    #[inline]
+    pub fn push_char(&mut self, c: char) -> Result<&mut Self, CBORError> {
+        let mut utf8 = [0u8; 4];
+        self.push_str(c.encode_utf8(&mut utf8))
+    }
+
+    /// Return the number of bytes in the text string.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` if nothing has been pushed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return the assembled text string, or `CBORError::UTF8Error` if it is not valid UTF-8.
+    pub fn finish(self) -> Result<&'a str, CBORError> {
+        let bytes: &'a [u8] = self.bytes;
+        core::str::from_utf8(&bytes[..self.len]).map_err(|_| CBORError::UTF8Error)
+    }
+}
+
 /// `EncodeBuffer` is a smart view over a byte slice onto which we are encoding CBOR data. In most
 /// cases it is wrapped with a `CBORBuilder`, but it can be used stand-alone in which case the
 /// simplest code looks something like:
@@ -174,6 +617,7 @@ pub struct EncodeBuffer<'buf> {
     bytes: &'buf mut [u8],
     index: usize,
     items: usize,
+    reject_non_finite_floats: bool,
 }
 
 impl<'buf, 'short> EncodeBuffer<'buf>
@@ -197,9 +641,22 @@ where
             bytes: b,
             index: 0,
             items: 0,
+            reject_non_finite_floats: false,
         }
     }
 
+    /// Set whether encoding a NaN or infinite float fails with `CBORError::NotAllowed`. The
+    /// default is that all floats are encoded. This is intended for profiles such as EAT and CWT,
+    /// in which only finite numeric values are meaningful.
+    ///
+    /// The setting applies to every item inserted into this buffer, including the contents of
+    /// arrays, maps and tags.
+    #[inline]
+    pub fn reject_non_finite_floats(mut self, reject: bool) -> Self {
+        self.reject_non_finite_floats = reject;
+        self
+    }
+
     /// Insert an [`EncodeItem`] item into an [`EncodeBuffer`].
     ///
     /// This function will attempt to encode a value whose type is an instance of [`EncodeItem`]
@@ -275,6 +732,233 @@ where
         Ok(self)
     }
 
+    /// Insert a `bstr` whose contents are the CBOR encoded by `f`.
+    ///
+    /// This is used for `bstr .cbor ...` in CDDL terms, for example to construct the protected
+    /// headers of a COSE message. The contents are encoded in place and the `bstr` length is
+    /// fixed up afterwards, so no separate scratch buffer is required.
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::*;
+    ///# use tps_minicbor::error::CBORError;
+    ///# use tps_minicbor::types::{array, map};
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = CBORBuilder::new(&mut buffer);
+    /// // [<< {1: -7} >>]
+    /// let _ = encoder.insert(&array(|buff| {
+    ///     buff.insert_bstr_wrapped(|buff| buff.insert(&map(|buff| buff.insert_key_value(&1, &-7))))
+    /// }))?;
+    /// assert_eq!(encoder.encoded()?, &[0x81, 0x43, 0xa1, 0x01, 0x26]);
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn insert_bstr_wrapped<F>(&mut self, f: F) -> Result<&mut Self, CBORError>
+    where
+        F: for<'f> FnOnce(&'f mut EncodeBuffer<'buf>) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>,
+    {
+        // Reserve one byte for the MT/AI byte, as for arrays and maps
+        let mt_ai_index = self.get_index()?;
+        let items_before = self.items;
+        let contents_start = mt_ai_index + 1;
+        let _ = self.update_index(1)?;
+        let _ = f(self)?;
+        let contents_end = self.get_index()?;
+        let contents_len = contents_end - contents_start;
+
+        let len_len = match contents_len {
+            0..=23 => 0,
+            24..=0xff => 1,
+            0x100..=0xffff => 2,
+            0x10000..=0xffff_ffff => 4,
+            _ => 8,
+        };
+        if len_len > 0 {
+            self.move_items(contents_start, contents_start + len_len, contents_len)?;
+        }
+        self.set_index_abs(mt_ai_index);
+        let _ = encode_unsigned(self, contents_len as u64)?;
+        self.set_mt(MT_BSTR);
+        self.set_index_abs(contents_end + len_len);
+
+        // The items inserted by `f` are inside the bstr
+        self.items = items_before + 1;
+        Ok(self)
+    }
+
+    /// Insert an array in which every entry is a `bstr`, such as a list of UUIDs or hashes.
+    ///
+    /// The number of entries is known in advance, so the array header is written directly and no
+    /// length fix up is needed. The array counts as a single inserted item.
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::*;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = EncodeBuffer::new(&mut buffer);
+    /// let _ = encoder.insert_bstr_array(&[&[0x01, 0x02], &[], &[0x03]])?;
+    /// assert_eq!(encoder.encoded()?, &[0x83, 0x42, 0x01, 0x02, 0x40, 0x41, 0x03]);
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn insert_bstr_array(&mut self, items: &[&[u8]]) -> Result<&mut Self, CBORError> {
+        let items_before = self.items;
+        let _ = self.sized_start(MT_ARRAY, items.len() as u64)?;
+        for item in items {
+            let _ = item.encode(self)?;
+        }
+        self.items = items_before + 1;
+        Ok(self)
+    }
+
+    /// Insert a `tstr` whose content is taken from `bytes`, e.g. text read from a transport.
+    ///
+    /// Returns `CBORError::UTF8Error`, and inserts nothing, if `bytes` is not valid UTF-8. Text
+    /// which is already an `&str` is always valid, and can be inserted directly with
+    /// [`EncodeBuffer::insert`].
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::*;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = EncodeBuffer::new(&mut buffer);
+    /// let _ = encoder.insert_tstr_from_bytes(b"ab")?;
+    /// assert!(matches!(encoder.insert_tstr_from_bytes(&[0xff]), Err(CBORError::UTF8Error)));
+    /// assert_eq!(encoder.encoded()?, &[0x62, 0x61, 0x62]);
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn insert_tstr_from_bytes(&mut self, bytes: &[u8]) -> Result<&mut Self, CBORError> {
+        let text = core::str::from_utf8(bytes).map_err(|_| CBORError::UTF8Error)?;
+        self.insert(&text)
+    }
+
+    /// Insert a simple value (major type 7), such as an application-specific simple value.
+    ///
+    /// Values below 24 are encoded on one byte, and values from 32 upwards on two bytes, as
+    /// `0xf8` followed by the value. Values 24 to 31 have no well-formed encoding, so
+    /// `CBORError::MalformedEncoding` is returned and nothing is inserted. Note that values 20 to
+    /// 23 are `false`, `true`, `null` and `undefined`.
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::*;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = EncodeBuffer::new(&mut buffer);
+    /// let _ = encoder.insert_simple(16)?.insert_simple(255)?;
+    /// assert!(matches!(encoder.insert_simple(24), Err(CBORError::MalformedEncoding)));
+    /// assert_eq!(encoder.encoded()?, &[0xf0, 0xf8, 0xff]);
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn insert_simple(&mut self, v: u8) -> Result<&mut Self, CBORError> {
+        let _ = encode_item_simple(self, v)?;
+        self.items += 1;
+        Ok(self)
+    }
+
+    /// Write the head of an item with major type `major` (0 to 7) and argument `argument`, using
+    /// the shortest encoding of the argument (RFC8949, Section 4.2.1).
+    ///
+    /// This is a low-level primitive for items which the `insert` methods do not cover: the
+    /// caller is responsible for writing any content which the head announces, such as the bytes
+    /// of a string or the entries of an array. Only the head is checked, so the item is not
+    /// counted until its content is complete, e.g. by the length fix up of an enclosing array.
+    ///
+    /// Returns `CBORError::MalformedEncoding`, and writes nothing, if `major` is greater than 7,
+    /// or if it is 7 and `argument` is 24 to 31, which has no well-formed encoding. Returns
+    /// `CBORError::EndOfBuffer` if the head does not fit.
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::*;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = EncodeBuffer::new(&mut buffer);
+    /// // An array of two items, 500 and simple(16), followed by its content
+    /// let _ = encoder.write_header(4, 2)?.insert(&500u16)?.write_header(7, 16)?;
+    /// assert!(matches!(encoder.write_header(8, 0), Err(CBORError::MalformedEncoding)));
+    /// assert_eq!(encoder.encoded()?, &[0x82, 0x19, 0x01, 0xf4, 0xf0]);
+    ///# Ok(())
+    ///# }
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn write_header(&mut self, major: u8, argument: u64) -> Result<&mut Self, CBORError> {
+        if major > 7 || (major == 7 && (24..32).contains(&argument)) {
+            return Err(CBORError::MalformedEncoding);
+        }
+        let len = encode_unsigned(self, argument)?;
+        self.set_mt(major << 5);
+        self.update_index(len.0 + 1)?;
+        Ok(self)
+    }
+
+    /// Reserve `n` bytes at the current position, to be written later with
+    /// [`EncodeBuffer::fill_slot`] once their value is known, e.g. a length or checksum which
+    /// precedes the content it covers. The slot is zero until it is filled.
+    ///
+    /// The slot is raw bytes rather than a CBOR item, so it is not counted as an item in an
+    /// enclosing array or map. The length fixup of an enclosing array, map or tag may move its
+    /// contents, so a slot reserved inside one must be filled before its closure returns.
+    ///
+    /// Returns `CBORError::EndOfBuffer` if there are fewer than `n` bytes left.
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::*;
+    ///# use tps_minicbor::error::CBORError;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = EncodeBuffer::new(&mut buffer);
+    /// // A two byte big-endian length, followed by the CBOR it covers
+    /// let slot = encoder.reserve(2)?;
+    /// let start = encoder.encoded()?.len();
+    /// let _ = encoder.insert(&"abc")?.insert(&500u16)?;
+    /// let len = (encoder.encoded()?.len() - start) as u16;
+    /// let _ = encoder.fill_slot(slot, &len.to_be_bytes())?;
+    /// assert_eq!(encoder.encoded()?, &[0x00, 0x07, 0x63, 0x61, 0x62, 0x63, 0x19, 0x01, 0xf4]);
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn reserve(&mut self, n: usize) -> Result<SlotToken, CBORError> {
+        if !within(self.bytes, self.index, n) {
+            return Err(CBORError::EndOfBuffer);
+        }
+        let token = SlotToken {
+            offset: self.index,
+            len: n,
+        };
+        self.bytes[self.index..self.index + n].fill(0);
+        self.index += n;
+        Ok(token)
+    }
+
+    /// Write `bytes` into a slot reserved with [`EncodeBuffer::reserve`]. The token is consumed,
+    /// so each slot is filled once.
+    ///
+    /// Returns `CBORError::WrongLength` with the length of `bytes` if it is not exactly the
+    /// length of the slot, and `CBORError::IndexOutOfBounds` if the slot is not within the CBOR
+    /// encoded so far (e.g. it was reserved in a different buffer).
+    pub fn fill_slot(&mut self, slot: SlotToken, bytes: &[u8]) -> Result<&mut Self, CBORError> {
+        if bytes.len() != slot.len {
+            return Err(CBORError::WrongLength(bytes.len()));
+        }
+        if slot.offset + slot.len > self.index {
+            return Err(CBORError::IndexOutOfBounds);
+        }
+        self.bytes[slot.offset..slot.offset + slot.len].copy_from_slice(bytes);
+        Ok(self)
+    }
+
+    /// Reverse the order of the last `n` bytes written, e.g. to convert content written in
+    /// little-endian order to big-endian.
+    #[inline]
+    pub(crate) fn reverse_tail(&mut self, n: usize) {
+        self.bytes[self.index - n..self.index].reverse();
+    }
+
     /// Tag the item that follows
     pub(crate) fn tag_next_item(&mut self, tag: u64) -> Result<usize, CBORError> {
         // Encode the tag
@@ -340,6 +1024,30 @@ where
         self.context_finalize_common(ctx)
     }
 
+    /// Write the header for an array or map whose number of entries, `n`, is known in advance,
+    /// so that no fix up is needed once the contents have been inserted.
+    ///
+    /// > End-users should not call this function directly. The [`array_sized`] and [`map_sized`]
+    /// > functions manage this automatically.
+    ///
+    /// The index of the start of the array or map contents is returned.
+    #[inline]
+    pub(crate) fn sized_start(&mut self, mt: u8, n: u64) -> Result<usize, CBORError> {
+        let len = encode_unsigned(self, n)?;
+        self.set_mt(mt);
+        self.update_index(len.0 + 1)?;
+        Ok(self.index)
+    }
+
+    /// Return the number of top-level CBOR items encoded since `start`.
+    ///
+    /// This is used to check, in debug builds, that the contents of a sized array or map
+    /// match the number of entries in the header.
+    #[inline]
+    pub(crate) fn items_since(&self, start: usize) -> usize {
+        count_items(&self.bytes[start..self.index])
+    }
+
     /// Marker for the start of a CBOR Tag structure, which must later be finalized with a call
     /// to `tag_finalize`.
     ///
@@ -367,10 +1075,18 @@ where
     ///
     /// > End-users should not call this function directly. The [`Array::array`] function manages
     /// > this automatically.
+    ///
+    /// A tag must wrap exactly one data item (RFC8949, Section 3.4), so `MalformedEncoding` is
+    /// returned if the tag content is empty or contains more than one item.
     #[inline]
-    pub(crate) fn tag_finalize(&mut self, _ctx: &EncodeContext) -> Result<&mut Self, CBORError> {
-        // TODO: Currently there is no good way to check that the Tag has only a single item
-        Ok(self)
+    pub(crate) fn tag_finalize(&mut self, ctx: &EncodeContext) -> Result<&mut Self, CBORError> {
+        // The tag and its content are a single item if the content is well-formed
+        let tagged = &self.bytes[ctx.mt_ai_index..self.get_index()?];
+        if count_items(tagged) == 1 {
+            Ok(self)
+        } else {
+            Err(CBORError::MalformedEncoding)
+        }
     }
 
     /// Return a slice containing the encoded input.
@@ -430,7 +1146,7 @@ where
     /// Will generate a buffer overflow error if the write would overflow the buffer
     #[cfg_attr(feature = "trace", trace)]
     fn write_byte_at_offset(&mut self, offset: usize, val: u8) -> Result<(), CBORError> {
-        if within(self.bytes, self.index, offset) {
+        if within(self.bytes, self.index, offset + 1) {
             self.bytes[self.index + offset] = val;
             Ok(())
         } else {
@@ -489,22 +1205,23 @@ where
     fn context_finalize_common(&mut self, ctx: &EncodeContext) -> Result<&mut Self, CBORError> {
         // Determine what we put into the array
         let context_encode_end = self.get_index()?;
-        let no_of_items_after_context_added = self.items;
         let context_items_len_bytes = context_encode_end - ctx.ctx_encode_start;
 
-        // Create a slice over the content of the array or map and count the items
+        // Create a slice over the content of the array or map and count the items. Only the
+        // top-level items are counted: the contents of nested arrays, maps and tags are not.
         let content = &self.bytes[ctx.ctx_encode_start..self.get_index()?];
-        let ctx_param_value = if context_items_len_bytes > 0 {
-            match ctx.context_type {
-                ContextType::Array => count_items(content),
-                ContextType::Map => count_items(content) / 2,
-                // There should be no path to ContextType::Tag - context_finalize_common is
-                // not called from tag_finalize()
-                ContextType::Tag => return Err(CBORError::NotAllowed),
-            }
+        let no_of_items_in_context = if context_items_len_bytes > 0 {
+            count_items(content)
         } else {
             0
         };
+        let ctx_param_value = match ctx.context_type {
+            ContextType::Array => no_of_items_in_context,
+            ContextType::Map => no_of_items_in_context / 2,
+            // There should be no path to ContextType::Tag - context_finalize_common is
+            // not called from tag_finalize()
+            ContextType::Tag => return Err(CBORError::NotAllowed),
+        };
 
         // We need to check the size of encoding for the number of array items. If it is more than
         // can fit on MT/AI byte, we will need to move the encoded array items to follow the encoded
@@ -544,7 +1261,7 @@ where
         match ctx.context_type {
             ContextType::Array => Ok(self),
             ContextType::Map => {
-                if (no_of_items_after_context_added - ctx.no_of_items_before_ctx) % 2 == 0 {
+                if no_of_items_in_context % 2 == 0 {
                     Ok(self)
                 } else {
                     Err(CBORError::MalformedEncoding)
@@ -558,17 +1275,7 @@ where
 }
 
 fn count_items(buf: &[u8]) -> usize {
-    let mut count = 0;
-    {
-        let decoder = CBORDecoder::from_slice(buf);
-        let inc_count = |_a, _b| {
-            count += 1;
-            Ok(())
-        };
-
-        let _r = decoder.many0(is_any(), inc_count);
-    }
-    count
+    SequenceBuffer::new(buf).into_iter().count()
 }
 
 /***************************************************************************************************
@@ -640,7 +1347,6 @@ pub trait EncodeItem {
 
 #[cfg(feature = "full")]
 impl<'buf> EncodeItem for CBOR<'buf> {
-    #[cfg_attr(feature = "trace", trace)]
     fn encode<'f, 'b>(
         &self,
         buf: &'f mut EncodeBuffer<'b>,
@@ -656,9 +1362,9 @@ impl<'buf> EncodeItem for CBOR<'buf> {
             CBOR::Float16(val) => (&val).encode(buf),
             CBOR::Bstr(bs) => bs.encode(buf),
             CBOR::Tstr(ts) => ts.encode(buf),
-            CBOR::Array(ar) => CBOR::Array(ar).encode(buf),
-            CBOR::Map(mp) => CBOR::Map(mp).encode(buf),
-            CBOR::Tag(tb) => CBOR::Tag(tb).encode(buf),
+            CBOR::Array(ar) => ar.encode(buf),
+            CBOR::Map(mp) => mp.encode(buf),
+            CBOR::Tag(tb) => tb.encode(buf),
             CBOR::Simple(v) => {
                 match v {
                     // Values below are reserved for specific usage or are illegal
@@ -695,9 +1401,9 @@ impl<'buf> EncodeItem for CBOR<'buf> {
             CBOR::Float16(val) => (&val).encode(buf),
             CBOR::Bstr(bs) => bs.encode(buf),
             CBOR::Tstr(ts) => ts.encode(buf),
-            CBOR::Array(ar) => CBOR::Array(ar).encode(buf),
-            CBOR::Map(mp) => CBOR::Map(mp).encode(buf),
-            CBOR::Tag(tb) => CBOR::Tag(tb).encode(buf),
+            CBOR::Array(ar) => ar.encode(buf),
+            CBOR::Map(mp) => mp.encode(buf),
+            CBOR::Tag(tb) => tb.encode(buf),
             CBOR::Simple(v) => {
                 match v {
                     // Values below are reserved for specific usage or are illegal
@@ -729,9 +1435,9 @@ impl<'buf> EncodeItem for CBOR<'buf> {
             }
             CBOR::Bstr(bs) => bs.encode(buf),
             CBOR::Tstr(ts) => ts.encode(buf),
-            CBOR::Array(ar) => CBOR::Array(ar).encode(buf),
-            CBOR::Map(mp) => CBOR::Map(mp).encode(buf),
-            CBOR::Tag(tb) => CBOR::Tag(tb).encode(buf),
+            CBOR::Array(ar) => ar.encode(buf),
+            CBOR::Map(mp) => mp.encode(buf),
+            CBOR::Tag(tb) => tb.encode(buf),
             CBOR::Simple(v) => {
                 match v {
                     // Values below are reserved for specific usage or are illegal
@@ -770,7 +1476,6 @@ impl EncodeItem for u32 {
     ///
     /// Value is serialized using the preferred (shortest) serialization as a Major Type 0.
     #[inline]
-    #[cfg_attr(feature = "trace", trace)]
     fn encode<'f, 'b>(
         &self,
         buf: &'f mut EncodeBuffer<'b>,
@@ -782,7 +1487,6 @@ impl EncodeItem for u32 {
 impl EncodeItem for u16 {
     /// Encode a `u16` value on a buffer
     #[inline]
-    #[cfg_attr(feature = "trace", trace)]
     fn encode<'f, 'b>(
         &self,
         buf: &'f mut EncodeBuffer<'b>,
@@ -794,7 +1498,6 @@ impl EncodeItem for u16 {
 impl EncodeItem for u8 {
     /// Encode a `u8` value on a buffer
     #[inline]
-    #[cfg_attr(feature = "trace", trace)]
     fn encode<'f, 'b>(
         &self,
         buf: &'f mut EncodeBuffer<'b>,
@@ -806,7 +1509,6 @@ impl EncodeItem for u8 {
 impl EncodeItem for bool {
     /// Encode a `bool` value on a buffer
     #[inline]
-    #[cfg_attr(feature = "trace", trace)]
     fn encode<'f, 'b>(
         &self,
         buf: &'f mut EncodeBuffer<'b>,
@@ -883,7 +1585,6 @@ impl EncodeItem for i32 {
     /// Value is serialized using the preferred (shortest) serialization as a Major Type 0
     /// or Major Type 1.
     #[inline]
-    #[cfg_attr(feature = "trace", trace)]
     fn encode<'f, 'b>(
         &self,
         buf: &'f mut EncodeBuffer<'b>,
@@ -898,7 +1599,6 @@ impl EncodeItem for i16 {
     /// Value is serialized using the preferred (shortest) serialization as a Major Type 0
     /// or Major Type 1.
     #[inline]
-    #[cfg_attr(feature = "trace", trace)]
     fn encode<'f, 'b>(
         &self,
         buf: &'f mut EncodeBuffer<'b>,
@@ -912,7 +1612,6 @@ impl EncodeItem for i8 {
     ///
     /// Value is serialized using the preferred (shortest) serialization as a Major Type 0
     /// or Major Type 1.
-    #[cfg_attr(feature = "trace", trace)]
     #[inline]
     fn encode<'f, 'b>(
         &self,
@@ -923,7 +1622,9 @@ impl EncodeItem for i8 {
 }
 
 impl EncodeItem for &str {
-    /// Encode an `&str` value onto a buffer.
+    /// Encode an `&str` value onto a buffer. This is the only way in which a `tstr` is written,
+    /// so text from any other source must be checked for valid UTF-8 before it gets here (see
+    /// [`EncodeBuffer::insert_tstr_from_bytes`]).
     #[cfg_attr(feature = "trace", trace)]
     fn encode<'f, 'b>(
         &self,
@@ -962,6 +1663,60 @@ impl EncodeItem for &[u8] {
     }
 }
 
+impl<const N: usize> EncodeItem for [u8; N] {
+    /// Encode a fixed length byte array, such as a UUID or a hash, as a `bstr`.
+    ///
+    /// This allows fixed length byte arrays to be used directly as map keys, e.g.
+    /// `insert_key_value(&uuid.bytes, &value)`.
+    #[inline]
+    fn encode<'f, 'b>(
+        &self,
+        buf: &'f mut EncodeBuffer<'b>,
+    ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        self.as_slice().encode(buf)
+    }
+}
+
+impl EncodeItem for Duration {
+    /// Encode a `Duration` on a buffer as a whole number of seconds.
+    ///
+    /// Value is serialized as a Major Type 0. Any fraction of a second is discarded.
+    #[inline]
+    fn encode<'f, 'b>(
+        &self,
+        buf: &'f mut EncodeBuffer<'b>,
+    ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        self.as_secs().encode(buf)
+    }
+}
+
+impl<T: EncodeItem + ?Sized> EncodeItem for &T {
+    /// Encode a reference to any encodable item, so that `&&T`, as often produced by closure
+    /// captures, can be passed to [`EncodeBuffer::insert`] without dereferencing by hand.
+    #[inline]
+    fn encode<'f, 'b>(
+        &self,
+        buf: &'f mut EncodeBuffer<'b>,
+    ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        (**self).encode(buf)
+    }
+}
+
+impl<T: EncodeItem> EncodeItem for Option<T> {
+    /// Encode a value of a CDDL nullable type (`T / null`), where `None` is encoded as `null`.
+    /// This is the counterpart of the `nullable` decode combinator.
+    #[inline]
+    fn encode<'f, 'b>(
+        &self,
+        buf: &'f mut EncodeBuffer<'b>,
+    ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        match self {
+            Some(v) => v.encode(buf),
+            None => (CBOR::Null).encode(buf),
+        }
+    }
+}
+
 #[cfg(feature = "float")]
 impl EncodeItem for f64 {
     /// Encode an `f64` value on a buffer.
@@ -974,6 +1729,9 @@ impl EncodeItem for f64 {
         &self,
         buf: &'f mut EncodeBuffer<'b>,
     ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        if buf.reject_non_finite_floats && !self.is_finite() {
+            return Err(CBORError::NotAllowed);
+        }
         match preferred_encode(FloatType::Float64(*self)) {
             FloatType::Float64(_) => {
                 buf.write_byte_at_offset(0, PAYLOAD_EIGHT_BYTES)?;
@@ -1001,6 +1759,9 @@ impl EncodeItem for f32 {
         &self,
         buf: &'f mut EncodeBuffer<'b>,
     ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        if buf.reject_non_finite_floats && !self.is_finite() {
+            return Err(CBORError::NotAllowed);
+        }
         match preferred_encode(FloatType::Float32(*self)) {
             FloatType::Float64(_) | FloatType::Float32(_) => {
                 buf.write_byte_at_offset(0, PAYLOAD_FOUR_BYTES)?;
@@ -1027,8 +1788,13 @@ impl EncodeItem for f16 {
         &self,
         buf: &'f mut EncodeBuffer<'b>,
     ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        if buf.reject_non_finite_floats && !self.is_finite() {
+            return Err(CBORError::NotAllowed);
+        }
+        // All NaN values are written as the canonical quiet NaN, 0xf97e00 (RFC8949, 4.2.2)
+        let value = if self.is_nan() { f16::NAN } else { *self };
         buf.write_byte_at_offset(0, PAYLOAD_TWO_BYTES)?;
-        buf.write_slice_at_offset(1, &(self.to_be_bytes()))?;
+        buf.write_slice_at_offset(1, &(value.to_be_bytes()))?;
         let written_bytes = 1 + size_of::<f16>();
         buf.set_mt(MT_FLOAT);
         buf.update_index(written_bytes)?;
@@ -1048,9 +1814,14 @@ enum FloatType {
 /// value. If it is, reduced size does not imply any loss of precision.
 ///
 /// The comparisons are made at the byte-wise representation level
+///
+/// NaN values, whatever their payload or signalling bit, are always reduced to the canonical
+/// half-precision quiet NaN, so that they encode as `0xf97e00` as required by RFC8949, 4.2.2.
 #[cfg(feature = "float")]
 fn preferred_encode(v: FloatType) -> FloatType {
     match v {
+        FloatType::Float64(v64) if v64.is_nan() => FloatType::Float16(f16::NAN),
+        FloatType::Float32(v32) if v32.is_nan() => FloatType::Float16(f16::NAN),
         FloatType::Float64(v64) => {
             let v32 = v64 as f32;
             let v16: f16 = f16::from_f64(v64);
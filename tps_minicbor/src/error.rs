@@ -56,6 +56,10 @@ pub enum CBORError {
     /// The item was not expecting this Additional Information encoding. Probably malformed CBOR
     #[cfg_attr(any(feature="full", test), error("The item was not expecting this AI encoding. Probably malformed"))]
     AIError,
+    /// The Additional Information value is one of the values 28 to 30, which are reserved by
+    /// RFC 8949. The encoding is not well-formed.
+    #[cfg_attr(any(feature="full", test), error("Reserved Additional Information value"))]
+    ReservedAdditionalInfo,
     /// Encoding is illegal or unsupported
     #[cfg_attr(any(feature="full", test), error("Encoding is illegal or unsupported"))]
     MalformedEncoding,
@@ -80,6 +84,10 @@ pub enum CBORError {
     /// A CBOR map does not contain the requested key
     #[cfg_attr(any(feature="full", test), error("Map does not contain the requested key"))]
     KeyNotPresent,
+    /// A CBOR map does not contain a required key. The position of the missing key in the list
+    /// of required keys is returned.
+    #[cfg_attr(any(feature="full", test), error("Map does not contain a required key"))]
+    MissingKey(usize),
     /// The requested array index was outside of the bounds of the encoded CBOR
     #[cfg_attr(any(feature="full", test), error("Array index out of bounds"))]
     IndexOutOfBounds,
@@ -95,4 +103,33 @@ pub enum CBORError {
     /// The type read is not allowed here.
     #[cfg_attr(any(feature="full", test), error("Type not allowed here"))]
     NotAllowed,
+    /// The destination buffer is too short for the item. The required length is returned.
+    #[cfg_attr(any(feature="full", test), error("Destination buffer too short for the item"))]
+    ShortBuffer(usize),
+    /// The item does not have the exact length required. The actual length is returned.
+    #[cfg_attr(any(feature="full", test), error("Item does not have the required length"))]
+    WrongLength(usize),
+    /// An array does not have an allowed number of items. The actual number of items is returned.
+    #[cfg_attr(any(feature="full", test), error("Array length outside the allowed range"))]
+    ArrayLength(usize),
+    /// The content of a tag does not have the type required for the tag. The tag value is
+    /// returned.
+    #[cfg_attr(any(feature="full", test), error("Tag content does not have the required type"))]
+    TagContentMismatch(u64),
+    /// An indefinite length item was found where the decoder requires definite lengths.
+    #[cfg_attr(any(feature="full", test), error("Indefinite length item not allowed"))]
+    IndefiniteNotAllowed,
+    /// A nesting depth, item count or length limit set by the caller was exceeded.
+    #[cfg_attr(any(feature="full", test), error("Decode limit exceeded"))]
+    LimitExceeded,
+    /// The encoding is well-formed, but does not follow the deterministic encoding requirements.
+    #[cfg_attr(any(feature="full", test), error("Encoding is not deterministic"))]
+    NonCanonical,
+    /// An item was decoded, but bytes remain which should have been consumed with it.
+    #[cfg_attr(any(feature="full", test), error("Unexpected trailing data after the item"))]
+    TrailingData,
+    /// A declared length or item count is too large to be represented as a `usize` on this
+    /// target.
+    #[cfg_attr(any(feature="full", test), error("Declared length does not fit in a usize"))]
+    LengthOverflow,
 }
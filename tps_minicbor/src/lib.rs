@@ -82,6 +82,9 @@
 //!
 //! > If you want a more complex example, `examples/trivial_cose` demonstrates how to construct,
 //! > sign and verify a COSE_Sign1 structure using a deterministic ECDSA P256/SHA256 signature.
+//! >
+//! > With the `eat` feature, the `eat` module provides these claims as a typed `ClaimsSet`, which
+//! > is encoded and decoded without writing the map by hand.
 //!
 //! ### Encoding
 //!
@@ -234,7 +237,8 @@
 
 // Pull in std if we are testing or if it is defined as feature (because we run tests on a
 // platform supporting I/O and full feature set.
-#[cfg(any(feature = "full", test))]
+#[cfg(any(feature = "std", test))]
+#[cfg_attr(feature = "trace", macro_use)]
 extern crate std;
 
 // If we are really building no_std, pull in core as well. It is aliased as std so that "use"
@@ -256,61 +260,102 @@ mod cbor_diag;
 
 mod constants;
 mod decode;
+#[cfg(feature = "combinators")]
 mod decode_combinators;
 mod encode;
 mod map;
+#[cfg(feature = "std")]
+mod net;
+#[cfg(feature = "full")]
+mod owned;
 mod tag;
 mod utils;
 
 /// The `error` module contains error definitions used throughout `tps_minicbor`.
 pub mod error;
 
+/// The `canonical` module re-encodes CBOR in the deterministic encoding of RFC8949, Section 4.2.
+pub mod canonical;
+
+/// The `cose` module contains helpers to build and parse COSE (RFC9052) message structures.
+#[cfg(feature = "cose")]
+pub mod cose;
+
+/// The `cwt` module contains helpers for the CWT (RFC8392) tag wrapping a COSE message.
+#[cfg(feature = "cose")]
+pub mod cwt;
+
+/// The `eat` module contains a typed builder and decoder for the common claims of an Entity
+/// Attestation Token claims-set.
+#[cfg(feature = "eat")]
+pub mod eat;
+
 /// The `types` module exports the main [`types::CBOR`] structure which represents a single
 /// CBOR item, and the [`types::array`], [`types::map`] and [`types::tag`] which simplify
 /// encoding of maps, arrays and tags, respectively.
 pub mod types {
-    pub use super::array::array;
-    pub use super::ast::CBOR;
-    pub use super::map::map;
-    pub use super::tag::tag;
+    pub use super::array::{array, array_sized, empty_array};
+    pub use super::ast::{CBORType, Key, Label, NumberKind, CBOR};
+    pub use super::map::{empty_map, map, map_sized};
+    pub use super::tag::{bignum, bignum_from_le, self_describe, tag, tag_raw, SELF_DESCRIBE_TAG};
+    #[cfg(feature = "full")]
+    pub use super::owned::OwnedCbor;
+    #[cfg(feature = "full")]
+    pub use super::tag::{date_time_str, DateTimeStr};
 }
 
 /// The `decoder` module exports types, functions and traits for decoding CBOR items from a buffer
 pub mod decoder {
     // Low-level API
     pub use super::array::ArrayBuf;
-    pub use super::decode::{DecodeBufIterator, SequenceBuffer};
-    pub use super::map::MapBuf;
+    pub use super::decode::{
+        DecodeBufIterator, DecodeLimits, DecodeOptions, LogReader, SequenceBuffer,
+    };
+    pub use super::decode::is_well_formed;
+    pub use super::map::{MapBuf, MapIndex, MapVisitor};
     pub use super::tag::TagBuf;
 
     // Decode Combinators API
+    #[cfg(feature = "combinators")]
     pub use super::decode_combinators::{
         apply, cond, decode_bool, decode_bstr, decode_int, decode_nint, decode_null,
-        decode_simple, decode_tstr, decode_uint, decode_undefined, is_any, is_array, is_bool,
-        is_bstr, is_eof, is_false, is_int, is_map, is_nint, is_null, is_simple, is_tag,
-        is_tag_with_value, is_true, is_tstr, is_uint, is_undefined, opt, or, with_pred,
-        with_value, CBORDecoder,
+        decode_simple, decode_tstr, decode_uint, decode_undefined, expect_bytes, expect_bytes_ct,
+        expect_text, is_any, is_array, is_bool, is_bstr, is_eof, is_false, is_int, is_label,
+        is_map, is_nint, is_null, is_number, is_simple, is_tag, is_tag_with_value, is_true,
+        is_tstr, is_uint, is_undefined, nullable, opt, or, with_pred, with_value, CBORDecoder,
+        Envelope,
     };
 
     pub use super::utils::{Allowable, Filter};
 
     pub use super::constants::allow::*;
 
-    #[cfg(feature = "full")]
-    pub use super::decode_combinators::{is_date_time, is_epoch};
+    #[cfg(all(feature = "full", feature = "combinators"))]
+    pub use super::decode_combinators::{decode_date_time_raw, is_date_time, is_epoch};
 }
 
 /// The `encoder` module exports the [`encoder::CBORBuilder`] and [`encoder::EncodeBuffer`]
 /// types, which are used to encode values as CBOR items.
 pub mod encoder {
-    pub use super::encode::{CBORBuilder, EncodeBuffer, EncodeContext, EncodeItem};
+    pub use super::encode::{
+        CBORBuilder, ConcatEncoder, EncodeBuffer, EncodeContext, EncodeItem, FixedCBORBuilder,
+        SlotToken, TstrBuilder,
+    };
+    pub use super::map::MapEncodeBuffer;
 }
 
-/// The `debug` module exports CBOR diagnostic pretty-printing
+/// The `debug` module exports CBOR diagnostic pretty-printing and other helpers for debugging and
+/// testing
 #[cfg(feature = "full")]
 pub mod debug {
     #[cfg(feature = "full")]
     pub use super::cbor_diag::print_hex;
     #[cfg(feature = "full")]
     pub use super::cbor_diag::Diag;
+    #[cfg(feature = "full")]
+    pub use super::cbor_diag::semantic_eq;
+    #[cfg(feature = "full")]
+    pub use super::cbor_diag::RawState;
+    #[cfg(feature = "full")]
+    pub use super::cbor_diag::{from_json, to_json};
 }
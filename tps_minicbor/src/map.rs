@@ -24,8 +24,15 @@
  * This implementation is designed for use in constrained systems and requires neither the Rust
  * standard library nor an allocator.
  **************************************************************************************************/
-use crate::ast::CBOR;
-use crate::decode::{DecodeBufIterator, DecodeBufIteratorSource};
+use crate::ast::{CBORType, Key, Label, CBOR};
+#[cfg(feature = "full")]
+use crate::cbor_diag::RawState;
+use crate::constants::MT_MAP;
+#[cfg(feature = "combinators")]
+use crate::decode::DecodeLimits;
+use crate::decode::{DecodeBufIterator, DecodeBufIteratorSource, DecodeOptions};
+#[cfg(feature = "combinators")]
+use crate::decode_combinators::CBORDecoder;
 use crate::error::CBORError;
 
 use crate::encode::{EncodeBuffer, EncodeContext, EncodeItem};
@@ -46,7 +53,8 @@ func_trace::init_depth_var!();
 /// a CBOR map with an exposed map-like API.
 ///
 /// This CBOR buffer implementation does not support indefinite length items.
-#[derive(PartialEq, Debug, Copy, Clone)]
+#[derive(PartialEq, Copy, Clone)]
+#[cfg_attr(not(feature = "full"), derive(Debug))]
 pub struct MapBuf<'buf> {
     bytes: &'buf [u8],
     n_pairs: usize,
@@ -62,6 +70,13 @@ impl<'buf> MapBuf<'buf> {
         }
     }
 
+    /// Return the internal state of the `MapBuf` for debugging. The `Debug` implementation shows
+    /// the contents in CBOR diagnostic notation instead.
+    #[cfg(feature = "full")]
+    pub fn raw_state(&self) -> RawState<'buf> {
+        RawState::new("MapBuf", self.bytes, "n_pairs", self.n_pairs as u64)
+    }
+
     /// Return the number of item pairs in the `MapBuf`.
     #[cfg_attr(feature = "trace", trace)]
     #[inline]
@@ -99,6 +114,108 @@ impl<'buf> MapBuf<'buf> {
         }
     }
 
+    /// Look-up a value using a COSE/CWT style [`Label`] as the key.
+    ///
+    /// As for [`MapBuf::lookup`], this returns `CBORError::KeyNotPresent` if the label is not
+    /// present in the map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::Label;
+    ///
+    /// // {1: -7, "kid": h'01'}
+    /// let bytes = [0xa2, 0x01, 0x26, 0x63, 0x6b, 0x69, 0x64, 0x41, 0x01];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     assert_eq!(mb.lookup_by_label::<i64>(Label::Int(1))?, -7);
+    ///     assert_eq!(mb.lookup_by_label::<&[u8]>(Label::Text("kid"))?, &[0x01]);
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn lookup_by_label<V>(self, label: Label<'_>) -> Result<V, CBORError>
+    where
+        V: TryFrom<CBOR<'buf>> + Clone,
+    {
+        match self.get(&CBOR::from(label)) {
+            Some(cbor) => V::try_from(cbor).map_err(|_| CBORError::IncompatibleType),
+            None => Err(CBORError::KeyNotPresent),
+        }
+    }
+
+    /// Look-up a value nested inside maps and arrays by following a path of [`Key`]s.
+    ///
+    /// Each `Key::Int` or `Key::Text` segment selects a value from a map and each `Key::Index`
+    /// segment selects an item from an array. The value at the end of the path is converted
+    /// (fallibly) from CBOR. An empty path returns the map itself.
+    ///
+    /// Returns `CBORError::KeyNotPresent` or `CBORError::IndexOutOfBounds` if a segment is
+    /// missing, and `CBORError::ExpectedType` if a segment is applied to the wrong container type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::Key;
+    ///
+    /// // {260: {0: ["1.3.4", 1]}}
+    /// let bytes = [0xa1, 0x19, 0x01, 0x04, 0xa1, 0x00, 0x82, 0x65, 0x31, 0x2e, 0x33, 0x2e, 0x34, 0x01];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     let version: &str = mb.lookup_path(&[Key::Int(260), Key::Int(0), Key::Index(0)])?;
+    ///     assert_eq!(version, "1.3.4");
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn lookup_path<V>(self, path: &[Key<'_>]) -> Result<V, CBORError>
+    where
+        V: TryFrom<CBOR<'buf>> + Clone,
+    {
+        let mut current = CBOR::Map(self);
+        for segment in path {
+            current = match (*segment, current) {
+                (Key::Int(k), CBOR::Map(mb)) => {
+                    mb.get(&CBOR::from(k)).ok_or(CBORError::KeyNotPresent)?
+                }
+                (Key::Text(s), CBOR::Map(mb)) => {
+                    mb.get(&CBOR::Tstr(s)).ok_or(CBORError::KeyNotPresent)?
+                }
+                (Key::Index(n), CBOR::Array(ab)) => {
+                    ab.into_iter().nth(n).ok_or(CBORError::IndexOutOfBounds)?
+                }
+                (Key::Index(_), _) => return Err(CBORError::ExpectedType("array")),
+                (_, _) => return Err(CBORError::ExpectedType("map")),
+            };
+        }
+        V::try_from(current).map_err(|_| CBORError::IncompatibleType)
+    }
+
+    /// Return the major type of the value corresponding to `key`, or `None` if the key is not
+    /// present. This allows the type of a value to be checked before it is converted, e.g. using
+    /// [`MapBuf::lookup`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::{CBORDecoder, MapBuf};
+    /// use tps_minicbor::types::CBORType;
+    ///
+    /// // {1: "text", 2: h'00'}
+    /// let _ = CBORDecoder::from_slice(&[0xa2, 0x01, 0x64, 0x74, 0x65, 0x78, 0x74, 0x02, 0x41, 0x00])
+    ///     .map(|mb: MapBuf| {
+    ///         assert_eq!(mb.value_type(1), Some(CBORType::Tstr));
+    ///         assert_eq!(mb.value_type(2), Some(CBORType::Bstr));
+    ///         assert_eq!(mb.value_type(3), None);
+    ///         Ok(())
+    ///     });
+    /// ```
+    #[cfg_attr(feature = "trace", trace(disable(key)))]
+    pub fn value_type<K>(self, key: K) -> Option<CBORType>
+    where
+        K: Into<CBOR<'buf>>,
+    {
+        self.get(&key.into()).and_then(|value| value.cbor_type())
+    }
+
     /// Return `true` if `MapBuf` contains the provided key
     #[cfg_attr(feature = "trace", trace)]
     #[inline]
@@ -109,6 +226,39 @@ impl<'buf> MapBuf<'buf> {
         }
     }
 
+    /// Check that `MapBuf` contains every key in `required`, e.g. the mandatory claims of a CWT.
+    ///
+    /// Returns `CBORError::MissingKey(n)` if `required[n]` is the first key which is not present.
+    /// The error carries the position of the key rather than the key itself, as errors do not
+    /// borrow from the input.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::error::CBORError;
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// // {1: "a", 4: 1000}
+    /// let bytes = [0xa2, 0x01, 0x61, 0x61, 0x04, 0x19, 0x03, 0xe8];
+    /// let decoder = CBORDecoder::from_slice(&bytes);
+    /// let _ = decoder.map(|mb| {
+    ///     assert!(mb.require_keys(&[CBOR::UInt(1), CBOR::UInt(4)]).is_ok());
+    ///     assert!(matches!(
+    ///         mb.require_keys(&[CBOR::UInt(1), CBOR::UInt(2)]),
+    ///         Err(CBORError::MissingKey(1))
+    ///     ));
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn require_keys(self, required: &[CBOR]) -> Result<(), CBORError> {
+        match required.iter().position(|key| !self.contains_key(key)) {
+            Some(n) => Err(CBORError::MissingKey(n)),
+            None => Ok(()),
+        }
+    }
+
     /// Return the value corresponding to key.
     #[cfg_attr(feature = "trace", trace)]
     #[inline]
@@ -119,6 +269,46 @@ impl<'buf> MapBuf<'buf> {
         }
     }
 
+    /// Look-up a value, returning both the exact encoded bytes of the value and the decoded item,
+    /// in a single pass over the map.
+    ///
+    /// The bytes are a slice of the original input, so they are byte-exact even if the value
+    /// does not use preferred serialization. This allows a sub-structure, such as a claim which
+    /// has a detached signature, to be hashed or verified as well as decoded. Returns
+    /// `CBORError::KeyNotPresent` if the key is not present in the map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::CBOR;
+    ///
+    /// // {1: {2: 3}}
+    /// let bytes = [0xa1, 0x01, 0xa1, 0x02, 0x03];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     let (raw, value) = mb.value_with_raw(1)?;
+    ///     assert_eq!(raw, &[0xa1, 0x02, 0x03]);
+    ///     assert!(matches!(value, CBOR::Map(_)));
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg_attr(feature = "trace", trace(disable(key)))]
+    pub fn value_with_raw<K>(self, key: K) -> Result<(&'buf [u8], CBOR<'buf>), CBORError>
+    where
+        K: Into<CBOR<'buf>>,
+    {
+        let key = key.into();
+        let mut it = self.into_iter();
+        while let Some(item_key) = it.next() {
+            let value_start = it.index;
+            let value = it.next().ok_or(CBORError::ValueNotPresent)?;
+            if item_key == key {
+                return Ok((&self.bytes[value_start..it.index], value));
+            }
+        }
+        Err(CBORError::KeyNotPresent)
+    }
+
     /// Return the value corresponding to an integer key.
     ///
     /// In general, integers and strings are the recommended types to be used for map keys, so it
@@ -139,6 +329,108 @@ impl<'buf> MapBuf<'buf> {
         self.get(&CBOR::from(v))
     }
 
+    /// Return the value corresponding to a `bstr` key.
+    ///
+    /// This suits maps keyed by fixed length binary identifiers such as UUIDs.
+    #[cfg_attr(feature = "trace", trace)]
+    #[inline]
+    pub fn get_bstr(self, v: &[u8]) -> Option<CBOR<'buf>> {
+        self.get(&CBOR::Bstr(v))
+    }
+
+    /// Look-up a value using a `bstr` key, such as the bytes of a UUID.
+    ///
+    /// As for [`MapBuf::lookup`], this returns `CBORError::KeyNotPresent` if the key is not
+    /// present in the map. Unlike [`MapBuf::lookup`], the key does not need to outlive the map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    ///
+    /// // {h'0102': "first", h'0304': "second"}
+    /// let bytes = [
+    ///     0xa2, 0x42, 0x01, 0x02, 0x65, 0x66, 0x69, 0x72, 0x73, 0x74, 0x42, 0x03, 0x04, 0x66, 0x73,
+    ///     0x65, 0x63, 0x6f, 0x6e, 0x64,
+    /// ];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     let key = [0x03u8, 0x04];
+    ///     assert_eq!(mb.lookup_bytes::<&str>(&key)?, "second");
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn lookup_bytes<V>(self, key: &[u8]) -> Result<V, CBORError>
+    where
+        V: TryFrom<CBOR<'buf>> + Clone,
+    {
+        match self.get_bstr(key) {
+            Some(cbor) => V::try_from(cbor).map_err(|_| CBORError::IncompatibleType),
+            None => Err(CBORError::KeyNotPresent),
+        }
+    }
+
+    /// Look-up a value which is a `bstr` holding embedded CBOR (`bstr .cbor ...` in CDDL terms),
+    /// such as the protected headers of a COSE message, returning a [`CBORDecoder`] over the
+    /// embedded item. This is the untagged equivalent of [`TagBuf::decode_embedded`].
+    ///
+    /// The embedded item is checked with the default [`DecodeLimits`] before the decoder is
+    /// returned. See [`MapBuf::lookup_embedded_with_limits`] to apply other limits. An empty `bstr`
+    /// is allowed, and gives a decoder with nothing to decode. Errors are:
+    ///
+    /// - `CBORError::KeyNotPresent` if the key is not present in the map.
+    /// - `CBORError::ExpectedType("bstr")` if the value is not a `bstr`.
+    /// - Any error from [`CBORDecoder::validate`] if the `bstr` does not hold exactly one
+    ///   well-formed item within the limits.
+    ///
+    /// [`TagBuf::decode_embedded`]: crate::decoder::TagBuf::decode_embedded
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    ///
+    /// // {1: << {1: -7} >>}
+    /// let bytes = [0xa1, 0x01, 0x43, 0xa1, 0x01, 0x26];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     mb.lookup_embedded(1)?.map(|headers| {
+    ///         assert_eq!(headers.lookup::<i64, i64>(1)?, -7);
+    ///         Ok(())
+    ///     })?;
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg(feature = "combinators")]
+    pub fn lookup_embedded<K>(self, key: K) -> Result<CBORDecoder<'buf>, CBORError>
+    where
+        K: Into<CBOR<'buf>>,
+    {
+        self.lookup_embedded_with_limits(key, &DecodeLimits::default())
+    }
+
+    /// Look-up a value which is a `bstr` holding embedded CBOR, returning a [`CBORDecoder`] over
+    /// the embedded item once it has been checked against `limits`, e.g. the limits applied to
+    /// the enclosing message. See [`MapBuf::lookup_embedded`].
+    #[cfg(feature = "combinators")]
+    pub fn lookup_embedded_with_limits<K>(
+        self,
+        key: K,
+        limits: &DecodeLimits,
+    ) -> Result<CBORDecoder<'buf>, CBORError>
+    where
+        K: Into<CBOR<'buf>>,
+    {
+        match self.get(&key.into()) {
+            Some(CBOR::Bstr(bytes)) => {
+                if !bytes.is_empty() {
+                    CBORDecoder::validate(bytes, limits)?;
+                }
+                Ok(CBORDecoder::from_slice(bytes))
+            }
+            Some(_) => Err(CBORError::ExpectedType("bstr")),
+            None => Err(CBORError::KeyNotPresent),
+        }
+    }
+
     /// Return value corresponding to a map item that can have either an integer or a string
     /// key. This is a common use-case in IETF standards where human readability vs compactness
     /// tradeoff is supported.
@@ -192,6 +484,258 @@ impl<'buf> MapBuf<'buf> {
         }
     }
 
+    /// Call `visitor` with each (key, value) pair in the map, in the order in which they are
+    /// encoded. Decoding stops at the first error returned by the visitor, and that error is
+    /// returned.
+    ///
+    /// This allows a map to be decoded into a structure with the caller controlling how keys are
+    /// matched, how missing keys are defaulted and how unknown keys are handled. See
+    /// [`MapVisitor`] for an example.
+    #[cfg_attr(feature = "trace", trace(disable(visitor)))]
+    pub fn visit<V>(self, visitor: &mut V) -> Result<(), CBORError>
+    where
+        V: MapVisitor<'buf> + ?Sized,
+    {
+        let mut items = self.into_iter();
+        while let Some(key) = items.next() {
+            match items.next() {
+                Some(value) => visitor.field(&key, &value)?,
+                None => return Err(CBORError::MalformedEncoding),
+            }
+        }
+        Ok(())
+    }
+
+    /// Return an iterator over the (key, value) pairs of the map whose key is not in `known`, in
+    /// the order in which they are encoded. Keys are compared by decoded value.
+    ///
+    /// This supports forwarding map entries which are not understood, such as unknown COSE
+    /// headers or extension fields, e.g. by re-encoding them with `insert_key_value`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::CBOR;
+    /// # use tps_minicbor::error::CBORError;
+    /// # fn main() -> Result<(), CBORError> {
+    /// // {1: -7, 99: 0, 4: '11'}
+    /// let bytes = [0xa3, 0x01, 0x26, 0x18, 0x63, 0x00, 0x04, 0x42, 0x31, 0x31];
+    /// let known = [CBOR::UInt(1), CBOR::UInt(4)];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     let mut unknown = mb.entries_except(&known);
+    ///     assert_eq!(unknown.next(), Some((CBOR::UInt(99), CBOR::UInt(0))));
+    ///     assert_eq!(unknown.next(), None);
+    ///     Ok(())
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn entries_except<'k>(
+        self,
+        known: &'k [CBOR<'k>],
+    ) -> impl Iterator<Item = (CBOR<'buf>, CBOR<'buf>)> + 'k
+    where
+        'buf: 'k,
+    {
+        self.pairs().filter(move |(key, _)| !known.contains(key))
+    }
+
+    /// Return an iterator over the integer-keyed (key, value) pairs of the map, in the order in
+    /// which they are encoded. Integer keys which do not fit in an `i64` are skipped.
+    ///
+    /// Together with [`MapBuf::text_entries`], this partitions a map with `int / tstr` keys, e.g.
+    /// to process the standard (integer-keyed) claims of a CWT separately from private
+    /// (text-keyed) claims.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::CBOR;
+    /// # use tps_minicbor::error::CBORError;
+    /// # fn main() -> Result<(), CBORError> {
+    /// // {1: "a", "x": 2, -1: 3}
+    /// let bytes = [0xa3, 0x01, 0x61, 0x61, 0x61, 0x78, 0x02, 0x20, 0x03];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     let mut ints = mb.int_entries();
+    ///     assert_eq!(ints.next(), Some((1, CBOR::Tstr("a"))));
+    ///     assert_eq!(ints.next(), Some((-1, CBOR::UInt(3))));
+    ///     assert_eq!(ints.next(), None);
+    ///     Ok(())
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn int_entries(self) -> impl Iterator<Item = (i64, CBOR<'buf>)> {
+        self.pairs()
+            .filter_map(|(key, value)| match Label::try_from(key) {
+                Ok(Label::Int(key)) => Some((key, value)),
+                _ => None,
+            })
+    }
+
+    /// Return an iterator over the text-keyed (key, value) pairs of the map, in the order in
+    /// which they are encoded. See [`MapBuf::int_entries`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::CBOR;
+    /// # use tps_minicbor::error::CBORError;
+    /// # fn main() -> Result<(), CBORError> {
+    /// // {1: "a", "x": 2, -1: 3}
+    /// let bytes = [0xa3, 0x01, 0x61, 0x61, 0x61, 0x78, 0x02, 0x20, 0x03];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     let mut texts = mb.text_entries();
+    ///     assert_eq!(texts.next(), Some(("x", CBOR::UInt(2))));
+    ///     assert_eq!(texts.next(), None);
+    ///     Ok(())
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn text_entries(self) -> impl Iterator<Item = (&'buf str, CBOR<'buf>)> {
+        self.pairs().filter_map(|(key, value)| match key {
+            CBOR::Tstr(key) => Some((key, value)),
+            _ => None,
+        })
+    }
+
+    /// (private) Return an iterator over the (key, value) pairs of the map, in the order in which
+    /// they are encoded.
+    fn pairs(self) -> impl Iterator<Item = (CBOR<'buf>, CBOR<'buf>)> {
+        let mut items = self.into_iter();
+        std::iter::from_fn(move || {
+            let key = items.next()?;
+            let value = items.next()?;
+            Some((key, value))
+        })
+    }
+
+    /// Extract the values of a set of integer-keyed map items in a single pass over the map.
+    ///
+    /// `bindings` is a table of `(key, value)` pairs. Every value is first set to `None`, and then
+    /// each value whose key is present in the map is set to the corresponding map value. Map
+    /// items whose key does not appear in `bindings` are ignored. If the map contains a key more
+    /// than once, the first occurrence is used, as for [`MapBuf::get`].
+    ///
+    /// This avoids a separate scan of the map for each key when decoding structures with many
+    /// optional claims, such as CWT and EAT: each map item is decoded once. Each integer key is
+    /// then found by a linear search of `bindings`, so the number of key comparisons is
+    /// O(map × bindings), which is small for typical claim tables.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::CBOR;
+    /// # use tps_minicbor::error::CBORError;
+    /// # fn main() -> Result<(), CBORError> {
+    /// // {1: "a", 4: 1000, 99: 0}
+    /// let bytes = [0xa3, 0x01, 0x61, 0x61, 0x04, 0x19, 0x03, 0xe8, 0x18, 0x63, 0x00];
+    /// let (mut iss, mut exp, mut nbf) = (None, None, None);
+    /// let _ = CBORDecoder::from_slice(&bytes)
+    ///     .map(|mb| mb.extract(&mut [(1, &mut iss), (4, &mut exp), (5, &mut nbf)]))?;
+    /// assert_eq!(iss, Some(CBOR::Tstr("a")));
+    /// assert_eq!(exp, Some(CBOR::UInt(1000)));
+    /// assert_eq!(nbf, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn extract(self, bindings: &mut [(i64, &mut Option<CBOR<'buf>>)]) -> Result<(), CBORError> {
+        self.extract_bindings(bindings, false)
+    }
+
+    /// As [`MapBuf::extract`], except that if a key in `bindings` occurs more than once in the
+    /// map, `CBORError::NotAllowed` is returned.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn extract_strict(
+        self,
+        bindings: &mut [(i64, &mut Option<CBOR<'buf>>)],
+    ) -> Result<(), CBORError> {
+        self.extract_bindings(bindings, true)
+    }
+
+    /// (private) Implementation of [`MapBuf::extract`] and [`MapBuf::extract_strict`].
+    #[cfg_attr(feature = "trace", trace)]
+    fn extract_bindings(
+        self,
+        bindings: &mut [(i64, &mut Option<CBOR<'buf>>)],
+        strict: bool,
+    ) -> Result<(), CBORError> {
+        for (_, value) in bindings.iter_mut() {
+            **value = None;
+        }
+        let mut items = self.into_iter();
+        while let Some(key) = items.next() {
+            let value = items.next().ok_or(CBORError::MalformedEncoding)?;
+            let key = match key {
+                CBOR::UInt(_) | CBOR::NInt(_) => i64::try_from(key).ok(),
+                _ => None,
+            };
+            if let Some((_, binding)) = bindings.iter_mut().find(|(k, _)| Some(*k) == key) {
+                match binding {
+                    Some(_) if strict => return Err(CBORError::NotAllowed),
+                    Some(_) => (),
+                    None => **binding = Some(value),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Build a [`MapIndex`] of the integer keys of the map, with room for up to `N` keys, for
+    /// decoders which look up many keys in the same map.
+    ///
+    /// Building the index is a single pass over the map. Each look-up through the index is then a
+    /// binary search of the keys followed by decoding only the value found, rather than a scan
+    /// which decodes every key and value before it. Keys which are not integers are not indexed,
+    /// but remain available through [`MapIndex::map`]. If an integer key occurs more than once,
+    /// the first occurrence is indexed, as for [`MapBuf::lookup`].
+    ///
+    /// Returns `CBORError::LimitExceeded` if the map has more than `N` distinct integer keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tps_minicbor::decoder::CBORDecoder;
+    /// use tps_minicbor::types::CBOR;
+    /// # use tps_minicbor::error::CBORError;
+    /// # fn main() -> Result<(), CBORError> {
+    /// // {4: "b", "x": 2, -1: 3, 1: "a"}
+    /// let bytes = [0xa4, 0x04, 0x61, 0x62, 0x61, 0x78, 0x02, 0x20, 0x03, 0x01, 0x61, 0x61];
+    /// let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+    ///     let index = mb.int_index::<8>()?;
+    ///     assert_eq!(index.len(), 3);
+    ///     assert_eq!(index.lookup::<&str>(1)?, "a");
+    ///     assert_eq!(index.get_int(-1), Some(CBOR::UInt(3)));
+    ///     assert_eq!(index.get_int(2), None);
+    ///     Ok(())
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn int_index<const N: usize>(self) -> Result<MapIndex<'buf, N>, CBORError> {
+        let mut index = MapIndex {
+            map: self,
+            entries: [(0, 0); N],
+            len: 0,
+        };
+        let mut items = self.into_iter();
+        while let Some(key) = items.next() {
+            let value_offset = items.index;
+            let _ = items.next().ok_or(CBORError::MalformedEncoding)?;
+            if let Ok(Label::Int(key)) = Label::try_from(key) {
+                index.insert(key, value_offset)?;
+            }
+        }
+        Ok(index)
+    }
+
     /// (private) If there is a key matching `search_key`, return the
     /// key and corresponding value, otherwise return a `KeyNotPresent` error.
     #[cfg_attr(feature = "trace", trace)]
@@ -225,7 +769,139 @@ impl<'buf> IntoIterator for MapBuf<'buf> {
             buf: self.bytes,
             index: 0,
             source: DecodeBufIteratorSource::Map,
+            options: DecodeOptions::default(),
+            last_tag: None,
+        }
+    }
+}
+
+/// An index of the integer keys of a [`MapBuf`], built with [`MapBuf::int_index`].
+///
+/// The index holds up to `N` keys, sorted, with the offset of the corresponding value in the map.
+/// It requires no allocator: the capacity is fixed when the index is built.
+#[derive(Debug, Copy, Clone)]
+pub struct MapIndex<'buf, const N: usize> {
+    map: MapBuf<'buf>,
+    entries: [(i64, usize); N],
+    len: usize,
+}
+
+impl<'buf, const N: usize> MapIndex<'buf, N> {
+    /// Return the number of integer keys in the index.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` if the map has no integer keys.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return the [`MapBuf`] which has been indexed, e.g. to look up keys which are not integers.
+    pub fn map(&self) -> MapBuf<'buf> {
+        self.map
+    }
+
+    /// Return the value corresponding to an integer key, as [`MapBuf::get_int`].
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn get_int(&self, key: i64) -> Option<CBOR<'buf>> {
+        let entries = &self.entries[..self.len];
+        let pos = entries.binary_search_by_key(&key, |&(k, _)| k).ok()?;
+        let mut items = self.map.into_iter();
+        items.index = entries[pos].1;
+        items.next()
+    }
+
+    /// Look-up a value using an integer key, as [`MapBuf::lookup`].
+    ///
+    /// Returns `CBORError::KeyNotPresent` if the key is not present in the map.
+    pub fn lookup<V>(&self, key: i64) -> Result<V, CBORError>
+    where
+        V: TryFrom<CBOR<'buf>> + Clone,
+    {
+        match self.get_int(key) {
+            Some(cbor) => V::try_from(cbor).map_err(|_| CBORError::IncompatibleType),
+            None => Err(CBORError::KeyNotPresent),
+        }
+    }
+
+    /// (private) Add `key`, whose value is at `value_offset`, keeping the keys sorted. A key which
+    /// is already present is ignored.
+    fn insert(&mut self, key: i64, value_offset: usize) -> Result<(), CBORError> {
+        if let Err(pos) = self.entries[..self.len].binary_search_by_key(&key, |&(k, _)| k) {
+            if self.len == N {
+                return Err(CBORError::LimitExceeded);
+            }
+            self.entries.copy_within(pos..self.len, pos + 1);
+            self.entries[pos] = (key, value_offset);
+            self.len += 1;
         }
+        Ok(())
+    }
+}
+
+/// The [`EncodeItem`] instance for `MapBuf` re-encodes a decoded map. The contents are copied
+/// unchanged, so the encoding and order of the keys and values is preserved exactly.
+impl<'buf> EncodeItem for MapBuf<'buf> {
+    fn encode<'f, 'b>(
+        &self,
+        buf: &'f mut EncodeBuffer<'b>,
+    ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        let _ = buf.sized_start(MT_MAP, self.n_pairs as u64)?;
+        buf.insert_bstr_cbor(self.bytes)
+    }
+}
+
+/// A visitor over the (key, value) pairs of a map, used with [`MapBuf::visit`].
+///
+/// The visitor is called once for each pair in the map. Returning an error aborts the visit.
+/// Closures with a matching signature implement `MapVisitor`.
+///
+/// # Example
+///
+/// ```
+/// use tps_minicbor::decoder::{CBORDecoder, MapVisitor};
+/// use tps_minicbor::error::CBORError;
+/// use tps_minicbor::types::CBOR;
+/// use std::convert::TryFrom;
+///
+/// #[derive(Default)]
+/// struct Header<'buf> {
+///     alg: Option<i64>,
+///     kid: &'buf [u8],
+/// }
+///
+/// impl<'buf> MapVisitor<'buf> for Header<'buf> {
+///     fn field(&mut self, key: &CBOR<'buf>, value: &CBOR<'buf>) -> Result<(), CBORError> {
+///         match key {
+///             CBOR::UInt(1) => self.alg = Some(i64::try_from(*value)?),
+///             CBOR::UInt(4) => self.kid = <&[u8]>::try_from(*value)?,
+///             _ => (), // Ignore unknown keys
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let mut header = Header::default();
+/// // {1: -7, 4: '11'}
+/// let _ = CBORDecoder::from_slice(&[0xa2, 0x01, 0x26, 0x04, 0x42, 0x31, 0x31])
+///     .map(|mb| mb.visit(&mut header))
+///     .unwrap();
+/// assert_eq!(header.alg, Some(-7));
+/// assert_eq!(header.kid, b"11");
+/// ```
+pub trait MapVisitor<'buf> {
+    /// Visit a single (key, value) pair.
+    fn field(&mut self, key: &CBOR<'buf>, value: &CBOR<'buf>) -> Result<(), CBORError>;
+}
+
+impl<'buf, F> MapVisitor<'buf> for F
+where
+    F: FnMut(&CBOR<'buf>, &CBOR<'buf>) -> Result<(), CBORError>,
+{
+    #[inline]
+    fn field(&mut self, key: &CBOR<'buf>, value: &CBOR<'buf>) -> Result<(), CBORError> {
+        self(key, value)
     }
 }
 
@@ -233,6 +909,84 @@ impl<'buf> IntoIterator for MapBuf<'buf> {
  * Encoding Maps
  **************************************************************************************************/
 
+/// The buffer passed to the closures of [`map`] and [`map_sized`].
+///
+/// `MapEncodeBuffer` wraps an [`EncodeBuffer`] but only exposes functions which insert a key
+/// together with its value. Inserting an unpaired item into a map is therefore a compile time
+/// error rather than a malformed map.
+///
+/// ```compile_fail
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::types::map;
+/// let mut buffer = [0u8; 16];
+/// let mut encoder = CBORBuilder::new(&mut buffer);
+/// // A map with a key but no value is rejected
+/// let _ = encoder.insert(&map(|buff| buff.insert(&1)));
+/// ```
+pub struct MapEncodeBuffer<'m, 'buf> {
+    buf: &'m mut EncodeBuffer<'buf>,
+}
+
+impl<'m, 'buf> MapEncodeBuffer<'m, 'buf> {
+    /// Insert a (key, value) pair of `EncodeItems` into the map.
+    pub fn insert_key_value(
+        &mut self,
+        key: &dyn EncodeItem,
+        value: &dyn EncodeItem,
+    ) -> Result<&mut Self, CBORError> {
+        let _ = self.buf.insert_key_value(key, value)?;
+        Ok(self)
+    }
+
+    /// Insert a key whose value is a `bstr` containing the CBOR encoded by `f`. See
+    /// [`EncodeBuffer::insert_bstr_wrapped`].
+    ///
+    /// ```
+    ///# use tps_minicbor::encoder::CBORBuilder;
+    ///# use tps_minicbor::error::CBORError;
+    ///# use tps_minicbor::types::map;
+    ///# fn main() -> Result<(), CBORError> {
+    /// let mut buffer = [0u8; 16];
+    /// let mut encoder = CBORBuilder::new(&mut buffer);
+    /// // {1: << {1: -7} >>}
+    /// let _ = encoder.insert(&map(|buff| {
+    ///     buff.insert_key_bstr_wrapped(&1, |buff| buff.insert(&map(|buff| buff.insert_key_value(&1, &-7))))
+    /// }))?;
+    /// assert_eq!(encoder.encoded()?, &[0xa1, 0x01, 0x43, 0xa1, 0x01, 0x26]);
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn insert_key_bstr_wrapped<F>(
+        &mut self,
+        key: &dyn EncodeItem,
+        f: F,
+    ) -> Result<&mut Self, CBORError>
+    where
+        F: for<'f> FnOnce(
+            &'f mut EncodeBuffer<'buf>,
+        ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>,
+    {
+        let _ = self.buf.insert(key)?;
+        let _ = self.buf.insert_bstr_wrapped(f)?;
+        Ok(self)
+    }
+
+    /// Insert a key whose value has already been encoded in CBOR. See
+    /// [`EncodeBuffer::insert_bstr_cbor`].
+    ///
+    /// > Note: it is the responsibility of the caller to ensure that `cbor` is exactly one
+    /// > CBOR encoded item. Failure to do so will break the pairing of keys and values.
+    pub fn insert_key_cbor(
+        &mut self,
+        key: &dyn EncodeItem,
+        cbor: &[u8],
+    ) -> Result<&mut Self, CBORError> {
+        let _ = self.buf.insert(key)?;
+        let _ = self.buf.insert_bstr_cbor(cbor)?;
+        Ok(self)
+    }
+}
+
 /// A container structure for the closure used to manage encoding of CBOR maps, and in particular
 /// to ensure that the correct lifetime bounds are specified.
 ///
@@ -242,9 +996,9 @@ impl<'buf> IntoIterator for MapBuf<'buf> {
 /// Users should never need to directly instantiate `Map`. Instead, see [`map`].
 pub struct Map<F>
 where
-    F: for<'f, 'buf> Fn(
-        &'f mut EncodeBuffer<'buf>,
-    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>,
+    F: for<'f, 'm, 'buf> Fn(
+        &'f mut MapEncodeBuffer<'m, 'buf>,
+    ) -> Result<&'f mut MapEncodeBuffer<'m, 'buf>, CBORError>,
 {
     f: F,
 }
@@ -252,9 +1006,9 @@ where
 /// `Map` provides a constructor to contain the closure that constructs it
 impl<F> Map<F>
 where
-    F: for<'f, 'buf> Fn(
-        &'f mut EncodeBuffer<'buf>,
-    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>,
+    F: for<'f, 'm, 'buf> Fn(
+        &'f mut MapEncodeBuffer<'m, 'buf>,
+    ) -> Result<&'f mut MapEncodeBuffer<'m, 'buf>, CBORError>,
 {
     pub fn new(f: F) -> Map<F> {
         Map { f }
@@ -265,9 +1019,9 @@ where
 /// calculate the size of the map and ensure that the number of items inserted is a multiple of two.
 impl<F> EncodeItem for Map<F>
 where
-    F: for<'f, 'buf> Fn(
-        &'f mut EncodeBuffer<'buf>,
-    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>,
+    F: for<'f, 'm, 'buf> Fn(
+        &'f mut MapEncodeBuffer<'m, 'buf>,
+    ) -> Result<&'f mut MapEncodeBuffer<'m, 'buf>, CBORError>,
 {
     fn encode<'f, 'buf>(
         &self,
@@ -275,7 +1029,7 @@ where
     ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
         let mut map_ctx = EncodeContext::new();
         buf.map_start(&mut map_ctx)?;
-        let _ = (self.f)(buf)?;
+        let _ = (self.f)(&mut MapEncodeBuffer { buf })?;
         buf.map_finalize(&map_ctx)?;
         Ok(buf)
     }
@@ -284,8 +1038,8 @@ where
 /// A convenience function for the user to create an instance of a CBOR map. The user provides a
 /// closure which constructs the map contents.
 ///
-/// The user can insert the map keys and values separately, but the use of the convenience function
-/// [`EncodeBuffer::insert_key_value`] helps to avoid errors.
+/// The closure is given a [`MapEncodeBuffer`], which only allows keys and values to be inserted
+/// in pairs, so it is not possible to construct a map with a key that has no value.
 ///
 /// ```
 ///# use tps_minicbor::encoder::CBORBuilder;
@@ -306,9 +1060,123 @@ where
 /// ```
 pub fn map<F>(f: F) -> Map<F>
 where
-    F: for<'f, 'buf> Fn(
-        &'f mut EncodeBuffer<'buf>,
-    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>,
+    F: for<'f, 'm, 'buf> Fn(
+        &'f mut MapEncodeBuffer<'m, 'buf>,
+    ) -> Result<&'f mut MapEncodeBuffer<'m, 'buf>, CBORError>,
 {
     Map::new(f)
 }
+
+/// A container structure for the closure used to encode a CBOR map whose number of entries is
+/// known in advance.
+///
+/// Users should never need to directly instantiate `MapSized`. Instead, see [`map_sized`].
+pub struct MapSized<F>
+where
+    F: for<'f, 'm, 'buf> Fn(
+        &'f mut MapEncodeBuffer<'m, 'buf>,
+    ) -> Result<&'f mut MapEncodeBuffer<'m, 'buf>, CBORError>,
+{
+    n: usize,
+    f: F,
+}
+
+/// `MapSized` provides a constructor to contain the closure that constructs it
+impl<F> MapSized<F>
+where
+    F: for<'f, 'm, 'buf> Fn(
+        &'f mut MapEncodeBuffer<'m, 'buf>,
+    ) -> Result<&'f mut MapEncodeBuffer<'m, 'buf>, CBORError>,
+{
+    pub fn new(n: usize, f: F) -> MapSized<F> {
+        MapSized { n, f }
+    }
+}
+
+/// The [`EncodeItem`] instance for `MapSized` writes the map header immediately. In debug builds,
+/// it checks that the closure inserted exactly two items (key and value) for each entry.
+impl<F> EncodeItem for MapSized<F>
+where
+    F: for<'f, 'm, 'buf> Fn(
+        &'f mut MapEncodeBuffer<'m, 'buf>,
+    ) -> Result<&'f mut MapEncodeBuffer<'m, 'buf>, CBORError>,
+{
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let start = buf.sized_start(MT_MAP, self.n as u64)?;
+        let _ = (self.f)(&mut MapEncodeBuffer { buf })?;
+        debug_assert_eq!(buf.items_since(start), 2 * self.n);
+        Ok(buf)
+    }
+}
+
+/// A convenience function for the user to create an instance of a CBOR map where the number of
+/// (key, value) entries, `n`, is known in advance. The header is written immediately, avoiding
+/// the need to fix up the map length once the contents are known.
+///
+/// The user is trusted to insert exactly `n` entries in release builds. Debug builds check this.
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::map_sized;
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 16];
+///    let expected : &[u8] = &[0xa2, 0x01, 0x02, 0x03, 0x04];
+///
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    encoder.insert(&map_sized(2, |buff| {
+///        buff.insert_key_value(&1, &2)?
+///            .insert_key_value(&3, &4)
+///    }))?;
+///    assert_eq!(encoder.encoded()?, expected);
+///#    Ok(())
+///# }
+/// ```
+pub fn map_sized<F>(n: usize, f: F) -> MapSized<F>
+where
+    F: for<'f, 'm, 'buf> Fn(
+        &'f mut MapEncodeBuffer<'m, 'buf>,
+    ) -> Result<&'f mut MapEncodeBuffer<'m, 'buf>, CBORError>,
+{
+    MapSized::new(n, f)
+}
+
+/// An empty CBOR map. This is encoded directly as `0xa0`, with no closure and no length fix-up.
+///
+/// Users should never need to directly instantiate `EmptyMap`. Instead, see [`empty_map`].
+#[derive(Debug, Copy, Clone)]
+pub struct EmptyMap;
+
+impl EncodeItem for EmptyMap {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let _ = buf.sized_start(MT_MAP, 0)?;
+        Ok(buf)
+    }
+}
+
+/// A convenience function for the user to create an empty CBOR map, `{}`. This is common in COSE,
+/// e.g. for an empty unprotected header.
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::empty_map;
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 16];
+///
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    let _ = encoder.insert(&empty_map());
+///    assert_eq!(encoder.encoded()?, &[0xa0]);
+///#    Ok(())
+///# }
+/// ```
+#[inline]
+pub fn empty_map() -> EmptyMap {
+    EmptyMap
+}
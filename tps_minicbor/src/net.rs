@@ -0,0 +1,127 @@
+/***************************************************************************************************
+ * Copyright (c) 2021-2022 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * rs_minicbor IP address support
+ *
+ * Encoding and decoding of IPv4 and IPv6 addresses using the CBOR tags registered in RFC9164.
+ * Only the plain address form, a tagged bstr of 4 (IPv4) or 16 (IPv6) bytes, is supported.
+ **************************************************************************************************/
+use std::convert::TryFrom;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::ast::CBOR;
+use crate::encode::{EncodeBuffer, EncodeItem};
+use crate::error::CBORError;
+use crate::tag::tag;
+
+#[cfg(feature = "trace")]
+use func_trace::trace;
+
+#[cfg(feature = "trace")]
+func_trace::init_depth_var!();
+
+/// CBOR tag for an IPv4 address (RFC9164)
+pub const TAG_IPV4: u64 = 52;
+/// CBOR tag for an IPv6 address (RFC9164)
+pub const TAG_IPV6: u64 = 54;
+
+/***************************************************************************************************
+ * Encoding IP Addresses
+ **************************************************************************************************/
+
+/// Encode an `Ipv4Addr` as `52(h'xxxxxxxx')`
+///
+/// ```
+///# use std::net::Ipv4Addr;
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# fn main() -> Result<(), CBORError> {
+/// let mut buffer = [0u8; 16];
+/// let mut encoder = CBORBuilder::new(&mut buffer);
+/// encoder.insert(&Ipv4Addr::new(192, 0, 2, 1))?;
+/// assert_eq!(encoder.encoded()?, &[0xd8, 0x34, 0x44, 0xc0, 0x00, 0x02, 0x01]);
+///# Ok(())
+///# }
+/// ```
+impl EncodeItem for Ipv4Addr {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let octets = self.octets();
+        tag(TAG_IPV4, |buf| buf.insert(&octets.as_slice())).encode(buf)
+    }
+}
+
+/// Encode an `Ipv6Addr` as `54(h'xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx')`
+impl EncodeItem for Ipv6Addr {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let octets = self.octets();
+        tag(TAG_IPV6, |buf| buf.insert(&octets.as_slice())).encode(buf)
+    }
+}
+
+/***************************************************************************************************
+ * Decoding IP Addresses
+ **************************************************************************************************/
+
+/// (private) Extract the address bytes from a CBOR item tagged with `tag_value`, checking that
+/// there are exactly `N` of them.
+fn address_bytes<const N: usize>(value: CBOR, tag_value: u64) -> Result<[u8; N], CBORError> {
+    match value {
+        CBOR::Tag(tb) if tb.get_tag() == tag_value => match tb.into_iter().next() {
+            Some(CBOR::Bstr(bytes)) => {
+                <[u8; N]>::try_from(bytes).map_err(|_| CBORError::WrongLength(bytes.len()))
+            }
+            Some(_) => Err(CBORError::IncompatibleType),
+            None => Err(CBORError::MalformedEncoding),
+        },
+        CBOR::Tag(_) => Err(CBORError::ExpectedTag(tag_value)),
+        _ => Err(CBORError::IncompatibleType),
+    }
+}
+
+/// Attempt to convert a CBOR item of the form `52(h'xxxxxxxx')` into an `Ipv4Addr`.
+///
+/// Returns `Err(CBORError::WrongLength)` if the bstr is not exactly 4 bytes long.
+impl<'buf> TryFrom<CBOR<'buf>> for Ipv4Addr {
+    type Error = CBORError;
+
+    #[cfg_attr(feature = "trace", trace)]
+    fn try_from(value: CBOR<'buf>) -> Result<Self, Self::Error> {
+        address_bytes::<4>(value, TAG_IPV4).map(Ipv4Addr::from)
+    }
+}
+
+/// Attempt to convert a CBOR item of the form `54(h'xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx')` into an
+/// `Ipv6Addr`.
+///
+/// Returns `Err(CBORError::WrongLength)` if the bstr is not exactly 16 bytes long.
+impl<'buf> TryFrom<CBOR<'buf>> for Ipv6Addr {
+    type Error = CBORError;
+
+    #[cfg_attr(feature = "trace", trace)]
+    fn try_from(value: CBOR<'buf>) -> Result<Self, Self::Error> {
+        address_bytes::<16>(value, TAG_IPV6).map(Ipv6Addr::from)
+    }
+}
@@ -0,0 +1,129 @@
+/***************************************************************************************************
+ * Copyright (c) 2023 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * rs_minicbor owned CBOR items
+ *
+ * A heap-allocated copy of a decoded item, which owns its contents and so may outlive the buffer
+ * it was decoded from. Requires an allocator, so only available with the `full` feature.
+ **************************************************************************************************/
+use std::boxed::Box;
+use std::string::String;
+use std::vec::Vec;
+
+use chrono::{DateTime, FixedOffset};
+use half::f16;
+
+use crate::ast::CBOR;
+
+/// An owned copy of a [`CBOR`] item, produced by [`CBOR::to_owned`]. The variants mirror those of
+/// `CBOR`, but strings are held in a `Vec<u8>` or `String`, and the contents of arrays, maps and
+/// tags are decoded into owned items.
+#[derive(PartialEq, Debug, Clone)]
+pub enum OwnedCbor {
+    /// A CBOR positive integer.
+    UInt(u64),
+    /// A CBOR negative integer, with the same representation as [`CBOR::NInt`].
+    NInt(u64),
+    /// A CBOR float encoded on 64 bits.
+    Float64(f64),
+    /// A CBOR float encoded on 32 bits.
+    Float32(f32),
+    /// A CBOR float encoded on 16 bits.
+    Float16(f16),
+    /// A CBOR bytestring.
+    Bstr(Vec<u8>),
+    /// A CBOR text string.
+    Tstr(String),
+    /// A CBOR array.
+    Array(Vec<OwnedCbor>),
+    /// A CBOR map, as (key, value) pairs in the order they were encoded.
+    Map(Vec<(OwnedCbor, OwnedCbor)>),
+    /// A CBOR tagged item, holding the tag value and the tagged item.
+    Tag(u64, Box<OwnedCbor>),
+    /// A CBOR simple value.
+    Simple(u8),
+    /// A CBOR `false` value.
+    False,
+    /// A CBOR `true` value.
+    True,
+    /// A CBOR `null` value.
+    Null,
+    /// A CBOR `undefined` value
+    Undefined,
+    /// The end of buffer marker. This is also used as the content of a tag with no content.
+    Eof,
+    /// A CBOR `Date-Time` item.
+    DateTime(DateTime<FixedOffset>),
+    /// A CBOR `Unix epoch` time.
+    Epoch(i64),
+}
+
+impl<'buf> CBOR<'buf> {
+    /// Make an owned copy of the item, which no longer borrows from the buffer it was decoded
+    /// from. The contents of arrays, maps and tags are copied recursively. As when iterating over
+    /// a container, decoding of its contents stops at the first item which cannot be decoded.
+    ///
+    /// ```
+    ///# use tps_minicbor::decoder::SequenceBuffer;
+    ///# use tps_minicbor::types::OwnedCbor;
+    /// let owned = {
+    ///     let bytes = vec![0x82, 0x61, 0x61, 0x41, 0x01];
+    ///     SequenceBuffer::new(&bytes).into_iter().next().unwrap().to_owned()
+    /// };
+    /// assert_eq!(
+    ///     owned,
+    ///     OwnedCbor::Array(vec![OwnedCbor::Tstr("a".into()), OwnedCbor::Bstr(vec![1])])
+    /// );
+    /// ```
+    pub fn to_owned(&self) -> OwnedCbor {
+        match *self {
+            CBOR::UInt(v) => OwnedCbor::UInt(v),
+            CBOR::NInt(v) => OwnedCbor::NInt(v),
+            CBOR::Float64(v) => OwnedCbor::Float64(v),
+            CBOR::Float32(v) => OwnedCbor::Float32(v),
+            CBOR::Float16(v) => OwnedCbor::Float16(v),
+            CBOR::Bstr(bs) => OwnedCbor::Bstr(bs.to_vec()),
+            CBOR::Tstr(s) => OwnedCbor::Tstr(String::from(s)),
+            CBOR::Array(ab) => {
+                OwnedCbor::Array(ab.into_iter().map(|item| item.to_owned()).collect())
+            }
+            CBOR::Map(mb) => {
+                let mut pairs = Vec::with_capacity(mb.len());
+                let mut it = mb.into_iter();
+                while let (Some(key), Some(value)) = (it.next(), it.next()) {
+                    pairs.push((key.to_owned(), value.to_owned()));
+                }
+                OwnedCbor::Map(pairs)
+            }
+            CBOR::Tag(tb) => {
+                let item = tb.into_iter().next().unwrap_or(CBOR::Eof);
+                OwnedCbor::Tag(tb.get_tag(), Box::new(item.to_owned()))
+            }
+            CBOR::Simple(v) => OwnedCbor::Simple(v),
+            CBOR::False => OwnedCbor::False,
+            CBOR::True => OwnedCbor::True,
+            CBOR::Null => OwnedCbor::Null,
+            CBOR::Undefined => OwnedCbor::Undefined,
+            CBOR::Eof => OwnedCbor::Eof,
+            CBOR::DateTime(dt) => OwnedCbor::DateTime(dt),
+            CBOR::Epoch(v) => OwnedCbor::Epoch(v),
+        }
+    }
+}
@@ -26,7 +26,12 @@
  **************************************************************************************************/
 use core::convert::TryFrom;
 use crate::ast::CBOR;
-use crate::decode::{DecodeBufIterator, DecodeBufIteratorSource};
+#[cfg(feature = "full")]
+use crate::cbor_diag::RawState;
+use crate::decode::{DecodeBufIterator, DecodeBufIteratorSource, DecodeOptions};
+use crate::decode::SequenceBuffer;
+#[cfg(feature = "combinators")]
+use crate::decode_combinators::CBORDecoder;
 
 use crate::encode::{EncodeBuffer, EncodeContext, EncodeItem};
 use crate::error::CBORError;
@@ -44,7 +49,8 @@ func_trace::init_depth_var!();
 /// a CBOR map with an exposed map-like API.
 ///
 /// This CBOR buffer implementation does not support indefinite length items.
-#[derive(PartialEq, Debug, Copy, Clone)]
+#[derive(PartialEq, Copy, Clone)]
+#[cfg_attr(not(feature = "full"), derive(Debug))]
 pub struct TagBuf<'buf> {
     tag: u64,
     bytes: &'buf [u8],
@@ -57,6 +63,13 @@ impl<'buf> TagBuf<'buf> {
         TagBuf { bytes: init, tag }
     }
 
+    /// Return the internal state of the `TagBuf` for debugging. The `Debug` implementation shows
+    /// the contents in CBOR diagnostic notation instead.
+    #[cfg(feature = "full")]
+    pub fn raw_state(&self) -> RawState<'buf> {
+        RawState::new("TagBuf", self.bytes, "tag", self.tag)
+    }
+
     /// Get the tag value for this instance of `TagBuf`.
     #[inline]
     #[cfg_attr(feature = "trace", trace)]
@@ -79,6 +92,129 @@ impl<'buf> TagBuf<'buf> {
             None => Err(CBORError::MalformedEncoding)
         }
     }
+
+    /// Decode the item embedded in a tag 24 (encoded CBOR data item, RFC8949, Section 3.4.5.1).
+    ///
+    /// The tag content must be a `bstr` holding exactly one well-formed CBOR item, which is
+    /// returned. Errors are:
+    ///
+    /// - `CBORError::ExpectedTag(24)` if this is not tag 24.
+    /// - `CBORError::TagContentMismatch(24)` if the tag content is not a `bstr`.
+    /// - `CBORError::TrailingData` if bytes remain in the `bstr` after the embedded item.
+    /// - Any error decoding the embedded item.
+    ///
+    /// ```
+    ///# use tps_minicbor::error::CBORError;
+    ///# use tps_minicbor::decoder::TagBuf;
+    ///# use tps_minicbor::types::CBOR;
+    ///# fn main() -> Result<(), CBORError> {
+    /// // 24(h'1817') and 24(h'1701'): the second has a trailing byte
+    /// let tb = TagBuf::new(&[0x42, 0x18, 0x17], 24);
+    /// assert_eq!(tb.decode_embedded()?, CBOR::UInt(23));
+    /// let tb = TagBuf::new(&[0x42, 0x17, 0x01], 24);
+    /// assert!(matches!(tb.decode_embedded(), Err(CBORError::TrailingData)));
+    ///# Ok(())
+    ///# }
+    /// ```
+    #[cfg(feature = "combinators")]
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn decode_embedded(self) -> Result<CBOR<'buf>, CBORError> {
+        if self.tag != 24 {
+            return Err(CBORError::ExpectedTag(24));
+        }
+        match self.into_iter().try_next()? {
+            CBOR::Bstr(bytes) => {
+                let decoder = CBORDecoder::from_slice(bytes);
+                let item = decoder.envelope().item()?;
+                decoder.finalize_strict()?;
+                Ok(item)
+            }
+            _ => Err(CBORError::TagContentMismatch(24)),
+        }
+    }
+
+    /// Return the magnitude of a bignum (tag 2 or 3, RFC8949, Section 3.4.3) as big-endian
+    /// bytes, which is the encoded byte order. The value of a tag 3 bignum is `-1 - magnitude`,
+    /// and the sign can be found with [`TagBuf::get_tag`].
+    ///
+    /// Returns `CBORError::ExpectedTag(2)` for other tags, and `CBORError::TagContentMismatch`
+    /// if the tag content is not a `bstr`.
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn bignum(self) -> Result<&'buf [u8], CBORError> {
+        if self.tag != 2 && self.tag != 3 {
+            return Err(CBORError::ExpectedTag(2));
+        }
+        match self.into_iter().try_next()? {
+            CBOR::Bstr(bytes) => Ok(bytes),
+            _ => Err(CBORError::TagContentMismatch(self.tag)),
+        }
+    }
+
+    /// Copy the magnitude of a bignum (see [`TagBuf::bignum`]) into `out` in little-endian byte
+    /// order, e.g. for a crypto library, returning the number of bytes written. Leading zeros in
+    /// the encoding are copied as trailing zeros.
+    ///
+    /// Returns `CBORError::ShortBuffer` with the required length if `out` is too short.
+    ///
+    /// ```
+    ///# use tps_minicbor::error::CBORError;
+    ///# use tps_minicbor::decoder::TagBuf;
+    ///# fn main() -> Result<(), CBORError> {
+    /// // 2(h'010000'), which is 65536
+    /// let tb = TagBuf::new(&[0x43, 0x01, 0x00, 0x00], 2);
+    /// assert_eq!(tb.bignum()?, &[0x01, 0x00, 0x00]);
+    /// let mut le = [0u8; 8];
+    /// assert_eq!(tb.bignum_to_le(&mut le)?, 3);
+    /// assert_eq!(&le[..3], &[0x00, 0x00, 0x01]);
+    /// assert!(matches!(tb.bignum_to_le(&mut le[..2]), Err(CBORError::ShortBuffer(3))));
+    ///# Ok(())
+    ///# }
+    /// ```
+    #[cfg_attr(feature = "trace", trace)]
+    pub fn bignum_to_le(self, out: &mut [u8]) -> Result<usize, CBORError> {
+        let magnitude = self.bignum()?;
+        if out.len() < magnitude.len() {
+            return Err(CBORError::ShortBuffer(magnitude.len()));
+        }
+        for (dst, src) in out.iter_mut().zip(magnitude.iter().rev()) {
+            *dst = *src;
+        }
+        Ok(magnitude.len())
+    }
+
+    /// (crate) Check the content of the tagged item against the type required by the strict
+    /// decoding profile for well-known tags (RFC8949, Section 3.4):
+    ///
+    /// - Tag 0 (date/time string) must enclose a `tstr`.
+    /// - Tag 1 (epoch-based date/time) must enclose an integer or a float.
+    /// - Tags 2 and 3 (bignums) must enclose a `bstr`.
+    /// - Tag 24 (encoded CBOR data item) must enclose a `bstr` holding exactly one well-formed
+    ///   CBOR item.
+    ///
+    /// Tags 21 to 23 (expected conversions) may enclose any item, and other tags are not checked.
+    /// A mismatch is reported as `CBORError::TagContentMismatch` with the tag value.
+    #[cfg_attr(feature = "trace", trace)]
+    pub(crate) fn check_strict_content(self) -> Result<(), CBORError> {
+        let content = self.into_iter().next().ok_or(CBORError::MalformedEncoding)?;
+        let allowed = match (self.tag, content) {
+            (0, CBOR::Tstr(_)) => true,
+            (1, CBOR::UInt(_)) | (1, CBOR::NInt(_)) => true,
+            #[cfg(feature = "float")]
+            (1, CBOR::Float16(_)) | (1, CBOR::Float32(_)) | (1, CBOR::Float64(_)) => true,
+            (2, CBOR::Bstr(_)) | (3, CBOR::Bstr(_)) => true,
+            (24, CBOR::Bstr(bytes)) => {
+                let mut it = SequenceBuffer::new(bytes).into_iter();
+                it.try_next().is_ok() && it.index == bytes.len()
+            }
+            (0..=3, _) | (24, _) => false,
+            _ => true,
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(CBORError::TagContentMismatch(self.tag))
+        }
+    }
 }
 
 impl<'buf> IntoIterator for TagBuf<'buf> {
@@ -92,14 +228,32 @@ impl<'buf> IntoIterator for TagBuf<'buf> {
             buf: self.bytes,
             index: 0,
             source: DecodeBufIteratorSource::Tag,
+            options: DecodeOptions::default(),
+            last_tag: None,
         }
     }
 }
 
+/// The [`EncodeItem`] instance for `TagBuf` re-encodes a decoded tagged item. The tagged item
+/// is copied unchanged.
+impl<'buf> EncodeItem for TagBuf<'buf> {
+    fn encode<'f, 'b>(
+        &self,
+        buf: &'f mut EncodeBuffer<'b>,
+    ) -> Result<&'f mut EncodeBuffer<'b>, CBORError> {
+        let _ = buf.tag_next_item(self.tag)?;
+        buf.insert_bstr_cbor(self.bytes)
+    }
+}
+
 /***************************************************************************************************
  * Encoding Tags
  **************************************************************************************************/
 
+/// CBOR tag for self-described CBOR (RFC8949, Section 3.4.6). Its encoding, `0xd9d9f7`, is
+/// used as a magic number to mark data as CBOR.
+pub const SELF_DESCRIBE_TAG: u64 = 55799;
+
 /// A container structure for the closure used to manage encoding of CBOR tags, and in particular
 /// to ensure that the correct lifetime bounds are specified.
 ///
@@ -171,3 +325,205 @@ where
 {
     Tag::new(tag, f)
 }
+
+/// A convenience function to mark the item constructed by the closure as self-described CBOR,
+/// by wrapping it in tag 55799 (RFC8949, Section 3.4.6).
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::self_describe;
+///
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 16];
+///
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    encoder.insert(&self_describe(|buf| buf.insert(&1u8)))?;
+///    assert_eq!(encoder.encoded()?, &[0xd9, 0xd9, 0xf7, 0x01]);
+///#    Ok(())
+///# }
+/// ```
+pub fn self_describe<F>(f: F) -> Tag<F>
+where
+    F: for<'f, 'buf> Fn(
+        &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError>,
+{
+    Tag::new(SELF_DESCRIBE_TAG, f)
+}
+
+/// A tag wrapping an item which has already been encoded, for example by another subsystem.
+///
+/// Users should never need to directly instantiate `TagRaw`. Instead, see [`tag_raw`].
+pub struct TagRaw<'a> {
+    tag: u64,
+    bytes: &'a [u8],
+}
+
+/// The [`EncodeItem`] instance for `TagRaw` writes the tag followed by the pre-encoded bytes,
+/// which are copied unchanged. `MalformedEncoding` is returned, and nothing is written, unless the
+/// bytes contain exactly one well-formed item.
+impl<'a> EncodeItem for TagRaw<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let mut it = SequenceBuffer::new(self.bytes).into_iter();
+        if self.bytes.is_empty() || it.try_next().is_err() || it.index != self.bytes.len() {
+            return Err(CBORError::MalformedEncoding);
+        }
+        let _ = buf.tag_next_item(self.tag)?;
+        buf.insert_bstr_cbor(self.bytes)
+    }
+}
+
+/// A convenience function to tag an item which has already been encoded. `bytes` must contain
+/// exactly one well-formed CBOR item.
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::tag_raw;
+///
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 16];
+///
+///    // 24(h'01') wrapped in tag 1000
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    encoder.insert(&tag_raw(1000, &[0xd8, 0x18, 0x41, 0x01]))?;
+///    assert_eq!(encoder.encoded()?, &[0xd9, 0x03, 0xe8, 0xd8, 0x18, 0x41, 0x01]);
+///#    Ok(())
+///# }
+/// ```
+pub fn tag_raw(tag: u64, bytes: &[u8]) -> TagRaw<'_> {
+    TagRaw { tag, bytes }
+}
+
+/// A date/time string (tag 0, RFC8949, Section 3.4.1) which is encoded exactly as given.
+///
+/// Users should never need to directly instantiate `DateTimeStr`. Instead, see [`date_time_str`].
+#[cfg(feature = "full")]
+pub struct DateTimeStr<'a> {
+    date_time: &'a str,
+}
+
+/// The [`EncodeItem`] instance for `DateTimeStr` writes tag 0 followed by the string, unchanged.
+/// `BadDateTime` is returned, and nothing is written, if the string is not an RFC3339 date/time.
+#[cfg(feature = "full")]
+impl<'a> EncodeItem for DateTimeStr<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        if chrono::DateTime::parse_from_rfc3339(self.date_time).is_err() {
+            return Err(CBORError::BadDateTime);
+        }
+        let _ = buf.tag_next_item(0)?;
+        buf.insert(&self.date_time)
+    }
+}
+
+/// Encode a date/time string as tag 0 without reformatting it, for example to re-encode a value
+/// returned by [`decode_date_time_raw`](crate::decoder::decode_date_time_raw). Encoding a
+/// `CBOR::DateTime` always uses the format of `DateTime::to_rfc3339`.
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::date_time_str;
+///
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 32];
+///
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    encoder.insert(&date_time_str("1970-01-01T00:00:00Z"))?;
+///    assert_eq!(&encoder.encoded()?[..2], &[0xc0, 0x74]);
+///    assert_eq!(&encoder.encoded()?[2..], b"1970-01-01T00:00:00Z");
+///#    Ok(())
+///# }
+/// ```
+#[cfg(feature = "full")]
+pub fn date_time_str(date_time: &str) -> DateTimeStr<'_> {
+    DateTimeStr { date_time }
+}
+
+/// A bignum (tag 2, RFC8949, Section 3.4.3) constructed from the bytes of an unsigned integer.
+///
+/// Users should never need to directly instantiate `Bignum`. Instead, see [`bignum`] and
+/// [`bignum_from_le`].
+pub struct Bignum<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+}
+
+/// The [`EncodeItem`] instance for `Bignum` writes tag 2 followed by the magnitude as a
+/// big-endian `bstr`, with leading zeros removed.
+impl<'a> EncodeItem for Bignum<'a> {
+    fn encode<'f, 'buf>(
+        &self,
+        buf: &'f mut EncodeBuffer<'buf>,
+    ) -> Result<&'f mut EncodeBuffer<'buf>, CBORError> {
+        let magnitude = if self.little_endian {
+            let end = self.bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            &self.bytes[..end]
+        } else {
+            let start = self.bytes.iter().position(|b| *b != 0).unwrap_or(self.bytes.len());
+            &self.bytes[start..]
+        };
+        let _ = buf.tag_next_item(2)?;
+        let buf = magnitude.encode(buf)?;
+        if self.little_endian {
+            buf.reverse_tail(magnitude.len());
+        }
+        Ok(buf)
+    }
+}
+
+/// Encode the unsigned integer held in big-endian `bytes` as a bignum (tag 2). Leading zeros are
+/// not encoded.
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::bignum;
+///
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 16];
+///
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    encoder.insert(&bignum(&[0x00, 0x01, 0x00, 0x00]))?;
+///    assert_eq!(encoder.encoded()?, &[0xc2, 0x43, 0x01, 0x00, 0x00]);
+///#    Ok(())
+///# }
+/// ```
+pub fn bignum(bytes: &[u8]) -> Bignum<'_> {
+    Bignum {
+        bytes,
+        little_endian: false,
+    }
+}
+
+/// Encode the unsigned integer held in little-endian `bytes`, as produced by many crypto
+/// libraries, as a bignum (tag 2). The bytes are reversed into the big-endian order required by
+/// RFC8949, and leading zeros are not encoded.
+///
+/// ```
+///# use tps_minicbor::encoder::CBORBuilder;
+///# use tps_minicbor::error::CBORError;
+///# use tps_minicbor::types::bignum_from_le;
+///
+///# fn main() -> Result<(), CBORError> {
+///    let mut buffer = [0u8; 16];
+///
+///    let mut encoder = CBORBuilder::new(&mut buffer);
+///    encoder.insert(&bignum_from_le(&[0x00, 0x00, 0x01, 0x00]))?;
+///    assert_eq!(encoder.encoded()?, &[0xc2, 0x43, 0x01, 0x00, 0x00]);
+///#    Ok(())
+///# }
+/// ```
+pub fn bignum_from_le(bytes: &[u8]) -> Bignum<'_> {
+    Bignum {
+        bytes,
+        little_endian: true,
+    }
+}
@@ -0,0 +1,273 @@
+/***************************************************************************************************
+ * Copyright (c) 2023 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+/***************************************************************************************************
+ * Tests for re-encoding CBOR in the deterministic encoding of RFC8949, Section 4.2
+ **************************************************************************************************/
+// These tests use the decode combinators, which require the `combinators` feature
+#![cfg(feature = "combinators")]
+
+extern crate tps_minicbor;
+
+use tps_minicbor::canonical::canonicalize;
+use tps_minicbor::decoder::{CBORDecoder, DecodeLimits};
+use tps_minicbor::error::CBORError;
+use tps_minicbor::types::CBOR;
+
+/// Canonicalize `src`, check that the result is accepted as deterministic and that it is
+/// unchanged by canonicalizing it again, and return it.
+fn canonical(src: &[u8]) -> Result<Vec<u8>, CBORError> {
+    let mut dst = [0u8; 64];
+    let len = canonicalize(src, &mut dst)?;
+    let limits = DecodeLimits {
+        canonical: true,
+        ..Default::default()
+    };
+    CBORDecoder::validate(&dst[..len], &limits)?;
+    let mut again = [0u8; 64];
+    let again_len = canonicalize(&dst[..len], &mut again)?;
+    assert_eq!(&again[..again_len], &dst[..len]);
+    Ok(dst[..len].to_vec())
+}
+
+#[test]
+fn canonical_integers() -> Result<(), CBORError> {
+    println!("<======================= canonical_integers =====================>");
+    // 0 and 23 written with one byte arguments
+    assert_eq!(canonical(&[0x18, 0x00])?, [0x00]);
+    assert_eq!(canonical(&[0x18, 0x17])?, [0x17]);
+    // 24 and 255 written with two and eight byte arguments
+    assert_eq!(canonical(&[0x19, 0x00, 0x18])?, [0x18, 0x18]);
+    assert_eq!(canonical(&[0x1b, 0, 0, 0, 0, 0, 0, 0, 0xff])?, [0x18, 0xff]);
+    // 65536 written with an eight byte argument, -1 with a four byte argument
+    assert_eq!(
+        canonical(&[0x1b, 0, 0, 0, 0, 0, 0x01, 0, 0])?,
+        [0x1a, 0, 0x01, 0, 0]
+    );
+    assert_eq!(canonical(&[0x3a, 0, 0, 0, 0])?, [0x20]);
+    // Lengths, tags and simple values are shortened too: h'01', 1(2), simple(255)
+    assert_eq!(canonical(&[0x58, 0x01, 0x01])?, [0x41, 0x01]);
+    assert_eq!(canonical(&[0xd8, 0x01, 0x19, 0x00, 0x02])?, [0xc1, 0x02]);
+    assert_eq!(canonical(&[0xf8, 0xff])?, [0xf8, 0xff]);
+    // Already canonical items are unchanged
+    let src = [0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+    assert_eq!(canonical(&src)?, src);
+    Ok(())
+}
+
+#[test]
+fn canonical_maps() -> Result<(), CBORError> {
+    println!("<======================= canonical_maps =====================>");
+    // {"b": 1, 10: 2, -1: 3, "a": 4, 100: 5} sorts as {10: 2, 100: 5, -1: 3, "a": 4, "b": 1}
+    let src = [
+        0xa5, 0x61, 0x62, 0x01, 0x0a, 0x02, 0x20, 0x03, 0x61, 0x61, 0x04, 0x18, 0x64, 0x05,
+    ];
+    assert_eq!(
+        canonical(&src)?,
+        [0xa5, 0x0a, 0x02, 0x18, 0x64, 0x05, 0x20, 0x03, 0x61, 0x61, 0x04, 0x61, 0x62, 0x01]
+    );
+
+    // Keys are compared after they are made canonical: {0x1801: [], 0: {2: 0, 1: 0}}
+    let src = [0xa2, 0x18, 0x01, 0x80, 0x00, 0xa2, 0x02, 0x00, 0x01, 0x00];
+    assert_eq!(
+        canonical(&src)?,
+        [0xa2, 0x00, 0xa2, 0x01, 0x00, 0x02, 0x00, 0x01, 0x80]
+    );
+
+    // Duplicate keys, including keys which are only equal once canonical: {1: 0, 0x1801: 0}
+    assert!(matches!(
+        canonicalize(&[0xa2, 0x01, 0x00, 0x18, 0x01, 0x00], &mut [0u8; 16]),
+        Err(CBORError::NotAllowed)
+    ));
+    Ok(())
+}
+
+#[test]
+fn canonical_mixed_key_types() -> Result<(), CBORError> {
+    println!("<======================= canonical_mixed_key_types =====================>");
+    // CWT claims {"iss": 0, 2: 1, "aud": 2, 1: 3} sort as {1: 3, 2: 1, "aud": 2, "iss": 0}
+    let src = [
+        0xa4, 0x63, 0x69, 0x73, 0x73, 0x00, 0x02, 0x01, 0x63, 0x61, 0x75, 0x64, 0x02, 0x01, 0x03,
+    ];
+    let dst = canonical(&src)?;
+    assert_eq!(
+        dst,
+        [
+            0xa4, 0x01, 0x03, 0x02, 0x01, 0x63, 0x61, 0x75, 0x64, 0x02, 0x63, 0x69, 0x73, 0x73,
+            0x00
+        ]
+    );
+    let _ = CBORDecoder::from_slice(&dst).map(|mb| {
+        let keys: Vec<CBOR> = mb.into_iter().step_by(2).collect();
+        assert_eq!(
+            keys,
+            [
+                CBOR::UInt(1),
+                CBOR::UInt(2),
+                CBOR::Tstr("aud"),
+                CBOR::Tstr("iss")
+            ]
+        );
+        Ok(())
+    })?;
+
+    // A one byte integer key sorts before a one byte (empty) text key: {"": 0, 23: 1}
+    assert_eq!(
+        canonical(&[0xa2, 0x60, 0x00, 0x17, 0x01])?,
+        [0xa2, 0x17, 0x01, 0x60, 0x00]
+    );
+    Ok(())
+}
+
+#[test]
+fn canonical_indefinite() -> Result<(), CBORError> {
+    println!("<======================= canonical_indefinite =====================>");
+    // (_ h'0102', h'', h'03'), (_ "a", "b")
+    assert_eq!(
+        canonical(&[0x5f, 0x42, 0x01, 0x02, 0x40, 0x41, 0x03, 0xff])?,
+        [0x43, 0x01, 0x02, 0x03]
+    );
+    assert_eq!(
+        canonical(&[0x7f, 0x61, 0x61, 0x61, 0x62, 0xff])?,
+        [0x62, 0x61, 0x62]
+    );
+    // [_ 1, [_ ], {_ 2: [_ 3]}]
+    let src = [
+        0x9f, 0x01, 0x9f, 0xff, 0xbf, 0x02, 0x9f, 0x03, 0xff, 0xff, 0xff,
+    ];
+    assert_eq!(canonical(&src)?, [0x83, 0x01, 0x80, 0xa1, 0x02, 0x81, 0x03]);
+
+    // A CBOR sequence: [_ ], 0x1801
+    let mut dst = [0u8; 16];
+    let len = canonicalize(&[0x9f, 0xff, 0x18, 0x01], &mut dst)?;
+    assert_eq!(&dst[..len], &[0x80, 0x01]);
+
+    // Malformed: a map with a key but no value, a missing break and a chunk of the wrong type
+    assert!(matches!(
+        canonicalize(&[0xbf, 0x01, 0xff], &mut dst),
+        Err(CBORError::MalformedEncoding)
+    ));
+    assert!(matches!(
+        canonicalize(&[0x9f, 0x01], &mut dst),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert!(matches!(
+        canonicalize(&[0x5f, 0x61, 0x61, 0xff], &mut dst),
+        Err(CBORError::MalformedEncoding)
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "float")]
+#[test]
+fn canonical_floats() -> Result<(), CBORError> {
+    println!("<======================= canonical_floats =====================>");
+    // 1.5 as f64 and f32, 100000.0 as f64, and a NaN with a payload
+    assert_eq!(
+        canonical(&[0xfb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0])?,
+        [0xf9, 0x3e, 0x00]
+    );
+    assert_eq!(canonical(&[0xfa, 0x3f, 0xc0, 0, 0])?, [0xf9, 0x3e, 0x00]);
+    assert_eq!(
+        canonical(&[0xfb, 0x40, 0xf8, 0x6a, 0, 0, 0, 0, 0])?,
+        [0xfa, 0x47, 0xc3, 0x50, 0x00]
+    );
+    assert_eq!(
+        canonical(&[0xfb, 0x7f, 0xf8, 0, 0, 0, 0, 0, 0x01])?,
+        [0xf9, 0x7e, 0x00]
+    );
+    Ok(())
+}
+
+#[test]
+fn canonical_short_buffer() -> Result<(), CBORError> {
+    println!("<======================= canonical_short_buffer =====================>");
+    // {"b": 1, "a": 2} needs 7 bytes
+    let src = [0xa2, 0x61, 0x62, 0x01, 0x61, 0x61, 0x02];
+    let mut dst = [0xffu8; 6];
+    assert!(matches!(
+        canonicalize(&src, &mut dst),
+        Err(CBORError::ShortBuffer(7))
+    ));
+    assert_eq!(dst, [0xff; 6]);
+    let mut dst = [0u8; 7];
+    assert_eq!(canonicalize(&src, &mut dst)?, 7);
+    assert_eq!(canonicalize(&[], &mut [])?, 0);
+    Ok(())
+}
+
+#[test]
+fn canonical_nesting() -> Result<(), CBORError> {
+    println!("<======================= canonical_nesting =====================>");
+    // [_ [_ ... [_ ] ... ]] nested to the default depth limit of 16
+    let mut src = [0x9fu8; 32];
+    src[16..].fill(0xff);
+    let mut dst = [0u8; 16];
+    assert_eq!(canonicalize(&src, &mut dst)?, 16);
+    assert_eq!(dst[..15], [0x81; 15]);
+    assert_eq!(dst[15], 0x80);
+
+    // One level deeper is rejected, for indefinite and definite length arrays and for tags
+    let mut src = [0x9fu8; 34];
+    src[17..].fill(0xff);
+    assert!(matches!(
+        canonicalize(&src, &mut dst),
+        Err(CBORError::LimitExceeded)
+    ));
+    let mut src = [0x81u8; 17];
+    src[16] = 0x80;
+    assert!(matches!(
+        canonicalize(&src, &mut dst),
+        Err(CBORError::LimitExceeded)
+    ));
+    let mut src = [0xc1u8; 18];
+    src[17] = 0x00;
+    assert!(matches!(
+        canonicalize(&src, &mut dst),
+        Err(CBORError::LimitExceeded)
+    ));
+
+    // Deep nesting fails without exhausting the stack
+    let src = vec![0x81u8; 1_000_000];
+    assert!(matches!(
+        canonicalize(&src, &mut dst),
+        Err(CBORError::LimitExceeded)
+    ));
+    let src = vec![0x9fu8; 1_000_000];
+    assert!(matches!(
+        canonicalize(&src, &mut dst),
+        Err(CBORError::LimitExceeded)
+    ));
+    Ok(())
+}
+
+#[test]
+fn canonical_nested_indefinite_is_linear() -> Result<(), CBORError> {
+    println!("<======================= canonical_nested_indefinite_is_linear =====================>");
+    // 4000 x [_ [_ [_ ... ]]] nested 16 deep. Counting the items of each indefinite length array
+    // by re-encoding them would take around 2^16 times as long as canonicalizing it once.
+    let mut one = [0x9fu8; 32];
+    one[16..].fill(0xff);
+    let src = one.repeat(4000);
+    let mut dst = vec![0u8; 16 * 4000];
+    let start = std::time::Instant::now();
+    assert_eq!(canonicalize(&src, &mut dst)?, 16 * 4000);
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    Ok(())
+}
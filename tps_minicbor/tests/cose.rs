@@ -0,0 +1,495 @@
+/***************************************************************************************************
+ * Copyright (c) 2020-2023 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+
+#![cfg(all(feature = "cose", feature = "combinators"))]
+
+extern crate tps_minicbor;
+
+use tps_minicbor::cose::{
+    empty_protected, encrypt0_decode, encrypt0_encode, is_cose_alg, protected, sig_structure,
+    sig_structure1, sign1_decode, sign1_decode_detached, sign1_detached, sign_decode, sign_encode,
+    Algorithm, CoseSignature, Encrypt0Message, HEADER_ALG,
+};
+use tps_minicbor::decoder::{is_any, is_uint, CBORDecoder, SequenceBuffer};
+use tps_minicbor::encoder::CBORBuilder;
+use tps_minicbor::error::CBORError;
+use tps_minicbor::types::{array, map, tag, CBOR};
+
+#[test]
+fn cose_encrypt0_round_trip() -> Result<(), CBORError> {
+    println!("<======================== cose_encrypt0_round_trip ========================>");
+    // Protected header: << {1: 1} >> (alg: A128GCM)
+    let protected = [0xa1, 0x01, 0x01];
+    let iv = [
+        0x02, 0xd1, 0xf7, 0xe6, 0xf2, 0x6c, 0x43, 0xd4, 0x86, 0x8d, 0x87, 0xce,
+    ];
+    let ciphertext = [
+        0x60, 0x97, 0x3a, 0x94, 0xbb, 0x28, 0x98, 0x00, 0x9e, 0xe5, 0x2e, 0xcf,
+    ];
+
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&encrypt0_encode(&protected, &iv, &ciphertext))?;
+    let encoded = encoder.encoded()?;
+    assert_eq!(&encoded[..2], &[0xd0, 0x83]);
+
+    let decoder = CBORDecoder::from_slice(encoded);
+    let _ = decoder.decode_with(is_any(), |cbor| {
+        assert_eq!(
+            encrypt0_decode(cbor)?,
+            Encrypt0Message {
+                protected: &protected,
+                alg: Some(1),
+                iv: &iv,
+                ciphertext: &ciphertext
+            }
+        );
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn cose_encrypt0_decode_errors() -> Result<(), CBORError> {
+    println!("<======================= cose_encrypt0_decode_errors =======================>");
+    let mut buffer = [0u8; 64];
+
+    // Untagged, empty protected header: accepted with no alg
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&array(|buf| {
+        buf.insert(&[].as_slice())?
+            .insert(&map(|buf| buf.insert_key_value(&5, &[1u8, 2].as_slice())))?
+            .insert(&[3u8].as_slice())
+    }))?;
+    let decoder = CBORDecoder::from_slice(encoder.encoded()?);
+    let _ = decoder.decode_with(is_any(), |cbor| {
+        let msg = encrypt0_decode(cbor)?;
+        assert_eq!(msg.alg, None);
+        assert_eq!(msg.iv, &[1, 2]);
+        Ok(())
+    })?;
+
+    // Wrong tag
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&tag(17, |buf| buf.insert(&array(|buf| buf.insert(&1)))))?;
+    let decoder = CBORDecoder::from_slice(encoder.encoded()?);
+    let r = decoder.decode_with(is_any(), |cbor| encrypt0_decode(cbor).map(|_| ()));
+    assert!(matches!(r, Err(CBORError::ExpectedTag(16))));
+
+    // Missing IV
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&tag(16, |buf| {
+        buf.insert(&array(|buf| {
+            buf.insert(&[].as_slice())?
+                .insert(&map(|buf| buf.insert_key_value(&4, &[1u8].as_slice())))?
+                .insert(&[3u8].as_slice())
+        }))
+    }))?;
+    let decoder = CBORDecoder::from_slice(encoder.encoded()?);
+    let r = decoder.decode_with(is_any(), |cbor| encrypt0_decode(cbor).map(|_| ()));
+    assert!(matches!(r, Err(CBORError::KeyNotPresent)));
+    Ok(())
+}
+
+#[test]
+fn cose_sign1_decode() -> Result<(), CBORError> {
+    println!("<=========================== cose_sign1_decode ===========================>");
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    // 18([<< {1: -7} >>, {4: '11'}, 'payload', h'0102...'])
+    let _ = encoder.insert(&tag(18, |buf| {
+        buf.insert(&array(|buf| {
+            buf.insert(&[0xa1u8, 0x01, 0x26].as_slice())?
+                .insert(&map(|buf| buf.insert_key_value(&4, &b"11".as_slice())))?
+                .insert(&b"payload".as_slice())?
+                .insert(&[0x01u8, 0x02, 0x03, 0x04].as_slice())
+        }))
+    }))?;
+    let _ = encoder.insert(&7)?;
+
+    let decoder = CBORDecoder::from_slice(encoder.encoded()?);
+    let sign1 = sign1_decode(&decoder)?;
+    assert_eq!(sign1.protected, &[0xa1, 0x01, 0x26]);
+    assert_eq!(sign1.unprotected.lookup::<u64, &[u8]>(4)?, b"11");
+    assert_eq!(sign1.payload, b"payload");
+    assert_eq!(sign1.signature, &[0x01, 0x02, 0x03, 0x04]);
+    // The decoder is positioned after the COSE_Sign1 structure
+    let mut next = 0u64;
+    let _ = decoder.value(is_uint(), &mut next)?;
+    assert_eq!(next, 7);
+    Ok(())
+}
+
+#[test]
+fn cose_sign1_decode_errors() -> Result<(), CBORError> {
+    println!("<======================== cose_sign1_decode_errors ========================>");
+    // Untagged
+    let decoder = CBORDecoder::from_slice(&[0x84, 0x40, 0xa0, 0x40, 0x40]);
+    assert!(matches!(
+        sign1_decode(&decoder),
+        Err(CBORError::ExpectedTag(18))
+    ));
+    // The decoder is not advanced on error
+    let _ = decoder.decode_with(is_any(), |_| Ok(()))?;
+
+    // Wrong tag (COSE_Encrypt0)
+    let decoder = CBORDecoder::from_slice(&[0xd0, 0x84, 0x40, 0xa0, 0x40, 0x40]);
+    assert!(matches!(
+        sign1_decode(&decoder),
+        Err(CBORError::ExpectedTag(18))
+    ));
+
+    // Three element array
+    let decoder = CBORDecoder::from_slice(&[0xd2, 0x83, 0x40, 0xa0, 0x40]);
+    assert!(matches!(
+        sign1_decode(&decoder),
+        Err(CBORError::MalformedEncoding)
+    ));
+
+    // Detached (nil) payload
+    let decoder = CBORDecoder::from_slice(&[0xd2, 0x84, 0x40, 0xa0, 0xf6, 0x40]);
+    assert!(matches!(
+        sign1_decode(&decoder),
+        Err(CBORError::MalformedEncoding)
+    ));
+    Ok(())
+}
+
+#[test]
+fn cose_protected_header() -> Result<(), CBORError> {
+    println!("<========================== cose_protected_header =========================>");
+    // 18([<< {1: -7} >>, {}, 'hi', h'0102'])
+    let bytes = [
+        0xd2, 0x84, 0x43, 0xa1, 0x01, 0x26, 0xa0, 0x42, 0x68, 0x69, 0x42, 0x01, 0x02,
+    ];
+    let sign1 = sign1_decode(&CBORDecoder::from_slice(&bytes))?;
+    let (raw, mb) = protected(CBOR::Bstr(sign1.protected))?;
+    // The raw bytes borrow from the message buffer
+    assert_eq!(raw.as_ptr(), bytes[3..].as_ptr());
+    assert_eq!(raw, &[0xa1, 0x01, 0x26]);
+    assert_eq!(mb.len(), 1);
+    assert_eq!(mb.lookup::<i64, i64>(HEADER_ALG)?, -7);
+
+    // An empty bstr is an empty header map
+    let (raw, mb) = protected(CBOR::Bstr(&[]))?;
+    assert!(raw.is_empty());
+    assert!(mb.is_empty());
+
+    // << {} >> is also an empty header map, but the raw bytes are kept as received
+    let (raw, mb) = protected(CBOR::Bstr(&[0xa0]))?;
+    assert_eq!(raw, &[0xa0]);
+    assert!(mb.is_empty());
+
+    // Not a bstr
+    assert!(matches!(
+        protected(CBOR::UInt(0)),
+        Err(CBORError::ExpectedType("bstr"))
+    ));
+    // << [] >>: not a map
+    assert!(matches!(
+        protected(CBOR::Bstr(&[0x80])),
+        Err(CBORError::MalformedEncoding)
+    ));
+    // << {}, 0 >>: trailing data
+    assert!(matches!(
+        protected(CBOR::Bstr(&[0xa0, 0x00])),
+        Err(CBORError::MalformedEncoding)
+    ));
+    // << {1: >>: truncated
+    assert!(matches!(
+        protected(CBOR::Bstr(&[0xa1, 0x01])),
+        Err(CBORError::MalformedEncoding)
+    ));
+    Ok(())
+}
+
+// A stand-in for a real signature algorithm: a rolling checksum over the Sig_structure
+fn checksum(tbs: &[u8], out: &mut [u8; 4]) {
+    for (i, b) in tbs.iter().enumerate() {
+        out[i % 4] = out[i % 4].rotate_left(3) ^ b;
+    }
+}
+
+#[test]
+fn cose_sign1_detached_round_trip() -> Result<(), CBORError> {
+    println!("<===================== cose_sign1_detached_round_trip =====================>");
+    // Protected header: << {1: -7} >> (alg: ES256)
+    let protected_hdr = [0xa1, 0x01, 0x26];
+    let payload = b"This is the content.";
+    let mut scratch = [0u8; 64];
+    let mut signature = [0u8; 4];
+
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let unprotected = map(|buf| buf.insert_key_value(&4, &b"11".as_slice()));
+    let sign1 = sign1_detached(&protected_hdr, &unprotected, payload, &mut scratch, |tbs| {
+        assert_eq!(&tbs[..12], b"\x84\x6aSignature1");
+        checksum(tbs, &mut signature);
+        Ok(&signature)
+    })?;
+    let _ = encoder.insert(&sign1)?;
+    let encoded = encoder.encoded()?;
+    // 18([<< {1: -7} >>, {4: '11'}, nil, signature])
+    assert_eq!(
+        &encoded[..12],
+        &[0xd2, 0x84, 0x43, 0xa1, 0x01, 0x26, 0xa1, 0x04, 0x42, 0x31, 0x31, 0xf6]
+    );
+
+    // The payload is not in the message, so must be supplied to verify the signature
+    let decoder = CBORDecoder::from_slice(encoded);
+    let parts = sign1_decode_detached(&decoder, payload)?;
+    assert_eq!(parts.protected, &protected_hdr);
+    assert_eq!(parts.unprotected.lookup::<u64, &[u8]>(4)?, b"11");
+    assert_eq!(parts.payload, payload);
+    let mut verify_scratch = [0u8; 64];
+    let tbs = sig_structure1(parts.protected, &[], parts.payload, &mut verify_scratch)?;
+    let mut expected = [0u8; 4];
+    checksum(tbs, &mut expected);
+    assert_eq!(parts.signature, &expected);
+
+    // A message with an attached payload is not detached, and vice versa
+    let decoder = CBORDecoder::from_slice(&[0xd2, 0x84, 0x40, 0xa0, 0x41, 0x00, 0x40]);
+    assert!(matches!(
+        sign1_decode_detached(&decoder, payload),
+        Err(CBORError::MalformedEncoding)
+    ));
+    let decoder = CBORDecoder::from_slice(encoded);
+    assert!(matches!(sign1_decode(&decoder), Err(CBORError::MalformedEncoding)));
+
+    // The scratch buffer must be able to hold the Sig_structure
+    let mut small = [0u8; 8];
+    assert!(matches!(
+        sig_structure1(&protected_hdr, &[], payload, &mut small),
+        Err(CBORError::EndOfBuffer)
+    ));
+    Ok(())
+}
+
+#[test]
+fn cose_empty_protected() -> Result<(), CBORError> {
+    println!("<========================== cose_empty_protected ==========================>");
+    let mut bytes = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&array(|buf| {
+        buf.insert(&empty_protected())?.insert(&map(|buf| Ok(buf)))
+    }))?;
+    // [h'', {}], not [<< {} >>, {}]
+    assert_eq!(encoder.encoded()?, &[0x82, 0x40, 0xa0]);
+
+    CBORDecoder::new(encoder.build()?).array(|ab| {
+        let (raw, mb) = protected(ab.index(0).ok_or(CBORError::IndexOutOfBounds)?)?;
+        assert!(raw.is_empty());
+        assert!(mb.is_empty());
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn cwt_wrap_unwrap() -> Result<(), CBORError> {
+    use tps_minicbor::cwt;
+
+    println!("<============================ cwt_wrap_unwrap ============================>");
+    let mut cose_bytes = [0u8; 32];
+    let mut encoder = CBORBuilder::new(&mut cose_bytes);
+    // 18([<< {1: -7} >>, {}, 'claims', h'0102'])
+    let _ = encoder.insert(&tag(18, |buf| {
+        buf.insert(&array(|buf| {
+            buf.insert(&[0xa1u8, 0x01, 0x26].as_slice())?
+                .insert(&map(|buf| Ok(buf)))?
+                .insert(&b"claims".as_slice())?
+                .insert(&[0x01u8, 0x02].as_slice())
+        }))
+    }))?;
+    let cose = encoder.encoded()?;
+
+    let mut cwt_bytes = [0u8; 32];
+    let n = cwt::wrap(cose, &mut cwt_bytes)?;
+    assert_eq!(&cwt_bytes[..2], &[0xd8, 0x3d]);
+    assert_eq!(&cwt_bytes[2..n], cose);
+    assert!(matches!(
+        cwt::wrap(cose, &mut [0u8; 8]),
+        Err(CBORError::EndOfBuffer)
+    ));
+
+    // Tagged and untagged CWTs decode in the same way
+    for input in [&cwt_bytes[..n], cose].iter() {
+        let decoder = CBORDecoder::from_slice(input);
+        let sign1 = sign1_decode(cwt::unwrap(&decoder)?)?;
+        assert_eq!(sign1.payload, b"claims");
+        decoder.finalize()?;
+    }
+
+    // The CWT tag with no content
+    let decoder = CBORDecoder::from_slice(&[0xd8, 0x3d]);
+    assert!(cwt::unwrap(&decoder).is_err());
+    Ok(())
+}
+
+#[test]
+fn cose_algorithm() -> Result<(), CBORError> {
+    use std::convert::TryFrom;
+
+    println!("<============================ cose_algorithm ============================>");
+    for alg in [
+        Algorithm::ES256,
+        Algorithm::ES384,
+        Algorithm::ES512,
+        Algorithm::EdDSA,
+        Algorithm::PS256,
+        Algorithm::PS384,
+        Algorithm::PS512,
+        Algorithm::HMAC256_64,
+        Algorithm::HMAC256,
+        Algorithm::HMAC384,
+        Algorithm::HMAC512,
+        Algorithm::A128GCM,
+        Algorithm::A192GCM,
+        Algorithm::A256GCM,
+    ] {
+        assert_eq!(Algorithm::try_from(i64::from(alg))?, alg);
+    }
+    assert!(matches!(Algorithm::try_from(0), Err(CBORError::NotAllowed)));
+    assert!(matches!(
+        Algorithm::try_from(CBOR::UInt(u64::MAX)),
+        Err(CBORError::NotAllowed)
+    ));
+
+    // Protected header: << {1: -7} >>
+    let mut buffer = [0u8; 8];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&map(|buff| {
+        buff.insert_key_value(&HEADER_ALG, &Algorithm::ES256)
+    }))?;
+    assert_eq!(encoder.encoded()?, &[0xa1, 0x01, 0x26]);
+    let (_, mb) = protected(CBOR::Bstr(encoder.encoded()?))?;
+    assert_eq!(mb.lookup::<i64, Algorithm>(HEADER_ALG)?, Algorithm::ES256);
+
+    // Sequence: 5, -65535, "ES256"
+    let seq = SequenceBuffer::new(&[0x05, 0x39, 0xff, 0xfe, 0x65, 0x45, 0x53, 0x32, 0x35, 0x36]);
+    let (it, alg) = is_cose_alg()(seq.into_iter())?;
+    assert_eq!(alg, Algorithm::HMAC256);
+    assert!(matches!(is_cose_alg()(it), Err(CBORError::NotAllowed)));
+    let (mut it, _) = is_any()(it)?;
+    assert!(matches!(
+        is_cose_alg()(it),
+        Err(CBORError::ExpectedType("int"))
+    ));
+    let _ = it.next();
+    assert!(matches!(is_cose_alg()(it), Err(CBORError::EndOfBuffer)));
+    Ok(())
+}
+
+#[test]
+fn cose_sign_two_signers_round_trip() -> Result<(), CBORError> {
+    println!("<==================== cose_sign_two_signers_round_trip ====================>");
+    let body_protected = [];
+    // Signer protected headers: << {1: -7} >> (ES256) and << {1: -8} >> (EdDSA)
+    let signer_protected = [[0xa1, 0x01, 0x26], [0xa1, 0x01, 0x27]];
+    let payload = b"This is the content.";
+
+    let mut signatures = [[0u8; 4]; 2];
+    for (protected_hdr, signature) in signer_protected.iter().zip(signatures.iter_mut()) {
+        let mut scratch = [0u8; 64];
+        let tbs = sig_structure(&body_protected, protected_hdr, &[], payload, &mut scratch)?;
+        assert_eq!(&tbs[..11], b"\x85\x69Signature");
+        checksum(tbs, signature);
+    }
+    assert_ne!(signatures[0], signatures[1]);
+
+    let body_unprotected = map(|buf| buf.insert_key_value(&3, &0));
+    let kid_1 = map(|buf| buf.insert_key_value(&4, &b"11".as_slice()));
+    let kid_2 = map(|buf| buf.insert_key_value(&4, &b"22".as_slice()));
+    let signers = [
+        CoseSignature {
+            protected: &signer_protected[0],
+            unprotected: &kid_1,
+            signature: &signatures[0],
+        },
+        CoseSignature {
+            protected: &signer_protected[1],
+            unprotected: &kid_2,
+            signature: &signatures[1],
+        },
+    ];
+    let mut buffer = [0u8; 128];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&sign_encode(
+        &body_protected,
+        &body_unprotected,
+        payload,
+        &signers,
+    ))?;
+    let encoded = encoder.encoded()?;
+    // 98([h'', {3: 0}, payload, [...]])
+    assert_eq!(&encoded[..7], &[0xd8, 0x62, 0x84, 0x40, 0xa1, 0x03, 0x00]);
+
+    let decoder = CBORDecoder::from_slice(encoded);
+    let parts = sign_decode(&decoder)?;
+    assert_eq!(parts.protected, &body_protected);
+    assert_eq!(parts.unprotected.lookup::<u64, u64>(3)?, 0);
+    assert_eq!(parts.payload, payload);
+    assert_eq!(parts.len(), 2);
+    let kids: [&[u8]; 2] = [b"11", b"22"];
+    let mut n_signers = 0;
+    for (signer, kid) in parts.signatures().zip(kids) {
+        n_signers += 1;
+        assert_eq!(signer.unprotected.lookup::<u64, &[u8]>(4)?, kid);
+        let mut scratch = [0u8; 64];
+        let tbs = sig_structure(
+            parts.protected,
+            signer.protected,
+            &[],
+            parts.payload,
+            &mut scratch,
+        )?;
+        let mut expected = [0u8; 4];
+        checksum(tbs, &mut expected);
+        assert_eq!(signer.signature, &expected);
+    }
+    assert_eq!(n_signers, 2);
+
+    // COSE_Sign1 is not COSE_Sign, there must be at least one signer, and each signer must be a
+    // three element array
+    let decoder = CBORDecoder::from_slice(&[0xd2, 0x84, 0x40, 0xa0, 0x41, 0x00, 0x40]);
+    assert!(matches!(
+        sign_decode(&decoder),
+        Err(CBORError::ExpectedTag(98))
+    ));
+    let decoder = CBORDecoder::from_slice(&[0xd8, 0x62, 0x84, 0x40, 0xa0, 0x41, 0x00, 0x80]);
+    assert!(matches!(
+        sign_decode(&decoder),
+        Err(CBORError::MalformedEncoding)
+    ));
+    let decoder = CBORDecoder::from_slice(&[
+        0xd8, 0x62, 0x84, 0x40, 0xa0, 0x41, 0x00, 0x81, 0x82, 0x40, 0xa0,
+    ]);
+    assert!(matches!(
+        sign_decode(&decoder),
+        Err(CBORError::MalformedEncoding)
+    ));
+    let mut buffer = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    assert!(matches!(
+        encoder.insert(&sign_encode(&[], &body_unprotected, payload, &[])),
+        Err(CBORError::MalformedEncoding)
+    ));
+    Ok(())
+}
@@ -0,0 +1,126 @@
+/***************************************************************************************************
+ * Copyright (c) 2020-2023 Qualcomm Innovation Center, Inc. All rights reserved.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+ * and associated documentation files (the “Software”), to deal in the Software without
+ * restriction, including without limitation the rights to use, copy, modify, merge, publish,
+ * distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+ * BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ * NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+ * DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ **************************************************************************************************/
+
+#![cfg(all(feature = "eat", feature = "combinators"))]
+
+extern crate tps_minicbor;
+
+use tps_minicbor::decoder::CBORDecoder;
+use tps_minicbor::eat::{
+    decode_claims, ClaimsSet, DebugStatus, HwVersion, OemId, SecurityLevel, EAT_DEBUG_STATUS,
+};
+use tps_minicbor::encoder::CBORBuilder;
+use tps_minicbor::error::CBORError;
+use tps_minicbor::types::map;
+
+// The HW block claims-set from the crate documentation (EAT, Appendix A.1.3)
+const TEE_EAT: &[u8] = &[
+    167, 10, 72, 148, 143, 136, 96, 209, 58, 70, 62, 25, 1, 0, 80, 1, 152, 245, 10, 79, 246, 192,
+    88, 97, 200, 134, 13, 19, 166, 56, 234, 25, 1, 2, 25, 250, 242, 25, 1, 5, 3, 25, 1, 6, 245, 25,
+    1, 7, 3, 25, 1, 4, 130, 99, 51, 46, 49, 1,
+];
+const NONCE: &[u8] = &[0x94, 0x8f, 0x88, 0x60, 0xd1, 0x3a, 0x46, 0x3e];
+const UEID: &[u8] = &[
+    0x01, 0x98, 0xf5, 0x0a, 0x4f, 0xf6, 0xc0, 0x58, 0x61, 0xc8, 0x86, 0x0d, 0x13, 0xa6, 0x38, 0xea,
+];
+
+#[test]
+fn eat_claims_round_trip() -> Result<(), CBORError> {
+    println!("<========================== eat_claims_round_trip ==========================>");
+    let decoder = CBORDecoder::from_slice(TEE_EAT);
+    let claims = decode_claims(&decoder)?;
+    assert_eq!(claims.nonce, Some(NONCE));
+    assert_eq!(claims.ueid, Some(UEID));
+    assert_eq!(claims.oemid, Some(OemId::Pen(64242)));
+    assert_eq!(
+        claims.hw_version,
+        Some(HwVersion {
+            version: "3.1",
+            scheme: Some(1)
+        })
+    );
+    assert_eq!(claims.security_level, Some(SecurityLevel::SecureRestricted));
+    assert_eq!(claims.secure_boot, Some(true));
+    assert_eq!(claims.debug_status, Some(DebugStatus::DisabledPermanently));
+    assert!(decoder.finalize_strict().is_ok());
+
+    // The same claims, set in any order, are encoded in canonical key order: the documentation
+    // example puts hwversion (260) last
+    let built = ClaimsSet::new()
+        .debug_status(DebugStatus::DisabledPermanently)
+        .hw_version("3.1", Some(1))
+        .secure_boot(true)
+        .security_level(SecurityLevel::SecureRestricted)
+        .oemid(OemId::Pen(64242))
+        .ueid(UEID)
+        .nonce(NONCE);
+    assert_eq!(built, claims);
+    let mut bytes = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&built)?;
+    let encoded = encoder.encoded()?;
+    assert_eq!(encoded.len(), TEE_EAT.len());
+    assert_eq!(&encoded[..37], &TEE_EAT[..37]);
+    assert_eq!(&encoded[37..46], &TEE_EAT[49..]);
+    assert_eq!(&encoded[46..], &TEE_EAT[37..49]);
+    assert_eq!(decode_claims(&CBORDecoder::from_slice(encoded))?, claims);
+    Ok(())
+}
+
+#[test]
+fn eat_claims_partial_and_errors() -> Result<(), CBORError> {
+    println!("<====================== eat_claims_partial_and_errors ======================>");
+    // An empty claims-set, and one with a random oemid and an unknown claim
+    let mut bytes = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&ClaimsSet::new())?;
+    assert_eq!(encoder.encoded()?, &[0xa0]);
+    let claims = decode_claims(&CBORDecoder::from_slice(encoder.encoded()?))?;
+    assert_eq!(claims, ClaimsSet::default());
+
+    let oemid = [0x5a; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&map(|buf| {
+        buf.insert_key_value(&258, &oemid.as_slice())?
+            .insert_key_value(&-75000, &"unknown")?
+            .insert_key_value(&260, &tps_minicbor::types::array(|buf| buf.insert(&"1.0")))
+    }))?;
+    let claims = decode_claims(&CBORDecoder::from_slice(encoder.encoded()?))?;
+    assert_eq!(claims.oemid, Some(OemId::Bytes(&oemid)));
+    assert_eq!(claims.hw_version.map(|v| v.scheme), Some(None));
+    assert_eq!(claims.nonce, None);
+
+    // Undefined enumerated values and wrongly typed claims are rejected
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&map(|buf| buf.insert_key_value(&EAT_DEBUG_STATUS, &5)))?;
+    assert!(matches!(
+        decode_claims(&CBORDecoder::from_slice(encoder.encoded()?)),
+        Err(CBORError::NotAllowed)
+    ));
+    // {10: "nonce"}
+    let decoder = CBORDecoder::from_slice(&[0xa1, 0x0a, 0x65, 0x6e, 0x6f, 0x6e, 0x63, 0x65]);
+    assert!(decode_claims(&decoder).is_err());
+    let decoder = CBORDecoder::from_slice(&[0x80]);
+    assert!(matches!(
+        decode_claims(&decoder),
+        Err(CBORError::ExpectedType("map"))
+    ));
+    Ok(())
+}
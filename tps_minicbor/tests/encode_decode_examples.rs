@@ -22,6 +22,8 @@
  *
  * Test cases from RFC8949, Table 6.
  **************************************************************************************************/
+// These tests use the decode combinators, which require the `combinators` feature
+#![cfg(feature = "combinators")]
 
 extern crate tps_minicbor;
 
@@ -306,3 +308,631 @@ fn foo() -> Result<(), CBORError> {
     }
     Ok(())
 }
+
+#[cfg(feature = "full")]
+#[test]
+fn semantic_eq_encodings() -> Result<(), CBORError> {
+    use tps_minicbor::debug::semantic_eq;
+
+    println!("<=============================== semantic_eq_encodings ===============================>");
+    // Integer widths: 10 and 10 on two, three and five bytes
+    assert!(semantic_eq(&[0x0a], &[0x18, 0x0a])?);
+    assert!(semantic_eq(&[0x0a], &[0x19, 0x00, 0x0a])?);
+    assert!(semantic_eq(&[0x29], &[0x3a, 0x00, 0x00, 0x00, 0x09])?);
+    assert!(!semantic_eq(&[0x0a], &[0x0b])?);
+    assert!(!semantic_eq(&[0x0a], &[0x29])?);
+
+    // Float precision: 1.5 as f16, f32 and f64
+    assert!(semantic_eq(&[0xf9, 0x3e, 0x00], &[0xfa, 0x3f, 0xc0, 0x00, 0x00])?);
+    assert!(semantic_eq(
+        &[0xf9, 0x3e, 0x00],
+        &[0xfb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+    )?);
+    // NaN is equal to NaN, whatever the encoding
+    assert!(semantic_eq(&[0xf9, 0x7e, 0x00], &[0xfa, 0x7f, 0xc0, 0x00, 0x00])?);
+    // Integers and floats are different
+    assert!(!semantic_eq(&[0x01], &[0xf9, 0x3c, 0x00])?);
+
+    // bstr and tstr are different
+    assert!(!semantic_eq(&[0x41, 0x61], &[0x61, 0x61])?);
+
+    // Map order is not significant, but array order is
+    // {1: [1, 2], "a": {}} and {"a": {}, 1: [1, 2]}
+    let a = [0xa2, 0x01, 0x82, 0x01, 0x02, 0x61, 0x61, 0xa0];
+    let b = [0xa2, 0x61, 0x61, 0xa0, 0x01, 0x82, 0x01, 0x02];
+    assert!(semantic_eq(&a, &b)?);
+    // {1: [2, 1], "a": {}}
+    let c = [0xa2, 0x01, 0x82, 0x02, 0x01, 0x61, 0x61, 0xa0];
+    assert!(!semantic_eq(&a, &c)?);
+    // {1: [1, 2]}
+    assert!(!semantic_eq(&a, &[0xa1, 0x01, 0x82, 0x01, 0x02])?);
+    // Duplicate keys do not hide a missing pair: {1: 1, 1: 1} and {1: 1, 2: 2}
+    assert!(!semantic_eq(&[0xa2, 0x01, 0x01, 0x01, 0x01], &[0xa2, 0x01, 0x01, 0x02, 0x02])?);
+
+    // Tags: 1(1) on different widths, and different tag values
+    assert!(semantic_eq(&[0xc1, 0x01], &[0xd8, 0x01, 0x18, 0x01])?);
+    assert!(!semantic_eq(&[0xc1, 0x01], &[0xc2, 0x01])?);
+
+    // Sequences must have the same number of items
+    assert!(semantic_eq(&[0x01, 0x02], &[0x01, 0x18, 0x02])?);
+    assert!(!semantic_eq(&[0x01, 0x02], &[0x01])?);
+    assert!(semantic_eq(&[], &[])?);
+
+    // Decoding errors are reported, even if the first items differ
+    assert!(matches!(semantic_eq(&[0x01], &[0x02, 0x1c]), Err(CBORError::ReservedAdditionalInfo)));
+    assert!(matches!(semantic_eq(&[0x82, 0x01], &[0x01]), Err(CBORError::EndOfBuffer)));
+    Ok(())
+}
+
+#[test]
+fn encode_decode_sequence() -> Result<(), CBORError> {
+    // Round trip of an RFC8742 CBOR sequence of three top-level items
+    println!("<======================= encode_decode_sequence =====================>");
+    let mut bytes = [0u8; 32];
+    let expected: &[u8] = &[0x18, 0x2a, 0x63, 0x61, 0x62, 0x63, 0x82, 0x01, 0x02];
+
+    let mut encoder = CBORBuilder::sequence(&mut bytes);
+    encoder
+        .insert(&42u8)?
+        .insert(&"abc")?
+        .insert(&array(|buf| buf.insert(&1u8)?.insert(&2u8)))?;
+    assert_eq!(encoder.encoded()?, expected);
+    assert_eq!(encoder.build()?.into_iter().count(), 3);
+
+    CBORDecoder::new(encoder.build()?)
+        .decode_with(is_uint(), |cbor| {
+            assert_eq!(u8::try_from(cbor)?, 42);
+            Ok(())
+        })?
+        .decode_with(is_tstr(), |cbor| {
+            assert_eq!(<&str>::try_from(cbor)?, "abc");
+            Ok(())
+        })?
+        .decode_with(is_array(), |cbor| {
+            CBORDecoder::from_array(cbor)?
+                .decode_with(is_uint(), |cbor| {
+                    assert_eq!(u8::try_from(cbor)?, 1);
+                    Ok(())
+                })?
+                .decode_with(is_uint(), |cbor| {
+                    assert_eq!(u8::try_from(cbor)?, 2);
+                    Ok(())
+                })?
+                .finalize()
+        })?
+        .finalize()
+}
+
+#[cfg(feature = "full")]
+#[test]
+fn diag_with_offsets() -> Result<(), CBORError> {
+    use tps_minicbor::debug::Diag;
+
+    println!("<======================= diag_with_offsets =====================>");
+    // {1: 32("a")}, -1
+    let bytes = [0xa1, 0x01, 0xd8, 0x20, 0x61, 0x61, 0x20];
+    let expected = "@0+6: {\n@1+1:   1 \n@2+4:   32(\n@4+2:     \"a\" \n@6+1: -1 \n";
+
+    let mut out = Vec::new();
+    SequenceBuffer::new(&bytes).with_offsets(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+    let mut out = Vec::new();
+    CBORDecoder::from_slice(&bytes).with_offsets(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+    // A bare CBOR item does not know where it came from
+    let mut out = Vec::new();
+    assert!(CBOR::UInt(1).with_offsets(&mut out).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+#[test]
+fn debug_diag_notation() -> Result<(), CBORError> {
+    println!("<======================= debug_diag_notation =====================>");
+    // {1: 2, 3: [4, 5], -1: 24(h'01ff'), "a": [true, null, simple(16)], 2: 1.5}
+    let bytes = [
+        0xa5, 0x01, 0x02, 0x03, 0x82, 0x04, 0x05, 0x20, 0xd8, 0x18, 0x42, 0x01, 0xff, 0x61, 0x61,
+        0x83, 0xf5, 0xf6, 0xf0, 0x02, 0xf9, 0x3e, 0x00,
+    ];
+    let item = SequenceBuffer::new(&bytes).into_iter().next().unwrap();
+    let expected = "{1: 2, 3: [4, 5], -1: 24(h'01ff'), \"a\": [true, null, simple(16)], 2: 1.5}";
+    assert_eq!(format!("{:?}", item), expected);
+
+    if let CBOR::Map(mb) = item {
+        assert_eq!(format!("{:?}", mb), expected);
+        assert_eq!(format!("{:?}", mb.get_int(3)), "Some([4, 5])");
+        assert_eq!(format!("{:?}", mb.get_int(-1)), "Some(24(h'01ff'))");
+        // The internal state remains available
+        let raw = format!("{:?}", mb.raw_state());
+        assert!(raw.starts_with("MapBuf { bytes: [1, 2, 3, 130, 4, 5, 32, 216, 24, "));
+        assert!(raw.ends_with("], n_pairs: 5 }"));
+    } else {
+        panic!("expected a map");
+    }
+
+    // Negative values and special floats
+    assert_eq!(format!("{:?}", CBOR::NInt(99)), "-100");
+    assert_eq!(
+        format!("{:?}", CBOR::Float64(f64::NEG_INFINITY)),
+        "-Infinity"
+    );
+    assert_eq!(format!("{:?}", CBOR::Float32(f32::NAN)), "NaN");
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+#[test]
+fn decode_to_owned() -> Result<(), CBORError> {
+    use tps_minicbor::types::OwnedCbor;
+
+    println!("<======================= decode_to_owned =====================>");
+    // The decoded item is moved out of the scope of the buffer it was decoded from
+    let decode = || -> Result<OwnedCbor, CBORError> {
+        // {1: "3.1", -2: [h'0102', 1(1363896240)], "a": simple(16)}
+        let bytes = vec![
+            0xa3, 0x01, 0x63, 0x33, 0x2e, 0x31, 0x21, 0x82, 0x42, 0x01, 0x02, 0xc1, 0x1a, 0x51,
+            0x4b, 0x67, 0xb0, 0x61, 0x61, 0xf0,
+        ];
+        let mut owned = OwnedCbor::Eof;
+        CBORDecoder::from_slice(&bytes).decode_with(is_map(), |cbor| {
+            owned = cbor.to_owned();
+            Ok(())
+        })?;
+        Ok(owned)
+    };
+    let expected = OwnedCbor::Map(vec![
+        (OwnedCbor::UInt(1), OwnedCbor::Tstr("3.1".to_string())),
+        (
+            OwnedCbor::NInt(1),
+            OwnedCbor::Array(vec![
+                OwnedCbor::Bstr(vec![1, 2]),
+                OwnedCbor::Tag(1, Box::new(OwnedCbor::UInt(1363896240))),
+            ]),
+        ),
+        (OwnedCbor::Tstr("a".to_string()), OwnedCbor::Simple(16)),
+    ]);
+    assert_eq!(decode()?, expected);
+
+    // 32("b")
+    let bytes = [0xd8, 0x20, 0x61, 0x62];
+    let cbor = SequenceBuffer::new(&bytes).into_iter().next().unwrap();
+    let expected = OwnedCbor::Tag(32, Box::new(OwnedCbor::Tstr("b".to_string())));
+    assert_eq!(cbor.to_owned(), expected);
+    Ok(())
+}
+
+#[test]
+fn encode_decode_uuid_keyed_map() -> Result<(), CBORError> {
+    // A service registry keyed by 16 byte UUIDs
+    println!("<======================= encode_decode_uuid_keyed_map =====================>");
+    let rot13_uuid: [u8; 16] = [
+        0x87, 0xba, 0xe7, 0x13, 0xb0, 0x8f, 0x5e, 0x28, 0xb9, 0xee, 0x4a, 0xa6, 0xe2, 0x02, 0x44,
+        0x0e,
+    ];
+    let other_uuid: [u8; 16] = [
+        0x0a, 0x28, 0x8f, 0x23, 0xb7, 0x36, 0x58, 0x26, 0xa8, 0x1a, 0x9f, 0xe6, 0x6e, 0x33, 0x16,
+        0x61,
+    ];
+    let unknown_uuid = [0u8; 16];
+    let mut bytes = [0u8; 128];
+
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&map(|buf| {
+        buf.insert_key_value(
+            &rot13_uuid,
+            &map(|buf| buf.insert_key_value(&1, &"rot13")?.insert_key_value(&2, &16u32)),
+        )?
+        .insert_key_value(
+            &other_uuid,
+            &map(|buf| buf.insert_key_value(&1, &"test")?.insert_key_value(&2, &1u32)),
+        )
+    }))?;
+    // The key is encoded as a 16 byte bstr
+    assert_eq!(&encoder.encoded()?[..3], &[0xa2, 0x50, 0x87]);
+
+    CBORDecoder::new(encoder.build()?)
+        .map(|registry| {
+            assert_eq!(registry.len(), 2);
+            let rot13: MapBuf = registry.lookup_bytes(&rot13_uuid)?;
+            assert_eq!(rot13.lookup::<i64, &str>(1)?, "rot13");
+            assert_eq!(rot13.lookup::<i64, u32>(2)?, 16);
+            let other: MapBuf = registry.lookup_bytes(&other_uuid)?;
+            assert_eq!(other.lookup::<i64, &str>(1)?, "test");
+            assert!(registry.get_bstr(&rot13_uuid[..8]).is_none());
+            assert!(matches!(
+                registry.lookup_bytes::<MapBuf>(&unknown_uuid),
+                Err(CBORError::KeyNotPresent)
+            ));
+            Ok(())
+        })?
+        .finalize()
+}
+
+#[test]
+fn encode_nested_references() -> Result<(), CBORError> {
+    // Items captured by reference in builder closures can be inserted without dereferencing
+    println!("<======================= encode_nested_references =====================>");
+    let mut bytes = [0u8; 32];
+    let mut expected_bytes = [0u8; 32];
+    let value = 1000u32;
+    let value_ref = &value;
+    let name = "abc";
+
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&array(|buf| {
+        buf.insert(&value_ref)?
+            .insert(&&&name)?
+            .insert(&map(|buf| buf.insert_key_value(&&1u8, &value_ref)))
+    }))?;
+
+    let mut expected = CBORBuilder::new(&mut expected_bytes);
+    expected.insert(&array(|buf| {
+        buf.insert(&value)?
+            .insert(&name)?
+            .insert(&map(|buf| buf.insert_key_value(&1u8, &value)))
+    }))?;
+    assert_eq!(encoder.encoded()?, expected.encoded()?);
+    Ok(())
+}
+
+#[test]
+fn encode_decode_self_describe() -> Result<(), CBORError> {
+    use std::cell::RefCell;
+    use tps_minicbor::types::self_describe;
+
+    println!("<======================= encode_decode_self_describe =====================>");
+    let mut bytes = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder
+        .insert(&self_describe(|buf| {
+            buf.insert(&tag(61, |buf| buf.insert(&1u8)))
+        }))?
+        .insert(&2u8)?;
+    assert_eq!(
+        encoder.encoded()?,
+        &[0xd9, 0xd9, 0xf7, 0xd8, 0x3d, 0x01, 0x02]
+    );
+
+    // The magic number is stripped, and reported once, leaving the tagged content to decode
+    let seen = RefCell::new(Vec::new());
+    let record = |tag| seen.borrow_mut().push(tag);
+    let decoder = CBORDecoder::new(encoder.build()?).on_tag(&record);
+    decoder
+        .skip_self_describe()?
+        .decode_with(is_tag_with_value(61), |_| Ok(()))?
+        .decode_with(is_uint(), |cbor| {
+            assert_eq!(u8::try_from(cbor)?, 2);
+            Ok(())
+        })?;
+    assert_eq!(*seen.borrow(), vec![55799, 61]);
+
+    // Items without the tag, including other tags, are left unchanged
+    for input in [&[0x01][..], &[0xd8, 0x3d, 0x01][..]].iter() {
+        let decoder = CBORDecoder::from_slice(input);
+        decoder
+            .skip_self_describe()?
+            .decode_with(is_any(), |_| Ok(()))?;
+    }
+    let decoder = CBORDecoder::from_slice(&[]);
+    decoder.skip_self_describe()?;
+
+    // The magic number without any content to tag
+    let decoder = CBORDecoder::from_slice(&[0xd9, 0xd9, 0xf7]);
+    assert!(decoder.skip_self_describe().is_err());
+    Ok(())
+}
+
+#[test]
+fn encode_decode_bstr_array() -> Result<(), CBORError> {
+    // A list of service instance UUIDs, as in a discovery response
+    println!("<======================= encode_decode_bstr_array =====================>");
+    let uuids: [[u8; 16]; 3] = [
+        [
+            0x87, 0xba, 0xe7, 0x13, 0xb0, 0x8f, 0x5e, 0x28, 0xb9, 0xee, 0x4a, 0xa6, 0xe2, 0x02,
+            0x44, 0x0e,
+        ],
+        [
+            0x0a, 0x28, 0x8f, 0x23, 0xb7, 0x36, 0x58, 0x26, 0xa8, 0x1a, 0x9f, 0xe6, 0x6e, 0x33,
+            0x16, 0x61,
+        ],
+        [0u8; 16],
+    ];
+    let mut bytes = [0u8; 64];
+    let mut expected_bytes = [0u8; 64];
+
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert_bstr_array(&[&uuids[0], &uuids[1], &uuids[2]])?;
+
+    let mut expected = CBORBuilder::new(&mut expected_bytes);
+    expected.insert(&array(|buf| {
+        buf.insert(&uuids[0])?.insert(&uuids[1])?.insert(&uuids[2])
+    }))?;
+    assert_eq!(encoder.encoded()?, expected.encoded()?);
+    assert_eq!(encoder.encoded()?.len(), 1 + 3 * 17);
+
+    CBORDecoder::new(encoder.build()?)
+        .array(|ab| {
+            assert_eq!(ab.len(), uuids.len());
+            for (i, uuid) in uuids.iter().enumerate() {
+                match ab.index(i) {
+                    Some(CBOR::Bstr(b)) => assert_eq!(b, &uuid[..]),
+                    _ => return Err(CBORError::ExpectedType("bstr")),
+                }
+            }
+            Ok(())
+        })?
+        .finalize()
+}
+
+#[cfg(feature = "full")]
+#[test]
+fn date_time_raw_round_trip() -> Result<(), CBORError> {
+    use tps_minicbor::types::date_time_str;
+
+    println!("<======================= date_time_raw_round_trip =====================>");
+    // 0("2013-03-21T20:04:00Z")
+    let input: &[u8] = &[
+        0xc0, 0x74, 0x32, 0x30, 0x31, 0x33, 0x2d, 0x30, 0x33, 0x2d, 0x32, 0x31, 0x54, 0x32, 0x30,
+        0x3a, 0x30, 0x34, 0x3a, 0x30, 0x30, 0x5a,
+    ];
+    let (_, (dt, raw)) = decode_date_time_raw()(SequenceBuffer::new(input).into_iter())?;
+    assert_eq!(dt.timestamp(), 1363896240);
+    assert_eq!(raw, "2013-03-21T20:04:00Z");
+
+    // The raw string is re-encoded unchanged
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&date_time_str(raw))?;
+    assert_eq!(encoder.encoded()?, input);
+
+    // Re-encoding the parsed value changes the encoding
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&CBOR::DateTime(dt))?;
+    assert_ne!(encoder.encoded()?, input);
+
+    // Strings which are not RFC3339 date/times, and other items, are rejected
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    assert!(matches!(
+        encoder.insert(&date_time_str("21 March 2013")),
+        Err(CBORError::BadDateTime)
+    ));
+    assert!(encoder.is_empty());
+    assert!(matches!(
+        decode_date_time_raw()(SequenceBuffer::new(&[0xc1, 0x00]).into_iter()),
+        Err(CBORError::ExpectedTag(0))
+    ));
+    assert!(matches!(
+        decode_date_time_raw()(SequenceBuffer::new(&[0xc0, 0x00]).into_iter()),
+        Err(CBORError::ExpectedType("tstr"))
+    ));
+    Ok(())
+}
+
+#[test]
+fn duration_round_trip() -> Result<(), CBORError> {
+    use std::time::Duration;
+
+    println!("<======================= duration_round_trip =====================>");
+    // [30, 1(90), -1, 1(-1)]; fractions of a second are not encoded
+    let mut buffer = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&array(|buff| {
+        buff.insert(&Duration::from_millis(30_500))?
+            .insert(&tag(1, |buff| buff.insert(&90)))?
+            .insert(&-1)?
+            .insert(&tag(1, |buff| buff.insert(&-1)))
+    }))?;
+    assert_eq!(
+        encoder.encoded()?,
+        &[0x84, 0x18, 0x1e, 0xc1, 0x18, 0x5a, 0x20, 0xc1, 0x20]
+    );
+
+    let decoder = CBORDecoder::from_slice(encoder.encoded()?);
+    let _ = decoder.array(|ab| {
+        let items = [ab.index(0), ab.index(1), ab.index(2), ab.index(3)];
+        assert_eq!(
+            Duration::try_from(items[0].unwrap())?,
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            Duration::try_from(&items[1].unwrap())?,
+            Duration::from_secs(90)
+        );
+        for item in &items[2..] {
+            assert!(matches!(
+                Duration::try_from(item.unwrap()),
+                Err(CBORError::IncompatibleType)
+            ));
+        }
+        Ok(())
+    })?;
+
+    // 1(1.5) and 1(-1.5)
+    #[cfg(feature = "float")]
+    {
+        let seq = SequenceBuffer::new(&[0xc1, 0xf9, 0x3e, 0x00, 0xc1, 0xf9, 0xbe, 0x00]);
+        let mut it = seq.into_iter();
+        assert_eq!(
+            Duration::try_from(it.next().unwrap())?,
+            Duration::from_millis(1500)
+        );
+        assert!(matches!(
+            Duration::try_from(it.next().unwrap()),
+            Err(CBORError::IncompatibleType)
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+#[test]
+fn cbor_to_json() -> Result<(), CBORError> {
+    use tps_minicbor::debug::to_json;
+
+    fn json(cbor: &[u8]) -> Result<String, CBORError> {
+        let mut out = Vec::new();
+        to_json(cbor, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    println!("<=============================== cbor_to_json ===============================>");
+    // Integers, including the most negative, and floats
+    assert_eq!(
+        json(&[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])?,
+        "18446744073709551615"
+    );
+    assert_eq!(
+        json(&[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])?,
+        "-18446744073709551616"
+    );
+    assert_eq!(json(&[0xf9, 0x3e, 0x00])?, "1.5");
+    assert_eq!(json(&[0xfa, 0x47, 0xc3, 0x50, 0x00])?, "100000.0");
+    // NaN, infinity, undefined and simple values have no JSON equivalent
+    assert_eq!(
+        json(&[0x84, 0xf9, 0x7e, 0x00, 0xf9, 0x7c, 0x00, 0xf7, 0xf0])?,
+        "[null,null,null,null]"
+    );
+    assert_eq!(json(&[0x83, 0xf5, 0xf4, 0xf6])?, "[true,false,null]");
+
+    // Text strings are escaped
+    assert_eq!(
+        json(&[0x65, 0x61, 0x22, 0x5c, 0x0a, 0x01])?,
+        r#""a\"\\\n\u0001""#
+    );
+    assert_eq!(json(&[0x62, 0xc3, 0xbc])?, "\"ü\"");
+
+    // Byte strings are base64url by default, and tags 21 to 23 select the encoding of the byte
+    // strings they enclose: [h'fbff', 22(h'fbff'), 23([h'fbff', 21(h'fbff')])]
+    let cbor = [
+        0x83, 0x42, 0xfb, 0xff, 0xd6, 0x42, 0xfb, 0xff, 0xd7, 0x82, 0x42, 0xfb, 0xff, 0xd5, 0x42,
+        0xfb, 0xff,
+    ];
+    assert_eq!(json(&cbor)?, r#"["-_8","+/8=",["FBFF","-_8"]]"#);
+    assert_eq!(json(&[0x43, 0x01, 0x02, 0x03])?, r#""AQID""#);
+    assert_eq!(json(&[0x41, 0x01])?, r#""AQ""#);
+    assert_eq!(json(&[0xd6, 0x41, 0x01])?, r#""AQ==""#);
+
+    // Other tags are dropped: 1(1363896240) and 32("a")
+    assert_eq!(json(&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0])?, "1363896240");
+    assert_eq!(json(&[0xd8, 0x20, 0x61, 0x61])?, r#""a""#);
+
+    // Map keys: {"a": 1, -2: 2, h'01': 3, true: 4, [1]: 5}
+    let cbor = [
+        0xa5, 0x61, 0x61, 0x01, 0x21, 0x02, 0x41, 0x01, 0x03, 0xf5, 0x04, 0x81, 0x01, 0x05,
+    ];
+    assert_eq!(json(&cbor)?, r#"{"a":1,"-2":2,"AQ":3,"true":4,"[1]":5}"#);
+
+    // Nothing is written for malformed CBOR, and only one item is converted
+    let mut out = Vec::new();
+    assert!(to_json(&[0x82, 0x01], &mut out).is_err());
+    assert!(out.is_empty());
+    assert!(matches!(json(&[0x01, 0x02]), Err(CBORError::TrailingData)));
+    assert!(matches!(json(&[]), Err(CBORError::EndOfBuffer)));
+    let mut short = [0u8; 3];
+    assert!(matches!(
+        to_json(&[0x62, 0x61, 0x62], &mut short[..]),
+        Err(CBORError::EndOfBuffer)
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+#[test]
+fn json_to_cbor() -> Result<(), CBORError> {
+    use tps_minicbor::debug::{from_json, semantic_eq, to_json};
+
+    fn cbor(json: &str) -> Result<Vec<u8>, CBORError> {
+        let mut out = [0u8; 64];
+        let len = from_json(json, &mut out)?;
+        Ok(out[..len].to_vec())
+    }
+
+    println!("<=============================== json_to_cbor ===============================>");
+    // Integers use the shortest encoding, and those beyond 64 bits become floats
+    assert_eq!(cbor("0")?, [0x00]);
+    assert_eq!(cbor("-0")?, [0x00]);
+    assert_eq!(cbor("500")?, [0x19, 0x01, 0xf4]);
+    assert_eq!(
+        cbor("-18446744073709551616")?,
+        [0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+    );
+    assert_eq!(
+        cbor("18446744073709551616")?,
+        [0xfa, 0x5f, 0x80, 0x00, 0x00]
+    );
+    // Numbers with a fraction or exponent are floats, even if integral
+    assert_eq!(cbor("1.0")?, [0xf9, 0x3c, 0x00]);
+    assert_eq!(cbor("-0.0")?, [0xf9, 0x80, 0x00]);
+    assert_eq!(cbor("1E2")?, [0xf9, 0x56, 0x40]);
+    assert_eq!(
+        cbor("0.1")?,
+        [0xfb, 0x3f, 0xb9, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]
+    );
+
+    // Escapes, including a surrogate pair for U+1F600
+    assert_eq!(
+        cbor(r#""a\"\\\/\nü""#)?,
+        [0x67, 0x61, 0x22, 0x5c, 0x2f, 0x0a, 0xc3, 0xbc]
+    );
+    assert_eq!(cbor(r#""😀""#)?, [0x64, 0xf0, 0x9f, 0x98, 0x80]);
+
+    // Whitespace, nesting, empty containers and repeated keys
+    let json = " { \"a\" : [ true , false , null , [ ] ] ,\n\t\"b\" : { } , \"a\" : 1 } ";
+    assert_eq!(
+        cbor(json)?,
+        [0xa3, 0x61, 0x61, 0x84, 0xf5, 0xf4, 0xf6, 0x80, 0x61, 0x62, 0xa0, 0x61, 0x61, 0x01]
+    );
+
+    // JSON produced by to_json converts back to the same CBOR, when there are no byte strings,
+    // tags or non-text keys: {"x": [1, -2, 1.5, "y", {"z": null}]}
+    let original = [
+        0xa1, 0x61, 0x78, 0x85, 0x01, 0x21, 0xf9, 0x3e, 0x00, 0x61, 0x79, 0xa1, 0x61, 0x7a, 0xf6,
+    ];
+    let mut json = Vec::new();
+    to_json(&original, &mut json)?;
+    let round_trip = cbor(std::str::from_utf8(&json).unwrap())?;
+    assert_eq!(round_trip, original);
+    assert!(semantic_eq(&round_trip, &original)?);
+
+    // Invalid JSON
+    for bad in [
+        "",
+        "[1,]",
+        "[1 2]",
+        "{1: 2}",
+        "{\"a\" 1}",
+        "1.",
+        "-",
+        "1e",
+        "tru",
+        "\"a",
+        "\"\u{1}\"",
+        r#""\x""#,
+        r#""\u12g4""#,
+        r#""\ud800""#,
+        r#""\udc00""#,
+        "[",
+    ] {
+        assert!(
+            matches!(cbor(bad), Err(CBORError::MalformedEncoding)),
+            "{:?}",
+            bad
+        );
+    }
+    assert!(matches!(cbor("1 2"), Err(CBORError::TrailingData)));
+    assert!(matches!(cbor("01"), Err(CBORError::TrailingData)));
+    assert!(matches!(cbor("1e400"), Err(CBORError::OutOfRange)));
+    let deep = "[".repeat(65) + &"]".repeat(65);
+    assert!(matches!(cbor(&deep), Err(CBORError::LimitExceeded)));
+    let deep = "[".repeat(64) + &"]".repeat(64);
+    assert_eq!(cbor(&deep)?.len(), 64);
+    assert!(matches!(
+        from_json("\"abcd\"", &mut [0u8; 4]),
+        Err(CBORError::EndOfBuffer)
+    ));
+    Ok(())
+}
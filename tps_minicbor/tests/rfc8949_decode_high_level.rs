@@ -22,12 +22,16 @@
  *
  * Test cases from RFC8949, Table 6.
  **************************************************************************************************/
+// These tests use the decode combinators, which require the `combinators` feature
+#![cfg(feature = "combinators")]
 
 extern crate tps_minicbor;
 
+use std::convert::TryFrom;
+
 use tps_minicbor::decoder::*;
 use tps_minicbor::error::CBORError;
-use tps_minicbor::types::{CBOR};
+use tps_minicbor::types::{CBORType, Key, Label, CBOR};
 
 #[test]
 fn decode_combinators_basic() -> Result<(), CBORError> {
@@ -598,8 +602,10 @@ fn rfc8949_decode_array() -> Result<(), CBORError> {
         println!("<======================= Test with empty array =====================>");
         let _decoder = CBORDecoder::from_slice(&[0x80]).array(|ab| {
             assert_eq!(ab.len(), 0);
+            assert_eq!(ab.index(0), None);
+            assert!(matches!(ab.item::<u8>(0), Err(CBORError::IndexOutOfBounds)));
             Ok(())
-        });
+        })?;
     }
     {
         println!("<======================= Test with [1,2,3] =====================>");
@@ -607,8 +613,14 @@ fn rfc8949_decode_array() -> Result<(), CBORError> {
             assert_eq!(ab.item::<u8>(2)?, 3u8);
             assert_eq!(ab.item::<u8>(1)?, 2u8);
             assert_eq!(ab.item::<u8>(0)?, 1u8);
+            // Out of range indices, at len(), len() + 1 and beyond
+            assert_eq!(ab.index(ab.len()), None);
+            assert_eq!(ab.index(ab.len() + 1), None);
+            assert_eq!(ab.index(usize::MAX), None);
+            assert!(matches!(ab.item::<u8>(ab.len()), Err(CBORError::IndexOutOfBounds)));
+            assert!(matches!(ab.item::<u8>(ab.len() + 1), Err(CBORError::IndexOutOfBounds)));
             Ok(())
-        });
+        })?;
     }
     {
         println!("<======================= Test with [1,[2,3],[4,5]] =====================>");
@@ -617,51 +629,46 @@ fn rfc8949_decode_array() -> Result<(), CBORError> {
         let _d = CBORDecoder::from_slice(&[0x83, 0x01, 0x82, 0x02, 0x03, 0x82, 0x04, 0x05]).array(
             |ab| {
                 assert_eq!(ab.item::<u8>(0)?, 1u8);
-                let a1 = CBORDecoder::from_array(ab.item(1)?)?;
-                let _ = a1.array(|ab1| {
-                    assert_eq!(ab1.item::<u8>(1)?, 3u8);
-                    assert_eq!(ab1.item::<u8>(0)?, 2u8);
-                    Ok(())
-                });
-                let a2 = CBORDecoder::from_array(ab.item(2)?)?;
-                let _ = a2.array(|ab2| {
-                    assert_eq!(ab2.item::<u8>(1)?, 5u8);
-                    assert_eq!(ab2.item::<u8>(0)?, 4u8);
-                    Ok(())
-                });
+                CBORDecoder::from_array(ab.item(1)?)?
+                    .decode_with(is_uint(), |cbor| Ok(assert_eq!(u8::try_from(cbor)?, 2u8)))?
+                    .decode_with(is_uint(), |cbor| Ok(assert_eq!(u8::try_from(cbor)?, 3u8)))?
+                    .finalize()?;
+                CBORDecoder::from_array(ab.item(2)?)?
+                    .decode_with(is_uint(), |cbor| Ok(assert_eq!(u8::try_from(cbor)?, 4u8)))?
+                    .decode_with(is_uint(), |cbor| Ok(assert_eq!(u8::try_from(cbor)?, 5u8)))?
+                    .finalize()?;
                 Ok(())
             },
-        );
+        )?;
     }
     {
         println!("<======================= Test with [1,2, ..., 25] =====================>");
-        let _ = CBORDecoder::from_slice(&[
+        CBORDecoder::from_slice(&[
             0x98, 0x19, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
             0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x18, 0x18,
             0x19,
         ])
         .array(|ab| {
-            for i in 0..=ab.len() {
-                assert_eq!(ab.item::<u8>(i)?, i.clone() as u8 + 1);
+            for i in 0..ab.len() {
+                assert_eq!(ab.item::<u8>(i)?, i as u8 + 1);
             }
             Ok(())
-        });
+        })?;
     }
     {
         println!(
             "<======================= Test with [\"a\", (\"b\": \"c\")]  =====================>"
         );
-        let _ = CBORDecoder::from_slice(&[0x82, 0x61, 0x61, 0xa1, 0x61, 0x62, 0x61, 0x63])
+        CBORDecoder::from_slice(&[0x82, 0x61, 0x61, 0xa1, 0x61, 0x62, 0x61, 0x63])
             .array(|ab| {
                 assert_eq!(ab.len(), 2);
                 assert_eq!(ab.item::<&str>(0)?, "a");
-                let _ = CBORDecoder::from_map(ab.item(2)?)?
-                    .map(|mb| {
-                    assert_eq!(mb.lookup::<&str, &str>("b")?, "c");
-                    Ok(())
-                });
+                CBORDecoder::from_map(ab.item(1)?)?
+                    .decode_with(is_tstr(), |cbor| Ok(assert_eq!(<&str>::try_from(cbor)?, "b")))?
+                    .decode_with(is_tstr(), |cbor| Ok(assert_eq!(<&str>::try_from(cbor)?, "c")))?
+                    .finalize()?;
                 Ok(())
-            });
+            })?;
     }
     Ok(())
 }
@@ -692,18 +699,15 @@ fn rfc8949_decode_map() -> Result<(), CBORError> {
         println!(
             "<======================= Test with (\"a\": 1, \"b\": [2,3])  =====================>"
         );
-        let _ = CBORDecoder::from_slice(&[0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x82, 0x02, 0x03])
+        CBORDecoder::from_slice(&[0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x82, 0x02, 0x03])
             .map(|mb| {
                 assert_eq!(mb.len(), 2);
                 assert_eq!(mb.lookup::<&str, u8>("a")?, 1);
-                let _a = CBORDecoder::from_map(mb.lookup("b")?)?
-                    .array(|ab| {
-                        assert_eq!(ab.item::<u8>(0)?, 2);
-                        assert_eq!(ab.item::<u8>(1)?, 3);
-                        Ok(())
-                    });
-                Ok(())
-            });
+                CBORDecoder::from_array(mb.lookup("b")?)?
+                    .decode_with(is_uint(), |cbor| Ok(assert_eq!(u8::try_from(cbor)?, 2)))?
+                    .decode_with(is_uint(), |cbor| Ok(assert_eq!(u8::try_from(cbor)?, 3)))?
+                    .finalize()
+            })?;
     }
     {
         println!(
@@ -804,3 +808,1846 @@ fn rfc8949_decode_tag() -> Result<(), CBORError> {
     }
     Ok(())
 }
+
+#[test]
+fn decode_with_raw_bytes() -> Result<(), CBORError> {
+    println!("<=============================== decode_with_raw_bytes =============================>");
+    // 24(h'6449455446') followed by [1, 2]
+    let bytes = [0xd8, 0x18, 0x45, 0x64, 0x49, 0x45, 0x54, 0x46, 0x82, 0x01, 0x02];
+    let _ = CBORDecoder::from_slice(&bytes)
+        .decode_with_raw(is_tag(), |cbor, raw| {
+            assert!(matches!(cbor, CBOR::Tag(_)));
+            assert_eq!(raw, &bytes[..8]);
+            Ok(())
+        })?
+        .decode_with_raw(is_array(), |_cbor, raw| {
+            assert_eq!(raw, &[0x82, 0x01, 0x02]);
+            Ok(())
+        })?;
+    Ok(())
+}
+
+#[test]
+fn decode_bstr_copy_into() -> Result<(), CBORError> {
+    println!("<=============================== decode_bstr_copy_into =============================>");
+    let _ = CBORDecoder::from_slice(&[0x44, 0x01, 0x02, 0x03, 0x04]).decode_with(
+        is_bstr(),
+        |cbor| {
+            let mut long = [0u8; 8];
+            assert_eq!(cbor.copy_bytes_into(&mut long)?, 4);
+            assert_eq!(&long[..4], &[1, 2, 3, 4]);
+            let mut short = [0u8; 3];
+            assert!(matches!(
+                cbor.copy_bytes_into(&mut short),
+                Err(CBORError::ShortBuffer(4))
+            ));
+            let mut exact = [0u8; 4];
+            cbor.copy_exact_into(&mut exact)?;
+            assert_eq!(exact, [1, 2, 3, 4]);
+            assert!(matches!(
+                cbor.copy_exact_into(&mut long),
+                Err(CBORError::WrongLength(4))
+            ));
+            assert!(matches!(
+                CBOR::UInt(1).copy_bytes_into(&mut long),
+                Err(CBORError::IncompatibleType)
+            ));
+            Ok(())
+        },
+    )?;
+    Ok(())
+}
+
+#[test]
+fn decode_one_then_trailer() -> Result<(), CBORError> {
+    println!("<=============================== decode_one_then_trailer =============================>");
+    // {1: "a"} followed by a 3 byte opaque trailer
+    let bytes = [0xa1, 0x01, 0x61, 0x61, 0x01, 0x02, 0x03];
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let (item, trailer) = decoder.decode_one_then(is_map(), |trailer| trailer)?;
+    assert!(matches!(item, CBOR::Map(_)));
+    assert_eq!(trailer, &[0x01, 0x02, 0x03]);
+    assert!(decoder.decode_with(is_any(), |_| Ok(())).is_err());
+
+    // No trailer at all
+    let (_, trailer) =
+        CBORDecoder::from_slice(&bytes[..4]).decode_one_then(is_map(), |trailer| trailer)?;
+    assert!(trailer.is_empty());
+    Ok(())
+}
+
+#[test]
+fn decode_unwrap_unknown_tags() -> Result<(), CBORError> {
+    println!("<=============================== decode_unwrap_unknown_tags =============================>");
+    // 32("a"), 24(23(h'01'))
+    let bytes = [0xd8, 0x20, 0x61, 0x61, 0xd8, 0x18, 0xd7, 0x41, 0x01];
+    {
+        // Default: tags are preserved
+        let decoder = CBORDecoder::from_slice(&bytes);
+        let _ = decoder.decode_with(is_any(), |cbor| {
+            assert!(matches!(cbor, CBOR::Tag(tb) if tb.get_tag() == 32));
+            Ok(())
+        })?;
+        assert_eq!(decoder.last_tag(), None);
+    }
+    {
+        let decoder = CBORDecoder::from_slice(&bytes).unwrap_unknown_tags(true);
+        let _ = decoder.decode_with(is_any(), |cbor| {
+            assert_eq!(cbor, CBOR::Tstr("a"));
+            Ok(())
+        })?;
+        assert_eq!(decoder.last_tag(), Some(32));
+        let _ = decoder.decode_with(is_any(), |cbor| {
+            assert_eq!(cbor, CBOR::Bstr(&[0x01]));
+            Ok(())
+        })?;
+        assert_eq!(decoder.last_tag(), Some(24));
+    }
+    Ok(())
+}
+
+#[test]
+fn decode_strict_tagged_map_key() -> Result<(), CBORError> {
+    println!("<============================= decode_strict_tagged_map_key =============================>");
+    // {1: "a", 1(0): "b"}
+    let bytes = [0xa2, 0x01, 0x61, 0x61, 0xc1, 0x00, 0x61, 0x62];
+    {
+        // Default: tagged keys are accepted
+        let decoder = CBORDecoder::from_slice(&bytes);
+        let _ = decoder.map(|mb| {
+            assert_eq!(mb.len(), 2);
+            Ok(())
+        })?;
+    }
+    {
+        let decoder = CBORDecoder::from_slice(&bytes).strict(true);
+        assert!(matches!(decoder.map(|_| Ok(())), Err(CBORError::NotAllowed)));
+        let decoder = CBORDecoder::from_slice(&bytes).strict(true);
+        assert!(matches!(
+            decoder.decode_with(is_map(), |_| Ok(())),
+            Err(CBORError::NotAllowed)
+        ));
+    }
+    {
+        // Strict mode accepts untagged keys
+        let decoder = CBORDecoder::from_slice(&[0xa1, 0x01, 0x61, 0x61]).strict(true);
+        let _ = decoder.map(|mb| {
+            assert_eq!(mb.lookup::<i64, &str>(1)?, "a");
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+#[test]
+fn decode_strict_nested_tagged_map_key() -> Result<(), CBORError> {
+    println!("<========================== decode_strict_nested_tagged_map_key ==========================>");
+    // [{1(2): 3}]
+    let in_array = [0x81, 0xa1, 0xc1, 0x02, 0x03];
+    // {1: {1(2): 3}}
+    let in_map = [0xa1, 0x01, 0xa1, 0xc1, 0x02, 0x03];
+    // 24({1(2): 3})
+    let in_tag = [0xd8, 0x18, 0xa1, 0xc1, 0x02, 0x03];
+    {
+        // Default: nested tagged keys are accepted
+        CBORDecoder::from_slice(&in_array).array(|ab| {
+            CBORDecoder::from_array(CBOR::Array(ab))?
+                .map(|_| Ok(()))
+                .map(|_| ())
+        })?;
+        CBORDecoder::from_slice(&in_map).map(|_| Ok(()))?;
+        CBORDecoder::from_slice(&in_tag).tag(|_| Ok(()))?;
+    }
+    {
+        let decoder = CBORDecoder::from_slice(&in_array).strict(true);
+        let result = decoder.array(|ab| {
+            CBORDecoder::from_array(CBOR::Array(ab))?
+                .map(|_| Ok(()))
+                .map(|_| ())
+        });
+        assert!(matches!(result, Err(CBORError::NotAllowed)));
+        let decoder = CBORDecoder::from_slice(&in_map).strict(true);
+        assert!(matches!(decoder.map(|_| Ok(())), Err(CBORError::NotAllowed)));
+        let decoder = CBORDecoder::from_slice(&in_tag).strict(true);
+        assert!(matches!(decoder.tag(|_| Ok(())), Err(CBORError::NotAllowed)));
+        let decoder = CBORDecoder::from_slice(&in_map).strict(true);
+        assert!(matches!(
+            decoder.decode_with(is_any(), |_| Ok(())),
+            Err(CBORError::NotAllowed)
+        ));
+    }
+    {
+        // Strict mode accepts nested untagged keys: [{1: {2: 3}}]
+        CBORDecoder::from_slice(&[0x81, 0xa1, 0x01, 0xa1, 0x02, 0x03])
+            .strict(true)
+            .array(|_| Ok(()))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn decode_strict_tag_content() -> Result<(), CBORError> {
+    println!("<============================= decode_strict_tag_content =============================>");
+    fn strict_tag(bytes: &[u8]) -> Result<(), CBORError> {
+        CBORDecoder::from_slice(bytes).strict(true).tag(|_| Ok(())).map(|_| ())
+    }
+
+    // Well-known tags with the required content type
+    strict_tag(&[0xc0, 0x61, 0x61])?; // 0("a")
+    strict_tag(&[0xc1, 0x01])?; // 1(1)
+    strict_tag(&[0xc1, 0x20])?; // 1(-1)
+    strict_tag(&[0xc2, 0x41, 0x01])?; // 2(h'01')
+    strict_tag(&[0xc3, 0x41, 0x01])?; // 3(h'01')
+    strict_tag(&[0xd5, 0x81, 0x01])?; // 21([1]) - expected conversions accept any item
+    strict_tag(&[0xd6, 0x61, 0x61])?; // 22("a")
+    strict_tag(&[0xd7, 0xa0])?; // 23({})
+    strict_tag(&[0xd8, 0x18, 0x42, 0x81, 0x01])?; // 24(<<[1]>>)
+    strict_tag(&[0xd8, 0x20, 0x01])?; // 32(1) - not checked
+    #[cfg(feature = "float")]
+    strict_tag(&[0xc1, 0xf9, 0x3e, 0x00])?; // 1(1.5)
+
+    // Content of the wrong type
+    assert!(matches!(strict_tag(&[0xc0, 0x01]), Err(CBORError::TagContentMismatch(0))));
+    assert!(matches!(strict_tag(&[0xc1, 0x61, 0x61]), Err(CBORError::TagContentMismatch(1))));
+    assert!(matches!(strict_tag(&[0xc2, 0x01]), Err(CBORError::TagContentMismatch(2))));
+    assert!(matches!(strict_tag(&[0xc3, 0x61, 0x61]), Err(CBORError::TagContentMismatch(3))));
+    assert!(matches!(strict_tag(&[0xc1, 0xc1, 0x01]), Err(CBORError::TagContentMismatch(1))));
+    #[cfg(feature = "float")]
+    assert!(matches!(
+        strict_tag(&[0xc0, 0xf9, 0x3e, 0x00]),
+        Err(CBORError::TagContentMismatch(0))
+    ));
+    // Tag 24 must enclose a bstr holding exactly one well-formed item
+    let bad_24: [&[u8]; 4] = [
+        &[0xd8, 0x18, 0x01],
+        &[0xd8, 0x18, 0x40],
+        &[0xd8, 0x18, 0x42, 0x01, 0x02],
+        &[0xd8, 0x18, 0x41, 0x1c],
+    ];
+    for bytes in bad_24.iter() {
+        assert!(matches!(strict_tag(bytes), Err(CBORError::TagContentMismatch(24))));
+    }
+
+    // Not checked unless strict
+    let _ = CBORDecoder::from_slice(&[0xc2, 0x01]).tag(|_| Ok(()))?;
+
+    // Other ways of decoding tags are also checked
+    let bytes = [0xc2, 0x01];
+    let decoder = CBORDecoder::from_slice(&bytes).strict(true);
+    assert!(matches!(
+        decoder.decode_with(is_tag(), |_| Ok(())),
+        Err(CBORError::TagContentMismatch(2))
+    ));
+    assert!(matches!(
+        decoder.decode_with(is_tag_with_value(2), |_| Ok(())),
+        Err(CBORError::TagContentMismatch(2))
+    ));
+    assert!(matches!(
+        decoder.decode_with(is_any(), |_| Ok(())),
+        Err(CBORError::TagContentMismatch(2))
+    ));
+    // 55799(2(1)) - each tag in an unwrapped chain is checked
+    let bytes = [0xd9, 0xd9, 0xf7, 0xc2, 0x01];
+    let decoder = CBORDecoder::from_slice(&bytes).strict(true).unwrap_unknown_tags(true);
+    assert!(matches!(
+        decoder.decode_with(is_any(), |_| Ok(())),
+        Err(CBORError::TagContentMismatch(2))
+    ));
+    Ok(())
+}
+
+#[test]
+fn decode_require_definite() -> Result<(), CBORError> {
+    println!("<============================= decode_require_definite =============================>");
+    // [_ 1, 2], (_ h'01'), (_ "a"), {_ }
+    let indefinite: [&[u8]; 4] = [
+        &[0x9f, 0x01, 0x02, 0xff],
+        &[0x5f, 0x41, 0x01, 0xff],
+        &[0x7f, 0x61, 0x61, 0xff],
+        &[0xbf, 0xff],
+    ];
+    for bytes in indefinite.iter() {
+        let decoder = CBORDecoder::from_slice(bytes).require_definite(true);
+        assert!(matches!(
+            decoder.decode_with(is_any(), |_| Ok(())),
+            Err(CBORError::IndefiniteNotAllowed)
+        ));
+    }
+    let decoder = CBORDecoder::from_slice(indefinite[0]).require_definite(true);
+    assert!(matches!(
+        decoder.array(|_| Ok(())),
+        Err(CBORError::IndefiniteNotAllowed)
+    ));
+    let decoder = CBORDecoder::from_slice(indefinite[1]).require_definite(true);
+    assert!(matches!(
+        decoder.decode_with(is_bstr(), |_| Ok(())),
+        Err(CBORError::IndefiniteNotAllowed)
+    ));
+
+    // Definite length items are unaffected
+    let bytes = [0x82, 0x01, 0x02, 0x41, 0x01];
+    let decoder = CBORDecoder::from_slice(&bytes).require_definite(true);
+    decoder
+        .decode_with(is_array(), |_| Ok(()))?
+        .decode_with(is_bstr(), |_| Ok(()))?;
+    decoder.finalize()
+}
+
+#[test]
+fn decode_validate() -> Result<(), CBORError> {
+    println!(
+        "<================================= decode_validate =================================>"
+    );
+    let defaults = DecodeLimits::default();
+    fn validate(bytes: &[u8], limits: DecodeLimits) -> Result<(), CBORError> {
+        CBORDecoder::validate(bytes, &limits)
+    }
+
+    // {1: [h'01', "a"], 2: 3(h'02')}
+    let message = [
+        0xa2, 0x01, 0x82, 0x41, 0x01, 0x61, 0x61, 0x02, 0xc3, 0x41, 0x02,
+    ];
+    validate(&message, defaults)?;
+    validate(
+        &message,
+        DecodeLimits {
+            max_depth: 2,
+            max_items: 2,
+            max_string_len: 1,
+            strict: true,
+            canonical: true,
+        },
+    )?;
+
+    // Single item with no trailing data
+    assert!(matches!(
+        validate(&[], defaults),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert!(matches!(
+        validate(&[0x01, 0x02], defaults),
+        Err(CBORError::EofExpected)
+    ));
+    // Not well-formed: truncated, or with bad UTF-8 deep in the structure
+    assert!(matches!(
+        validate(&message[..10], defaults),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert!(matches!(
+        validate(&[0x81, 0x81, 0x61, 0xff], defaults),
+        Err(CBORError::UTF8Error)
+    ));
+
+    // Limits
+    let limited = |f: fn(&mut DecodeLimits)| {
+        let mut limits = defaults;
+        f(&mut limits);
+        validate(&message, limits)
+    };
+    assert!(matches!(
+        limited(|l| l.max_depth = 1),
+        Err(CBORError::LimitExceeded)
+    ));
+    assert!(matches!(
+        limited(|l| l.max_items = 1),
+        Err(CBORError::LimitExceeded)
+    ));
+    assert!(matches!(
+        limited(|l| l.max_string_len = 0),
+        Err(CBORError::LimitExceeded)
+    ));
+    // Deep nesting is rejected before it is walked
+    let deep = [0x81; 1000];
+    assert!(matches!(
+        validate(&deep, defaults),
+        Err(CBORError::LimitExceeded)
+    ));
+
+    // Strict profile
+    let strict = DecodeLimits {
+        strict: true,
+        ..defaults
+    };
+    assert!(matches!(
+        validate(&[0xa1, 0xc1, 0x00, 0x01], strict),
+        Err(CBORError::NotAllowed)
+    ));
+    assert!(matches!(
+        validate(&[0x81, 0xc2, 0x01], strict),
+        Err(CBORError::TagContentMismatch(2))
+    ));
+    validate(&[0x81, 0xc2, 0x01], defaults)?;
+
+    // Deterministic encoding
+    let canonical = DecodeLimits {
+        canonical: true,
+        ..defaults
+    };
+    let not_canonical: [&[u8]; 5] = [
+        &[0x18, 0x17],                         // 23 in two bytes
+        &[0x99, 0x00, 0x01, 0x01],             // array length in three bytes
+        &[0xa2, 0x02, 0x00, 0x01, 0x00],       // {2: 0, 1: 0}
+        &[0xa2, 0x01, 0x00, 0x01, 0x00],       // {1: 0, 1: 0}
+        &[0xa2, 0x20, 0x00, 0x18, 0x18, 0x00], // {-1: 0, 24: 0}
+    ];
+    for bytes in not_canonical.iter() {
+        validate(bytes, defaults)?;
+        assert!(matches!(
+            validate(bytes, canonical),
+            Err(CBORError::NonCanonical)
+        ));
+    }
+    // {24: 0, -1: 0} is in order, as keys are sorted by their encoding, not their value
+    validate(&[0xa2, 0x18, 0x18, 0x00, 0x20, 0x00], canonical)?;
+    assert!(matches!(
+        validate(&[0x5f, 0x41, 0x01, 0xff], canonical),
+        Err(CBORError::IndefiniteNotAllowed)
+    ));
+
+    // is_well_formed uses the default limits
+    assert!(is_well_formed(&message));
+    assert!(!is_well_formed(&[]));
+    assert!(!is_well_formed(&message[..10]));
+    assert!(!is_well_formed(&[0x81, 0x81, 0x61, 0xff]));
+    assert!(!is_well_formed(&deep));
+
+    // Indefinite length items: [_ 1], {_ 1: 1}, (_ h'01'), (_ "a", "b"), [_ [_ ], {_ }]
+    assert!(is_well_formed(&[0x9f, 0x01, 0xff]));
+    assert!(is_well_formed(&[0xbf, 0x01, 0x01, 0xff]));
+    assert!(is_well_formed(&[0x5f, 0x41, 0x01, 0xff]));
+    assert!(is_well_formed(&[0x7f, 0x61, 0x61, 0x61, 0x62, 0xff]));
+    assert!(is_well_formed(&[0x9f, 0x9f, 0xff, 0xbf, 0xff, 0xff]));
+    // ...and malformed ones: no break, a key with no value, a stray break, a chunk of the wrong
+    // type, bad UTF-8 in a chunk and nesting beyond the default depth
+    assert!(!is_well_formed(&[0x9f, 0x01]));
+    assert!(!is_well_formed(&[0xbf, 0x01, 0xff]));
+    assert!(!is_well_formed(&[0xff]));
+    assert!(!is_well_formed(&[0x5f, 0x61, 0x61, 0xff]));
+    assert!(!is_well_formed(&[0x7f, 0x61, 0xff, 0xff]));
+    assert!(!is_well_formed(&[0x9f; 1000]));
+    assert!(matches!(
+        validate(&[0x9f, 0x01, 0x02, 0xff], DecodeLimits { max_items: 1, ..defaults }),
+        Err(CBORError::LimitExceeded)
+    ));
+    assert!(matches!(
+        validate(&[0x7f, 0x61, 0x61, 0x61, 0x62, 0xff], DecodeLimits { max_string_len: 1, ..defaults }),
+        Err(CBORError::LimitExceeded)
+    ));
+
+    // Floats of each width, whether or not the `float` feature is enabled
+    assert!(is_well_formed(&[0xf9, 0x3e, 0x00]));
+    assert!(is_well_formed(&[0x81, 0xfa, 0x3f, 0xc0, 0x00, 0x00]));
+    assert!(is_well_formed(&[0xfb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]));
+    assert!(!is_well_formed(&[0xfa, 0x3f, 0xc0, 0x00]));
+    Ok(())
+}
+
+#[test]
+fn decode_saturating_integers() -> Result<(), CBORError> {
+    use std::convert::TryFrom;
+
+    println!("<============================= decode_saturating_integers =============================>");
+    // [4294967296, -4294967297, 100, -100, "a"]
+    let bytes = [
+        0x85, 0x1b, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x3b, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00, 0x18, 0x64, 0x38, 0x63, 0x61, 0x61,
+    ];
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let _ = decoder.array(|ab| {
+        let big = ab.index(0).unwrap();
+        let small = ab.index(1).unwrap();
+        assert_eq!(big.as_u32_saturating()?, u32::MAX);
+        assert_eq!(big.as_i32_saturating()?, i32::MAX);
+        assert_eq!(small.as_u32_saturating()?, 0);
+        assert_eq!(small.as_i32_saturating()?, i32::MIN);
+        assert_eq!(ab.index(2).unwrap().as_u32_saturating()?, 100);
+        assert_eq!(ab.index(3).unwrap().as_i32_saturating()?, -100);
+        assert!(matches!(
+            ab.index(4).unwrap().as_u32_saturating(),
+            Err(CBORError::IncompatibleType)
+        ));
+        // The default conversions still fail on overflow
+        assert!(u32::try_from(big).is_err());
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_expect_bytes_and_text() -> Result<(), CBORError> {
+    println!("<============================ decode_expect_bytes_and_text ============================>");
+    // h'010203', "ok"
+    let bytes = [0x43, 0x01, 0x02, 0x03, 0x62, 0x6f, 0x6b];
+    let nonce = [0x01u8, 0x02, 0x03];
+    let text = "ok";
+    {
+        let decoder = CBORDecoder::from_slice(&bytes);
+        let _ = decoder
+            .decode_with(expect_bytes(&nonce), |_| Ok(()))?
+            .decode_with(expect_text(text), |_| Ok(()))?;
+    }
+    {
+        let decoder = CBORDecoder::from_slice(&bytes);
+        let _ = decoder.decode_with(expect_bytes_ct(&nonce), |_| Ok(()))?;
+    }
+    {
+        // Mismatches, including a prefix of the expected value
+        let decoder = CBORDecoder::from_slice(&bytes);
+        assert!(matches!(
+            decoder.decode_with(expect_bytes(&nonce[..2]), |_| Ok(())),
+            Err(CBORError::FailedPredicate)
+        ));
+        assert!(matches!(
+            decoder.decode_with(expect_bytes_ct(&[0x01, 0x02, 0x04]), |_| Ok(())),
+            Err(CBORError::FailedPredicate)
+        ));
+        assert!(matches!(
+            decoder.decode_with(expect_text("ok"), |_| Ok(())),
+            Err(CBORError::ExpectedType("tstr"))
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn decode_array_check_len() -> Result<(), CBORError> {
+    println!("<=============================== decode_array_check_len ===============================>");
+    // [1, 2, 3, 4, 5, 6]
+    let bytes = [0x86, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let _ = decoder.array(|ab| {
+        // len == min, len == max
+        ab.check_len(6, 10)?;
+        ab.check_len(2, 6)?;
+        ab.check_len(6, 6)?;
+        // len == max + 1, len == min - 1
+        assert!(matches!(ab.check_len(2, 5), Err(CBORError::ArrayLength(6))));
+        assert!(matches!(ab.check_len(7, 9), Err(CBORError::ArrayLength(6))));
+        Ok(())
+    })?;
+    let decoder = CBORDecoder::from_slice(&[0x80]);
+    let _ = decoder.array(|ab| {
+        ab.check_len(0, 1)?;
+        assert!(matches!(ab.check_len(1, 1), Err(CBORError::ArrayLength(0))));
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_on_tag_callback() -> Result<(), CBORError> {
+    use std::cell::RefCell;
+
+    println!("<=============================== decode_on_tag_callback ===============================>");
+    // 1(0), 55799(61(h'01')), [32("a")], "b"
+    let bytes = [
+        0xc1, 0x00, 0xd9, 0xd9, 0xf7, 0xd8, 0x3d, 0x41, 0x01, 0x81, 0xd8, 0x20, 0x61, 0x61, 0x61,
+        0x62,
+    ];
+    let seen = RefCell::new(Vec::new());
+    let record = |tag| seen.borrow_mut().push(tag);
+    let decoder = CBORDecoder::from_slice(&bytes).on_tag(&record);
+    let _ = decoder.decode_with(is_tag(), |_| Ok(()))?;
+    // A failed parse does not report the tags it has looked at
+    assert!(decoder.decode_with(is_int(), |_| Ok(())).is_err());
+    let _ = decoder.decode_with(is_tag(), |_| Ok(()))?;
+    // Tags within an array are seen
+    let _ = decoder.decode_with(is_array(), |_| Ok(()))?;
+    let _ = decoder.decode_with(is_tstr(), |_| Ok(()))?;
+    assert_eq!(*seen.borrow(), vec![1, 55799, 61, 32]);
+
+    // Tags in nested arrays and maps, including map keys, are seen in encoding order:
+    // [1(0), {2(h''): 3(h'')}]
+    let seen = RefCell::new(Vec::new());
+    let record = |tag| seen.borrow_mut().push(tag);
+    let nested = [0x82, 0xc1, 0x00, 0xa1, 0xc2, 0x40, 0xc3, 0x40];
+    let decoder = CBORDecoder::from_slice(&nested).on_tag(&record);
+    decoder.array(|ab| {
+        // Decoders over the nested items do not report the tags again
+        CBORDecoder::from_array(CBOR::Array(ab))?
+            .decode_with(is_tag(), |_| Ok(()))?
+            .decode_with(is_map(), |_| Ok(()))?
+            .finalize()
+    })?;
+    assert_eq!(*seen.borrow(), vec![1, 2, 3]);
+
+    // [1(0), {2: 3(h'')}], where the map key is an untagged 2
+    let seen = RefCell::new(Vec::new());
+    let record = |tag| seen.borrow_mut().push(tag);
+    let nested = [0x82, 0xc1, 0x00, 0xa1, 0x02, 0xc3, 0x40];
+    let decoder = CBORDecoder::from_slice(&nested).on_tag(&record);
+    decoder.array(|_| Ok(()))?;
+    assert_eq!(*seen.borrow(), vec![1, 3]);
+
+    // The callback does not affect tag unwrapping
+    let seen = RefCell::new(Vec::new());
+    let record = |tag| seen.borrow_mut().push(tag);
+    let decoder = CBORDecoder::from_slice(&bytes[2..])
+        .unwrap_unknown_tags(true)
+        .on_tag(&record);
+    let _ = decoder.decode_with(is_any(), |cbor| {
+        assert_eq!(cbor, CBOR::Bstr(&[0x01]));
+        Ok(())
+    })?;
+    assert_eq!(*seen.borrow(), vec![55799, 61]);
+    Ok(())
+}
+
+#[test]
+fn decode_bstr_streaming() -> Result<(), CBORError> {
+    println!("<=============================== decode_bstr_streaming ===============================>");
+    // h'00 01 .. c7' (200 bytes), 1
+    let mut bytes = vec![0x58, 200];
+    bytes.extend(0..200u8);
+    bytes.push(0x01);
+
+    let mut chunk_lens = Vec::new();
+    let mut contents = Vec::new();
+    let mut next = 0;
+    let decoder = CBORDecoder::from_slice(&bytes);
+    decoder
+        .decode_bstr_streaming(|chunk| {
+            chunk_lens.push(chunk.len());
+            contents.extend_from_slice(chunk);
+        })?
+        .value(is_uint(), &mut next)?;
+    assert_eq!(chunk_lens, vec![64, 64, 64, 8]);
+    assert_eq!(contents, bytes[2..202]);
+    assert_eq!(next, 1u64);
+
+    // Not a bstr
+    let mut called = false;
+    assert!(matches!(
+        CBORDecoder::from_slice(&[0x61, 0x61]).decode_bstr_streaming(|_| called = true),
+        Err(CBORError::ExpectedType("bstr"))
+    ));
+    assert!(!called);
+    Ok(())
+}
+
+#[test]
+fn decode_truncated_tag() -> Result<(), CBORError> {
+    println!("<=============================== decode_truncated_tag ===============================>");
+    // A tag with no content is malformed, and must not panic
+    assert!(matches!(
+        CBORDecoder::from_slice(&[0xc1]).decode_with(is_tag(), |_| Ok(())),
+        Err(CBORError::EndOfBuffer)
+    ));
+    Ok(())
+}
+
+#[test]
+fn decode_map_value_type() -> Result<(), CBORError> {
+    println!("<=============================== decode_map_value_type ===============================>");
+    // {1: 42, 2: -1, "a": [], "b": {}, 3: 1(0), 4: true, 5: null}
+    let bytes = [
+        0xa7, 0x01, 0x18, 0x2a, 0x02, 0x20, 0x61, 0x61, 0x80, 0x61, 0x62, 0xa0, 0x03, 0xc1, 0x00,
+        0x04, 0xf5, 0x05, 0xf6,
+    ];
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        assert_eq!(mb.value_type(1), Some(CBORType::UInt));
+        assert_eq!(mb.value_type(2), Some(CBORType::NInt));
+        assert_eq!(mb.value_type("a"), Some(CBORType::Array));
+        assert_eq!(mb.value_type("b"), Some(CBORType::Map));
+        assert_eq!(mb.value_type(3), Some(CBORType::Tag));
+        assert_eq!(mb.value_type(4), Some(CBORType::Simple));
+        assert_eq!(mb.value_type(5), Some(CBORType::Simple));
+        assert_eq!(mb.value_type(6), None);
+
+        // Branch on the type of a value before converting it
+        let value = match mb.value_type(1) {
+            Some(CBORType::UInt) => mb.lookup::<_, u64>(1)?,
+            _ => 0,
+        };
+        assert_eq!(value, 42);
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_map_visit() -> Result<(), CBORError> {
+    use std::convert::TryFrom;
+
+    println!("<=============================== decode_map_visit ===============================>");
+    #[derive(Default)]
+    struct Claims<'buf> {
+        iss: &'buf str,
+        exp: u64,
+        nonce: Option<&'buf [u8]>,
+        unknown: usize,
+    }
+
+    impl<'buf> MapVisitor<'buf> for Claims<'buf> {
+        fn field(&mut self, key: &CBOR<'buf>, value: &CBOR<'buf>) -> Result<(), CBORError> {
+            match key {
+                CBOR::UInt(1) => self.iss = <&str>::try_from(*value)?,
+                CBOR::UInt(4) => self.exp = u64::try_from(*value)?,
+                CBOR::NInt(74) => self.nonce = Some(<&[u8]>::try_from(*value)?),
+                _ => self.unknown += 1,
+            }
+            Ok(())
+        }
+    }
+
+    // {1: "a", 4: 1000, -75: h'0102', "x": 0}
+    let bytes = [
+        0xa4, 0x01, 0x61, 0x61, 0x04, 0x19, 0x03, 0xe8, 0x38, 0x4a, 0x42, 0x01, 0x02, 0x61, 0x78,
+        0x00,
+    ];
+    let mut claims = Claims::default();
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| mb.visit(&mut claims))?;
+    assert_eq!(claims.iss, "a");
+    assert_eq!(claims.exp, 1000);
+    assert_eq!(claims.nonce, Some([0x01, 0x02].as_slice()));
+    assert_eq!(claims.unknown, 1);
+
+    // The visitor can abort decoding, e.g. when a value has the wrong type
+    let mut claims = Claims::default();
+    // {1: 2}
+    assert!(matches!(
+        CBORDecoder::from_slice(&[0xa1, 0x01, 0x02]).map(|mb| mb.visit(&mut claims)),
+        Err(CBORError::IncompatibleType)
+    ));
+
+    // Closures are visitors
+    let mut keys = 0;
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        mb.visit(&mut |_: &CBOR, _: &CBOR| {
+            keys += 1;
+            Ok(())
+        })
+    })?;
+    assert_eq!(keys, 4);
+    Ok(())
+}
+
+#[test]
+fn decode_message_envelope() -> Result<(), CBORError> {
+    println!("<=============================== decode_message_envelope ===============================>");
+    // 11({1: "abc"}), 0
+    let bytes = [0xcb, 0xa1, 0x01, 0x63, 0x61, 0x62, 0x63, 0x00];
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let (msg_id, body) = decoder.message(&[10, 11])?;
+    assert_eq!(msg_id, 11);
+    let mut text = "";
+    let _ = body.map(|mb| {
+        text = mb.lookup(1)?;
+        Ok(())
+    })?;
+    assert_eq!(text, "abc");
+    // The outer decoder is positioned after the message
+    let mut next = 1u64;
+    let _ = decoder.value(is_uint(), &mut next)?;
+    assert_eq!(next, 0);
+
+    // Tag value not expected, and the decoder is not advanced
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(decoder.message(&[1, 10]), Err(CBORError::NotAllowed)));
+    assert!(decoder.message(&[11]).is_ok());
+
+    // Tag value too large to be a message ID
+    let decoder = CBORDecoder::from_slice(&[0xdb, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x0b, 0xa0]);
+    assert!(matches!(decoder.message(&[11]), Err(CBORError::NotAllowed)));
+
+    // Not a tag
+    let decoder = CBORDecoder::from_slice(&bytes[1..]);
+    assert!(matches!(decoder.message(&[11]), Err(CBORError::ExpectedType("tag"))));
+
+    // Strict checks cover the whole message: 11({1(0): 1})
+    let decoder = CBORDecoder::from_slice(&[0xcb, 0xa1, 0xc1, 0x00, 0x01]).strict(true);
+    assert!(matches!(decoder.message(&[11]), Err(CBORError::NotAllowed)));
+
+    // Decoder options apply to the body: 11(simple(16))
+    let decoder = CBORDecoder::from_slice(&[0xcb, 0xf8, 0x10]).strict(true);
+    let (_, body) = decoder.message(&[11])?;
+    assert!(matches!(body.envelope().item(), Err(CBORError::NonCanonical)));
+    Ok(())
+}
+
+#[test]
+fn decode_fold() -> Result<(), CBORError> {
+    use std::convert::TryFrom;
+
+    println!("<=============================== decode_fold ===============================>");
+    // 10, 20, 30, 40, "x"
+    let bytes = [0x0a, 0x14, 0x18, 0x1e, 0x18, 0x28, 0x61, 0x78];
+
+    // Sum, stopping at max
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let sum = decoder.fold(0, 3, is_uint(), 0u64, |sum, _, cbor| Ok(sum + u64::try_from(cbor)?))?;
+    assert_eq!(sum, 60);
+
+    // Collect into a bounded buffer, with the iteration number
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let (buf, len) = decoder.fold(0, usize::MAX, is_uint(), ([0u64; 4], 0), |(mut buf, _), n, cbor| {
+        buf[n - 1] = u64::try_from(cbor)?;
+        Ok((buf, n))
+    })?;
+    assert_eq!(&buf[..len], &[10, 20, 30, 40]);
+    let _ = decoder.decode_with(is_tstr(), |_| Ok(()))?;
+
+    // Too few items
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(
+        decoder.fold(5, 10, is_uint(), (), |_, _, _| Ok(())),
+        Err(CBORError::RangeUnderflow(4))
+    ));
+
+    // Fallible accumulation, and errors from the closure are returned
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(
+        decoder.fold(0, 10, is_uint(), 0u8, |acc, _, cbor| {
+            acc.checked_add(u8::try_from(cbor)?).ok_or(CBORError::OutOfRange)
+        }),
+        Ok(100)
+    ));
+    assert!(matches!(
+        decoder.fold(0, 10, is_tstr(), 0usize, |_, _, _| Err(CBORError::FailedPredicate)),
+        Err(CBORError::FailedPredicate)
+    ));
+    Ok(())
+}
+
+#[test]
+fn decode_map_extract() -> Result<(), CBORError> {
+    println!("<=============================== decode_map_extract ===============================>");
+    // {1: "a", 4: 1000, -75: h'0102', "x": 0}
+    let bytes = [
+        0xa4, 0x01, 0x61, 0x61, 0x04, 0x19, 0x03, 0xe8, 0x38, 0x4a, 0x42, 0x01, 0x02, 0x61, 0x78,
+        0x00,
+    ];
+    let mut iss = None;
+    let mut exp = None;
+    let mut nonce = None;
+    let mut cti = Some(CBOR::UInt(0));
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        mb.extract(&mut [(1, &mut iss), (4, &mut exp), (-75, &mut nonce), (7, &mut cti)])
+    })?;
+    assert_eq!(iss, Some(CBOR::Tstr("a")));
+    assert_eq!(exp, Some(CBOR::UInt(1000)));
+    assert_eq!(nonce, Some(CBOR::Bstr(&[0x01, 0x02])));
+    assert_eq!(cti, None);
+
+    // {1: 2, 1: 3}: first occurrence wins, unless strict
+    let bytes = [0xa2, 0x01, 0x02, 0x01, 0x03];
+    let mut one = None;
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| mb.extract(&mut [(1, &mut one)]))?;
+    assert_eq!(one, Some(CBOR::UInt(2)));
+    assert!(matches!(
+        CBORDecoder::from_slice(&bytes).map(|mb| mb.extract_strict(&mut [(1, &mut one)])),
+        Err(CBORError::NotAllowed)
+    ));
+
+    // Duplicates of keys which are not bound are ignored, even if strict
+    let mut two = None;
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| mb.extract_strict(&mut [(2, &mut two)]))?;
+    assert_eq!(two, None);
+    Ok(())
+}
+
+#[test]
+fn decode_label() -> Result<(), CBORError> {
+    use std::convert::TryFrom;
+
+    println!("<=============================== decode_label ===============================>");
+    // {1: -7, -1: 2, "kid": h'01'}
+    let bytes = [0xa3, 0x01, 0x26, 0x20, 0x02, 0x63, 0x6b, 0x69, 0x64, 0x41, 0x01];
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        assert_eq!(mb.lookup_by_label::<i64>(Label::Int(1))?, -7);
+        assert_eq!(mb.lookup_by_label::<u32>(Label::from(-1))?, 2);
+        assert_eq!(mb.lookup_by_label::<&[u8]>(Label::from("kid"))?, &[0x01]);
+        assert!(matches!(
+            mb.lookup_by_label::<u32>(Label::Text("alg")),
+            Err(CBORError::KeyNotPresent)
+        ));
+        Ok(())
+    })?;
+
+    // 1, -1, "kid"
+    let mut labels = [Label::Int(0); 3];
+    let decoder = CBORDecoder::from_slice(&[0x01, 0x20, 0x63, 0x6b, 0x69, 0x64]);
+    for label in labels.iter_mut() {
+        let _ = decoder.decode_with(is_label(), |cbor| {
+            *label = Label::try_from(cbor)?;
+            Ok(())
+        })?;
+    }
+    assert_eq!(labels, [Label::Int(1), Label::Int(-1), Label::Text("kid")]);
+    assert_eq!(CBOR::from(Label::Int(-7)), CBOR::NInt(6));
+
+    // Only int and tstr are labels, and integers must fit in an i64
+    let decoder = CBORDecoder::from_slice(&[0x41, 0x01]);
+    assert!(matches!(
+        decoder.decode_with(is_label(), |_| Ok(())),
+        Err(CBORError::ExpectedType("label"))
+    ));
+    let decoder = CBORDecoder::from_slice(&[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    assert!(matches!(
+        decoder.decode_with(is_label(), |_| Ok(())),
+        Err(CBORError::OutOfRange)
+    ));
+    assert!(matches!(Label::try_from(CBOR::Null), Err(CBORError::IncompatibleType)));
+    Ok(())
+}
+
+#[test]
+fn decode_map_lookup_path() -> Result<(), CBORError> {
+    println!("<========================== decode_map_lookup_path ==========================>");
+    // {260: {0: ["1.3.4", 1], "name": "acme"}, 2: [{"x": 7}]}
+    let bytes = [
+        0xa2, 0x19, 0x01, 0x04, 0xa2, 0x00, 0x82, 0x65, 0x31, 0x2e, 0x33, 0x2e, 0x34, 0x01, 0x64,
+        0x6e, 0x61, 0x6d, 0x65, 0x64, 0x61, 0x63, 0x6d, 0x65, 0x02, 0x81, 0xa1, 0x61, 0x78, 0x07,
+    ];
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        let version: &str = mb.lookup_path(&[Key::Int(260), Key::Int(0), Key::Index(0)])?;
+        assert_eq!(version, "1.3.4");
+        let scheme: u8 = mb.lookup_path(&[Key::Int(260), Key::Int(0), Key::Index(1)])?;
+        assert_eq!(scheme, 1);
+        let name: &str = mb.lookup_path(&[Key::Int(260), Key::Text("name")])?;
+        assert_eq!(name, "acme");
+        let x: u32 = mb.lookup_path(&[Key::Int(2), Key::Index(0), Key::Text("x")])?;
+        assert_eq!(x, 7);
+        let whole: MapBuf = mb.lookup_path(&[])?;
+        assert_eq!(whole.len(), 2);
+
+        // Missing segments
+        assert!(matches!(
+            mb.lookup_path::<u32>(&[Key::Int(260), Key::Int(1)]),
+            Err(CBORError::KeyNotPresent)
+        ));
+        assert!(matches!(
+            mb.lookup_path::<u32>(&[Key::Int(2), Key::Index(1)]),
+            Err(CBORError::IndexOutOfBounds)
+        ));
+        // Segments applied to the wrong container type
+        assert!(matches!(
+            mb.lookup_path::<u32>(&[Key::Int(260), Key::Index(0)]),
+            Err(CBORError::ExpectedType("array"))
+        ));
+        assert!(matches!(
+            mb.lookup_path::<u32>(&[Key::Int(2), Key::Int(0)]),
+            Err(CBORError::ExpectedType("map"))
+        ));
+        assert!(matches!(
+            mb.lookup_path::<u32>(&[Key::Int(260), Key::Text("name"), Key::Index(0)]),
+            Err(CBORError::ExpectedType("array"))
+        ));
+        // Leaf of the wrong type
+        assert!(matches!(
+            mb.lookup_path::<u32>(&[Key::Int(260), Key::Text("name")]),
+            Err(CBORError::IncompatibleType)
+        ));
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_simple_normalization() -> Result<(), CBORError> {
+    use std::convert::TryFrom;
+
+    println!("<======================= decode_simple_normalization ========================>");
+    // Simple values 20..23 always decode to the named variants, in both encoding forms
+    let decoder = CBORDecoder::from_slice(&[0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0x15, 0xf0]);
+    let expected = [
+        CBOR::False,
+        CBOR::True,
+        CBOR::Null,
+        CBOR::Undefined,
+        CBOR::True,
+        CBOR::Simple(16),
+    ];
+    for item in expected.iter() {
+        let _ = decoder.decode_with(is_any(), |cbor| {
+            assert_eq!(cbor, *item);
+            Ok(())
+        })?;
+    }
+
+    assert_eq!(CBOR::Simple(20).normalized(), CBOR::False);
+    assert_eq!(CBOR::Simple(22).normalized(), CBOR::Null);
+    assert_eq!(CBOR::Simple(23).normalized(), CBOR::Undefined);
+    assert_eq!(CBOR::Simple(0).normalized(), CBOR::Simple(0));
+    assert_eq!(CBOR::UInt(20).normalized(), CBOR::UInt(20));
+
+    assert!(!bool::try_from(CBOR::Simple(20))?);
+    assert!(bool::try_from(CBOR::Simple(21))?);
+    assert!(matches!(bool::try_from(CBOR::Simple(22)), Err(CBORError::IncompatibleType)));
+    assert!(matches!(bool::try_from(CBOR::Simple(0)), Err(CBORError::IncompatibleType)));
+    Ok(())
+}
+
+#[test]
+fn decode_map_entries_except() -> Result<(), CBORError> {
+    use tps_minicbor::encoder::CBORBuilder;
+    use tps_minicbor::types::map;
+
+    println!("<======================== decode_map_entries_except =========================>");
+    // {1: -7, "ext": true, 4: '11', -70000: [1]}
+    let bytes = [
+        0xa4, 0x01, 0x26, 0x63, 0x65, 0x78, 0x74, 0xf5, 0x04, 0x42, 0x31, 0x31, 0x3a, 0x00, 0x01,
+        0x11, 0x6f, 0x81, 0x01,
+    ];
+    let known = [CBOR::UInt(1), CBOR::UInt(4)];
+    let mut out = [0u8; 32];
+    let mut encoder = CBORBuilder::new(&mut out);
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        let mut unknown = mb.entries_except(&known);
+        assert_eq!(unknown.next(), Some((CBOR::Tstr("ext"), CBOR::True)));
+        assert!(matches!(unknown.next(), Some((CBOR::NInt(69999), CBOR::Array(_)))));
+        assert_eq!(unknown.next(), None);
+
+        // Nothing known: every entry is returned. Everything known: none are.
+        assert_eq!(mb.entries_except(&[]).count(), 4);
+        let all = [CBOR::UInt(1), CBOR::UInt(4), CBOR::Tstr("ext"), CBOR::NInt(69999)];
+        assert_eq!(mb.entries_except(&all).count(), 0);
+
+        // Forward the unknown entries unchanged
+        encoder.insert(&map(|buf| {
+            for (key, value) in mb.entries_except(&known) {
+                buf.insert_key_value(&key, &value)?;
+            }
+            Ok(buf)
+        }))?;
+        Ok(())
+    })?;
+    assert_eq!(
+        encoder.encoded()?,
+        &[0xa2, 0x63, 0x65, 0x78, 0x74, 0xf5, 0x3a, 0x00, 0x01, 0x11, 0x6f, 0x81, 0x01]
+    );
+    Ok(())
+}
+
+#[test]
+fn decode_dispatch_by_type() -> Result<(), CBORError> {
+    println!("<======================= decode_dispatch_by_type =====================>");
+    use std::cell::Cell;
+
+    // {1: 2}, [3, 4], "a"
+    let bytes = [0xa1, 0x01, 0x02, 0x82, 0x03, 0x04, 0x61, 0x61];
+    let map_value = Cell::new(0);
+    let array_len = Cell::new(0);
+    let on_map = |cbor: CBOR| {
+        if let CBOR::Map(mb) = cbor {
+            map_value.set(mb.lookup::<i64, u32>(1)?);
+        }
+        Ok(())
+    };
+    let on_array = |cbor: CBOR| {
+        if let CBOR::Array(ab) = cbor {
+            array_len.set(ab.into_iter().count());
+        }
+        Ok(())
+    };
+    let decoder = CBORDecoder::from_slice(&bytes);
+    decoder
+        .dispatch_by_type(&[(CBORType::Map, &on_map), (CBORType::Array, &on_array)])?
+        .dispatch_by_type(&[(CBORType::Map, &on_map), (CBORType::Array, &on_array)])?;
+    assert_eq!(map_value.get(), 2);
+    assert_eq!(array_len.get(), 2);
+
+    // No handler for a tstr: the decoder is left on the item
+    assert!(matches!(
+        decoder.dispatch_by_type(&[(CBORType::Map, &on_map), (CBORType::Array, &on_array)]),
+        Err(CBORError::NotAllowed)
+    ));
+    decoder.decode_with(is_tstr(), |cbor| {
+        assert_eq!(cbor, CBOR::Tstr("a"));
+        Ok(())
+    })?;
+    decoder.finalize()
+}
+
+#[test]
+fn decode_map_lookup_containers() -> Result<(), CBORError> {
+    println!("<======================= decode_map_lookup_containers =====================>");
+    // {1: [1, 2], 2: {3: 4}, 3: 100(5)}
+    let bytes = [
+        0xa3, 0x01, 0x82, 0x01, 0x02, 0x02, 0xa1, 0x03, 0x04, 0x03, 0xd8, 0x64, 0x05,
+    ];
+    let decoder = CBORDecoder::from_slice(&bytes);
+    decoder.decode_with(is_map(), |cbor| {
+        if let CBOR::Map(mb) = cbor {
+            let ab: ArrayBuf = mb.lookup(1)?;
+            assert_eq!(ab.len(), 2);
+            assert_eq!(ab.index(1), Some(CBOR::UInt(2)));
+
+            let inner: MapBuf = mb.lookup(2)?;
+            assert_eq!(inner.lookup::<i64, u8>(3)?, 4);
+
+            let tb: TagBuf = mb.lookup(3)?;
+            assert_eq!(tb.get_tag(), 100);
+            assert_eq!(tb.into_iter().next(), Some(CBOR::UInt(5)));
+
+            // The value must be of the requested container type
+            assert!(matches!(
+                mb.lookup::<i64, TagBuf>(1),
+                Err(CBORError::IncompatibleType)
+            ));
+            assert!(matches!(
+                mb.lookup::<i64, ArrayBuf>(3),
+                Err(CBORError::IncompatibleType)
+            ));
+        }
+        Ok(())
+    })?;
+    decoder.finalize()
+}
+
+#[test]
+fn decode_number_kind() -> Result<(), CBORError> {
+    println!("<======================= decode_number_kind =====================>");
+    use tps_minicbor::types::NumberKind;
+
+    // 1, -2, "1"
+    let bytes = [0x01, 0x21, 0x61, 0x31];
+    let mut kinds = Vec::new();
+    let decoder = CBORDecoder::from_slice(&bytes);
+    for _ in 0..2 {
+        decoder.decode_with(is_number(), |cbor| {
+            kinds.push(cbor.number_kind());
+            Ok(())
+        })?;
+    }
+    assert_eq!(kinds, vec![Some(NumberKind::UInt), Some(NumberKind::NInt)]);
+
+    // A tstr is not a number, even if it looks like one
+    assert!(matches!(
+        decoder.decode_with(is_number(), |_| Ok(())),
+        Err(CBORError::ExpectedType("number"))
+    ));
+    decoder.decode_with(is_tstr(), |cbor| {
+        assert_eq!(cbor.number_kind(), None);
+        Ok(())
+    })?;
+    decoder.finalize()?;
+
+    // 1.0, which a sender may use for a version that is also allowed to be 1
+    #[cfg(feature = "float")]
+    CBORDecoder::from_slice(&[0xf9, 0x3c, 0x00])
+        .decode_with(is_number(), |cbor| {
+            assert_eq!(cbor.number_kind(), Some(NumberKind::Float));
+            Ok(())
+        })?
+        .finalize()?;
+    Ok(())
+}
+
+#[test]
+fn decode_map_partition_keys() -> Result<(), CBORError> {
+    println!("<======================= decode_map_partition_keys =====================>");
+    // A CWT claims set with standard and private claims:
+    // {1: "iss", "priv": 5, -260: h'01', "x": true, 2: "sub"}
+    let bytes = [
+        0xa5, 0x01, 0x63, 0x69, 0x73, 0x73, 0x64, 0x70, 0x72, 0x69, 0x76, 0x05, 0x39, 0x01, 0x03,
+        0x41, 0x01, 0x61, 0x78, 0xf5, 0x02, 0x63, 0x73, 0x75, 0x62,
+    ];
+    CBORDecoder::from_slice(&bytes)
+        .map(|mb| {
+            let ints: Vec<(i64, CBOR)> = mb.int_entries().collect();
+            assert_eq!(
+                ints,
+                vec![
+                    (1, CBOR::Tstr("iss")),
+                    (-260, CBOR::Bstr(&[0x01])),
+                    (2, CBOR::Tstr("sub"))
+                ]
+            );
+            let texts: Vec<(&str, CBOR)> = mb.text_entries().collect();
+            assert_eq!(texts, vec![("priv", CBOR::UInt(5)), ("x", CBOR::True)]);
+            Ok(())
+        })?
+        .finalize()?;
+
+    // Keys of other types, and integers outside the range of i64, are in neither bucket
+    // {h'00': 1, 18446744073709551615: 2}
+    let bytes = [
+        0xa2, 0x41, 0x00, 0x01, 0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02,
+    ];
+    CBORDecoder::from_slice(&bytes)
+        .map(|mb| {
+            assert_eq!(mb.int_entries().count(), 0);
+            assert_eq!(mb.text_entries().count(), 0);
+            Ok(())
+        })?
+        .finalize()
+}
+
+#[test]
+fn decode_envelope() -> Result<(), CBORError> {
+    println!("<=== decode_envelope ===>");
+    // 1000({1: {2: "abc"}, 3: [4, 5]}), 6
+    let bytes = [
+        0xd9, 0x03, 0xe8, 0xa2, 0x01, 0xa1, 0x02, 0x63, 0x61, 0x62, 0x63, 0x03, 0x82, 0x04, 0x05,
+        0x06,
+    ];
+
+    // Tag, then map, then key
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let text: &str = decoder.envelope().tag(1000).map().key(1).key(2).value()?;
+    assert_eq!(text, "abc");
+    let mut last = 0u64;
+    let _ = decoder.value(is_uint(), &mut last)?;
+    assert_eq!(last, 6);
+    decoder.finalize()?;
+
+    // Array index and a nested decoder
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let item = decoder
+        .envelope()
+        .tag(1000)
+        .key(3)
+        .array()
+        .index(1)
+        .item()?;
+    assert_eq!(item, CBOR::UInt(5));
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let (mut key, mut text) = (0u64, "");
+    let inner = decoder.envelope().tag(1000).key(1).decoder()?;
+    inner
+        .value(is_uint(), &mut key)?
+        .value(is_tstr(), &mut text)?
+        .finalize()?;
+    assert_eq!((key, text), (2, "abc"));
+
+    // The first error is reported
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(
+        decoder.envelope().tag(1001).map().key(1).item(),
+        Err(CBORError::ExpectedTag(1001))
+    ));
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(
+        decoder.envelope().tag(1000).array().index(0).item(),
+        Err(CBORError::ExpectedType("array"))
+    ));
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(
+        decoder.envelope().tag(1000).key(7).item(),
+        Err(CBORError::KeyNotPresent)
+    ));
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(
+        decoder.envelope().tag(1000).key(3).index(2).item(),
+        Err(CBORError::IndexOutOfBounds)
+    ));
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(
+        decoder.envelope().tag(1000).key(1).key(2).value::<u64>(),
+        Err(CBORError::IncompatibleType)
+    ));
+
+    // An empty decoder reports the error from reading the first item
+    let decoder = CBORDecoder::from_slice(&[]);
+    assert!(decoder.envelope().tag(1000).item().is_err());
+    Ok(())
+}
+
+#[test]
+fn decode_tag24_embedded() -> Result<(), CBORError> {
+    println!("<=== decode_tag24_embedded ===>");
+    let embedded = |bytes: &[u8]| -> Result<u64, CBORError> {
+        let decoder = CBORDecoder::from_slice(bytes);
+        let mut value = 0u64;
+        let _ = decoder.tag(|tb| {
+            match tb.decode_embedded()? {
+                CBOR::UInt(v) => value = v,
+                _ => return Err(CBORError::IncompatibleType),
+            }
+            Ok(())
+        })?;
+        Ok(value)
+    };
+
+    // 24(h'1864')
+    assert_eq!(embedded(&[0xd8, 0x18, 0x42, 0x18, 0x64])?, 100);
+
+    // Trailing bytes hidden inside the bstr are rejected: 24(h'186400'), 24(h'0102')
+    assert!(matches!(
+        embedded(&[0xd8, 0x18, 0x43, 0x18, 0x64, 0x00]),
+        Err(CBORError::TrailingData)
+    ));
+    assert!(matches!(
+        embedded(&[0xd8, 0x18, 0x42, 0x01, 0x02]),
+        Err(CBORError::TrailingData)
+    ));
+
+    // Empty and truncated content is not an embedded item: 24(h''), 24(h'18')
+    assert!(embedded(&[0xd8, 0x18, 0x40]).is_err());
+    assert!(embedded(&[0xd8, 0x18, 0x41, 0x18]).is_err());
+
+    // Tag 24 must enclose a bstr, and other tags are rejected: 24(1), 25(h'01')
+    assert!(matches!(
+        embedded(&[0xd8, 0x18, 0x01]),
+        Err(CBORError::TagContentMismatch(24))
+    ));
+    let decoder = CBORDecoder::from_slice(&[0xd8, 0x19, 0x41, 0x01]);
+    let _ = decoder.tag(|tb| {
+        assert!(matches!(
+            tb.decode_embedded(),
+            Err(CBORError::ExpectedTag(24))
+        ));
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_sequence_skipping() -> Result<(), CBORError> {
+    println!("<=== decode_sequence_skipping ===>");
+    // undefined, [1], undefined, null, undefined, undefined, 2
+    let bytes = [0xf7, 0x81, 0x01, 0xf7, 0xf6, 0xf7, 0xf7, 0x02];
+    let mut items = Vec::new();
+    CBORDecoder::from_slice(&bytes)
+        .sequence_skipping(CBOR::Undefined, |n, cbor| {
+            items.push((n, cbor));
+            Ok(())
+        })?
+        .finalize()?;
+    assert_eq!(items.len(), 3);
+    assert!(matches!(items[0], (1, CBOR::Array(_))));
+    assert_eq!(items[1], (2, CBOR::Null));
+    assert_eq!(items[2], (3, CBOR::UInt(2)));
+
+    // Any item may be the padding, and a sequence of only padding has no items
+    let mut count = 0;
+    let _ = CBORDecoder::from_slice(&bytes).sequence_skipping(CBOR::Null, |_, _| {
+        count += 1;
+        Ok(())
+    })?;
+    assert_eq!(count, 6);
+    let _ = CBORDecoder::from_slice(&[0xf7, 0xf7])
+        .sequence_skipping(CBOR::Undefined, |_, _| Err(CBORError::NotAllowed))?;
+
+    // Malformed items and closure errors are reported
+    assert!(matches!(
+        CBORDecoder::from_slice(&[0xf7, 0x62, 0x61])
+            .sequence_skipping(CBOR::Undefined, |_, _| Ok(())),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert!(matches!(
+        CBORDecoder::from_slice(&[0xf7, 0x01])
+            .sequence_skipping(CBOR::Undefined, |_, _| Err(CBORError::NotAllowed)),
+        Err(CBORError::NotAllowed)
+    ));
+    Ok(())
+}
+
+#[test]
+fn decode_next_item_len() -> Result<(), CBORError> {
+    println!("<=== decode_next_item_len ===>");
+    // 24(h'01'), [1, [2, 3]], {"a": 1000000}, (_ h'01', h'0203'), 18446744073709551615
+    let bytes = [
+        0xd8, 0x18, 0x41, 0x01, 0x82, 0x01, 0x82, 0x02, 0x03, 0xa1, 0x61, 0x61, 0x1a, 0x00, 0x0f,
+        0x42, 0x40, 0x5f, 0x41, 0x01, 0x42, 0x02, 0x03, 0xff, 0x1b, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff,
+    ];
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let mut lens = Vec::new();
+    while !decoder.remaining_bytes().is_empty() {
+        let len = decoder.next_item_len()?;
+        // The length does not advance the decoder, and matches the decoded item
+        assert_eq!(decoder.next_item_len()?, len);
+        lens.push(len);
+        if decoder.remaining_bytes()[0] == 0x5f {
+            // Indefinite length strings cannot be decoded, but can be skipped as raw bytes
+            let rest = &decoder.remaining_bytes()[len..];
+            let decoder = CBORDecoder::from_slice(rest);
+            let _ = decoder.value(is_uint(), &mut 0u64)?;
+            break;
+        }
+        let before = decoder.remaining_bytes().len();
+        let _ = decoder.ignore::<_, ()>(is_any())?;
+        assert_eq!(before - decoder.remaining_bytes().len(), len);
+    }
+    assert_eq!(lens, vec![4, 5, 8, 7]);
+
+    // Truncated and unsupported items, and an empty decoder
+    let decoder = CBORDecoder::from_slice(&[0x82, 0x01, 0x42, 0x01]);
+    assert!(matches!(
+        decoder.next_item_len(),
+        Err(CBORError::EndOfBuffer)
+    ));
+    let decoder = CBORDecoder::from_slice(&[0x9f, 0x01, 0xff]);
+    assert!(matches!(
+        decoder.next_item_len(),
+        Err(CBORError::NotImplemented)
+    ));
+    let decoder = CBORDecoder::from_slice(&[0x5f, 0x61, 0x61, 0xff]);
+    assert!(matches!(
+        decoder.next_item_len(),
+        Err(CBORError::MalformedEncoding)
+    ));
+    let decoder = CBORDecoder::from_slice(&[0x1c]);
+    assert!(matches!(
+        decoder.next_item_len(),
+        Err(CBORError::ReservedAdditionalInfo)
+    ));
+    let decoder = CBORDecoder::from_slice(&[]);
+    assert!(matches!(
+        decoder.next_item_len(),
+        Err(CBORError::EndOfBuffer)
+    ));
+    Ok(())
+}
+
+#[test]
+fn decode_strict_preferred_simple() -> Result<(), CBORError> {
+    println!("<=== decode_strict_preferred_simple ===>");
+    fn strict_item(bytes: &[u8]) -> Result<(), CBORError> {
+        CBORDecoder::from_slice(bytes)
+            .strict(true)
+            .envelope()
+            .item()
+            .map(|_| ())
+    }
+
+    // Simple values 0 to 23 must be encoded in the initial byte: simple(16), simple(32)
+    assert!(CBORDecoder::from_slice(&[0xf8, 0x10])
+        .envelope()
+        .item()
+        .is_ok());
+    assert!(matches!(
+        strict_item(&[0xf8, 0x10]),
+        Err(CBORError::NonCanonical)
+    ));
+    strict_item(&[0xf0])?;
+    strict_item(&[0xf8, 0x20])?;
+
+    #[cfg(feature = "float")]
+    {
+        let canonical = DecodeLimits {
+            canonical: true,
+            ..DecodeLimits::default()
+        };
+
+        // 1.0 as f64, f32 and f16
+        let f64_one = [0xfb, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let f32_one = [0xfa, 0x3f, 0x80, 0x00, 0x00];
+        assert!(CBORDecoder::from_slice(&f64_one).envelope().item().is_ok());
+        assert!(matches!(
+            strict_item(&f64_one),
+            Err(CBORError::NonCanonical)
+        ));
+        assert!(matches!(
+            strict_item(&f32_one),
+            Err(CBORError::NonCanonical)
+        ));
+        strict_item(&[0xf9, 0x3c, 0x00])?;
+        assert!(matches!(
+            CBORDecoder::validate(&f64_one, &canonical),
+            Err(CBORError::NonCanonical)
+        ));
+
+        // An f64 which fits in an f32 but not an f16 (100000.0), and one which needs 64 bits (1.1)
+        assert!(matches!(
+            strict_item(&[0xfb, 0x40, 0xf8, 0x6a, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            Err(CBORError::NonCanonical)
+        ));
+        strict_item(&[0xfa, 0x47, 0xc3, 0x50, 0x00])?;
+        strict_item(&[0xfb, 0x3f, 0xf1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a])?;
+
+        // NaN must be an f16, and infinity fits in an f16
+        strict_item(&[0xf9, 0x7e, 0x00])?;
+        assert!(matches!(
+            strict_item(&[0xfa, 0x7f, 0xc0, 0x00, 0x00]),
+            Err(CBORError::NonCanonical)
+        ));
+        assert!(matches!(
+            strict_item(&[0xfa, 0x7f, 0x80, 0x00, 0x00]),
+            Err(CBORError::NonCanonical)
+        ));
+
+        // Nested floats are checked by validate: [1.0]
+        let mut nested = [0x81u8; 10];
+        nested[1..].copy_from_slice(&f64_one);
+        CBORDecoder::validate(&nested, &DecodeLimits::default())?;
+        assert!(matches!(
+            CBORDecoder::validate(&nested, &canonical),
+            Err(CBORError::NonCanonical)
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn decode_array_enumerate_as() -> Result<(), CBORError> {
+    println!("<=== decode_array_enumerate_as ===>");
+    // [1, 2, 3]
+    let bytes = [0x83, 0x01, 0x02, 0x03];
+    let _ = CBORDecoder::from_slice(&bytes).array(|ab| {
+        let pairs = ab
+            .enumerate_as::<u8>()
+            .collect::<Result<Vec<(usize, u8)>, _>>()?;
+        assert_eq!(pairs, [(0, 1), (1, 2), (2, 3)]);
+        Ok(())
+    })?;
+
+    // [-1, [], 256] followed by 4: conversion errors are reported per item, and the item after
+    // the array is not included
+    let bytes = [0x83, 0x20, 0x80, 0x19, 0x01, 0x00, 0x04];
+    let _ = CBORDecoder::from_slice(&bytes).array(|ab| {
+        let results: Vec<_> = ab.enumerate_as::<i16>().collect();
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[0], Ok((0, -1))));
+        assert!(matches!(results[1], Err(CBORError::IncompatibleType)));
+        assert!(matches!(results[2], Ok((2, 256))));
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_nullable() -> Result<(), CBORError> {
+    use tps_minicbor::encoder::CBORBuilder;
+    use tps_minicbor::types::array;
+
+    println!("<=== decode_nullable ===>");
+    // The same field holds null (0xf6) in the first array, and a value in the second
+    let mut bytes = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    let field: Option<u16> = None;
+    encoder.insert(&array(|buf| buf.insert(&field)?.insert(&"a")))?;
+    let field: Option<u16> = Some(500);
+    encoder.insert(&array(|buf| buf.insert(&field)?.insert(&"a")))?;
+    let encoded = encoder.encoded()?;
+    assert_eq!(
+        encoded,
+        &[0x82, 0xf6, 0x61, 0x61, 0x82, 0x19, 0x01, 0xf4, 0x61, 0x61]
+    );
+
+    let mut fields = Vec::new();
+    let mut it = SequenceBuffer::new(encoded).into_iter();
+    while let Some(CBOR::Array(ab)) = it.next() {
+        let (items, field) = nullable(is_uint())(ab.into_iter())?;
+        let (_items, _) = is_tstr()(items)?;
+        fields.push(field);
+    }
+    assert_eq!(fields, [None, Some(CBOR::UInt(500))]);
+
+    // Unlike opt, an item which is neither null nor a match is an error
+    let it = SequenceBuffer::new(&[0x61, 0x61]).into_iter();
+    assert!(matches!(
+        nullable(is_uint())(it),
+        Err(CBORError::ExpectedType(_))
+    ));
+    assert!(matches!(opt(is_uint())(it), Ok((_, None))));
+    // An absent item is an error too
+    let it = SequenceBuffer::new(&[]).into_iter();
+    assert!(nullable(is_uint())(it).is_err());
+    Ok(())
+}
+
+#[test]
+fn decode_map_lookup_embedded() -> Result<(), CBORError> {
+    println!("<=== decode_map_lookup_embedded ===>");
+    // {1: << [1, [2]] >>, 2: "x", 3: h'0102', 4: h''}
+    let bytes = [
+        0xa4, 0x01, 0x44, 0x82, 0x01, 0x81, 0x02, 0x02, 0x61, 0x78, 0x03, 0x42, 0x01, 0x02, 0x04,
+        0x40,
+    ];
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        mb.lookup_embedded(1)?.array(|ab| {
+            assert_eq!(ab.len(), 2);
+            assert_eq!(ab.index(0), Some(CBOR::UInt(1)));
+            match ab.index(1) {
+                Some(CBOR::Array(inner)) => assert_eq!(inner.index(0), Some(CBOR::UInt(2))),
+                _ => panic!("expected an array"),
+            }
+            Ok(())
+        })?;
+
+        // An empty bstr gives a decoder with nothing to decode
+        mb.lookup_embedded(4)?.finalize_strict()?;
+
+        // Limits are applied to the embedded item
+        let limits = DecodeLimits {
+            max_depth: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            mb.lookup_embedded_with_limits(1, &limits),
+            Err(CBORError::LimitExceeded)
+        ));
+
+        // Values which are not a bstr holding a single item, and missing keys
+        assert!(matches!(
+            mb.lookup_embedded(2),
+            Err(CBORError::ExpectedType("bstr"))
+        ));
+        assert!(matches!(mb.lookup_embedded(3), Err(CBORError::EofExpected)));
+        assert!(matches!(
+            mb.lookup_embedded(5),
+            Err(CBORError::KeyNotPresent)
+        ));
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_finish_concatenated_messages() -> Result<(), CBORError> {
+    println!("<=== decode_finish_concatenated_messages ===>");
+    // Two messages, [1, 2] and {"a": 3}, followed by part of a third
+    let input: &[u8] = &[0x82, 0x01, 0x02, 0xa1, 0x61, 0x61, 0x03, 0x83];
+    let mut cursor = 0;
+
+    let decoder = CBORDecoder::from_slice(&input[cursor..]);
+    let _ = decoder.decode_with(is_array(), |_| Ok(()))?;
+    assert!(matches!(
+        decoder.finalize_strict(),
+        Err(CBORError::TrailingData)
+    ));
+    cursor += decoder.finish()?;
+    assert_eq!(cursor, 3);
+
+    let decoder = CBORDecoder::from_slice(&input[cursor..]);
+    let mut v = 0u64;
+    let _ = decoder.map(|mb| {
+        v = mb.lookup("a")?;
+        Ok(())
+    })?;
+    assert_eq!(v, 3);
+    cursor += decoder.finish()?;
+    assert_eq!(cursor, 7);
+    assert_eq!(decoder.remaining_bytes(), &input[cursor..]);
+
+    // Nothing has been consumed from a fresh decoder
+    let decoder = CBORDecoder::from_slice(&input[cursor..]);
+    assert_eq!(decoder.finish()?, 0);
+    Ok(())
+}
+
+#[test]
+fn decode_map_require_keys() -> Result<(), CBORError> {
+    println!("<=== decode_map_require_keys ===>");
+    // CWT claims {1: "iss", 4: 1000} - "sub" (2) is missing
+    let bytes = [0xa2, 0x01, 0x63, 0x69, 0x73, 0x73, 0x04, 0x19, 0x03, 0xe8];
+    let required = [CBOR::UInt(1), CBOR::UInt(2), CBOR::UInt(4)];
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        match mb.require_keys(&required) {
+            Err(CBORError::MissingKey(n)) => assert_eq!(required[n], CBOR::UInt(2)),
+            _ => panic!("Expected the missing key to be reported"),
+        }
+        assert!(mb.require_keys(&[CBOR::UInt(4), CBOR::UInt(1)]).is_ok());
+        assert!(mb.require_keys(&[]).is_ok());
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_reject_non_finite_floats() -> Result<(), CBORError> {
+    println!("<=== decode_reject_non_finite_floats ===>");
+    // +Infinity (f16), NaN (f32) and -Infinity (f64)
+    let non_finite: [&[u8]; 3] = [
+        &[0xf9, 0x7c, 0x00],
+        &[0xfa, 0x7f, 0xc0, 0x00, 0x00],
+        &[0xfb, 0xff, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    ];
+    for bytes in non_finite.iter() {
+        let decoder = CBORDecoder::from_slice(bytes).reject_non_finite_floats(true);
+        assert!(matches!(
+            decoder.decode_with(is_any(), |_| Ok(())),
+            Err(CBORError::NotAllowed)
+        ));
+        assert!(matches!(
+            decoder.decode_with(is_number(), |_| Ok(())),
+            Err(CBORError::NotAllowed)
+        ));
+        #[cfg(feature = "float")]
+        assert!(CBORDecoder::from_slice(bytes)
+            .decode_with(is_any(), |_| Ok(()))
+            .is_ok());
+    }
+
+    // Finite floats and other simple values are unaffected: [1.5, true]
+    #[cfg(feature = "float")]
+    {
+        let decoder =
+            CBORDecoder::from_slice(&[0xf9, 0x3e, 0x00, 0xf5]).reject_non_finite_floats(true);
+        let _ = decoder.decode_with(is_number(), |cbor| {
+            assert_eq!(cbor, CBOR::Float16(half::f16::from_f32(1.5)));
+            Ok(())
+        })?;
+        let _ = decoder.decode_with(is_bool(), |_| Ok(()))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn decode_map_value_with_raw() -> Result<(), CBORError> {
+    println!("<=== decode_map_value_with_raw ===>");
+    // {"claim": {1: 1, 2: "a"}, 3: 4}, where the inner 1 is not in preferred serialization
+    let bytes = [
+        0xa2, 0x65, 0x63, 0x6c, 0x61, 0x69, 0x6d, 0xa2, 0x18, 0x01, 0x01, 0x02, 0x61, 0x61, 0x03,
+        0x04,
+    ];
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        let (raw, value) = mb.value_with_raw("claim")?;
+        assert_eq!(raw, &bytes[7..14]);
+        match value {
+            CBOR::Map(claim) => assert_eq!(claim.lookup::<u64, &str>(2)?, "a"),
+            _ => panic!("Expected the claim to be a map"),
+        }
+        // The raw bytes can be decoded again independently
+        let _ = CBORDecoder::from_slice(raw).map(|claim| {
+            assert_eq!(claim.lookup::<u64, u64>(1)?, 1);
+            Ok(())
+        })?;
+
+        assert_eq!(mb.value_with_raw(3)?, (&bytes[15..], CBOR::UInt(4)));
+        assert!(matches!(
+            mb.value_with_raw(4),
+            Err(CBORError::KeyNotPresent)
+        ));
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_tagged_map_and_array() -> Result<(), CBORError> {
+    println!("<=== decode_tagged_map_and_array ===>");
+    // 18([h'', {4: '11'}, 'hi', h'0102']), 61({8: 2}), 3
+    let bytes = [
+        0xd2, 0x84, 0x40, 0xa1, 0x04, 0x42, 0x31, 0x31, 0x42, 0x68, 0x69, 0x42, 0x01, 0x02, 0xd8,
+        0x3d, 0xa1, 0x08, 0x02, 0x03,
+    ];
+    let decoder = CBORDecoder::from_slice(&bytes);
+    let _ = decoder
+        .tagged_array(18, |ab| {
+            assert_eq!(ab.len(), 4);
+            let unprotected = ab.item::<MapBuf>(1)?;
+            assert_eq!(unprotected.lookup::<u64, &[u8]>(4)?, b"11");
+            assert_eq!(ab.item::<&[u8]>(3)?, &[0x01, 0x02]);
+            Ok(())
+        })?
+        .tagged_map(61, |mb| {
+            assert_eq!(mb.lookup::<u64, u64>(8)?, 2);
+            Ok(())
+        })?;
+
+    // The next item is not tagged: the decoder is not advanced
+    assert!(matches!(
+        decoder.tagged_map(61, |_| Ok(())),
+        Err(CBORError::ExpectedTag(61))
+    ));
+    let _ = decoder.decode_with(is_uint(), |cbor| {
+        assert_eq!(cbor, CBOR::UInt(3));
+        Ok(())
+    })?;
+
+    // A different tag, or a tagged item of another type
+    let decoder = CBORDecoder::from_slice(&bytes);
+    assert!(matches!(
+        decoder.tagged_array(98, |_| Ok(())),
+        Err(CBORError::ExpectedTag(98))
+    ));
+    assert!(matches!(
+        decoder.tagged_map(18, |_| Ok(())),
+        Err(CBORError::ExpectedType(_))
+    ));
+    let _ = decoder.tagged_array(18, |_| Ok(()))?;
+    assert!(matches!(
+        decoder.tagged_array(61, |_| Ok(())),
+        Err(CBORError::ExpectedType(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn decode_map_int_index() -> Result<(), CBORError> {
+    println!("<=== decode_map_int_index ===>");
+    // {19: 1900, -18: 1800, 17: 1700, ..., -2: 200, 1: 100, "x": true, 1: 1}
+    let mut bytes = vec![0xb5];
+    for key in (1..20u8).rev() {
+        let value = (key as u16 * 100).to_be_bytes();
+        let head = if key % 2 == 0 { 0x20 | (key - 1) } else { key };
+        bytes.extend_from_slice(&[head, 0x19, value[0], value[1]]);
+    }
+    bytes.extend_from_slice(&[0x61, 0x78, 0xf5, 0x01, 0x01]);
+
+    let _ = CBORDecoder::from_slice(&bytes).map(|mb| {
+        let index = mb.int_index::<20>()?;
+        assert_eq!(index.len(), 19);
+        // Every integer key gives the same value through the index as directly
+        for key in -19..20 {
+            assert_eq!(index.get_int(key), mb.get_int(key));
+        }
+        // The first of the duplicate keys is indexed
+        assert_eq!(index.lookup::<u64>(1)?, 100);
+        assert_eq!(index.lookup::<u64>(-18)?, 1800);
+        assert!(matches!(
+            index.lookup::<u64>(2),
+            Err(CBORError::KeyNotPresent)
+        ));
+        assert!(matches!(
+            index.lookup::<&str>(1),
+            Err(CBORError::IncompatibleType)
+        ));
+        assert!(index.map().lookup::<&str, bool>("x")?);
+
+        // There is not enough room for every key
+        assert!(matches!(
+            mb.int_index::<18>(),
+            Err(CBORError::LimitExceeded)
+        ));
+        Ok(())
+    })?;
+
+    let _ = CBORDecoder::from_slice(&[0xa1, 0x61, 0x78, 0x01]).map(|mb| {
+        assert!(mb.int_index::<0>()?.is_empty());
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn decode_reset() -> Result<(), CBORError> {
+    use std::cell::RefCell;
+
+    println!("<=== decode_reset ===>");
+    // A stream of messages: 32("a"), 61(1), 1, then 1("a"), an epoch time with text content
+    let stream = [
+        0xd8, 0x20, 0x61, 0x61, 0xd8, 0x3d, 0x01, 0x01, 0xc1, 0x61, 0x61,
+    ];
+    let messages = [&stream[0..4], &stream[4..7], &stream[7..8], &stream[8..]];
+    let seen = RefCell::new(Vec::new());
+    let record = |tag| seen.borrow_mut().push(tag);
+    let mut decoder = CBORDecoder::from_slice(messages[0])
+        .strict(true)
+        .unwrap_unknown_tags(true)
+        .on_tag(&record);
+    let _ = decoder.decode_with_raw(is_any(), |cbor, _| {
+        assert_eq!(cbor, CBOR::Tstr("a"));
+        Ok(())
+    })?;
+    assert_eq!(decoder.last_tag(), Some(32));
+
+    // The options and callback are kept for the next message
+    decoder.reset(messages[1]);
+    let _ = decoder.decode_with_raw(is_any(), |cbor, _| {
+        assert_eq!(cbor, CBOR::UInt(1));
+        Ok(())
+    })?;
+    assert_eq!(decoder.last_tag(), Some(61));
+    assert_eq!(*seen.borrow(), vec![32, 61]);
+
+    // The last tag is cleared, and decoding starts at the beginning of the new buffer
+    decoder.reset(messages[2]);
+    assert_eq!(decoder.last_tag(), None);
+    assert_eq!(decoder.remaining_bytes(), &[0x01]);
+    let _ = decoder.decode_with_raw(is_uint(), |_, _| Ok(()))?;
+    decoder.finalize()?;
+
+    // The decoder is still strict
+    decoder.reset(messages[3]);
+    assert!(matches!(
+        decoder.tag(|_| Ok(())),
+        Err(CBORError::TagContentMismatch(1))
+    ));
+    let _ = CBORDecoder::from_slice(messages[3]).tag(|_| Ok(()))?;
+    Ok(())
+}
@@ -31,6 +31,7 @@ use std::convert::TryFrom;
 use half::f16;
 
 use tps_minicbor::decoder::*;
+use tps_minicbor::error::CBORError;
 use tps_minicbor::types::CBOR;
 
 /***************************************************************************************************
@@ -655,3 +656,191 @@ fn rfc8949_decode_float() {
         assert!(false)
     }
 }
+
+#[test]
+#[cfg(feature = "float")]
+fn decode_nan_normalized() {
+    println!("<======================= decode_nan_normalized =====================>");
+    // Signalling NaN (f16)
+    assert!(matches!(
+        decode_single(&[0xf9, 0x7c, 0x01]),
+        Some(CBOR::Float16(v)) if v.to_bits() == f16::NAN.to_bits()
+    ));
+    // Negative NaN with payload (f32)
+    assert!(matches!(
+        decode_single(&[0xfa, 0xff, 0xc0, 0x12, 0x34]),
+        Some(CBOR::Float32(v)) if v.to_bits() == f32::NAN.to_bits()
+    ));
+    // Signalling NaN (f64)
+    assert!(matches!(
+        decode_single(&[0xfb, 0x7f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]),
+        Some(CBOR::Float64(v)) if v.to_bits() == f64::NAN.to_bits()
+    ));
+}
+
+fn try_decode_single(buf: &[u8]) -> Result<CBOR<'_>, CBORError> {
+    SequenceBuffer::new(buf).into_iter().try_next()
+}
+
+// Indefinite length strings may only contain definite length chunks of the same major type
+// (RFC8949, Section 3.2.3)
+#[test]
+fn decode_indefinite_string_chunks() {
+    println!("<======================= decode_indefinite_string_chunks =====================>");
+    // (_ h'0102', (_ h'03')) - nested indefinite length chunk
+    assert!(matches!(
+        try_decode_single(&[0x5f, 0x42, 0x01, 0x02, 0x5f, 0x41, 0x03, 0xff, 0xff]),
+        Err(CBORError::MalformedEncoding)
+    ));
+    // (_ "a", (_ "b")) - nested indefinite length chunk
+    assert!(matches!(
+        try_decode_single(&[0x7f, 0x61, 0x61, 0x7f, 0x61, 0x62, 0xff, 0xff]),
+        Err(CBORError::MalformedEncoding)
+    ));
+    // (_ "a", h'62') - bstr chunk inside tstr
+    assert!(matches!(
+        try_decode_single(&[0x7f, 0x61, 0x61, 0x41, 0x62, 0xff]),
+        Err(CBORError::MalformedEncoding)
+    ));
+    // (_ h'01', "b") - tstr chunk inside bstr
+    assert!(matches!(
+        try_decode_single(&[0x5f, 0x41, 0x01, 0x61, 0x62, 0xff]),
+        Err(CBORError::MalformedEncoding)
+    ));
+    // (_ h'01', 1) - chunk which is not a string
+    assert!(matches!(
+        try_decode_single(&[0x5f, 0x41, 0x01, 0x01, 0xff]),
+        Err(CBORError::MalformedEncoding)
+    ));
+    // (_ "a" - no break
+    assert!(matches!(
+        try_decode_single(&[0x7f, 0x61, 0x61]),
+        Err(CBORError::EndOfBuffer)
+    ));
+    // (_ h'ff' as tstr chunk) - invalid UTF-8 in a chunk
+    assert!(matches!(
+        try_decode_single(&[0x7f, 0x61, 0xff, 0xff]),
+        Err(CBORError::UTF8Error)
+    ));
+    // Well-formed indefinite length strings are not (yet) supported
+    assert!(matches!(
+        try_decode_single(&[0x5f, 0x42, 0x01, 0x02, 0x41, 0x03, 0xff]),
+        Err(CBORError::NotImplemented)
+    ));
+    assert!(matches!(
+        try_decode_single(&[0x7f, 0x65, 0x73, 0x74, 0x72, 0x65, 0x61, 0x64, 0x6d, 0x69, 0x6e, 0x67, 0xff]),
+        Err(CBORError::NotImplemented)
+    ));
+    // try_next() reports the end of the buffer
+    assert!(matches!(try_decode_single(&[]), Err(CBORError::EndOfBuffer)));
+}
+
+// Declared lengths and item counts which do not fit in a usize are rejected rather than truncated,
+// which matters on 32 bit targets parsing untrusted input
+#[test]
+fn decode_length_overflow() {
+    println!("<========================= decode_length_overflow =========================>");
+    // A bstr, tstr and array each declaring 2^32 + 1 bytes or items
+    let oversized = [
+        [0x5b, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00],
+        [0x7b, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x61],
+        [0x9b, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x01],
+    ];
+    for bytes in oversized.iter() {
+        #[cfg(target_pointer_width = "32")]
+        assert!(matches!(
+            try_decode_single(bytes),
+            Err(CBORError::LengthOverflow)
+        ));
+        #[cfg(target_pointer_width = "64")]
+        assert!(matches!(
+            try_decode_single(bytes),
+            Err(CBORError::EndOfBuffer)
+        ));
+    }
+    // A map declaring 2^63 pairs has more items than any usize can count
+    assert!(matches!(
+        try_decode_single(&[0xbb, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]),
+        Err(CBORError::LengthOverflow)
+    ));
+}
+
+// Additional information values 28 to 30 are reserved and not well-formed for any major type
+// (RFC8949, Section 3)
+#[test]
+fn decode_reserved_additional_info() {
+    println!("<======================= decode_reserved_additional_info =====================>");
+    for mt in [0x00u8, 0x20, 0x40, 0x60, 0x80, 0xa0, 0xc0, 0xe0] {
+        for ai in 28u8..=30 {
+            assert!(matches!(
+                try_decode_single(&[mt | ai, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+                Err(CBORError::ReservedAdditionalInfo)
+            ));
+        }
+    }
+    // 0x1c - major type 0, additional information 28
+    assert!(matches!(try_decode_single(&[0x1c]), Err(CBORError::ReservedAdditionalInfo)));
+    // [1, 0x1d] - reserved value inside an array
+    assert!(matches!(
+        try_decode_single(&[0x82, 0x01, 0x1d]),
+        Err(CBORError::ReservedAdditionalInfo)
+    ));
+}
+
+#[test]
+fn decode_log_reader() -> Result<(), CBORError> {
+    println!("<======================= decode_log_reader =====================>");
+    // Records 1, {1: "a"} and h'0102' (a length prefixed record), with only part of the last
+    let log = [0x01, 0xa1, 0x01, 0x61, 0x61, 0x42, 0x01, 0x02];
+    let mut reader = LogReader::new(&log[..6]);
+    let mut records = Vec::new();
+    for record in &mut reader {
+        records.push(record?);
+    }
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0], CBOR::UInt(1));
+    assert!(matches!(records[1], CBOR::Map(mb) if mb.len() == 1));
+    assert!(reader.is_truncated());
+    assert_eq!(reader.consumed(), 5);
+
+    // Resume once the rest of the record has arrived
+    let mut ring = [0u8; 8];
+    let partial = reader.remaining();
+    ring[..partial.len()].copy_from_slice(partial);
+    ring[partial.len()..3].copy_from_slice(&log[6..]);
+    let mut reader = LogReader::new(&ring[..3]);
+    assert_eq!(reader.next().transpose()?, Some(CBOR::Bstr(&[0x01, 0x02])));
+    assert_eq!(reader.next().transpose()?, None);
+    assert!(!reader.is_truncated());
+    assert_eq!(reader.consumed(), 3);
+
+    // A truncated head is partial too, but a malformed record is reported once
+    let mut reader = LogReader::new(&[0x01, 0x19, 0x01]);
+    assert_eq!(reader.next().transpose()?, Some(CBOR::UInt(1)));
+    assert!(reader.next().is_none() && reader.is_truncated());
+    let mut reader = LogReader::new(&[0x01, 0x1c, 0x02]);
+    assert!(reader.next().is_some());
+    assert!(matches!(
+        reader.next(),
+        Some(Err(CBORError::ReservedAdditionalInfo))
+    ));
+    assert!(reader.next().is_none());
+    assert!(!reader.is_truncated());
+    assert_eq!(reader.consumed(), 1);
+    Ok(())
+}
+
+#[test]
+fn decode_is_well_formed() {
+    println!("<=============================== decode_is_well_formed ===============================>");
+    // The check needs neither the decode combinators nor the `full` and `float` features:
+    // [_ 1, "a"], {_ 1: 2}, (_ h'01', h'02'), 1.0 as f64
+    assert!(is_well_formed(&[0x9f, 0x01, 0x61, 0x61, 0xff]));
+    assert!(is_well_formed(&[0xbf, 0x01, 0x02, 0xff]));
+    assert!(is_well_formed(&[0x5f, 0x41, 0x01, 0x41, 0x02, 0xff]));
+    assert!(is_well_formed(&[0xfb, 0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]));
+    // Missing break, truncated float, and two items
+    assert!(!is_well_formed(&[0x9f, 0x01]));
+    assert!(!is_well_formed(&[0xfa, 0x3f, 0x80]));
+    assert!(!is_well_formed(&[0x01, 0x02]));
+}
@@ -22,6 +22,8 @@
  *
  * Test cases from RFC7049, Table 6.
  **************************************************************************************************/
+// These tests use the decode combinators, which require the `combinators` feature
+#![cfg(feature = "combinators")]
 
 extern crate tps_minicbor;
 
@@ -30,7 +32,10 @@ use half::f16;
 
 use tps_minicbor::encoder::*;
 use tps_minicbor::error::CBORError;
-use tps_minicbor::types::{array, map, tag, CBOR};
+use tps_minicbor::types::{
+    array, array_sized, bignum, bignum_from_le, empty_array, empty_map, map, map_sized, tag,
+    tag_raw, CBOR,
+};
 
 #[test]
 fn rfc8949_encode_int() -> Result<(), CBORError> {
@@ -517,6 +522,39 @@ fn rfc8949_encode_float() -> Result<(), CBORError> {
     Ok(())
 }
 
+#[test]
+#[cfg(feature = "float")]
+fn encode_nan_canonical() -> Result<(), CBORError> {
+    println!("<======================= encode_nan_canonical ======================>");
+    let mut bytes = [0u8; 32];
+
+    // Quiet NaN, signalling NaN and NaN with payload and sign bit, in each precision, must all
+    // produce the canonical half-precision NaN.
+    let nans_64 = [
+        f64::NAN,
+        f64::from_bits(0x7ff0_0000_0000_0001),
+        f64::from_bits(0xfff8_0000_dead_beef),
+    ];
+    for val in nans_64.iter() {
+        let mut encoder = CBORBuilder::new(&mut bytes);
+        encoder.insert(val)?;
+        assert_eq!(encoder.encoded()?, &[0xf9, 0x7e, 0x00]);
+    }
+    let nans_32 = [f32::NAN, f32::from_bits(0x7f80_0001), f32::from_bits(0xffc0_1234)];
+    for val in nans_32.iter() {
+        let mut encoder = CBORBuilder::new(&mut bytes);
+        encoder.insert(val)?;
+        assert_eq!(encoder.encoded()?, &[0xf9, 0x7e, 0x00]);
+    }
+    let nans_16 = [f16::from_bits(0x7c01), f16::from_bits(0xfe01)];
+    for val in nans_16.iter() {
+        let mut encoder = CBORBuilder::new(&mut bytes);
+        encoder.insert(val)?;
+        assert_eq!(encoder.encoded()?, &[0xf9, 0x7e, 0x00]);
+    }
+    Ok(())
+}
+
 #[test]
 #[cfg(feature = "float")]
 fn rfc8949_encode_tag() -> Result<(), CBORError> {
@@ -599,6 +637,11 @@ fn rfc8949_encode_empty_array() -> Result<(), CBORError> {
     let mut encoder = CBORBuilder::new(&mut buffer);
     let _ = encoder.insert(&array(|buff| Ok(buff)))?;
     assert_eq!(encoder.encoded()?, expected);
+
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&empty_array())?;
+    assert_eq!(encoder.encoded()?, expected);
     Ok(())
 }
 
@@ -697,6 +740,21 @@ fn rfc8949_encode_empty_map() -> Result<(), CBORError> {
     let mut encoder = CBORBuilder::new(&mut buffer);
     let _ = encoder.insert(&map(|buff| Ok(buff)))?;
     assert_eq!(encoder.encoded()?, expected);
+
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&empty_map())?;
+    assert_eq!(encoder.encoded()?, expected);
+
+    // [h'', {}, []] - each empty container counts as a single item
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&array(|buff| {
+        buff.insert(&CBOR::Bstr(&[]))?
+            .insert(&empty_map())?
+            .insert(&empty_array())
+    }))?;
+    assert_eq!(encoder.encoded()?, &[0x83, 0x40, 0xa0, 0x80]);
     Ok(())
 }
 
@@ -752,3 +810,659 @@ fn rfc8949_encode_map_long() -> Result<(), CBORError> {
 }
 
 
+
+#[test]
+fn encode_sized_array_and_map() -> Result<(), CBORError> {
+    println!("<====================== encode_sized_array_and_map ======================>");
+    let mut sized_buf = [0u8; 64];
+    let mut unsized_buf = [0u8; 64];
+
+    // Sized and unsized encodings of a long array must be identical
+    let mut sized = CBORBuilder::new(&mut sized_buf);
+    let _ = sized.insert(&array_sized(25, |buff| {
+        for i in 1..=25u32 {
+            let _ = buff.insert(&i)?;
+        }
+        Ok(buff)
+    }))?;
+    let mut unsized_enc = CBORBuilder::new(&mut unsized_buf);
+    let _ = unsized_enc.insert(&array(|buff| {
+        for i in 1..=25u32 {
+            let _ = buff.insert(&i)?;
+        }
+        Ok(buff)
+    }))?;
+    assert_eq!(&sized.encoded()?[..2], &[0x98, 0x19]);
+    assert_eq!(sized.encoded()?, unsized_enc.encoded()?);
+
+    // Sized and unsized encodings of a nested map must be identical
+    let mut sized_buf = [0u8; 64];
+    let mut unsized_buf = [0u8; 64];
+    let mut sized = CBORBuilder::new(&mut sized_buf);
+    let _ = sized.insert(&map_sized(2, |buff| {
+        buff.insert_key_value(&"a", &1)?
+            .insert_key_value(&"b", &array_sized(2, |buff| buff.insert(&2)?.insert(&3)))
+    }))?;
+    let mut unsized_enc = CBORBuilder::new(&mut unsized_buf);
+    let _ = unsized_enc.insert(&map(|buff| {
+        buff.insert_key_value(&"a", &1)?
+            .insert_key_value(&"b", &array(|buff| buff.insert(&2)?.insert(&3)))
+    }))?;
+    assert_eq!(sized.encoded()?, unsized_enc.encoded()?);
+    Ok(())
+}
+
+#[test]
+fn encode_builder_len() -> Result<(), CBORError> {
+    println!("<========================== encode_builder_len ==========================>");
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    assert!(encoder.is_empty());
+    let _ = encoder.insert(&1u8)?;
+    assert_eq!(encoder.len(), 1);
+    let _ = encoder.insert(&map(|buff| {
+        buff.insert_key_value(&"a", &array(|buff| buff.insert(&2)?.insert(&3)))
+    }))?;
+    assert_eq!(encoder.len(), encoder.encoded()?.len());
+    assert_eq!(encoder.len(), 7);
+    Ok(())
+}
+
+#[test]
+fn encode_map_with_single_nested_value() -> Result<(), CBORError> {
+    println!("<================== encode_map_with_single_nested_value ==================>");
+    // {1: ["a"]} - the nested array item must not count towards the map's key/value parity
+    let mut buffer = [0u8; 16];
+    let expected: &[u8] = &[0xa1, 0x01, 0x81, 0x61, 0x61];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&map(|buff| {
+        buff.insert_key_value(&1, &array(|buff| buff.insert(&"a")))
+    }))?;
+    assert_eq!(encoder.encoded()?, expected);
+    Ok(())
+}
+
+#[test]
+fn encode_map_key_helpers() -> Result<(), CBORError> {
+    println!("<======================== encode_map_key_helpers ========================>");
+    // {1: [2], 3: << 4 >>}, with the value of key 1 already encoded
+    let expected: &[u8] = &[0xa2, 0x01, 0x81, 0x02, 0x03, 0x41, 0x04];
+    let mut buffer = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&map(|buff| {
+        buff.insert_key_cbor(&1, &[0x81, 0x02])?
+            .insert_key_bstr_wrapped(&3, |buff| buff.insert(&4))
+    }))?;
+    assert_eq!(encoder.encoded()?, expected);
+
+    let mut buffer = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&map_sized(2, |buff| {
+        buff.insert_key_cbor(&1, &[0x81, 0x02])?
+            .insert_key_bstr_wrapped(&3, |buff| buff.insert(&4))
+    }))?;
+    assert_eq!(encoder.encoded()?, expected);
+    Ok(())
+}
+
+#[test]
+fn encode_insert_simple() -> Result<(), CBORError> {
+    println!("<========================= encode_insert_simple =========================>");
+    let mut buffer = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder
+        .insert_simple(0)?
+        .insert_simple(19)?
+        .insert_simple(32)?
+        .insert_simple(255)?;
+    assert_eq!(encoder.encoded()?, &[0xe0, 0xf3, 0xf8, 0x20, 0xf8, 0xff]);
+
+    // There is no well-formed encoding for 24 to 31, and nothing is inserted
+    for v in 24..=31 {
+        assert!(matches!(
+            encoder.insert_simple(v),
+            Err(CBORError::MalformedEncoding)
+        ));
+    }
+    assert_eq!(encoder.len(), 6);
+
+    // Simple values count as a single item in an array
+    let mut buffer = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&array(|buff| buff.insert_simple(16)?.insert_simple(100)))?;
+    assert_eq!(encoder.encoded()?, &[0x82, 0xf0, 0xf8, 0x64]);
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn encode_decode_ip_addresses() -> Result<(), CBORError> {
+    use std::convert::TryFrom;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use tps_minicbor::decoder::{is_tag, CBORDecoder};
+
+    println!("<====================== encode_decode_ip_addresses ======================>");
+    let v4 = Ipv4Addr::new(192, 0, 2, 1);
+    let v6 = Ipv6Addr::new(0x2001, 0xdb8, 0x1234, 0, 0, 0, 0, 1);
+    let mut buffer = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buffer);
+    let _ = encoder.insert(&v4)?.insert(&v6)?;
+    let encoded = encoder.encoded()?;
+    assert_eq!(&encoded[..7], &[0xd8, 0x34, 0x44, 0xc0, 0x00, 0x02, 0x01]);
+    assert_eq!(&encoded[7..10], &[0xd8, 0x36, 0x50]);
+
+    let decoder = CBORDecoder::from_slice(encoded);
+    let mut d4 = Ipv4Addr::UNSPECIFIED;
+    let mut d6 = Ipv6Addr::UNSPECIFIED;
+    let _ = decoder
+        .decode_with(is_tag(), |cbor| {
+            d4 = Ipv4Addr::try_from(cbor)?;
+            Ok(())
+        })?
+        .decode_with(is_tag(), |cbor| {
+            d6 = Ipv6Addr::try_from(cbor)?;
+            Ok(())
+        })?;
+    assert_eq!(d4, v4);
+    assert_eq!(d6, v6);
+
+    // A tag 52 payload which is not 4 bytes long is rejected
+    let decoder = CBORDecoder::from_slice(&[0xd8, 0x34, 0x43, 0xc0, 0x00, 0x02]);
+    let r = decoder.decode_with(is_tag(), |cbor| Ipv4Addr::try_from(cbor).map(|_| ()));
+    assert!(matches!(r, Err(CBORError::WrongLength(3))));
+    // ... as is an untagged item
+    assert!(matches!(
+        Ipv4Addr::try_from(CBOR::UInt(1)),
+        Err(CBORError::IncompatibleType)
+    ));
+    Ok(())
+}
+
+#[test]
+fn encode_tag_requires_one_item() -> Result<(), CBORError> {
+    println!("<====================== encode_tag_requires_one_item ======================>");
+    let mut buf = [0u8; 16];
+
+    // No item inside the tag
+    let mut encoder = CBORBuilder::new(&mut buf);
+    assert!(matches!(
+        encoder.insert(&tag(1, |buff| Ok(buff))),
+        Err(CBORError::MalformedEncoding)
+    ));
+
+    // Two items inside the tag
+    let mut encoder = CBORBuilder::new(&mut buf);
+    assert!(matches!(
+        encoder.insert(&tag(1, |buff| buff.insert(&1)?.insert(&2))),
+        Err(CBORError::MalformedEncoding)
+    ));
+
+    // Nested tags and a tagged array each wrap exactly one item
+    let mut encoder = CBORBuilder::new(&mut buf);
+    let _ = encoder.insert(&tag(55799, |buff| {
+        buff.insert(&tag(1, |buff| buff.insert(&array(|buff| buff.insert(&1)?.insert(&2)))))
+    }))?;
+    assert_eq!(
+        encoder.encoded()?,
+        &[0xd9, 0xd9, 0xf7, 0xc1, 0x82, 0x01, 0x02]
+    );
+    Ok(())
+}
+
+#[test]
+fn encode_negative_keyed_map_round_trip() -> Result<(), CBORError> {
+    use tps_minicbor::decoder::{CBORDecoder, MapBuf};
+
+    println!("<================== encode_negative_keyed_map_round_trip ==================>");
+    // COSE style header map with negative labels:
+    // {-1: 1, -2: h'0102', 1: -7, 2: {-1: -256, -25: [-1]}, -3: 1(-65537)}
+    let input = [
+        0xa5, 0x20, 0x01, 0x21, 0x42, 0x01, 0x02, 0x01, 0x26, 0x02, 0xa2, 0x20, 0x38, 0xff, 0x38,
+        0x18, 0x81, 0x20, 0x22, 0xc1, 0x3a, 0x00, 0x01, 0x00, 0x00,
+    ];
+    let mut decoded: Option<MapBuf> = None;
+    let _ = CBORDecoder::from_slice(&input).map(|mb| {
+        decoded = Some(mb);
+        Ok(())
+    })?;
+    let mb = decoded.unwrap();
+    assert_eq!(mb.lookup::<i64, i64>(1)?, -7);
+    assert_eq!(mb.lookup::<i64, &[u8]>(-2)?, &[0x01, 0x02]);
+
+    // Re-encode the map pair by pair
+    let mut buf = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buf);
+    let _ = encoder.insert(&map(|buff| {
+        let mut items = mb.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            let _ = buff.insert_key_value(&key, &value)?;
+        }
+        Ok(buff)
+    }))?;
+    assert_eq!(encoder.encoded()?, &input);
+
+    // Re-encode the decoded map as a single item
+    let mut buf = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buf);
+    let _ = encoder.insert(&CBOR::Map(mb))?;
+    assert_eq!(encoder.encoded()?, &input);
+    Ok(())
+}
+
+#[test]
+fn encode_bstr_wrapped() -> Result<(), CBORError> {
+    println!("<========================== encode_bstr_wrapped ==========================>");
+    // Short contents: [<< {1: -7} >>, 1]
+    let mut buf = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut buf);
+    let _ = encoder.insert(&array(|buff| {
+        buff.insert_bstr_wrapped(|buff| buff.insert(&map(|buff| buff.insert_key_value(&1, &-7))))?
+            .insert(&1)
+    }))?;
+    assert_eq!(encoder.encoded()?, &[0x82, 0x43, 0xa1, 0x01, 0x26, 0x01]);
+
+    // Contents of 24 bytes or more need the length to be moved: {0: << "abcdefghijklmnopqrstuvwxyz" >>}
+    let alphabet = "abcdefghijklmnopqrstuvwxyz";
+    let mut wrapped_buf = [0u8; 64];
+    let mut encoder = CBORBuilder::new(&mut wrapped_buf);
+    let _ = encoder.insert(&map(|buff| {
+        buff.insert_key_bstr_wrapped(&0, |buff| buff.insert(&alphabet))
+    }))?;
+
+    // Compare with the contents being built in a separate buffer
+    let mut inner_buf = [0u8; 32];
+    let mut inner = CBORBuilder::new(&mut inner_buf);
+    let _ = inner.insert(&alphabet)?;
+    let mut outer_buf = [0u8; 64];
+    let mut outer = CBORBuilder::new(&mut outer_buf);
+    let _ = outer.insert(&map(|buff| buff.insert_key_value(&0, &inner.encoded()?)))?;
+    assert_eq!(&encoder.encoded()?[..4], &[0xa1, 0x00, 0x58, 28]);
+    assert_eq!(encoder.encoded()?, outer.encoded()?);
+
+    // Empty contents
+    let mut buf = [0u8; 8];
+    let mut encoder = CBORBuilder::new(&mut buf);
+    let _ = encoder.insert(&array(|buff| buff.insert_bstr_wrapped(|buff| Ok(buff))))?;
+    assert_eq!(encoder.encoded()?, &[0x81, 0x40]);
+
+    // Contents which do not fit in the buffer
+    let mut buf = [0u8; 8];
+    let mut encoder = CBORBuilder::new(&mut buf);
+    assert!(encoder
+        .insert(&array(|buff| buff.insert_bstr_wrapped(|buff| buff.insert(&alphabet))))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn encode_insert_tracked() -> Result<(), CBORError> {
+    println!("<======================= encode_insert_tracked =====================>");
+    let mut buf = [0u8; 6];
+    let mut encoder = CBORBuilder::sequence(&mut buf);
+    assert_eq!(encoder.insert_tracked(&1u8)?, 1);
+    assert_eq!(encoder.insert_tracked(&"ab")?, 4);
+
+    // [1, 2, 3] runs out of space part way through: the partial array is discarded
+    assert!(matches!(
+        encoder.insert_tracked(&array(|buff| buff.insert(&1u8)?.insert(&2u8)?.insert(&3u8))),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert_eq!(encoder.len(), 4);
+    assert_eq!(encoder.encoded()?, &[0x01, 0x62, 0x61, 0x62]);
+
+    // An item which exactly fills the remaining space, then one which does not fit at all
+    assert_eq!(encoder.insert_tracked(&0x18u8)?, 6);
+    assert!(matches!(
+        encoder.insert_tracked(&0u8),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert_eq!(encoder.encoded()?, &[0x01, 0x62, 0x61, 0x62, 0x18, 0x18]);
+    Ok(())
+}
+
+#[test]
+fn encode_fixed_builder() -> Result<(), CBORError> {
+    println!("<======================= encode_fixed_builder =====================>");
+    let mut encoder = CBORBuilder::new_array::<6>();
+    assert!(encoder.is_empty());
+    encoder.insert(&1u8)?.insert(&"ab")?;
+    assert_eq!(encoder.len(), 4);
+
+    // [1, 2, 3] runs out of space part way through: the partial array is discarded
+    assert!(matches!(
+        encoder.insert(&array(|buff| buff.insert(&1u8)?.insert(&2u8)?.insert(&3u8))),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert_eq!(encoder.encoded(), &[0x01, 0x62, 0x61, 0x62]);
+
+    // The result is the same as for a CBORBuilder over an external buffer
+    let mut bytes = [0u8; 6];
+    let mut expected = CBORBuilder::new(&mut bytes);
+    expected.insert(&1u8)?.insert(&"ab")?;
+    let (array, len) = encoder.into_array();
+    assert_eq!(&array[..len], expected.encoded()?);
+    assert_eq!(&array[len..], &[0, 0]);
+    Ok(())
+}
+
+#[test]
+fn encode_concat() -> Result<(), CBORError> {
+    println!("<======================= encode_concat =====================>");
+    let mut header = [0u8; 4];
+    let mut body = [0u8; 16];
+    let mut encoder = ConcatEncoder::new(&mut header, &mut body);
+    assert!(encoder.is_empty());
+
+    // 10, {1: "a", 2: h'0102'}
+    encoder.body().insert(&map(|buff| {
+        buff.insert_key_value(&1u8, &"a")?
+            .insert_key_value(&2u8, &[0x01u8, 0x02].as_slice())
+    }))?;
+    encoder.header().insert(&10u8)?;
+    let expected = [0x0a, 0xa2, 0x01, 0x61, 0x61, 0x02, 0x42, 0x01, 0x02];
+    assert_eq!(encoder.len(), expected.len());
+
+    // The output may be exactly the required size, and the message decodes as a sequence
+    let mut out = [0u8; 9];
+    let message = encoder.finalize(&mut out)?;
+    assert_eq!(message.bytes, &expected);
+    assert_eq!(message.into_iter().count(), 2);
+
+    // A short output buffer reports the required length and is left unchanged
+    let mut short = [0xffu8; 8];
+    assert!(matches!(
+        encoder.finalize(&mut short),
+        Err(CBORError::ShortBuffer(9))
+    ));
+    assert_eq!(short, [0xff; 8]);
+
+    // An empty header leaves just the body
+    let mut header = [0u8; 0];
+    let mut body = [0u8; 4];
+    let mut encoder = ConcatEncoder::new(&mut header, &mut body);
+    encoder.body().insert(&"ab")?;
+    let mut out = [0u8; 4];
+    assert_eq!(encoder.finalize(&mut out)?.bytes, &[0x62, 0x61, 0x62]);
+    Ok(())
+}
+
+#[test]
+fn encode_tstr_from_bytes() -> Result<(), CBORError> {
+    println!("<======================= encode_tstr_from_bytes =====================>");
+    let mut buf = [0u8; 32];
+    let mut encoder = CBORBuilder::new(&mut buf);
+    encoder
+        .insert_tstr_from_bytes(b"")?
+        .insert_tstr_from_bytes("水".as_bytes())?;
+
+    // Invalid UTF-8: a lone continuation byte, a truncated sequence and an encoded surrogate
+    for bytes in [&[0x80u8][..], &[0x61, 0xe6, 0xb0], &[0xed, 0xa0, 0x80]].iter() {
+        assert!(matches!(
+            encoder.insert_tstr_from_bytes(bytes),
+            Err(CBORError::UTF8Error)
+        ));
+    }
+    assert_eq!(encoder.encoded()?, &[0x60, 0x63, 0xe6, 0xb0, 0xb4]);
+
+    // The result is the same as inserting the equivalent &str
+    let mut expected_buf = [0u8; 32];
+    let mut expected = CBORBuilder::new(&mut expected_buf);
+    expected.insert(&"")?.insert(&"水")?;
+    assert_eq!(encoder.encoded()?, expected.encoded()?);
+    Ok(())
+}
+
+#[test]
+fn encode_reserve_slot() -> Result<(), CBORError> {
+    println!("<======================= encode_reserve_slot =====================>");
+    let mut buf = [0xffu8; 8];
+    let mut encoder = CBORBuilder::new(&mut buf);
+    let slot = encoder.reserve(4)?;
+    assert_eq!((slot.offset(), slot.len()), (0, 4));
+    encoder.insert(&[1u8, 2].as_slice())?;
+    // Reserved bytes are zero until the slot is filled
+    assert_eq!(encoder.encoded()?, &[0, 0, 0, 0, 0x42, 0x01, 0x02]);
+
+    // The slot must be filled with exactly its length
+    let mut other = [0u8; 8];
+    let mut other_encoder = CBORBuilder::new(&mut other);
+    let short = other_encoder.reserve(4)?;
+    assert!(matches!(
+        encoder.fill_slot(short, &[1, 2, 3]),
+        Err(CBORError::WrongLength(3))
+    ));
+    encoder.fill_slot(slot, b"CBOR")?;
+    assert_eq!(encoder.encoded()?, b"CBOR\x42\x01\x02");
+
+    // No space for the slot, then an empty slot and one which fills the buffer
+    assert!(matches!(encoder.reserve(2), Err(CBORError::EndOfBuffer)));
+    let empty = encoder.reserve(0)?;
+    assert!(empty.is_empty());
+    encoder.fill_slot(empty, &[])?;
+    let last = encoder.reserve(1)?;
+    encoder.fill_slot(last, &[0xf6])?;
+    assert_eq!(encoder.encoded()?, b"CBOR\x42\x01\x02\xf6");
+
+    // A slot reserved in a longer buffer is outside this one
+    let mut long = [0u8; 16];
+    let mut long_encoder = EncodeBuffer::new(&mut long);
+    let _ = long_encoder.insert(&"0123456789")?;
+    let far = long_encoder.reserve(2)?;
+    let mut buf = [0u8; 16];
+    let mut encoder = EncodeBuffer::new(&mut buf);
+    let _ = encoder.insert(&1u8)?;
+    assert!(matches!(
+        encoder.fill_slot(far, &[0, 0]),
+        Err(CBORError::IndexOutOfBounds)
+    ));
+    Ok(())
+}
+
+#[test]
+fn encode_tag_raw() -> Result<(), CBORError> {
+    println!("<======================= encode_tag_raw =====================>");
+    let mut bytes = [0u8; 32];
+
+    // [32("a"), 1(h'01')], with both tagged items pre-encoded
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&array(|buf| {
+        buf.insert(&tag_raw(32, &[0x61, 0x61]))?
+            .insert(&tag_raw(1, &[0x41, 0x01]))
+    }))?;
+    assert_eq!(
+        encoder.encoded()?,
+        &[0x82, 0xd8, 0x20, 0x61, 0x61, 0xc1, 0x41, 0x01]
+    );
+
+    // Nothing is written unless there is exactly one well-formed item
+    let mut bytes = [0u8; 32];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    for raw in [&[][..], &[0x01, 0x02], &[0x82, 0x01], &[0x1c]] {
+        assert!(matches!(
+            encoder.insert(&tag_raw(24, raw)),
+            Err(CBORError::MalformedEncoding)
+        ));
+    }
+    assert_eq!(encoder.encoded()?.len(), 0);
+    Ok(())
+}
+
+#[test]
+fn encode_bignum_byte_order() -> Result<(), CBORError> {
+    use tps_minicbor::decoder::SequenceBuffer;
+
+    println!("<======================= encode_bignum_byte_order =====================>");
+    // 2(h'0102') from big-endian and little-endian bytes, with leading zeros trimmed
+    let expected = [0xc2, 0x42, 0x01, 0x02];
+    let mut bytes = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&bignum(&[0x00, 0x00, 0x01, 0x02]))?;
+    assert_eq!(encoder.encoded()?, &expected);
+    let mut bytes = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&bignum_from_le(&[0x02, 0x01, 0x00, 0x00]))?;
+    assert_eq!(encoder.encoded()?, &expected);
+
+    // Zero is an empty bstr, and a single byte is unchanged by reversal
+    let mut bytes = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&array(|buf| {
+        buf.insert(&bignum_from_le(&[0x00, 0x00]))?
+            .insert(&bignum(&[]))?
+            .insert(&bignum_from_le(&[0xff]))
+    }))?;
+    assert_eq!(
+        encoder.encoded()?,
+        &[0x83, 0xc2, 0x40, 0xc2, 0x40, 0xc2, 0x41, 0xff]
+    );
+
+    // Decoding returns the big-endian magnitude, or copies it out in little-endian order
+    let mut le = [0u8; 4];
+    match SequenceBuffer::new(&expected).into_iter().next() {
+        Some(CBOR::Tag(tb)) => {
+            assert_eq!(tb.bignum()?, &[0x01, 0x02]);
+            assert_eq!(tb.bignum_to_le(&mut le)?, 2);
+        }
+        _ => panic!("expected a tag"),
+    }
+    assert_eq!(le, [0x02, 0x01, 0x00, 0x00]);
+
+    // Only tags 2 and 3 holding a bstr are bignums
+    match SequenceBuffer::new(&[0xc3, 0x01]).into_iter().next() {
+        Some(CBOR::Tag(tb)) => {
+            assert!(matches!(tb.bignum(), Err(CBORError::TagContentMismatch(3))))
+        }
+        _ => panic!("expected a tag"),
+    }
+    match SequenceBuffer::new(&[0xc4, 0x41, 0x01]).into_iter().next() {
+        Some(CBOR::Tag(tb)) => assert!(matches!(tb.bignum(), Err(CBORError::ExpectedTag(2)))),
+        _ => panic!("expected a tag"),
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "float")]
+fn encode_reject_non_finite_floats() -> Result<(), CBORError> {
+    println!("<================== encode_reject_non_finite_floats ==================>");
+    let mut bytes = [0u8; 32];
+
+    let mut encoder = CBORBuilder::new(&mut bytes).reject_non_finite_floats(true);
+    assert!(matches!(
+        encoder.insert(&f16::INFINITY),
+        Err(CBORError::NotAllowed)
+    ));
+    assert!(matches!(
+        encoder.insert(&f32::NAN),
+        Err(CBORError::NotAllowed)
+    ));
+    assert!(matches!(
+        encoder.insert(&array(|buf| buf
+            .insert(&1.5f64)?
+            .insert(&f64::NEG_INFINITY))),
+        Err(CBORError::NotAllowed)
+    ));
+
+    // Finite floats are encoded as usual
+    let mut encoder = CBORBuilder::new(&mut bytes).reject_non_finite_floats(true);
+    encoder.insert(&1.5f64)?;
+    assert_eq!(encoder.encoded()?, &[0xf9, 0x3e, 0x00]);
+
+    // By default, non-finite floats are encoded
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&f16::INFINITY)?;
+    assert_eq!(encoder.encoded()?, &[0xf9, 0x7c, 0x00]);
+    Ok(())
+}
+
+#[test]
+fn encode_tstr_builder() -> Result<(), CBORError> {
+    println!("<======================== encode_tstr_builder ========================>");
+    let mut text = [0u8; 8];
+    let mut builder = TstrBuilder::new(&mut text);
+    assert!(builder.is_empty());
+    builder.push_str("€=")?;
+    for digit in 1..=3 {
+        builder.push_char(core::char::from_digit(digit, 10).unwrap())?;
+    }
+    assert_eq!(builder.len(), 7);
+
+    // A fragment which does not fit is not written, even in part
+    assert!(matches!(
+        builder.push_str("ab"),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert!(matches!(
+        builder.push_char('€'),
+        Err(CBORError::EndOfBuffer)
+    ));
+    builder.push_char('!')?;
+    assert!(matches!(
+        builder.push_char('!'),
+        Err(CBORError::EndOfBuffer)
+    ));
+    let amount = builder.finish()?;
+    assert_eq!(amount, "€=123!");
+
+    let mut bytes = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&map(|buf| buf.insert_key_value(&1, &amount)))?;
+    assert_eq!(
+        encoder.encoded()?,
+        &[0xa1, 0x01, 0x68, 0xe2, 0x82, 0xac, 0x3d, 0x31, 0x32, 0x33, 0x21]
+    );
+    Ok(())
+}
+
+#[test]
+fn encode_write_header() -> Result<(), CBORError> {
+    println!("<========================== encode_write_header =========================>");
+    // The argument is always written on the shortest encoding, for every major type
+    let cases: [(u8, u64, &[u8]); 8] = [
+        (0, 23, &[0x17]),
+        (1, 24, &[0x38, 0x18]),
+        (2, 255, &[0x58, 0xff]),
+        (3, 256, &[0x79, 0x01, 0x00]),
+        (4, 65536, &[0x9a, 0x00, 0x01, 0x00, 0x00]),
+        (
+            5,
+            u32::MAX as u64 + 1,
+            &[0xbb, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00],
+        ),
+        (6, 1, &[0xc1]),
+        (7, 32, &[0xf8, 0x20]),
+    ];
+    for (major, argument, expected) in cases.iter() {
+        let mut bytes = [0u8; 16];
+        let mut encoder = EncodeBuffer::new(&mut bytes);
+        let _ = encoder.write_header(*major, *argument)?;
+        assert_eq!(encoder.encoded()?, *expected);
+    }
+
+    // Headers written inside an array are counted with their content
+    let mut bytes = [0u8; 16];
+    let mut encoder = CBORBuilder::new(&mut bytes);
+    encoder.insert(&array(|buf| {
+        buf.write_header(6, 1)?.insert(&0)?.write_header(7, 16)?;
+        Ok(buf)
+    }))?;
+    assert_eq!(encoder.encoded()?, &[0x82, 0xc1, 0x00, 0xf0]);
+
+    // Nothing is written for an invalid major type, a malformed simple value or a short buffer
+    let mut bytes = [0u8; 2];
+    let mut encoder = EncodeBuffer::new(&mut bytes);
+    assert!(matches!(
+        encoder.write_header(8, 0),
+        Err(CBORError::MalformedEncoding)
+    ));
+    assert!(matches!(
+        encoder.write_header(7, 24),
+        Err(CBORError::MalformedEncoding)
+    ));
+    assert!(matches!(
+        encoder.write_header(0, 256),
+        Err(CBORError::EndOfBuffer)
+    ));
+    assert_eq!(encoder.encoded()?, &[]);
+    Ok(())
+}